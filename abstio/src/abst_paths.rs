@@ -5,6 +5,7 @@
 // constantly, or plumb it around with a borrow? Or maybe even owned.
 
 use anyhow::Result;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 use abstutil::basename;
@@ -62,7 +63,7 @@ pub fn path<I: AsRef<str>>(p: I) -> String {
 }
 
 /// A single city is identified using this.
-#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, JsonSchema)]
 pub struct CityName {
     /// A two letter lowercase country code, from https://en.wikipedia.org/wiki/ISO_3166-1_alpha-2.
     /// To represent imaginary/test cities, use the code `zz`.
@@ -183,7 +184,7 @@ impl CityName {
 }
 
 /// A single map is identified using this.
-#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, JsonSchema)]
 pub struct MapName {
     pub city: CityName,
     /// The name of the map within the city, in filename-friendly form -- for example, "downtown"
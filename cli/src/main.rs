@@ -11,6 +11,7 @@ mod geojson_to_osmosis;
 mod import_grid2demand;
 mod import_scenario;
 mod one_step_import;
+mod optimize_signal_offsets;
 mod pick_geofabrik;
 
 use anyhow::Result;
@@ -173,6 +174,25 @@ enum Command {
         #[structopt(flatten)]
         opts: map_model::RawToMapOptions,
     },
+    /// Coordinates the offsets of a chain of adjacent traffic signals, maximizing the green-wave
+    /// bandwidth in both directions along the corridor. The new offsets are saved as map edits.
+    OptimizeSignalOffsets {
+        /// The path to a map
+        #[structopt(long)]
+        map: String,
+        /// The path to existing edits to apply first. The new offsets are added to these.
+        #[structopt(long)]
+        edits: Option<String>,
+        /// The IDs of the traffic signals along the corridor, in order, separated by commas
+        #[structopt(long, use_delimiter = true)]
+        intersections: Vec<usize>,
+        /// Optimize the plans in effect at this time of day, like "07:30:00"
+        #[structopt(long, parse(try_from_str = geom::Time::parse), default_value = "00:00:00")]
+        time: geom::Time,
+        /// The name of the edits to save
+        #[structopt(long)]
+        edits_name: String,
+    },
     /// Regenerate all maps and scenarios from scratch.
     RegenerateEverything {
         /// If this command is being run in the cloud, parallelize the jobs by specifying which
@@ -255,6 +275,13 @@ async fn main() -> Result<()> {
             drive_on_left,
             opts,
         } => importer::oneshot(osm_input, clip_path, drive_on_left, opts),
+        Command::OptimizeSignalOffsets {
+            map,
+            edits,
+            intersections,
+            time,
+            edits_name,
+        } => optimize_signal_offsets::run(map, edits, intersections, time, edits_name)?,
         Command::RegenerateEverything {
             shard_num,
             num_shards,
@@ -0,0 +1,51 @@
+use anyhow::Result;
+
+use abstutil::Timer;
+use geom::Time;
+use map_model::{IntersectionID, Map, MapEdits};
+
+pub fn run(
+    map: String,
+    edits: Option<String>,
+    intersections: Vec<usize>,
+    time: Time,
+    edits_name: String,
+) -> Result<()> {
+    let mut timer = Timer::new("optimize signal offsets");
+    let mut map = Map::load_synchronously(map, &mut timer);
+    if let Some(path) = edits {
+        let edits = MapEdits::load_from_file(&map, path, &mut timer)?;
+        map.must_apply_edits(edits, &mut timer);
+    }
+
+    let corridor: Vec<IntersectionID> = intersections.into_iter().map(IntersectionID).collect();
+    let result = map_model::optimize_corridor_offsets(&map, &corridor, time)?;
+    println!("Cycle length: {}", result.cycle_length);
+    println!(
+        "Forward bandwidth: {} -> {}",
+        result.forward_bandwidth_before, result.forward_bandwidth_after
+    );
+    println!(
+        "Backward bandwidth: {} -> {}",
+        result.backward_bandwidth_before, result.backward_bandwidth_after
+    );
+    for (i, plan, offset) in &result.offsets {
+        println!("  {} (plan {}): offset {}", i, plan + 1, offset);
+    }
+
+    let cmds = result.to_edit_cmds(&map);
+    if cmds.is_empty() {
+        println!("The current offsets are already the best found; not writing any edits");
+        return Ok(());
+    }
+    let mut edits = map.get_edits().clone();
+    edits.commands.extend(cmds);
+    edits.edits_name = edits_name;
+    map.must_apply_edits(edits, &mut timer);
+    map.save_edits();
+    println!(
+        "Wrote {}",
+        abstio::path_edits(map.get_name(), &map.get_edits().edits_name)
+    );
+    Ok(())
+}
@@ -148,6 +148,11 @@ pub fn clip_map(map: &mut RawMap, timer: &mut Timer) {
             .all(|pt| boundary_polygon.contains_pt(*pt))
     });
 
+    map.bike_share_docks
+        .retain(|d| boundary_polygon.contains_pt(d.pt));
+    map.charging_stations
+        .retain(|c| boundary_polygon.contains_pt(c.pt));
+
     let mut result_areas = Vec::new();
     for orig_area in map.areas.drain(..) {
         for polygon in map.boundary_polygon.intersection(&orig_area.polygon) {
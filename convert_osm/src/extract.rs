@@ -6,12 +6,20 @@ use abstio::MapName;
 use abstutil::{Tags, Timer};
 use geom::{Distance, FindClosest, HashablePt2D, Polygon, Pt2D, Ring};
 use kml::{ExtraShape, ExtraShapes};
-use map_model::raw::{RawArea, RawBuilding, RawMap, RawParkingLot, RawRoad, RestrictionType};
+use map_model::raw::{
+    RawArea, RawBikeShareDock, RawBuilding, RawChargingStation, RawMap, RawParkingLot, RawRoad,
+    RestrictionType,
+};
 use map_model::{osm, Amenity, AreaType, Direction, DrivingSide, NamePerLanguage};
 
 use crate::osm_geom::{get_multipolygon_members, glue_multipolygon, multipoly_geometry};
 use crate::{transit, Options};
 
+/// Most docks in OSM don't say how many bikes fit
+const DEFAULT_BIKE_SHARE_DOCK_CAPACITY: usize = 10;
+/// Same for charging stations
+const DEFAULT_CHARGING_STATION_CAPACITY: usize = 2;
+
 pub struct OsmExtract {
     /// Unsplit roads
     pub roads: Vec<(WayID, RawRoad)>,
@@ -73,6 +81,33 @@ pub fn extract_osm(map: &mut RawMap, opts: &Options, timer: &mut Timer) -> OsmEx
         for amenity in get_bldg_amenities(&node.tags) {
             out.amenities.push((node.pt, amenity));
         }
+        if node.tags.is("amenity", "bicycle_rental")
+            || node.tags.is("amenity", "kick-scooter_rental")
+        {
+            map.bike_share_docks.push(RawBikeShareDock {
+                osm_id: *id,
+                pt: node.pt,
+                name: node.tags.get("name").cloned(),
+                capacity: node
+                    .tags
+                    .get("capacity")
+                    .and_then(|x| x.parse::<usize>().ok())
+                    .unwrap_or(DEFAULT_BIKE_SHARE_DOCK_CAPACITY),
+                scooters: node.tags.is("amenity", "kick-scooter_rental"),
+            });
+        }
+        if node.tags.is("amenity", "charging_station") {
+            map.charging_stations.push(RawChargingStation {
+                osm_id: *id,
+                pt: node.pt,
+                name: node.tags.get("name").cloned(),
+                capacity: node
+                    .tags
+                    .get("capacity")
+                    .and_then(|x| x.parse::<usize>().ok())
+                    .unwrap_or(DEFAULT_CHARGING_STATION_CAPACITY),
+            });
+        }
     }
 
     // and cycleways
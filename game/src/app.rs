@@ -93,6 +93,46 @@ impl App {
                     "- passengers_alighting: {} bytes",
                     prettyprint_usize(serialized_size_bytes(&a.passengers_alighting))
                 );
+                println!(
+                    "- car_occupancy: {} bytes",
+                    prettyprint_usize(serialized_size_bytes(&a.car_occupancy))
+                );
+                println!(
+                    "- ride_hail_waits: {} bytes",
+                    prettyprint_usize(serialized_size_bytes(&a.ride_hail_waits))
+                );
+                println!(
+                    "- ride_hail_curb_stops: {} bytes",
+                    prettyprint_usize(serialized_size_bytes(&a.ride_hail_curb_stops))
+                );
+                println!(
+                    "- ride_hail_utilization: {} bytes",
+                    prettyprint_usize(serialized_size_bytes(&a.ride_hail_utilization))
+                );
+                println!(
+                    "- deliveries: {} bytes",
+                    prettyprint_usize(serialized_size_bytes(&a.deliveries))
+                );
+                println!(
+                    "- loading_zone_stops: {} bytes",
+                    prettyprint_usize(serialized_size_bytes(&a.loading_zone_stops))
+                );
+                println!(
+                    "- bike_share_inventory: {} bytes",
+                    prettyprint_usize(serialized_size_bytes(&a.bike_share_inventory))
+                );
+                println!(
+                    "- bike_share_empty_docks: {} bytes",
+                    prettyprint_usize(serialized_size_bytes(&a.bike_share_empty_docks))
+                );
+                println!(
+                    "- bike_share_full_docks: {} bytes",
+                    prettyprint_usize(serialized_size_bytes(&a.bike_share_full_docks))
+                );
+                println!(
+                    "- bike_share_rebalancing: {} bytes",
+                    prettyprint_usize(serialized_size_bytes(&a.bike_share_rebalancing))
+                );
                 println!(
                     "- started_trips: {} bytes",
                     prettyprint_usize(serialized_size_bytes(&a.started_trips))
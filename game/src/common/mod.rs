@@ -375,6 +375,9 @@ pub fn color_for_mode(app: &App, m: TripMode) -> Color {
         TripMode::Bike => app.cs.unzoomed_bike,
         TripMode::Transit => app.cs.unzoomed_bus,
         TripMode::Drive => app.cs.unzoomed_car,
+        TripMode::RideHail => app.cs.unzoomed_ride_hail,
+        TripMode::BikeShare => app.cs.unzoomed_bike_share,
+        TripMode::ParkAndRide => app.cs.unzoomed_park_and_ride,
     }
 }
 
@@ -384,7 +387,9 @@ pub fn color_for_agent_type(app: &App, a: AgentType) -> Color {
         AgentType::Bike => app.cs.unzoomed_bike,
         AgentType::Bus | AgentType::Train => app.cs.unzoomed_bus,
         AgentType::TransitRider => app.cs.bus_trip,
+        AgentType::CarPassenger => app.cs.unzoomed_car,
         AgentType::Car => app.cs.unzoomed_car,
+        AgentType::Truck => app.cs.unzoomed_truck,
     }
 }
 
@@ -396,6 +401,9 @@ pub fn color_for_trip_phase(app: &App, tpt: TripPhaseType) -> Color {
         TripPhaseType::Parking => app.cs.parking_trip,
         TripPhaseType::WaitingForBus(_, _) => app.cs.bus_layer,
         TripPhaseType::RidingBus(_, _, _) => app.cs.bus_trip,
+        TripPhaseType::WaitingForRide => app.cs.bus_layer,
+        TripPhaseType::RidingInCar(_) => app.cs.unzoomed_car,
+        TripPhaseType::Unloading(_) => app.cs.parking_trip,
         TripPhaseType::Cancelled | TripPhaseType::Finished => unreachable!(),
         TripPhaseType::DelayedStart => Color::YELLOW,
     }
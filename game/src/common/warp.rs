@@ -187,7 +187,10 @@ fn inner_warp_to_id(ctx: &mut EventCtx, app: &mut App, line: &str) -> Option<Tra
             }
             'R' => {
                 let r = BusRouteID(idx);
-                app.primary.map.maybe_get_br(r)?;
+                app.primary
+                    .map
+                    .maybe_get_br(r)
+                    .filter(|r| !r.is_deleted())?;
                 return Some(Transition::Multi(vec![
                     Transition::Pop,
                     Transition::ModifyState(Box::new(move |state, ctx, app| {
@@ -102,7 +102,7 @@ impl EditMode {
                     .primary
                     .sim
                     .handle_live_edits(&app.primary.map, &mut timer);
-                if trips == 0 && parked_cars == 0 {
+                if trips.is_empty() && parked_cars == 0 {
                     Transition::Pop
                 } else {
                     Transition::Replace(PopupMsg::new_state(
@@ -111,7 +111,7 @@ impl EditMode {
                         vec![
                             format!(
                                 "Your edits interrupted {} trips and displaced {} parked cars",
-                                prettyprint_usize(trips),
+                                prettyprint_usize(trips.len()),
                                 prettyprint_usize(parked_cars)
                             ),
                             "Simulation results won't be finalized unless you restart from \
@@ -185,6 +185,9 @@ impl State<App> for EditMode {
                 "finish editing" => {
                     return self.quit(ctx, app);
                 }
+                "Create a new transit route" => {
+                    return Transition::Push(RouteEditor::new_route(ctx, app));
+                }
                 _ => unreachable!(),
             }
         }
@@ -698,6 +701,10 @@ fn make_topcenter(ctx: &mut EventCtx, app: &App) -> Panel {
             ))
             .hotkey(Key::Escape)
             .build_widget(ctx, "finish editing"),
+        ctx.style()
+            .btn_outline
+            .text("Create a new transit route")
+            .build_def(ctx),
     ]))
     .aligned(HorizontalAlignment::Center, VerticalAlignment::Top)
     .build(ctx)
@@ -755,6 +762,12 @@ pub fn apply_map_edits(ctx: &mut EventCtx, app: &mut App, edits: MapEdits) {
             app.primary.draw_map.get_pl(pl).clear_rendering();
         }
 
+        for bs in effects.changed_bus_stops {
+            app.primary
+                .draw_map
+                .recreate_bus_stop(ctx, bs, &app.primary.map, &app.cs);
+        }
+
         if app.primary.layer.as_ref().and_then(|l| l.name()) == Some("map edits") {
             app.primary.layer = Some(Box::new(crate::layer::map::Static::edits(ctx, app)));
         }
@@ -909,7 +922,9 @@ fn cmd_to_id(cmd: &EditCmd) -> Option<ID> {
     match cmd {
         EditCmd::ChangeRoad { r, .. } => Some(ID::Road(*r)),
         EditCmd::ChangeIntersection { i, .. } => Some(ID::Intersection(*i)),
-        EditCmd::ChangeRouteSchedule { .. } => None,
+        EditCmd::ChangeRouteSchedule { .. } | EditCmd::ChangeRoute { .. } => None,
+        EditCmd::ChangeParkingLot { id, .. } => Some(ID::ParkingLot(*id)),
+        EditCmd::ChangeBusStop { id, .. } => Some(ID::Lane(id.sidewalk)),
     }
 }
 
@@ -1,52 +1,184 @@
-use geom::{Duration, Time};
-use map_model::{BusRouteID, EditCmd};
+use geom::{Circle, Distance, Duration, Time};
+use map_gui::tools::PopupMsg;
+use map_gui::ID;
+use map_model::{
+    BusRouteID, BusStopID, EditBusStop, EditCmd, LaneID, PathConstraints, PathRequest,
+};
 use widgetry::{
-    EventCtx, GfxCtx, HorizontalAlignment, Key, Line, Outcome, Panel, Spinner, State, TextExt,
-    VerticalAlignment, Widget,
+    Drawable, EventCtx, GeomBatch, GfxCtx, HorizontalAlignment, Key, Line, Outcome, Panel, Spinner,
+    State, Text, TextBox, TextExt, VerticalAlignment, Widget,
 };
 
 use crate::app::App;
 use crate::app::Transition;
+use crate::common::CommonState;
 use crate::edit::apply_map_edits;
 
+/// Design a new transit route, or change the stops, schedule, or existence of an existing one.
 pub struct RouteEditor {
     panel: Panel,
     route: BusRouteID,
+    /// The stops the route will serve, in order. Only saved when the player is done.
+    stops: Vec<BusStopID>,
+    draw_route: Drawable,
 }
 
 impl RouteEditor {
     pub fn new_state(ctx: &mut EventCtx, app: &mut App, id: BusRouteID) -> Box<dyn State<App>> {
         app.primary.current_selection = None;
 
-        let route = app.primary.map.get_br(id);
-        Box::new(RouteEditor {
-            panel: Panel::new_builder(Widget::col(vec![
-                Widget::row(vec![
-                    Line("Route editor").small_heading().into_widget(ctx),
-                    ctx.style().btn_close_widget(ctx),
-                ]),
-                Line(&route.full_name).into_widget(ctx),
-                // TODO This UI needs design, just something to start plumbing the edits
-                Widget::row(vec![
-                    "Frequency".text_widget(ctx),
-                    Spinner::widget(
-                        ctx,
-                        "freq_mins",
-                        (Duration::minutes(1), Duration::hours(2)),
-                        Duration::hours(1),
-                        Duration::minutes(1),
-                    ),
-                ]),
+        let map = &app.primary.map;
+        let existing = map.get_br_edit(id);
+        let name = existing
+            .as_ref()
+            .map(|r| r.full_name.clone())
+            .unwrap_or_else(|| format!("New route {}", id.0));
+
+        let mut col = vec![
+            Widget::row(vec![
+                Line("Route editor").small_heading().into_widget(ctx),
+                ctx.style().btn_close_widget(ctx),
+            ]),
+            Widget::row(vec![
+                "Name".text_widget(ctx).centered_vert(),
+                TextBox::default_widget(ctx, "name", name),
+            ]),
+            Text::new().into_widget(ctx).named("stops"),
+            Text::from_all(vec![
+                Line("Click").fg(ctx.style().text_hotkey_color),
+                Line(" a stop to add it, or a sidewalk to create a new stop"),
+            ])
+            .into_widget(ctx),
+            ctx.style()
+                .btn_solid_primary
+                .text("Save route")
+                .hotkey(Key::Enter)
+                .build_def(ctx),
+        ];
+        if existing.is_some() {
+            col.push(Widget::horiz_separator(ctx, 1.0));
+            col.push(Widget::row(vec![
+                "Frequency".text_widget(ctx),
+                Spinner::widget(
+                    ctx,
+                    "freq_mins",
+                    (Duration::minutes(1), Duration::hours(2)),
+                    Duration::hours(1),
+                    Duration::minutes(1),
+                ),
+            ]));
+            col.push(
+                ctx.style()
+                    .btn_outline
+                    .text("Apply schedule")
+                    .build_def(ctx),
+            );
+            col.push(
                 ctx.style()
-                    .btn_solid_primary
-                    .text("Apply")
-                    .hotkey(Key::Enter)
+                    .btn_solid_destructive
+                    .text("Delete route")
                     .build_def(ctx),
-            ]))
-            .aligned(HorizontalAlignment::Center, VerticalAlignment::Top)
-            .build(ctx),
+            );
+        }
+
+        let mut editor = RouteEditor {
+            panel: Panel::new_builder(Widget::col(col))
+                .aligned(HorizontalAlignment::Center, VerticalAlignment::Top)
+                .build(ctx),
             route: id,
-        })
+            stops: existing.map(|r| r.stops).unwrap_or_default(),
+            draw_route: Drawable::empty(ctx),
+        };
+        editor.stops_changed(ctx, app);
+        Box::new(editor)
+    }
+
+    /// Design a route from scratch.
+    pub fn new_route(ctx: &mut EventCtx, app: &mut App) -> Box<dyn State<App>> {
+        let id = app.primary.map.new_bus_route_id();
+        RouteEditor::new_state(ctx, app, id)
+    }
+
+    fn stops_changed(&mut self, ctx: &mut EventCtx, app: &App) {
+        let map = &app.primary.map;
+
+        let mut col = Vec::new();
+        for (idx, bs) in self.stops.iter().enumerate() {
+            col.push(Widget::row(vec![
+                format!("{}. {}", idx + 1, map.get_bs(*bs).name)
+                    .text_widget(ctx)
+                    .centered_vert(),
+                ctx.style()
+                    .btn_close()
+                    .override_style(&ctx.style().btn_plain_destructive)
+                    .build_widget(ctx, format!("remove stop {}", idx + 1)),
+            ]));
+        }
+        if col.is_empty() {
+            col.push("No stops yet".text_widget(ctx));
+        }
+        self.panel
+            .replace(ctx, "stops", Widget::col(col).named("stops"));
+
+        let mut batch = GeomBatch::new();
+        for pair in self.stops.windows(2) {
+            let req = PathRequest::vehicle(
+                map.get_bs(pair[0]).driving_pos,
+                map.get_bs(pair[1]).driving_pos,
+                PathConstraints::Bus,
+            );
+            if let Some(pl) = map.pathfind(req).ok().and_then(|path| path.trace(map)) {
+                batch.push(
+                    app.cs.unzoomed_bus.alpha(0.8),
+                    pl.make_polygons(Distance::meters(2.0)),
+                );
+            }
+        }
+        for bs in &self.stops {
+            batch.push(
+                app.cs.selected,
+                Circle::new(map.get_bs(*bs).sidewalk_pos.pt(map), Distance::meters(3.0))
+                    .to_polygon(),
+            );
+        }
+        self.draw_route = ctx.upload(batch);
+    }
+
+    /// Create a new bus stop where the player clicked on a sidewalk.
+    fn create_stop(&mut self, ctx: &mut EventCtx, app: &mut App, l: LaneID) -> Option<Transition> {
+        let map = &app.primary.map;
+        let pt = ctx.canvas.get_cursor_in_map_space()?;
+        let pl = &map.get_l(l).lane_center_pts;
+        let (dist_along, _) = pl.dist_along_of_point(pl.project_pt(pt))?;
+        if map
+            .get_parent(l)
+            .find_closest_lane(l, |l| PathConstraints::Bus.can_use(l, map))
+            .is_none()
+        {
+            return Some(Transition::Push(PopupMsg::new_state(
+                ctx,
+                "Error",
+                vec!["Buses can't use this road, so it can't have a bus stop"],
+            )));
+        }
+
+        let id = map.new_bus_stop_id(l);
+        let mut edits = map.get_edits().clone();
+        edits.commands.push(EditCmd::ChangeBusStop {
+            id,
+            old: None,
+            new: Some(EditBusStop {
+                name: format!(
+                    "Stop on {}",
+                    map.get_parent(l).get_name(app.opts.language.as_ref())
+                ),
+                dist_along,
+            }),
+        });
+        apply_map_edits(ctx, app, edits);
+        self.stops.push(id);
+        self.stops_changed(ctx, app);
+        None
     }
 }
 
@@ -54,12 +186,67 @@ impl State<App> for RouteEditor {
     fn event(&mut self, ctx: &mut EventCtx, app: &mut App) -> Transition {
         ctx.canvas_movement();
 
+        if ctx.redo_mouseover() {
+            app.recalculate_current_selection(ctx);
+            let keep = match app.primary.current_selection {
+                Some(ID::BusStop(_)) => true,
+                Some(ID::Lane(l)) => app.primary.map.get_l(l).is_sidewalk(),
+                _ => false,
+            };
+            if !keep {
+                app.primary.current_selection = None;
+            }
+        }
+        match app.primary.current_selection {
+            Some(ID::BusStop(bs))
+                if self.stops.last() != Some(&bs)
+                    && app.per_obj.left_click(ctx, "add this stop to the route") =>
+            {
+                self.stops.push(bs);
+                self.stops_changed(ctx, app);
+            }
+            Some(ID::Lane(l)) if app.per_obj.left_click(ctx, "create a new stop here") => {
+                if let Some(t) = self.create_stop(ctx, app, l) {
+                    return t;
+                }
+            }
+            _ => {}
+        }
+
         if let Outcome::Clicked(x) = self.panel.event(ctx) {
             match x.as_ref() {
                 "close" => {
                     return Transition::Pop;
                 }
-                "Apply" => {
+                "Save route" => {
+                    let map = &app.primary.map;
+                    let mut new = match map.br_edit_with_stops(self.route, self.stops.clone()) {
+                        Ok(new) => new,
+                        Err(err) => {
+                            return Transition::Push(PopupMsg::new_state(
+                                ctx,
+                                "Error",
+                                vec![err.to_string()],
+                            ));
+                        }
+                    };
+                    let old = map.get_br_edit(self.route);
+                    new.full_name = self.panel.text_box("name");
+                    if old.is_none() {
+                        new.short_name = new.full_name.clone();
+                    }
+                    if old.as_ref() != Some(&new) {
+                        let mut edits = map.get_edits().clone();
+                        edits.commands.push(EditCmd::ChangeRoute {
+                            id: self.route,
+                            old,
+                            new: Some(new),
+                        });
+                        apply_map_edits(ctx, app, edits);
+                    }
+                    return Transition::Pop;
+                }
+                "Apply schedule" => {
                     let freq = self.panel.spinner("freq_mins");
                     let mut now = Time::START_OF_DAY;
                     let mut hourly_times = Vec::new();
@@ -78,14 +265,35 @@ impl State<App> for RouteEditor {
 
                     return Transition::Pop;
                 }
-                _ => unreachable!(),
+                "Delete route" => {
+                    let mut edits = app.primary.map.get_edits().clone();
+                    edits.commands.push(EditCmd::ChangeRoute {
+                        id: self.route,
+                        old: app.primary.map.get_br_edit(self.route),
+                        new: None,
+                    });
+                    apply_map_edits(ctx, app, edits);
+
+                    return Transition::Pop;
+                }
+                x => {
+                    if let Some(idx) = x.strip_prefix("remove stop ") {
+                        let idx = idx.parse::<usize>().unwrap();
+                        self.stops.remove(idx - 1);
+                        self.stops_changed(ctx, app);
+                    } else {
+                        unreachable!()
+                    }
+                }
             }
         }
 
         Transition::Keep
     }
 
-    fn draw(&self, g: &mut GfxCtx, _: &App) {
+    fn draw(&self, g: &mut GfxCtx, app: &App) {
+        g.redraw(&self.draw_route);
         self.panel.draw(g);
+        CommonState::draw_osd(g, app);
     }
 }
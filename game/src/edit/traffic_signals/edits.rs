@@ -1,7 +1,8 @@
-use geom::Duration;
+use geom::{Duration, Time};
 use map_gui::tools::{ChooseSomething, FilePicker, PopupMsg};
 use map_model::{
     ControlStopSign, ControlTrafficSignal, EditCmd, EditIntersection, IntersectionID, StageType,
+    TransitSignalPriority,
 };
 use widgetry::{
     Choice, DrawBaselayer, EventCtx, Key, Line, Panel, SimpleState, Spinner, State, Text, TextExt,
@@ -14,17 +15,40 @@ use crate::edit::{apply_map_edits, check_sidewalk_connectivity, StopSignEditor};
 use crate::sandbox::GameplayMode;
 
 pub struct ChangeDuration {
+    plan: usize,
     idx: usize,
 }
 
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Control {
+    FixedOrVariable,
+    Actuated,
+    MaxPressure,
+}
+
 impl ChangeDuration {
     pub fn new_state(
         ctx: &mut EventCtx,
         app: &App,
         signal: &ControlTrafficSignal,
+        plan: usize,
         idx: usize,
     ) -> Box<dyn State<App>> {
         let i = app.primary.map.get_i(signal.id);
+        let stage = &signal.get_stages(plan)[idx];
+        let control = match stage.stage_type {
+            StageType::Fixed(_) | StageType::Variable(_, _, _) => Control::FixedOrVariable,
+            StageType::Actuated(_, _, _) => Control::Actuated,
+            StageType::MaxPressure(_, _) => Control::MaxPressure,
+        };
+        // For actuated and max-pressure stages, "additional" is the time past the minimum, and
+        // "delay" is the passage time.
+        let (delay, additional) = match stage.stage_type {
+            StageType::Fixed(_) => (Duration::ZERO, Duration::ZERO),
+            StageType::Variable(_, delay, additional) => (delay, additional),
+            StageType::Actuated(min, passage, max) => (passage, max - min),
+            StageType::MaxPressure(min, max) => (Duration::ZERO, max - min),
+        };
         let panel = Panel::new_builder(Widget::col(vec![
             Widget::row(vec![
                 Line("How long should this stage last?")
@@ -32,13 +56,26 @@ impl ChangeDuration {
                     .into_widget(ctx),
                 ctx.style().btn_close_widget(ctx),
             ]),
+            Widget::row(vec![
+                "Control:".text_widget(ctx).centered_vert(),
+                Widget::dropdown(
+                    ctx,
+                    "control",
+                    control,
+                    vec![
+                        Choice::new("fixed or variable timing", Control::FixedOrVariable),
+                        Choice::new("actuated by detectors", Control::Actuated),
+                        Choice::new("adaptive max-pressure", Control::MaxPressure),
+                    ],
+                ),
+            ]),
             Widget::row(vec![
                 "Duration:".text_widget(ctx).centered_vert(),
                 Spinner::widget(
                     ctx,
                     "duration",
-                    (signal.get_min_crossing_time(idx, i), Duration::minutes(5)),
-                    signal.stages[idx].stage_type.simple_duration(),
+                    (stage.get_min_crossing_time(i), Duration::minutes(5)),
+                    stage.stage_type.simple_duration(),
                     Duration::seconds(1.0),
                 ),
             ]),
@@ -46,18 +83,9 @@ impl ChangeDuration {
                 .secondary()
                 .into_widget(ctx),
             Widget::col(vec![
-                Text::from_all(match signal.stages[idx].stage_type {
-                    StageType::Fixed(_) => vec![
-                        Line("Fixed timing").small_heading(),
-                        Line(" (Adjust both values below to enable variable timing)"),
-                    ],
-                    StageType::Variable(_, _, _) => vec![
-                        Line("Variable timing").small_heading(),
-                        Line(" (Set either values below to 0 to use fixed timing."),
-                    ],
-                })
-                .into_widget(ctx)
-                .named("timing type"),
+                describe_timing(control, delay, additional)
+                    .into_widget(ctx)
+                    .named("timing type"),
                 Widget::row(vec![
                     "How much additional time can this stage last?"
                         .text_widget(ctx)
@@ -66,10 +94,7 @@ impl ChangeDuration {
                         ctx,
                         "additional",
                         (Duration::ZERO, Duration::minutes(5)),
-                        match signal.stages[idx].stage_type {
-                            StageType::Fixed(_) => Duration::ZERO,
-                            StageType::Variable(_, _, additional) => additional,
-                        },
+                        additional,
                         Duration::seconds(1.0),
                     ),
                 ]),
@@ -81,10 +106,7 @@ impl ChangeDuration {
                         ctx,
                         "delay",
                         (Duration::ZERO, Duration::seconds(300.0)),
-                        match signal.stages[idx].stage_type {
-                            StageType::Fixed(_) => Duration::ZERO,
-                            StageType::Variable(_, delay, _) => delay,
-                        },
+                        delay,
                         Duration::seconds(1.0),
                     ),
                 ]),
@@ -99,10 +121,36 @@ impl ChangeDuration {
                 .build_def(ctx),
         ]))
         .build(ctx);
-        <dyn SimpleState<_>>::new_state(panel, Box::new(ChangeDuration { idx }))
+        <dyn SimpleState<_>>::new_state(panel, Box::new(ChangeDuration { plan, idx }))
     }
 }
 
+fn describe_timing(control: Control, delay: Duration, additional: Duration) -> Text {
+    Text::from_all(match control {
+        Control::FixedOrVariable => {
+            if delay == Duration::ZERO || additional == Duration::ZERO {
+                vec![
+                    Line("Fixed timing").small_heading(),
+                    Line(" (Adjust both values below to enable variable timing)"),
+                ]
+            } else {
+                vec![
+                    Line("Variable timing").small_heading(),
+                    Line(" (Set either values below to 0 to use fixed timing."),
+                ]
+            }
+        }
+        Control::Actuated => vec![
+            Line("Actuated timing").small_heading(),
+            Line(" (Extends while vehicles keep arriving, and skipped if nobody is waiting)"),
+        ],
+        Control::MaxPressure => vec![
+            Line("Max-pressure timing").small_heading(),
+            Line(" (Switches to the stage with the longest queues every time the duration passes)"),
+        ],
+    })
+}
+
 impl SimpleState<App> for ChangeDuration {
     fn on_click(&mut self, _: &mut EventCtx, _: &mut App, x: &str, panel: &Panel) -> Transition {
         match x {
@@ -111,18 +159,30 @@ impl SimpleState<App> for ChangeDuration {
                 let dt = panel.spinner("duration");
                 let delay = panel.spinner("delay");
                 let additional = panel.spinner("additional");
-                let new_type = if delay == Duration::ZERO || additional == Duration::ZERO {
-                    StageType::Fixed(dt)
-                } else {
-                    StageType::Variable(dt, delay, additional)
+                let new_type = match panel.dropdown_value("control") {
+                    Control::FixedOrVariable => {
+                        if delay == Duration::ZERO || additional == Duration::ZERO {
+                            StageType::Fixed(dt)
+                        } else {
+                            StageType::Variable(dt, delay, additional)
+                        }
+                    }
+                    Control::Actuated => {
+                        StageType::Actuated(dt, delay.max(Duration::seconds(1.0)), dt + additional)
+                    }
+                    Control::MaxPressure => {
+                        let min = dt.max(Duration::seconds(1.0));
+                        StageType::MaxPressure(min, min + additional)
+                    }
                 };
+                let plan = self.plan;
                 let idx = self.idx;
                 Transition::Multi(vec![
                     Transition::Pop,
                     Transition::ModifyState(Box::new(move |state, ctx, app| {
                         let editor = state.downcast_mut::<TrafficSignalEditor>().unwrap();
                         editor.add_new_edit(ctx, app, idx, |ts| {
-                            ts.stages[idx].stage_type = new_type.clone();
+                            ts.get_stages_mut(plan)[idx].stage_type = new_type.clone();
                         });
                     })),
                 ])
@@ -137,20 +197,10 @@ impl SimpleState<App> for ChangeDuration {
         _: &mut App,
         panel: &mut Panel,
     ) -> Option<Transition> {
-        let new_label = Text::from_all(
-            if panel.spinner::<Duration>("delay") == Duration::ZERO
-                || panel.spinner::<Duration>("additional") == Duration::ZERO
-            {
-                vec![
-                    Line("Fixed timing").small_heading(),
-                    Line(" (Adjust both values below to enable variable timing)"),
-                ]
-            } else {
-                vec![
-                    Line("Variable timing").small_heading(),
-                    Line(" (Set either values below to 0 to use fixed timing."),
-                ]
-            },
+        let new_label = describe_timing(
+            panel.dropdown_value("control"),
+            panel.spinner("delay"),
+            panel.spinner("additional"),
         )
         .into_widget(ctx);
         panel.replace(ctx, "timing type", new_label);
@@ -169,6 +219,88 @@ impl SimpleState<App> for ChangeDuration {
     }
 }
 
+pub struct AddPlan {
+    copy_from: usize,
+}
+
+impl AddPlan {
+    /// The new plan starts as a copy of `copy_from`.
+    pub fn new_state(ctx: &mut EventCtx, copy_from: usize) -> Box<dyn State<App>> {
+        let panel = Panel::new_builder(Widget::col(vec![
+            Widget::row(vec![
+                Line("Add a time-of-day plan")
+                    .small_heading()
+                    .into_widget(ctx),
+                ctx.style().btn_close_widget(ctx),
+            ]),
+            Widget::row(vec![
+                "Start time (after midnight):"
+                    .text_widget(ctx)
+                    .centered_vert(),
+                Spinner::widget(
+                    ctx,
+                    "start time",
+                    (
+                        Duration::minutes(15),
+                        Duration::hours(24) - Duration::minutes(15),
+                    ),
+                    Duration::hours(7),
+                    Duration::minutes(15),
+                ),
+            ]),
+            Line("The new plan copies the current one, and lasts until the next plan starts")
+                .secondary()
+                .into_widget(ctx),
+            ctx.style()
+                .btn_solid_primary
+                .text("Add")
+                .hotkey(Key::Enter)
+                .build_def(ctx),
+        ]))
+        .build(ctx);
+        <dyn SimpleState<_>>::new_state(panel, Box::new(AddPlan { copy_from }))
+    }
+}
+
+impl SimpleState<App> for AddPlan {
+    fn on_click(&mut self, _: &mut EventCtx, _: &mut App, x: &str, panel: &Panel) -> Transition {
+        match x {
+            "close" => Transition::Pop,
+            "Add" => {
+                let start_time = panel.spinner("start time");
+                let copy_from = self.copy_from;
+                Transition::Multi(vec![
+                    Transition::Pop,
+                    Transition::ModifyState(Box::new(move |state, ctx, app| {
+                        let editor = state.downcast_mut::<TrafficSignalEditor>().unwrap();
+                        editor.add_new_edit(ctx, app, 0, |ts| {
+                            ts.add_plan(copy_from, start_time);
+                        });
+                        let new_plan = app
+                            .primary
+                            .map
+                            .get_traffic_signal(*editor.members.iter().next().unwrap())
+                            .plan_idx_at(Time::START_OF_DAY + start_time);
+                        editor.change_plan(ctx, app, new_plan);
+                    })),
+                ])
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn other_event(&mut self, ctx: &mut EventCtx, _: &mut App) -> Transition {
+        if ctx.normal_left_click() && ctx.canvas.get_cursor_in_screen_space().is_none() {
+            return Transition::Pop;
+        }
+        Transition::Keep
+    }
+
+    fn draw_baselayer(&self) -> DrawBaselayer {
+        DrawBaselayer::PreviousState
+    }
+}
+
 pub fn edit_entire_signal(
     ctx: &mut EventCtx,
     app: &App,
@@ -187,6 +319,17 @@ pub fn edit_entire_signal(
     let use_template = "use template";
     let all_walk = "add an all-walk stage at the end";
     let major_minor_timing = "use timing pattern for a major/minor intersection";
+    let transit_priority = if app
+        .primary
+        .map
+        .get_traffic_signal(i)
+        .transit_priority
+        .is_some()
+    {
+        "disable transit signal priority"
+    } else {
+        "enable transit signal priority for buses and trains"
+    };
     let stop_sign = "convert to stop signs";
     let close = "close intersection for construction";
     let reset = "reset to default";
@@ -203,6 +346,7 @@ pub fn edit_entire_signal(
         choices.push(all_walk.to_string());
     }
     choices.push(major_minor_timing.to_string());
+    choices.push(transit_priority.to_string());
     // TODO Conflating stop signs and construction here
     if mode.can_edit_stop_signs() {
         choices.push(stop_sign.to_string());
@@ -266,8 +410,15 @@ pub fn edit_entire_signal(
                 ],
                 Box::new(move |timing, ctx, app| {
                     let mut new_signal = app.primary.map.get_traffic_signal(i).clone();
-                    match new_signal.adjust_major_minor_timing(timing.0, timing.1, &app.primary.map)
-                    {
+                    let result = (0..new_signal.num_plans()).try_for_each(|plan| {
+                        new_signal.adjust_major_minor_timing(
+                            plan,
+                            timing.0,
+                            timing.1,
+                            &app.primary.map,
+                        )
+                    });
+                    match result {
                         Ok(()) => Transition::Multi(vec![
                             Transition::Pop,
                             Transition::ModifyState(Box::new(move |state, ctx, app| {
@@ -285,6 +436,25 @@ pub fn edit_entire_signal(
                     }
                 }),
             )),
+            x if x == transit_priority => Transition::Multi(vec![
+                Transition::Pop,
+                Transition::ModifyState(Box::new(move |state, ctx, app| {
+                    let enable = app
+                        .primary
+                        .map
+                        .get_traffic_signal(i)
+                        .transit_priority
+                        .is_none();
+                    let editor = state.downcast_mut::<TrafficSignalEditor>().unwrap();
+                    editor.add_new_edit(ctx, app, 0, |ts| {
+                        ts.transit_priority = if enable {
+                            Some(TransitSignalPriority::default_limits())
+                        } else {
+                            None
+                        };
+                    });
+                })),
+            ]),
             x if x == stop_sign => {
                 original.apply(app);
 
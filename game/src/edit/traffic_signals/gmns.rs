@@ -9,7 +9,8 @@ use geom::{Angle, Duration, LonLat, Pt2D};
 use map_gui::tools::PopupMsg;
 use map_model::{
     osm, ControlTrafficSignal, DirectedRoadID, DrivingSide, EditCmd, EditIntersection,
-    IntersectionID, Map, Movement, MovementID, Stage, StageType, TurnPriority, TurnType,
+    IntersectionID, Map, Movement, MovementID, SignalPlan, Stage, StageType, TurnPriority,
+    TurnType,
 };
 use widgetry::{EventCtx, State};
 
@@ -19,6 +20,9 @@ use crate::App;
 /// This imports timing.csv from https://github.com/asu-trans-ai-lab/Vol2Timing. It operates in a
 /// best-effort / permissive mode, skipping over mismatched movements and other problems and should
 /// still be considered experimental.
+///
+/// If every timing plan for the intersection has a `time_period`, all of them are imported as
+/// time-of-day plans. The earliest plan is stretched to start at midnight.
 pub fn import(map: &Map, i: IntersectionID, path: &str) -> Result<ControlTrafficSignal> {
     let i = map.get_i(i);
     let mut matches_per_plan: BTreeMap<String, Vec<Record>> = BTreeMap::new();
@@ -32,24 +36,69 @@ pub fn import(map: &Map, i: IntersectionID, path: &str) -> Result<ControlTraffic
             .or_insert_with(Vec::new)
             .push(rec);
     }
+    if matches_per_plan.is_empty() {
+        bail!("no matches for {}", i.orig_id);
+    }
 
-    // For now, just use any arbitrary plan
-    let mut records = matches_per_plan
-        .into_iter()
-        .next()
-        .ok_or_else(|| anyhow!("no matches for {}", i.orig_id))?
-        .1;
-    records.sort_by_key(|rec| rec.stage);
+    // Figure out when each plan starts
+    let mut plans: Vec<(Duration, Vec<Record>)> = Vec::new();
+    for (plan_id, records) in matches_per_plan {
+        match records[0].time_period.as_ref() {
+            Some(time_period) => {
+                plans.push((parse_time_period(time_period)?, records));
+            }
+            None => {
+                if !plans.is_empty() {
+                    warn!(
+                        "Timing plan {} for {} has no time_period, so only importing one plan",
+                        plan_id, i.orig_id
+                    );
+                }
+                plans = vec![(Duration::ZERO, records)];
+                break;
+            }
+        }
+    }
+    plans.sort_by_key(|(start_time, _)| *start_time);
+    for pair in plans.windows(2) {
+        if pair[0].0 == pair[1].0 {
+            bail!(
+                "Multiple timing plans for {} start at {}",
+                i.orig_id,
+                pair[0].0
+            );
+        }
+    }
 
     let snapper = Snapper::new(map, i.id)?;
 
     let mut signal = ControlTrafficSignal::new(map, i.id);
-    signal.stages.clear();
+    for (idx, (start_time, records)) in plans.into_iter().enumerate() {
+        let mut stages = import_stages(map, &snapper, records)?;
+        add_crosswalks(&mut stages, i.id, map);
+        if idx == 0 {
+            signal.stages = stages;
+        } else {
+            signal.later_plans.push(SignalPlan {
+                start_time,
+                stages,
+                offset: Duration::ZERO,
+            });
+        }
+    }
+
+    Ok(signal)
+}
+
+fn import_stages(map: &Map, snapper: &Snapper, mut records: Vec<Record>) -> Result<Vec<Stage>> {
+    records.sort_by_key(|rec| rec.stage);
+
+    let mut stages: Vec<Stage> = Vec::new();
     for rec in records {
         let stage_idx = rec.stage - 1;
-        match signal.stages.len().cmp(&stage_idx) {
+        match stages.len().cmp(&stage_idx) {
             std::cmp::Ordering::Equal => {
-                signal.stages.push(Stage {
+                stages.push(Stage {
                     protected_movements: BTreeSet::new(),
                     yield_movements: BTreeSet::new(),
                     stage_type: StageType::Fixed(Duration::seconds(rec.green_time as f64)),
@@ -60,7 +109,7 @@ pub fn import(map: &Map, i: IntersectionID, path: &str) -> Result<ControlTraffic
             }
             std::cmp::Ordering::Greater => {}
         }
-        let stage = &mut signal.stages[stage_idx];
+        let stage = &mut stages[stage_idx];
 
         if stage.stage_type.simple_duration() != Duration::seconds(rec.green_time as f64) {
             bail!(
@@ -94,10 +143,22 @@ pub fn import(map: &Map, i: IntersectionID, path: &str) -> Result<ControlTraffic
             stage.yield_movements.insert(mvmnt);
         }
     }
+    Ok(stages)
+}
 
-    add_crosswalks(&mut signal, map);
-
-    Ok(signal)
+// Something like "0700_0800". Only the start time matters; each plan lasts until the next starts.
+fn parse_time_period(raw: &str) -> Result<Duration> {
+    let start = raw
+        .split('_')
+        .next()
+        .filter(|x| x.len() == 4)
+        .ok_or_else(|| anyhow!("bad time_period {}", raw))?;
+    let hours = start[0..2].parse::<usize>()?;
+    let minutes = start[2..4].parse::<usize>()?;
+    if hours >= 24 || minutes >= 60 {
+        bail!("bad time_period {}", raw);
+    }
+    Ok(Duration::hours(hours) + Duration::minutes(minutes))
 }
 
 pub fn import_all(ctx: &mut EventCtx, app: &mut App, path: &str) -> Box<dyn State<App>> {
@@ -165,6 +226,9 @@ struct Record {
     #[serde(deserialize_with = "parse_osm_ids", rename = "osm_node_id")]
     osm_ids: Vec<osm::NodeID>,
     timing_plan_id: String,
+    // Something like "0700_0800". Older files don't have this.
+    #[serde(default)]
+    time_period: Option<String>,
     green_time: usize,
     #[serde(rename = "stage_no")]
     stage: usize,
@@ -335,14 +399,14 @@ fn cardinal_direction(angle: Angle) -> &'static str {
 // The GMNS input doesn't include crosswalks yet -- and even once it does, it's likely the two map
 // models will disagree about where sidewalks exist. Try to add all crosswalks to the stage where
 // they're compatible. Downgrade right turns from protected to permitted as needed.
-fn add_crosswalks(signal: &mut ControlTrafficSignal, map: &Map) {
+fn add_crosswalks(stages: &mut [Stage], i: IntersectionID, map: &Map) {
     let downgrade_type = if map.get_config().driving_side == DrivingSide::Right {
         TurnType::Right
     } else {
         TurnType::Left
     };
 
-    let i = map.get_i(signal.id);
+    let i = map.get_i(i);
     let mut crosswalks: Vec<MovementID> = Vec::new();
     for id in i.movements.keys() {
         if id.crosswalk {
@@ -352,7 +416,7 @@ fn add_crosswalks(signal: &mut ControlTrafficSignal, map: &Map) {
 
     // We could try to look for straight turns parallel to the crosswalk, but... just brute-force
     // it
-    for stage in &mut signal.stages {
+    for stage in stages {
         crosswalks.retain(|id| {
             if stage.could_be_protected(*id, i) {
                 stage.edit_movement(&i.movements[id], TurnPriority::Protected);
@@ -377,3 +441,30 @@ fn add_crosswalks(signal: &mut ControlTrafficSignal, map: &Map) {
         });
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_time_period() {
+        assert_eq!(parse_time_period("0000_0700").unwrap(), Duration::ZERO);
+        assert_eq!(
+            parse_time_period("0730_0900").unwrap(),
+            Duration::hours(7) + Duration::minutes(30)
+        );
+        // Only the start matters
+        assert_eq!(parse_time_period("1900").unwrap(), Duration::hours(19));
+
+        for bad in [
+            "",
+            "730_0900",
+            "07:30_0900",
+            "2400_0100",
+            "0760_0900",
+            "ab00",
+        ] {
+            assert!(parse_time_period(bad).is_err(), "{} should've failed", bad);
+        }
+    }
+}
@@ -3,7 +3,7 @@ use std::collections::{BTreeSet, VecDeque};
 use anyhow::Result;
 
 use abstutil::Timer;
-use geom::{Distance, Line, Polygon, Pt2D};
+use geom::{Distance, Duration, Line, Polygon, Pt2D, Time};
 use map_gui::options::TrafficSignalStyle;
 use map_gui::render::{traffic_signal, DrawMovement, DrawOptions};
 use map_gui::tools::PopupMsg;
@@ -36,6 +36,8 @@ pub struct TrafficSignalEditor {
 
     mode: GameplayMode,
     members: BTreeSet<IntersectionID>,
+    // All members have the same time-of-day plans
+    current_plan: usize,
     current_stage: usize,
 
     movements: Vec<DrawMovement>,
@@ -74,9 +76,10 @@ impl TrafficSignalEditor {
         synced.apply(app);
 
         let mut editor = TrafficSignalEditor {
-            side_panel: make_side_panel(ctx, app, &members, 0),
+            side_panel: make_side_panel(ctx, app, &members, 0, 0),
             top_panel: make_top_panel(ctx, app, false, false),
             mode,
+            current_plan: 0,
             current_stage: 0,
             movements: Vec::new(),
             movement_selected: None,
@@ -94,18 +97,47 @@ impl TrafficSignalEditor {
     }
 
     fn change_stage(&mut self, ctx: &mut EventCtx, app: &App, idx: usize) {
+        // An edit may have removed the current plan
+        let num_plans = app
+            .primary
+            .map
+            .get_traffic_signal(*self.members.iter().next().unwrap())
+            .num_plans();
+        if self.current_plan >= num_plans {
+            self.current_plan = 0;
+        }
+
         if self.current_stage == idx {
-            let mut new = make_side_panel(ctx, app, &self.members, self.current_stage);
+            let mut new = make_side_panel(
+                ctx,
+                app,
+                &self.members,
+                self.current_plan,
+                self.current_stage,
+            );
             new.restore(ctx, &self.side_panel);
             self.side_panel = new;
         } else {
             self.current_stage = idx;
-            self.side_panel = make_side_panel(ctx, app, &self.members, self.current_stage);
+            self.side_panel = make_side_panel(
+                ctx,
+                app,
+                &self.members,
+                self.current_plan,
+                self.current_stage,
+            );
         }
 
         self.recalc_draw_current(ctx, app);
     }
 
+    fn change_plan(&mut self, ctx: &mut EventCtx, app: &App, plan: usize) {
+        self.current_plan = plan;
+        self.current_stage = 0;
+        self.side_panel = make_side_panel(ctx, app, &self.members, plan, 0);
+        self.recalc_draw_current(ctx, app);
+    }
+
     fn add_new_edit<F: Fn(&mut ControlTrafficSignal)>(
         &mut self,
         ctx: &mut EventCtx,
@@ -129,12 +161,17 @@ impl TrafficSignalEditor {
         let mut batch = GeomBatch::new();
         let mut movements = Vec::new();
         for i in &self.members {
-            let stage = &app.primary.map.get_traffic_signal(*i).stages[self.current_stage];
+            let stage = &app
+                .primary
+                .map
+                .get_traffic_signal(*i)
+                .get_stages(self.current_plan)[self.current_stage];
             for (m, draw) in DrawMovement::for_i(
                 ctx.prerender,
                 &app.primary.map,
                 &app.cs,
                 *i,
+                self.current_plan,
                 self.current_stage,
             ) {
                 if self
@@ -208,7 +245,8 @@ impl State<App> for TrafficSignalEditor {
             .primary
             .map
             .get_traffic_signal(*self.members.iter().next().unwrap());
-        let num_stages = canonical_signal.stages.len();
+        let plan = self.current_plan;
+        let num_stages = canonical_signal.get_stages(plan).len();
 
         match self.side_panel.event(ctx) {
             Outcome::Clicked(x) => match x.as_ref() {
@@ -226,11 +264,12 @@ impl State<App> for TrafficSignalEditor {
                         ctx,
                         app,
                         self.members.clone(),
+                        plan,
                     ));
                 }
                 "Add a new stage" => {
                     self.add_new_edit(ctx, app, num_stages, |ts| {
-                        ts.stages.push(Stage::new());
+                        ts.get_stages_mut(plan).push(Stage::new());
                     });
                     return Transition::Keep;
                 }
@@ -239,14 +278,33 @@ impl State<App> for TrafficSignalEditor {
                         ctx,
                         app,
                         canonical_signal,
+                        plan,
                         self.current_stage,
                     ));
                 }
                 "delete stage" => {
                     let idx = self.current_stage;
                     self.add_new_edit(ctx, app, 0, |ts| {
-                        ts.stages.remove(idx);
+                        ts.get_stages_mut(plan).remove(idx);
+                    });
+                    return Transition::Keep;
+                }
+                "previous plan" => {
+                    self.change_plan(ctx, app, plan - 1);
+                    return Transition::Keep;
+                }
+                "next plan" => {
+                    self.change_plan(ctx, app, plan + 1);
+                    return Transition::Keep;
+                }
+                "Add a time-of-day plan" => {
+                    return Transition::Push(edits::AddPlan::new_state(ctx, plan));
+                }
+                "delete plan" => {
+                    self.add_new_edit(ctx, app, 0, |ts| {
+                        ts.remove_plan(plan);
                     });
+                    self.change_plan(ctx, app, plan - 1);
                     return Transition::Keep;
                 }
                 "previous stage" => {
@@ -269,7 +327,7 @@ impl State<App> for TrafficSignalEditor {
             },
             Outcome::DragDropReleased(_, old_idx, new_idx) => {
                 self.add_new_edit(ctx, app, new_idx, |ts| {
-                    ts.stages.swap(old_idx, new_idx);
+                    ts.get_stages_mut(plan).swap(old_idx, new_idx);
                 });
             }
             _ => {}
@@ -291,8 +349,8 @@ impl State<App> for TrafficSignalEditor {
                             "Error: missing turns",
                             vec![
                                 "Some turns are missing from this traffic signal",
-                                "They've all been added as a new first stage. Please update your \
-                                changes to include them.",
+                                "They've all been added as a new first stage in each plan. Please \
+                                update your changes to include them.",
                             ],
                         ));
                     } else if let Err(err) = self.validate_all_members(app) {
@@ -365,6 +423,7 @@ impl State<App> for TrafficSignalEditor {
                         ctx,
                         app,
                         self.members.clone(),
+                        plan,
                         self.current_stage,
                     ));
                 }
@@ -407,7 +466,7 @@ impl State<App> for TrafficSignalEditor {
                     let signal = app.primary.map.get_traffic_signal(m.id.parent);
                     let i = app.primary.map.get_i(signal.id);
                     if m.hitbox.contains_pt(pt) {
-                        let stage = &signal.stages[self.current_stage];
+                        let stage = &signal.get_stages(plan)[self.current_stage];
                         let next_priority = match stage.get_priority_of_movement(m.id) {
                             TurnPriority::Banned => {
                                 if stage.could_be_protected(m.id, i) {
@@ -443,7 +502,7 @@ impl State<App> for TrafficSignalEditor {
             let mut txt = Text::new();
             txt.add_line(Line(format!(
                 "{} {}",
-                match signal.stages[self.current_stage].get_priority_of_movement(id) {
+                match signal.get_stages(plan)[self.current_stage].get_priority_of_movement(id) {
                     TurnPriority::Protected => "Protected",
                     TurnPriority::Yield => "Yielding",
                     TurnPriority::Banned => "Forbidden",
@@ -466,7 +525,7 @@ impl State<App> for TrafficSignalEditor {
                 ctx,
                 format!(
                     "toggle from {:?} to {:?}",
-                    signal.stages[self.current_stage].get_priority_of_movement(id),
+                    signal.get_stages(plan)[self.current_stage].get_priority_of_movement(id),
                     pri
                 ),
             ) {
@@ -474,7 +533,7 @@ impl State<App> for TrafficSignalEditor {
                 let movement = app.primary.map.get_i(id.parent).movements[&id].clone();
                 self.add_new_edit(ctx, app, idx, |ts| {
                     if ts.id == id.parent {
-                        ts.stages[idx].edit_movement(&movement, pri);
+                        ts.get_stages_mut(plan)[idx].edit_movement(&movement, pri);
                     }
                 });
                 return Transition::KeepWithMouseover;
@@ -604,11 +663,13 @@ fn make_side_panel(
     ctx: &mut EventCtx,
     app: &App,
     members: &BTreeSet<IntersectionID>,
+    plan: usize,
     selected: usize,
 ) -> Panel {
     let map = &app.primary.map;
     // Use any member for stage duration
     let canonical_signal = map.get_traffic_signal(*members.iter().next().unwrap());
+    let stages = canonical_signal.get_stages(plan);
 
     let mut txt = Text::new();
     if members.len() == 1 {
@@ -642,6 +703,56 @@ fn make_side_panel(
     }
     let mut col = vec![txt.into_widget(ctx)];
 
+    // Time-of-day plan controls
+    let num_plans = canonical_signal.num_plans();
+    col.push(
+        Widget::row(vec![
+            ctx.style()
+                .btn_plain
+                .icon_bytes(include_labeled_bytes!(
+                    "../../../../widgetry/icons/arrow_left.svg"
+                ))
+                .disabled(plan == 0)
+                .build_widget(ctx, "previous plan"),
+            ctx.style()
+                .btn_plain
+                .icon_bytes(include_labeled_bytes!(
+                    "../../../../widgetry/icons/arrow_right.svg"
+                ))
+                .disabled(plan == num_plans - 1)
+                .build_widget(ctx, "next plan"),
+            format!(
+                "Plan {}/{}: {} - {}",
+                plan + 1,
+                num_plans,
+                (Time::START_OF_DAY + canonical_signal.get_plan_start_time(plan)).ampm_tostring(),
+                if plan == num_plans - 1 {
+                    "midnight".to_string()
+                } else {
+                    (Time::START_OF_DAY + canonical_signal.get_plan_start_time(plan + 1))
+                        .ampm_tostring()
+                }
+            )
+            .text_widget(ctx)
+            .centered_vert(),
+            // The plan starting at midnight can't be removed
+            if plan != 0 {
+                ctx.style()
+                    .btn_solid_destructive
+                    .icon("system/assets/tools/trash.svg")
+                    .build_widget(ctx, "delete plan")
+            } else {
+                Widget::nothing()
+            },
+            ctx.style()
+                .btn_plain
+                .icon("system/assets/speed/plus.svg")
+                .build_widget(ctx, "Add a time-of-day plan"),
+        ])
+        .padding(10)
+        .bg(app.cs.inner_panel_bg),
+    );
+
     // Stage controls
     col.push(
         Widget::row(vec![
@@ -657,14 +768,21 @@ fn make_side_panel(
                 .icon_bytes(include_labeled_bytes!(
                     "../../../../widgetry/icons/arrow_right.svg"
                 ))
-                .disabled(selected == canonical_signal.stages.len() - 1)
+                .disabled(selected == stages.len() - 1)
                 .build_widget(ctx, "next stage"),
-            match canonical_signal.stages[selected].stage_type {
+            match stages[selected].stage_type {
                 StageType::Fixed(d) => format!("Stage duration: {}", d),
                 StageType::Variable(min, delay, additional) => format!(
                     "Stage duration: {}, {}, {} (variable)",
                     min, delay, additional
                 ),
+                StageType::Actuated(min, passage, max) => format!(
+                    "Stage duration: {} to {}, {} passage (actuated)",
+                    min, max, passage
+                ),
+                StageType::MaxPressure(min, max) => {
+                    format!("Stage duration: {} to {} (max-pressure)", min, max)
+                }
             }
             .text_widget(ctx)
             .centered_vert(),
@@ -673,7 +791,7 @@ fn make_side_panel(
                 .icon("system/assets/tools/pencil.svg")
                 .hotkey(Key::X)
                 .build_widget(ctx, "change duration"),
-            if canonical_signal.stages.len() > 1 {
+            if stages.len() > 1 {
                 ctx.style()
                     .btn_solid_destructive
                     .icon("system/assets/tools/trash.svg")
@@ -698,18 +816,20 @@ fn make_side_panel(
     );
 
     let mut drag_drop = DragDrop::new(ctx, "stage cards", StackAxis::Horizontal);
-    for idx in 0..canonical_signal.stages.len() {
+    for (idx, stage) in stages.iter().enumerate() {
         let mut stack = GeomBatchStack::vertical(vec![
             Text::from(Line(format!(
                 "Stage {}: {}",
                 idx + 1,
-                match canonical_signal.stages[idx].stage_type {
+                match stage.stage_type {
                     StageType::Fixed(d) => format!("{}", d),
                     StageType::Variable(min, _, _) => format!("{} (v)", min),
+                    StageType::Actuated(min, _, _) => format!("{} (a)", min),
+                    StageType::MaxPressure(min, _) => format!("{} (mp)", min),
                 },
             )))
             .render(ctx),
-            draw_multiple_signals(ctx, app, members, idx, &translations),
+            draw_multiple_signals(ctx, app, members, plan, idx, &translations),
         ]);
         stack.set_spacing(10.0);
         let icon_batch = stack.batch();
@@ -745,7 +865,7 @@ fn make_side_panel(
         // TODO Say "normally" to account for variable stages?
         format!(
             "One full cycle lasts {}",
-            canonical_signal.simple_cycle_duration()
+            canonical_signal.simple_cycle_duration(plan)
         )
         .text_widget(ctx)
         .centered_vert(),
@@ -804,26 +924,47 @@ impl BundleEdits {
         BundleEdits { signals }
     }
 
-    // If the intersections haven't been edited together before, the number of stages and the
-    // durations might not match up. Just initially force them to align somehow.
+    // If the intersections haven't been edited together before, the time-of-day plans, the
+    // number of stages, and the durations might not match up. Just initially force them to align
+    // somehow.
     fn synchronize(app: &App, members: &BTreeSet<IntersectionID>) -> BundleEdits {
         let map = &app.primary.map;
-        // Pick one of the members with the most stages as canonical.
+        // Pick one of the members with the most plans, then the most stages, as canonical.
         let canonical = map.get_traffic_signal(
             *members
                 .iter()
-                .max_by_key(|i| map.get_traffic_signal(**i).stages.len())
+                .max_by_key(|i| {
+                    let signal = map.get_traffic_signal(**i);
+                    (signal.num_plans(), signal.stages.len())
+                })
                 .unwrap(),
         );
 
         let mut signals = Vec::new();
         for i in members {
             let mut signal = map.get_traffic_signal(*i).clone();
-            for (idx, canonical_stage) in canonical.stages.iter().enumerate() {
-                if signal.stages.len() == idx {
-                    signal.stages.push(Stage::new());
+            // Use the same plan start times, copying whatever plan was in effect before
+            let start_times: Vec<Duration> = (1..canonical.num_plans())
+                .map(|plan| canonical.get_plan_start_time(plan))
+                .collect();
+            signal
+                .later_plans
+                .retain(|plan| start_times.contains(&plan.start_time));
+            for start_time in start_times {
+                let copy_from = signal.plan_idx_at(Time::START_OF_DAY + start_time);
+                if signal.get_plan_start_time(copy_from) != start_time {
+                    signal.add_plan(copy_from, start_time);
+                }
+            }
+
+            for plan in 0..canonical.num_plans() {
+                let stages = signal.get_stages_mut(plan);
+                for (idx, canonical_stage) in canonical.get_stages(plan).iter().enumerate() {
+                    if stages.len() == idx {
+                        stages.push(Stage::new());
+                    }
+                    stages[idx].stage_type = canonical_stage.stage_type.clone();
                 }
-                signal.stages[idx].stage_type = canonical_stage.stage_type.clone();
             }
             signals.push(signal);
         }
@@ -834,35 +975,44 @@ impl BundleEdits {
 
 // If None, nothing missing.
 fn check_for_missing_turns(app: &App, members: &BTreeSet<IntersectionID>) -> Option<BundleEdits> {
-    let mut all_missing = BTreeSet::new();
+    let num_plans = app
+        .primary
+        .map
+        .get_traffic_signal(*members.iter().next().unwrap())
+        .num_plans();
+    // Per plan
+    let mut all_missing = vec![BTreeSet::new(); num_plans];
     for i in members {
-        all_missing.extend(
-            app.primary
-                .map
-                .get_traffic_signal(*i)
-                .missing_turns(app.primary.map.get_i(*i)),
-        );
+        let signal = app.primary.map.get_traffic_signal(*i);
+        for (plan, missing) in all_missing.iter_mut().enumerate() {
+            missing.extend(signal.missing_turns(plan, app.primary.map.get_i(*i)));
+        }
     }
-    if all_missing.is_empty() {
+    if all_missing.iter().all(|missing| missing.is_empty()) {
         return None;
     }
 
     let mut bundle = BundleEdits::get_current(app, members);
-    // Stick all the missing turns in a new stage at the beginning.
+    // For every plan with something missing, stick all the missing turns in a new stage at the
+    // beginning. Every member gets the new stage, to keep the number of stages in sync.
     for signal in &mut bundle.signals {
-        let mut stage = Stage::new();
-        // TODO Could do this more efficiently
-        for m in &all_missing {
-            if m.parent != signal.id {
-                continue;
+        for (plan, missing) in all_missing.iter().enumerate() {
+            let mut stage = Stage::new();
+            // TODO Could do this more efficiently
+            for m in missing {
+                if m.parent != signal.id {
+                    continue;
+                }
+                if m.crosswalk {
+                    stage.protected_movements.insert(*m);
+                } else {
+                    stage.yield_movements.insert(*m);
+                }
             }
-            if m.crosswalk {
-                stage.protected_movements.insert(*m);
-            } else {
-                stage.yield_movements.insert(*m);
+            if !missing.is_empty() {
+                signal.get_stages_mut(plan).insert(0, stage);
             }
         }
-        signal.stages.insert(0, stage);
     }
     Some(bundle)
 }
@@ -871,6 +1021,7 @@ fn draw_multiple_signals(
     ctx: &mut EventCtx,
     app: &App,
     members: &BTreeSet<IntersectionID>,
+    plan: usize,
     idx: usize,
     translations: &[(f64, f64)],
 ) -> GeomBatch {
@@ -883,7 +1034,7 @@ fn draw_multiple_signals(
         );
         traffic_signal::draw_signal_stage(
             ctx.prerender,
-            &app.primary.map.get_traffic_signal(*i).stages[idx],
+            &app.primary.map.get_traffic_signal(*i).get_stages(plan)[idx],
             idx,
             *i,
             None,
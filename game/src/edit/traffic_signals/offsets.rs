@@ -13,8 +13,10 @@ use crate::app::{App, Transition};
 use crate::common::CommonState;
 use crate::edit::traffic_signals::fade_irrelevant;
 
+// Offsets are tuned for one time-of-day plan at a time.
 pub struct ShowAbsolute {
     members: BTreeSet<IntersectionID>,
+    plan: usize,
     labels: Drawable,
 }
 
@@ -23,6 +25,7 @@ impl ShowAbsolute {
         ctx: &mut EventCtx,
         app: &App,
         members: BTreeSet<IntersectionID>,
+        plan: usize,
     ) -> Box<dyn State<App>> {
         let mut batch = fade_irrelevant(app, &members);
         for i in &members {
@@ -31,7 +34,7 @@ impl ShowAbsolute {
                     app.primary
                         .map
                         .get_traffic_signal(*i)
-                        .offset
+                        .get_offset(plan)
                         .to_string(&app.opts.units),
                 )
                 .bg(Color::PURPLE)
@@ -57,6 +60,7 @@ impl ShowAbsolute {
             panel,
             Box::new(ShowAbsolute {
                 members,
+                plan,
                 labels: ctx.upload(batch),
             }),
         )
@@ -84,7 +88,13 @@ impl SimpleState<App> for ShowAbsolute {
     fn other_event(&mut self, ctx: &mut EventCtx, app: &mut App) -> Transition {
         ctx.canvas_movement();
         if let Some(i) = app.click_on_intersection(ctx, "select base intersection") {
-            return Transition::Replace(ShowRelative::new_state(ctx, app, i, self.members.clone()));
+            return Transition::Replace(ShowRelative::new_state(
+                ctx,
+                app,
+                i,
+                self.members.clone(),
+                self.plan,
+            ));
         }
 
         Transition::Keep
@@ -100,6 +110,7 @@ impl SimpleState<App> for ShowAbsolute {
 struct ShowRelative {
     base: IntersectionID,
     members: BTreeSet<IntersectionID>,
+    plan: usize,
     labels: Drawable,
 }
 
@@ -109,8 +120,9 @@ impl ShowRelative {
         app: &App,
         base: IntersectionID,
         members: BTreeSet<IntersectionID>,
+        plan: usize,
     ) -> Box<dyn State<App>> {
-        let base_offset = app.primary.map.get_traffic_signal(base).offset;
+        let base_offset = app.primary.map.get_traffic_signal(base).get_offset(plan);
         let mut batch = fade_irrelevant(app, &members);
         for i in &members {
             if *i == base {
@@ -119,7 +131,7 @@ impl ShowRelative {
                     app.primary.map.get_i(*i).polygon.clone(),
                 );
             } else {
-                let offset = app.primary.map.get_traffic_signal(*i).offset - base_offset;
+                let offset = app.primary.map.get_traffic_signal(*i).get_offset(plan) - base_offset;
                 batch.append(
                     Text::from(offset.to_string(&app.opts.units))
                         .bg(Color::PURPLE)
@@ -147,6 +159,7 @@ impl ShowRelative {
             Box::new(ShowRelative {
                 base,
                 members,
+                plan,
                 labels: ctx.upload(batch),
             }),
         )
@@ -156,7 +169,12 @@ impl ShowRelative {
 impl SimpleState<App> for ShowRelative {
     fn on_click(&mut self, ctx: &mut EventCtx, app: &mut App, x: &str, _: &Panel) -> Transition {
         match x {
-            "close" => Transition::Replace(ShowAbsolute::new_state(ctx, app, self.members.clone())),
+            "close" => Transition::Replace(ShowAbsolute::new_state(
+                ctx,
+                app,
+                self.members.clone(),
+                self.plan,
+            )),
             _ => unreachable!(),
         }
     }
@@ -177,6 +195,7 @@ impl SimpleState<App> for ShowRelative {
                 self.base,
                 i,
                 self.members.clone(),
+                self.plan,
             ));
         }
 
@@ -194,6 +213,7 @@ struct TuneRelative {
     i1: IntersectionID,
     i2: IntersectionID,
     members: BTreeSet<IntersectionID>,
+    plan: usize,
     labels: Drawable,
 }
 
@@ -204,6 +224,7 @@ impl TuneRelative {
         i1: IntersectionID,
         i2: IntersectionID,
         members: BTreeSet<IntersectionID>,
+        plan: usize,
     ) -> Box<dyn State<App>> {
         let mut batch = fade_irrelevant(app, &btreeset! {i1, i2});
         let map = &app.primary.map;
@@ -224,8 +245,8 @@ impl TuneRelative {
             car_dt += r.length() / r.speed_limit;
         }
 
-        let offset1 = map.get_traffic_signal(i1).offset;
-        let offset2 = map.get_traffic_signal(i2).offset;
+        let offset1 = map.get_traffic_signal(i1).get_offset(plan);
+        let offset2 = map.get_traffic_signal(i2).get_offset(plan);
         let panel = Panel::new_builder(Widget::col(vec![
             Widget::row(vec![
                 Line(format!("Tuning offset between {} and {}", i1, i2))
@@ -273,6 +294,7 @@ impl TuneRelative {
                 i1,
                 i2,
                 members,
+                plan,
                 labels: ctx.upload(batch),
             }),
         )
@@ -292,8 +314,12 @@ impl SimpleState<App> for TuneRelative {
             "Update offset" => {
                 let mut ts = app.primary.map.get_traffic_signal(self.i2).clone();
                 let relative = panel.spinner("offset");
-                let offset1 = app.primary.map.get_traffic_signal(self.i1).offset;
-                ts.offset = offset1 + relative;
+                let offset1 = app
+                    .primary
+                    .map
+                    .get_traffic_signal(self.i1)
+                    .get_offset(self.plan);
+                ts.set_offset(self.plan, offset1 + relative);
                 app.primary.map.incremental_edit_traffic_signal(ts);
                 Transition::Multi(vec![
                     Transition::Pop,
@@ -302,6 +328,7 @@ impl SimpleState<App> for TuneRelative {
                         app,
                         self.i1,
                         self.members.clone(),
+                        self.plan,
                     )),
                 ])
             }
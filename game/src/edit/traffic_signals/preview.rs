@@ -1,7 +1,6 @@
 use std::collections::BTreeSet;
 
 use abstutil::Timer;
-use geom::Duration;
 use map_gui::tools::ChooseSomething;
 use map_model::IntersectionID;
 use widgetry::{
@@ -74,6 +73,7 @@ pub fn make_previewer(
     ctx: &mut EventCtx,
     app: &App,
     members: BTreeSet<IntersectionID>,
+    plan: usize,
     stage: usize,
 ) -> Box<dyn State<App>> {
     let random = "random agents around these intersections".to_string();
@@ -90,13 +90,15 @@ pub fn make_previewer(
             if x == "random agents around these intersections" {
                 for (idx, i) in members.into_iter().enumerate() {
                     if idx == 0 {
-                        // Start at the current stage
+                        // Start at the current stage of the current plan
                         let signal = app.primary.map.get_traffic_signal(i);
                         // TODO Use the offset correctly
                         // TODO If there are variable stages, this could land anywhere
-                        let mut step = Duration::ZERO;
+                        // TODO The signal only switches plans at the end of a stage, so this is
+                        // slightly off for later plans
+                        let mut step = signal.get_plan_start_time(plan);
                         for idx in 0..stage {
-                            step += signal.stages[idx].stage_type.simple_duration();
+                            step += signal.get_stages(plan)[idx].stage_type.simple_duration();
                         }
                         app.primary.sim.timed_step(
                             &app.primary.map,
@@ -35,12 +35,21 @@ impl ZoneEditor {
             // Starting a new zone
             btreeset! { start.id }
         };
-        let allow_through_traffic = start
+        let mut allow_through_traffic: BTreeSet<TripMode> = start
             .access_restrictions
             .allow_through_traffic
             .into_iter()
             .map(TripMode::from_constraints)
             .collect();
+        // Ride-hailing vehicles share the car constraints
+        if allow_through_traffic.contains(&TripMode::Drive) {
+            allow_through_traffic.insert(TripMode::RideHail);
+            allow_through_traffic.insert(TripMode::ParkAndRide);
+        }
+        // And shared bikes share the bike constraints
+        if allow_through_traffic.contains(&TripMode::Bike) {
+            allow_through_traffic.insert(TripMode::BikeShare);
+        }
 
         let (draw, legend) = draw_zone(ctx, app, &members);
         let orig_members = members.clone();
@@ -104,6 +113,10 @@ impl State<App> for ZoneEditor {
                     // The original allow_through_traffic always includes this, and there's no way
                     // to exclude it, so stay consistent.
                     allow_through_traffic.insert(PathConstraints::Train);
+                    // Trucks aren't a separate mode here; they follow the same rules as cars.
+                    if allow_through_traffic.contains(PathConstraints::Car) {
+                        allow_through_traffic.insert(PathConstraints::Truck);
+                    }
                     let new_access_restrictions = AccessRestrictions {
                         allow_through_traffic,
                     };
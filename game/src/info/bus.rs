@@ -1,5 +1,5 @@
 use abstutil::{prettyprint_usize, Counter};
-use geom::{Circle, Distance, Time};
+use geom::{Circle, Distance, Duration, Time};
 use map_gui::tools::ColorNetwork;
 use map_gui::ID;
 use map_model::{BusRoute, BusRouteID, BusStopID, PathStep};
@@ -228,6 +228,7 @@ fn route_body(ctx: &mut EventCtx, app: &App, details: &mut Details, id: BusRoute
     let mut boardings: Counter<BusStopID> = Counter::new();
     let mut alightings: Counter<BusStopID> = Counter::new();
     let mut waiting: Counter<BusStopID> = Counter::new();
+    let mut denied: Counter<BusStopID> = Counter::new();
     for bs in &route.stops {
         if let Some(list) = app.primary.sim.get_analytics().passengers_boarding.get(bs) {
             for (_, r, _) in list {
@@ -243,6 +244,13 @@ fn route_body(ctx: &mut EventCtx, app: &App, details: &mut Details, id: BusRoute
                 }
             }
         }
+        if let Some(list) = app.primary.sim.get_analytics().denied_boardings.get(bs) {
+            for (_, r, left_behind) in list {
+                if *r == id {
+                    denied.add(*bs, *left_behind);
+                }
+            }
+        }
 
         for (_, r, _, _) in app.primary.sim.get_people_waiting_at_stop(*bs) {
             if *r == id {
@@ -264,6 +272,55 @@ fn route_body(ctx: &mut EventCtx, app: &App, details: &mut Details, id: BusRoute
         ])
         .into_widget(ctx),
     );
+    if denied.sum() > 0 {
+        rows.push(
+            Line(format!(
+                "{} people left behind by full {}",
+                prettyprint_usize(denied.sum()),
+                route.plural_noun()
+            ))
+            .fg(Color::RED)
+            .into_widget(ctx),
+        );
+    }
+    let crowding = app.primary.sim.get_analytics().crowding_per_segment(id);
+    if let Some((stop, (_, peak))) = crowding
+        .iter()
+        .max_by(|(_, (_, a)), (_, (_, b))| a.partial_cmp(b).unwrap())
+    {
+        rows.push(
+            Text::from_all(vec![
+                Line("Most crowded"),
+                Line(format!(
+                    ": {}% full leaving {}",
+                    (peak * 100.0).round(),
+                    map.get_bs(*stop).name
+                ))
+                .secondary(),
+            ])
+            .into_widget(ctx),
+        );
+    }
+
+    if let Some(deviations) = app.primary.sim.get_analytics().schedule_deviations.get(&id) {
+        let late = deviations
+            .iter()
+            .map(|(_, _, late_by)| *late_by)
+            .sum::<Duration>()
+            / (deviations.len() as f64);
+        rows.push(
+            Text::from_all(vec![
+                Line("Schedule"),
+                Line(if late >= Duration::ZERO {
+                    format!(": {} late on average", late)
+                } else {
+                    format!(": {} early on average", -late)
+                })
+                .secondary(),
+            ])
+            .into_widget(ctx),
+        );
+    }
 
     rows.push(format!("{} stops", route.stops.len()).text_widget(ctx));
     {
@@ -320,7 +377,7 @@ fn route_body(ctx: &mut EventCtx, app: &App, details: &mut Details, id: BusRoute
         rows.push(
             ctx.style()
                 .btn_outline
-                .text("Edit schedule")
+                .text("Edit route")
                 .hotkey(Key::E)
                 .build_widget(ctx, format!("edit {}", route.id)),
         );
@@ -314,22 +314,61 @@ fn traffic_signal_body(ctx: &mut EventCtx, app: &App, id: IntersectionID) -> Wid
     let bbox = Polygon::rectangle(zoom * bounds.width(), zoom * bounds.height());
 
     let signal = app.primary.map.get_traffic_signal(id);
+    // Describe whichever time-of-day plan is in effect right now
+    let (plan, _, _) = app.primary.sim.current_plan_stage_and_remaining_time(id);
+    let stages = signal.get_stages(plan);
     {
         let mut txt = Text::new();
-        txt.add_line(Line(format!("{} stages", signal.stages.len())).small_heading());
-        txt.add_line(format!("Signal offset: {}", signal.offset));
+        txt.add_line(Line(format!("{} stages", stages.len())).small_heading());
+        if signal.num_plans() > 1 {
+            txt.add_line(format!(
+                "Plan {} of {}, starting at {}",
+                plan + 1,
+                signal.num_plans(),
+                (Time::START_OF_DAY + signal.get_plan_start_time(plan)).ampm_tostring()
+            ));
+        }
+        txt.add_line(format!("Signal offset: {}", signal.get_offset(plan)));
         {
             let mut total = Duration::ZERO;
-            for s in &signal.stages {
+            for s in stages {
                 total += s.stage_type.simple_duration();
             }
             // TODO Say "normally" or something?
             txt.add_line(format!("One cycle lasts {}", total));
         }
+        if let Some(ref tsp) = signal.transit_priority {
+            txt.add_line(format!(
+                "Transit signal priority: extend green by up to {}, cut red short by up to {}",
+                tsp.max_extension, tsp.max_truncation
+            ));
+            let mut saved = Duration::ZERO;
+            let mut added = Duration::ZERO;
+            let mut count = 0;
+            if let Some(list) = app
+                .primary
+                .sim
+                .get_analytics()
+                .transit_signal_priority
+                .get(&id)
+            {
+                for (_, _, transit_delay_saved, cross_street_delay_added) in list {
+                    saved += *transit_delay_saved;
+                    added += *cross_street_delay_added;
+                    count += 1;
+                }
+            }
+            txt.add_line(format!(
+                "Used {} times today, saving transit about {} and costing everybody else about {}",
+                prettyprint_usize(count),
+                saved,
+                added
+            ));
+        }
         rows.push(txt.into_widget(ctx));
     }
 
-    for (idx, stage) in signal.stages.iter().enumerate() {
+    for (idx, stage) in stages.iter().enumerate() {
         rows.push(
             match stage.stage_type {
                 StageType::Fixed(d) => Line(format!("Stage {}: {}", idx + 1, d)),
@@ -340,6 +379,19 @@ fn traffic_signal_body(ctx: &mut EventCtx, app: &App, id: IntersectionID) -> Wid
                     delay,
                     additional
                 )),
+                StageType::Actuated(min, passage, max) => Line(format!(
+                    "Stage {}: {} to {}, {} passage (actuated)",
+                    idx + 1,
+                    min,
+                    max,
+                    passage
+                )),
+                StageType::MaxPressure(min, max) => Line(format!(
+                    "Stage {}: {} to {} (max-pressure)",
+                    idx + 1,
+                    min,
+                    max
+                )),
             }
             .into_widget(ctx),
         );
@@ -146,8 +146,10 @@ fn trips_body(
                     ctx.prerender,
                     match trip.mode {
                         TripMode::Walk => "system/assets/meters/pedestrian.svg",
-                        TripMode::Bike => "system/assets/meters/bike.svg",
-                        TripMode::Drive => "system/assets/meters/car.svg",
+                        TripMode::Bike | TripMode::BikeShare => "system/assets/meters/bike.svg",
+                        TripMode::Drive | TripMode::RideHail | TripMode::ParkAndRide => {
+                            "system/assets/meters/car.svg"
+                        }
                         TripMode::Transit => "system/assets/meters/bus.svg",
                     },
                 )
@@ -553,17 +555,20 @@ fn parked_car_body(ctx: &mut EventCtx, app: &App, details: &mut Details, id: Car
     // TODO prev trips, next trips, etc
     let mut rows = vec![];
 
-    let p = app.primary.sim.get_owner_of_car(id).unwrap();
-    rows.push(
-        ctx.style()
-            .btn_outline
-            .text(format!("Owned by {}", p))
-            .build_def(ctx),
-    );
-    details.hyperlinks.insert(
-        format!("Owned by {}", p),
-        Tab::PersonTrips(p, BTreeMap::new()),
-    );
+    if let Some(p) = app.primary.sim.get_owner_of_car(id) {
+        rows.push(
+            ctx.style()
+                .btn_outline
+                .text(format!("Owned by {}", p))
+                .build_def(ctx),
+        );
+        details.hyperlinks.insert(
+            format!("Owned by {}", p),
+            Tab::PersonTrips(p, BTreeMap::new()),
+        );
+    } else {
+        rows.push("Part of the ride-hailing fleet".text_widget(ctx));
+    }
 
     if let Some(p) = app.primary.sim.lookup_parked_car(id) {
         match p.spot {
@@ -591,6 +596,9 @@ fn parked_car_body(ctx: &mut EventCtx, app: &App, details: &mut Details, id: Car
             )
             .text_widget(ctx),
         );
+        if let Some(ref battery) = p.vehicle.battery {
+            rows.push(format!("Battery at {}%", battery.percent().round()).text_widget(ctx));
+        }
     } else {
         rows.push("No longer parked".text_widget(ctx));
     }
@@ -628,8 +636,12 @@ fn header(
                     AgentID::Car(c) => match c.vehicle_type {
                         VehicleType::Car => ("driving", Some("system/assets/meters/car.svg")),
                         VehicleType::Bike => ("biking", Some("system/assets/meters/bike.svg")),
+                        VehicleType::Truck => ("delivering", Some("system/assets/meters/car.svg")),
                         VehicleType::Bus | VehicleType::Train => unreachable!(),
                     },
+                    AgentID::CarPassenger(_, _) => {
+                        ("riding in a car", Some("system/assets/meters/car.svg"))
+                    }
                     AgentID::BusPassenger(_, _) => {
                         ("riding a bus", Some("system/assets/meters/bus.svg"))
                     }
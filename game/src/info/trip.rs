@@ -689,6 +689,9 @@ fn make_timeline(
                         "system/assets/timeline/waiting_for_bus.svg"
                     }
                     TripPhaseType::RidingBus(_, _, _) => "system/assets/timeline/riding_bus.svg",
+                    TripPhaseType::WaitingForRide => "system/assets/timeline/delayed_start.svg",
+                    TripPhaseType::RidingInCar(_) => "system/assets/timeline/driving.svg",
+                    TripPhaseType::Unloading(_) => "system/assets/timeline/parking.svg",
                     TripPhaseType::Cancelled | TripPhaseType::Finished => unreachable!(),
                     TripPhaseType::DelayedStart => "system/assets/timeline/delayed_start.svg",
                 },
@@ -0,0 +1,165 @@
+use abstutil::prettyprint_usize;
+use geom::Time;
+use map_gui::tools::{make_heatmap, HeatmapOptions};
+use sim::Emissions;
+use widgetry::mapspace::ToggleZoomed;
+use widgetry::{EventCtx, GfxCtx, Line, Outcome, Panel, Text, Toggle, Widget};
+
+use crate::app::App;
+use crate::layer::{header, Layer, LayerOutcome, PANEL_PLACEMENT};
+
+/// The heatmap is built from points, so each road gets one point per this many grams emitted.
+const GRAMS_CO2_PER_PT: f64 = 1000.0;
+const GRAMS_NOX_PER_PT: f64 = 1.0;
+
+pub struct EmissionsMap {
+    time: Time,
+    opts: Options,
+    draw: ToggleZoomed,
+    panel: Panel,
+}
+
+impl Layer for EmissionsMap {
+    fn name(&self) -> Option<&'static str> {
+        Some("emissions")
+    }
+    fn event(&mut self, ctx: &mut EventCtx, app: &mut App) -> Option<LayerOutcome> {
+        if app.primary.sim.time() != self.time {
+            let mut new = EmissionsMap::new(ctx, app, self.opts.clone());
+            new.panel.restore(ctx, &self.panel);
+            *self = new;
+        }
+
+        match self.panel.event(ctx) {
+            Outcome::Clicked(x) => match x.as_ref() {
+                "close" => {
+                    return Some(LayerOutcome::Close);
+                }
+                _ => unreachable!(),
+            },
+            _ => {
+                let new_opts = self.options();
+                if self.opts != new_opts {
+                    *self = EmissionsMap::new(ctx, app, new_opts);
+                }
+            }
+        }
+        None
+    }
+    fn draw(&self, g: &mut GfxCtx, _: &App) {
+        self.panel.draw(g);
+        self.draw.draw(g);
+    }
+    fn draw_minimap(&self, g: &mut GfxCtx) {
+        g.redraw(&self.draw.unzoomed);
+    }
+}
+
+impl EmissionsMap {
+    pub fn new(ctx: &mut EventCtx, app: &App, opts: Options) -> EmissionsMap {
+        let map = &app.primary.map;
+        let mut pts = Vec::new();
+        for (r, emissions) in app
+            .primary
+            .sim
+            .get_analytics()
+            .emissions_per_road(app.primary.sim.time())
+        {
+            let n = if opts.show_nox {
+                emissions.nox / GRAMS_NOX_PER_PT
+            } else {
+                emissions.co2 / GRAMS_CO2_PER_PT
+            }
+            .round() as usize;
+            // Spread the points out along the road
+            let pl = &map.get_r(r).center_pts;
+            for i in 0..n {
+                let dist = pl.length() * ((i as f64 + 0.5) / (n as f64));
+                if let Ok((pt, _)) = pl.dist_along(dist) {
+                    pts.push(pt);
+                }
+            }
+        }
+
+        let mut draw = ToggleZoomed::builder();
+        let legend = make_heatmap(
+            ctx,
+            &mut draw.unzoomed,
+            map.get_bounds(),
+            pts,
+            &opts.heatmap,
+        );
+        let controls = make_controls(ctx, app, &opts, legend);
+        EmissionsMap {
+            time: app.primary.sim.time(),
+            opts,
+            draw: draw.build(ctx),
+            panel: controls,
+        }
+    }
+
+    fn options(&self) -> Options {
+        Options {
+            heatmap: HeatmapOptions::from_controls(&self.panel),
+            show_nox: self.panel.is_checked("Show NOx"),
+        }
+    }
+}
+
+#[derive(Clone, PartialEq)]
+pub struct Options {
+    pub heatmap: HeatmapOptions,
+    /// Or CO2
+    pub show_nox: bool,
+}
+
+fn make_controls(ctx: &mut EventCtx, app: &App, opts: &Options, legend: Widget) -> Panel {
+    let now = app.primary.sim.time();
+    let after = app.primary.sim.get_analytics().total_emissions(now);
+
+    let mut txt = Text::new();
+    txt.add_line(Line(format!(
+        "CO2: {} kg",
+        prettyprint_usize((after.co2 / 1000.0).round() as usize)
+    )));
+    txt.add_line(Line(format!(
+        "NOx: {} g",
+        prettyprint_usize(after.nox.round() as usize)
+    )));
+    if app.has_prebaked().is_some() {
+        let before = app.prebaked().total_emissions(now);
+        txt.add_line(Line("Compared to before map edits:").secondary());
+        txt.add_line(Line(format!("CO2: {}", describe_change(after, before, true))).secondary());
+        txt.add_line(Line(format!("NOx: {}", describe_change(after, before, false))).secondary());
+    }
+
+    let mut col = vec![header(ctx, "Emissions"), txt.into_widget(ctx)];
+    col.push(Toggle::choice(
+        ctx,
+        "Show NOx",
+        "NOx",
+        "CO2",
+        None,
+        opts.show_nox,
+    ));
+    col.extend(opts.heatmap.to_controls(ctx, legend));
+
+    Panel::new_builder(Widget::col(col))
+        .aligned_pair(PANEL_PLACEMENT)
+        .build(ctx)
+}
+
+fn describe_change(after: Emissions, before: Emissions, co2: bool) -> String {
+    let (after, before, unit) = if co2 {
+        (after.co2 / 1000.0, before.co2 / 1000.0, "kg")
+    } else {
+        (after.nox, before.nox, "g")
+    };
+    let delta = after - before;
+    let pct = if before == 0.0 {
+        String::new()
+    } else {
+        format!(" ({:+.1}%)", 100.0 * delta / before)
+    };
+    format!("{:+.0} {}{}", delta, unit, pct)
+}
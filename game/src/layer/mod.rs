@@ -9,6 +9,7 @@ use crate::app::{App, Transition};
 use crate::sandbox::dashboards;
 
 pub mod elevation;
+mod emissions;
 pub mod favorites;
 pub mod map;
 mod pandemic;
@@ -144,6 +145,7 @@ impl PickLayer {
                     "Data".text_widget(ctx),
                     btn("traffic signal demand", Key::M),
                     btn("commuter patterns", Key::R),
+                    btn("emissions", Key::C),
                 ]),
             ])
             .evenly_spaced(),
@@ -192,6 +194,16 @@ impl State<App> for PickLayer {
                 "high stress" => {
                     app.primary.layer = Some(Box::new(map::Static::high_stress(ctx, app)));
                 }
+                "emissions" => {
+                    app.primary.layer = Some(Box::new(emissions::EmissionsMap::new(
+                        ctx,
+                        app,
+                        emissions::Options {
+                            heatmap: HeatmapOptions::new(),
+                            show_nox: false,
+                        },
+                    )));
+                }
                 "favorite buildings" => {
                     app.primary.layer = Some(Box::new(favorites::ShowFavorites::new(ctx, app)));
                 }
@@ -141,6 +141,9 @@ impl TransitRoutes {
         // Sort descending by count, but ascending by name. Hence the funny negation.
         let mut routes: Vec<(isize, isize, isize, String, BusRouteID)> = Vec::new();
         for r in app.primary.map.all_bus_routes() {
+            if r.is_deleted() {
+                continue;
+            }
             routes.push((
                 -(boardings.get(r.id) as isize),
                 -(alightings.get(r.id) as isize),
@@ -1,8 +1,10 @@
-use geom::Duration;
-use sim::{TripEndpoint, TripID, TripPhaseType};
+use abstutil::prettyprint_usize;
+use geom::{Distance, Duration, Time};
+use sim::{Analytics, TripEndpoint, TripID, TripPhaseType};
 use widgetry::table::{Col, Filter, Table};
 use widgetry::{
-    EventCtx, Filler, GeomBatch, GfxCtx, Line, Outcome, Panel, State, Text, Toggle, Widget,
+    EventCtx, Filler, GeomBatch, GfxCtx, Line, LinePlot, Outcome, Panel, PlotOptions, Series,
+    State, Text, Toggle, Widget,
 };
 
 use crate::app::{App, Transition};
@@ -53,6 +55,7 @@ impl ParkingOverhead {
                     Filler::square_width(ctx, 0.15).named("preview"),
                 ])
                 .evenly_spaced(),
+                parking_summary(ctx, app),
                 table.render(ctx, app),
             ])
             .section(ctx),
@@ -104,6 +107,72 @@ impl State<App> for ParkingOverhead {
     }
 }
 
+/// Revenue and time limit violations at priced spots, cruising for parking, and how full onstreet
+/// parking and lots get each hour
+fn parking_summary(ctx: &mut EventCtx, app: &App) -> Widget {
+    let analytics = app.primary.sim.get_analytics();
+    let num_overstays = analytics
+        .parking_payments
+        .iter()
+        .filter(|(_, _, _, overstay)| *overstay > Duration::ZERO)
+        .count();
+    let total_cruising: Distance = analytics
+        .parking_cruising
+        .iter()
+        .map(|(_, _, dist)| *dist)
+        .sum();
+
+    let txt = Text::from_multiline(vec![
+        Line(format!(
+            "Parking revenue: ${:.2} from {} payments",
+            analytics.parking_revenue() as f64 / 100.0,
+            prettyprint_usize(analytics.parking_payments.len())
+        )),
+        Line(format!(
+            "{} cars stayed past a time limit",
+            prettyprint_usize(num_overstays)
+        )),
+        Line(format!(
+            "{} drivers had to search for parking, driving {} in total",
+            prettyprint_usize(analytics.parking_cruising.len()),
+            total_cruising.to_string(&app.opts.units)
+        )),
+    ]);
+
+    let mut series = vec![Series {
+        label: format!("After \"{}\"", app.primary.map.get_edits().edits_name),
+        color: app.cs.after_changes,
+        pts: occupancy_per_hour(analytics, app.primary.sim.time()),
+    }];
+    if app.has_prebaked().is_some() {
+        series.push(Series {
+            label: format!("Before \"{}\"", app.primary.map.get_edits().edits_name),
+            color: app.cs.before_changes.alpha(0.5),
+            pts: occupancy_per_hour(app.prebaked(), app.primary.sim.get_end_of_day()),
+        });
+    }
+
+    Widget::col(vec![
+        txt.into_widget(ctx),
+        LinePlot::new_widget(
+            ctx,
+            "peak onstreet and lot spots filled per hour",
+            series,
+            PlotOptions::fixed(),
+            app.opts.units,
+        ),
+    ])
+}
+
+fn occupancy_per_hour(analytics: &Analytics, now: Time) -> Vec<(Time, usize)> {
+    analytics
+        .parking_occupancy_per_hour(now)
+        .into_iter()
+        .enumerate()
+        .map(|(hour, cnt)| (Time::START_OF_DAY + Duration::hours(hour), cnt))
+        .collect()
+}
+
 struct Entry {
     trip: TripID,
     total_duration: Duration,
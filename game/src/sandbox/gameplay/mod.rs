@@ -78,6 +78,7 @@ pub trait GameplayState: downcast_rs::Downcast {
 }
 downcast_rs::impl_downcast!(GameplayState);
 
+#[allow(clippy::large_enum_variant)]
 pub enum LoadScenario {
     Nothing,
     Path(String),
@@ -190,7 +191,10 @@ impl GameplayMode {
                     }
                     _ => {}
                 },
-                EditCmd::ChangeRouteSchedule { .. } => {}
+                EditCmd::ChangeRouteSchedule { .. }
+                | EditCmd::ChangeParkingLot { .. }
+                | EditCmd::ChangeBusStop { .. }
+                | EditCmd::ChangeRoute { .. } => {}
             }
         }
         true
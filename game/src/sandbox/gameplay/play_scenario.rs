@@ -264,6 +264,34 @@ impl EditScenarioModifiers {
                 .text("Repeat schedule multiple days")
                 .build_def(ctx),
         ]));
+        rows.push(Widget::row(vec![
+            Spinner::widget(ctx, "ride_hail_fleet", (0, 10_000), 100_usize, 10),
+            ctx.style()
+                .btn_outline
+                .text("Set ride-hailing fleet size")
+                .build_def(ctx),
+        ]));
+        rows.push(Widget::row(vec![
+            Spinner::widget(ctx, "pct_electric_cars", (0, 100), 10_usize, 5),
+            ctx.style()
+                .btn_outline
+                .text("Set percent of electric cars")
+                .build_def(ctx),
+        ]));
+        rows.push(Widget::row(vec![
+            Spinner::widget(ctx, "max_pct_speeding", (0, 50), 10_usize, 5),
+            ctx.style()
+                .btn_outline
+                .text("Vary driver behavior")
+                .build_def(ctx),
+        ]));
+        rows.push(Widget::row(vec![
+            Spinner::widget(ctx, "delivery_tours", (1, 10_000), 100_usize, 10),
+            ctx.style()
+                .btn_outline
+                .text("Add delivery truck tours")
+                .build_def(ctx),
+        ]));
         rows.push(Widget::horiz_separator(ctx, 1.0));
         rows.push(
             Widget::row(vec![
@@ -360,6 +388,46 @@ impl State<App> for EditScenarioModifiers {
                         self.modifiers.clone(),
                     ));
                 }
+                "Set ride-hailing fleet size" => {
+                    self.modifiers.push(ScenarioModifier::RideHailFleet(
+                        self.panel.spinner("ride_hail_fleet"),
+                    ));
+                    return Transition::Replace(EditScenarioModifiers::new_state(
+                        ctx,
+                        self.scenario_name.clone(),
+                        self.modifiers.clone(),
+                    ));
+                }
+                "Vary driver behavior" => {
+                    self.modifiers.push(ScenarioModifier::VaryDriverBehavior(
+                        self.panel.spinner("max_pct_speeding"),
+                    ));
+                    return Transition::Replace(EditScenarioModifiers::new_state(
+                        ctx,
+                        self.scenario_name.clone(),
+                        self.modifiers.clone(),
+                    ));
+                }
+                "Set percent of electric cars" => {
+                    self.modifiers.push(ScenarioModifier::ElectricCars(
+                        self.panel.spinner("pct_electric_cars"),
+                    ));
+                    return Transition::Replace(EditScenarioModifiers::new_state(
+                        ctx,
+                        self.scenario_name.clone(),
+                        self.modifiers.clone(),
+                    ));
+                }
+                "Add delivery truck tours" => {
+                    self.modifiers.push(ScenarioModifier::AddDeliveryTours(
+                        self.panel.spinner("delivery_tours"),
+                    ));
+                    return Transition::Replace(EditScenarioModifiers::new_state(
+                        ctx,
+                        self.scenario_name.clone(),
+                        self.modifiers.clone(),
+                    ));
+                }
                 x => {
                     if let Some(x) = x.strip_prefix("delete modifier ") {
                         self.modifiers.remove(x.parse::<usize>().unwrap() - 1);
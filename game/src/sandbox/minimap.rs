@@ -253,6 +253,28 @@ fn make_agent_toggles(ctx: &mut EventCtx, app: &App, is_enabled: [bool; 4]) -> V
                 prettyprint_usize(counts.sov_drivers)
             ))
             .secondary(),
+            Line(format!(
+                "{} carpool passengers in {} cars",
+                prettyprint_usize(counts.carpool_passengers),
+                prettyprint_usize(counts.carpool_drivers)
+            ))
+            .secondary(),
+            Line(format!(
+                "{} riders in {} ride-hailing vehicles",
+                prettyprint_usize(counts.ride_hail_riders),
+                prettyprint_usize(counts.ride_hail_vehicles)
+            ))
+            .secondary(),
+            Line(format!(
+                "{} cyclists on shared bikes",
+                prettyprint_usize(counts.bike_share_riders)
+            ))
+            .secondary(),
+            Line(format!(
+                "Delivery trucks: {}",
+                prettyprint_usize(counts.trucks)
+            ))
+            .secondary(),
         ]);
         colored_checkbox(
             ctx,
@@ -260,7 +282,12 @@ fn make_agent_toggles(ctx: &mut EventCtx, app: &App, is_enabled: [bool; 4]) -> V
             is_car_enabled,
             app.cs.unzoomed_car,
             "system/assets/meters/car.svg",
-            &prettyprint_usize(counts.sov_drivers),
+            &prettyprint_usize(
+                counts.sov_drivers
+                    + counts.carpool_drivers
+                    + counts.ride_hail_vehicles
+                    + counts.trucks,
+            ),
             tooltip,
         )
     };
@@ -1,8 +1,13 @@
 use std::{cmp, f64, fmt, ops};
 
+use schemars::gen::SchemaGenerator;
+use schemars::schema::Schema;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-use crate::{deserialize_f64, serialize_f64, trim_f64, Duration, Speed, UnitFmt};
+use crate::{
+    deserialize_f64, serialize_f64, trim_f64, trimmed_f64_schema, Duration, Speed, UnitFmt,
+};
 
 /// A distance, in meters. Can be negative.
 #[derive(Clone, Copy, Debug, PartialEq, PartialOrd, Serialize, Deserialize)]
@@ -20,6 +25,16 @@ impl Ord for Distance {
     }
 }
 
+impl JsonSchema for Distance {
+    fn schema_name() -> String {
+        "Distance".to_string()
+    }
+
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        trimmed_f64_schema("Meters")
+    }
+}
+
 impl Distance {
     pub const ZERO: Distance = Distance::const_meters(0.0);
 
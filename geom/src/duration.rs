@@ -2,11 +2,16 @@ use std::{cmp, ops};
 
 use anyhow::Result;
 use instant::Instant;
+use schemars::gen::SchemaGenerator;
+use schemars::schema::Schema;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 use abstutil::elapsed_seconds;
 
-use crate::{deserialize_f64, serialize_f64, trim_f64, Distance, Speed, UnitFmt};
+use crate::{
+    deserialize_f64, serialize_f64, trim_f64, trimmed_f64_schema, Distance, Speed, UnitFmt,
+};
 
 /// A duration, in seconds. Can be negative.
 #[derive(Clone, Copy, Debug, PartialEq, PartialOrd, Serialize, Deserialize)]
@@ -24,6 +29,16 @@ impl Ord for Duration {
     }
 }
 
+impl JsonSchema for Duration {
+    fn schema_name() -> String {
+        "Duration".to_string()
+    }
+
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        trimmed_f64_schema("Seconds")
+    }
+}
+
 impl Duration {
     pub const ZERO: Duration = Duration::const_seconds(0.0);
     pub const EPSILON: Duration = Duration::const_seconds(0.0001);
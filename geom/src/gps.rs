@@ -5,14 +5,19 @@ use std::io::{BufRead, BufReader, Write};
 use anyhow::Result;
 use geojson::{GeoJson, Value};
 use ordered_float::NotNan;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 use crate::{Distance, GPSBounds, Pt2D};
 
 /// Represents a (longitude, latitude) point.
-#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Serialize, Deserialize)]
+#[derive(
+    Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Serialize, Deserialize, JsonSchema,
+)]
 pub struct LonLat {
+    #[schemars(with = "f64")]
     longitude: NotNan<f64>,
+    #[schemars(with = "f64")]
     latitude: NotNan<f64>,
 }
 
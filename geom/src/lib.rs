@@ -3,6 +3,7 @@
 #[macro_use]
 extern crate anyhow;
 
+use schemars::schema::{InstanceType, Metadata, Schema, SchemaObject};
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
 pub use crate::angle::Angle;
@@ -63,6 +64,19 @@ fn deserialize_f64<'de, D: Deserializer<'de>>(d: D) -> Result<f64, D::Error> {
     Ok(x as f64 / 10_000.0)
 }
 
+/// Describes a trimmed `f64` serialized by `serialize_f64`, for JSON schemas.
+fn trimmed_f64_schema(units: &str) -> Schema {
+    Schema::Object(SchemaObject {
+        metadata: Some(Box::new(Metadata {
+            description: Some(format!("{}, multiplied by 10,000", units)),
+            ..Default::default()
+        })),
+        instance_type: Some(InstanceType::Integer.into()),
+        format: Some("int32".to_string()),
+        ..Default::default()
+    })
+}
+
 /// Specifies how to stringify different geom objects.
 #[derive(Clone, Serialize, Deserialize, Copy)]
 pub struct UnitFmt {
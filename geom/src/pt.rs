@@ -1,6 +1,7 @@
 use std::fmt;
 
 use ordered_float::NotNan;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 use crate::{
@@ -8,11 +9,13 @@ use crate::{
 };
 
 /// This represents world-space in meters.
-#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, JsonSchema)]
 pub struct Pt2D {
     #[serde(serialize_with = "serialize_f64", deserialize_with = "deserialize_f64")]
+    #[schemars(with = "Distance")]
     x: f64,
     #[serde(serialize_with = "serialize_f64", deserialize_with = "deserialize_f64")]
+    #[schemars(with = "Distance")]
     y: f64,
 }
 
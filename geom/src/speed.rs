@@ -1,8 +1,13 @@
 use std::{cmp, ops};
 
+use schemars::gen::SchemaGenerator;
+use schemars::schema::Schema;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-use crate::{deserialize_f64, serialize_f64, trim_f64, Distance, Duration, UnitFmt};
+use crate::{
+    deserialize_f64, serialize_f64, trim_f64, trimmed_f64_schema, Distance, Duration, UnitFmt,
+};
 
 /// In meters per second. Can be negative.
 #[derive(Clone, Copy, Debug, PartialEq, PartialOrd, Serialize, Deserialize)]
@@ -20,6 +25,16 @@ impl Ord for Speed {
     }
 }
 
+impl JsonSchema for Speed {
+    fn schema_name() -> String {
+        "Speed".to_string()
+    }
+
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        trimmed_f64_schema("Meters per second")
+    }
+}
+
 impl Speed {
     pub const ZERO: Speed = Speed::const_meters_per_second(0.0);
 
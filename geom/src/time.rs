@@ -2,9 +2,12 @@ use std::{cmp, ops};
 
 use anyhow::Result;
 use ordered_float::NotNan;
+use schemars::gen::SchemaGenerator;
+use schemars::schema::Schema;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-use crate::{deserialize_f64, serialize_f64, trim_f64, Duration};
+use crate::{deserialize_f64, serialize_f64, trim_f64, trimmed_f64_schema, Duration};
 
 /// In seconds since midnight. Can't be negative.
 #[derive(Clone, Copy, Debug, Default, PartialEq, PartialOrd, Serialize, Deserialize)]
@@ -22,6 +25,16 @@ impl Ord for Time {
     }
 }
 
+impl JsonSchema for Time {
+    fn schema_name() -> String {
+        "Time".to_string()
+    }
+
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        trimmed_f64_schema("Seconds since midnight")
+    }
+}
+
 #[allow(clippy::derive_hash_xor_eq)] // false positive
 impl std::hash::Hash for Time {
     fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
@@ -0,0 +1,1006 @@
+//! Describes every route in the API, so that requests can be validated the same way everywhere and
+//! an OpenAPI document can be generated for clients.
+//!
+//! All routes are served under the `/v1` prefix. For compatibility with older clients, they're
+//! also served without any prefix, but the HTTP method isn't checked for those.
+
+use std::collections::BTreeMap;
+
+use anyhow::Result;
+use hyper::{Body, Method, Response, StatusCode};
+use schemars::gen::{SchemaGenerator, SchemaSettings};
+use schemars::schema::{InstanceType, Metadata, Schema, SchemaObject};
+use schemars::JsonSchema;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Deserializer, Serialize};
+
+use geom::{Duration, Time};
+use map_model::{
+    ControlTrafficSignal, CorridorOffsets, IntersectionID, PermanentEditCmd, PermanentMapEdits,
+};
+use sim::{ExternalPerson, TripID};
+
+use crate::events::{self, TimedEvent};
+use crate::{
+    apply_edits, create_session, delete_session, delete_snapshot, get_agent_positions,
+    get_all_geometry, get_all_traffic_signal_state, get_all_trip_time_lower_bounds,
+    get_blocked_by_graph, get_cumulative_thruput, get_delays, get_edit_road_command, get_edits,
+    get_emissions, get_finished_trips, get_intersection_geometry, get_openapi, get_road_thruput,
+    get_time, get_traffic_signal, get_transit_priority, get_trip_time_lower_bound, goto_time,
+    list_sessions, load_sim, new_person, optimize_corridor_offsets, reset_sim, restore_snapshot,
+    set_traffic_signal, step_traffic_signals, take_snapshot, AgentPositions, BlockedByGraph,
+    Delays, EmissionsSummary, FinishedTrip, LiveEditResults, LiveEdits, LoadSim, RoadThroughput,
+    Session, SignalAction, SignalObservations, SnapshotHandle, Throughput, TrafficSignalState,
+    TransitPriorityRequest,
+};
+
+pub const VERSION_PREFIX: &str = "/v1";
+
+pub struct Route {
+    pub path: &'static str,
+    pub method: HttpMethod,
+    pub summary: &'static str,
+    /// Query parameters. Every route operating on a session also accepts `session`.
+    pub params: &'static [Param],
+    /// If the route expects a JSON body, describes it
+    pub body: Option<RequestBody>,
+    pub output: Output,
+    pub handler: Handler,
+}
+
+pub struct RequestBody {
+    pub description: &'static str,
+    /// If false, the body may be empty
+    pub required: bool,
+    pub schema: BodySchema,
+}
+
+/// Describes the JSON in a request or response body, usually generated from the Rust type with
+/// `schema`.
+pub type BodySchema = fn(&mut SchemaGenerator) -> Schema;
+
+fn schema<T: JsonSchema>(gen: &mut SchemaGenerator) -> Schema {
+    gen.subschema_for::<T>()
+}
+
+/// How a route is handled. Every handler takes the query string; most also take the request
+/// body.
+pub enum Handler {
+    /// Doesn't operate on any one session
+    Global(fn(&str, &[u8]) -> Result<String>),
+    /// Operates on one session, picked by the `session` query parameter
+    Session(fn(&mut Session, &str, &[u8]) -> Result<String>),
+    /// Finds its own session and responds incrementally
+    Stream(fn(&str) -> Result<Body>),
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum HttpMethod {
+    Get,
+    Post,
+}
+
+impl HttpMethod {
+    pub fn matches(self, method: &Method) -> bool {
+        match self {
+            HttpMethod::Get => *method == Method::GET,
+            HttpMethod::Post => *method == Method::POST,
+        }
+    }
+
+    fn lowercase(self) -> &'static str {
+        match self {
+            HttpMethod::Get => "get",
+            HttpMethod::Post => "post",
+        }
+    }
+}
+
+pub struct Param {
+    pub name: &'static str,
+    pub schema: ParamType,
+    pub required: bool,
+    pub description: &'static str,
+}
+
+pub enum ParamType {
+    Integer,
+    Boolean,
+    String,
+    /// Parsed by `Time::parse`, like "07:30:00"
+    Time,
+    /// Parsed by `Duration::parse`, like "1:30"
+    Duration,
+    /// A comma-separated list of integers
+    IntegerList,
+}
+
+pub enum Output {
+    /// Plain text, described by the string
+    Text(&'static str),
+    /// JSON, described by the string
+    Json(&'static str, BodySchema),
+    /// Newline-delimited JSON, sent incrementally. The schema describes each line.
+    JsonLines(&'static str, BodySchema),
+}
+
+impl Output {
+    fn content_type(&self) -> &'static str {
+        match self {
+            Output::Text(_) => "text/plain",
+            Output::Json(_, _) => "application/json",
+            Output::JsonLines(_, _) => "application/x-ndjson",
+        }
+    }
+
+    fn description(&self) -> &'static str {
+        match self {
+            Output::Text(x) | Output::Json(x, _) | Output::JsonLines(x, _) => x,
+        }
+    }
+}
+
+const fn required(name: &'static str, schema: ParamType, description: &'static str) -> Param {
+    Param {
+        name,
+        schema,
+        required: true,
+        description,
+    }
+}
+
+const fn optional(name: &'static str, schema: ParamType, description: &'static str) -> Param {
+    Param {
+        name,
+        schema,
+        required: false,
+        description,
+    }
+}
+
+pub static ROUTES: &[Route] = &[
+    Route {
+        path: "/openapi.json",
+        method: HttpMethod::Get,
+        summary: "Describes this API",
+        params: &[],
+        body: None,
+        output: Output::Json("An OpenAPI 3 document", openapi_schema),
+        handler: Handler::Global(get_openapi),
+    },
+    // Managing sessions
+    Route {
+        path: "/session/create",
+        method: HttpMethod::Post,
+        summary: "Start a new, independent simulation",
+        params: &[],
+        body: Some(RequestBody {
+            description: "An object with scenario, modifiers, and edits, like /sim/load. If \
+                          omitted, use the scenario the server started with.",
+            required: false,
+            schema: schema::<LoadSim>,
+        }),
+        output: Output::Text("The new session ID"),
+        handler: Handler::Global(create_session),
+    },
+    Route {
+        path: "/session/list",
+        method: HttpMethod::Get,
+        summary: "List all session IDs",
+        params: &[],
+        body: None,
+        output: Output::Json("A list of session IDs", schema::<Vec<usize>>),
+        handler: Handler::Global(list_sessions),
+    },
+    Route {
+        path: "/session/delete",
+        method: HttpMethod::Get,
+        summary: "Delete a session",
+        params: &[required("id", ParamType::Integer, "The session ID")],
+        body: None,
+        output: Output::Text("A confirmation"),
+        handler: Handler::Global(delete_session),
+    },
+    // Controlling the simulation
+    Route {
+        path: "/sim/reset",
+        method: HttpMethod::Get,
+        summary: "Reset the simulation to midnight, using the current scenario and edits",
+        params: &[],
+        body: None,
+        output: Output::Text("A confirmation"),
+        handler: Handler::Session(reset_sim),
+    },
+    Route {
+        path: "/sim/load",
+        method: HttpMethod::Post,
+        summary: "Change the scenario, modifiers, and edits, then reset the simulation",
+        params: &[],
+        body: Some(RequestBody {
+            description: "An object with scenario, modifiers, and edits",
+            required: true,
+            schema: schema::<LoadSim>,
+        }),
+        output: Output::Text("A confirmation"),
+        handler: Handler::Session(load_sim),
+    },
+    Route {
+        path: "/sim/get-time",
+        method: HttpMethod::Get,
+        summary: "Get the current time in the simulation",
+        params: &[],
+        body: None,
+        output: Output::Text("The current time"),
+        handler: Handler::Session(get_time),
+    },
+    Route {
+        path: "/sim/goto-time",
+        method: HttpMethod::Get,
+        summary: "Simulate until the specified time",
+        params: &[required("t", ParamType::Time, "The time to simulate until")],
+        body: None,
+        output: Output::Text("A confirmation"),
+        handler: Handler::Session(goto_time),
+    },
+    Route {
+        path: "/sim/new-person",
+        method: HttpMethod::Post,
+        summary: "Add a new person to the simulation",
+        params: &[],
+        body: Some(RequestBody {
+            description: "An ExternalPerson",
+            required: true,
+            schema: schema::<ExternalPerson>,
+        }),
+        output: Output::Text("The new person's ID"),
+        handler: Handler::Session(new_person),
+    },
+    Route {
+        path: "/sim/stream-events",
+        method: HttpMethod::Get,
+        summary: "Simulate until the specified time, streaming events as they happen",
+        params: &[
+            required("t", ParamType::Time, "The time to simulate until"),
+            optional(
+                "step",
+                ParamType::Duration,
+                "How often to send events. Defaults to 1 minute of simulation time.",
+            ),
+            optional(
+                "kinds",
+                ParamType::String,
+                "A comma-separated list of event types, like TripFinished",
+            ),
+            optional(
+                "intersections",
+                ParamType::IntegerList,
+                "Only send events involving these intersections or other specified objects",
+            ),
+            optional(
+                "roads",
+                ParamType::IntegerList,
+                "Only send events involving these roads or other specified objects",
+            ),
+            optional(
+                "routes",
+                ParamType::IntegerList,
+                "Only send events involving these transit routes or other specified objects",
+            ),
+            optional(
+                "trips",
+                ParamType::IntegerList,
+                "Only send events involving these trips or other specified objects",
+            ),
+            optional(
+                "people",
+                ParamType::IntegerList,
+                "Only send events involving these people or other specified objects",
+            ),
+        ],
+        body: None,
+        output: Output::JsonLines(
+            "One object per event, with the time and the event",
+            schema::<TimedEvent>,
+        ),
+        handler: Handler::Stream(events::stream_events),
+    },
+    Route {
+        path: "/sim/snapshot",
+        method: HttpMethod::Get,
+        summary: "Remember the current state of the simulation and map edits, to restore later",
+        params: &[optional(
+            "save",
+            ParamType::Boolean,
+            "Also write a savestate and the map edits to disk",
+        )],
+        body: None,
+        output: Output::Json(
+            "The snapshot ID, the time, and the path of the savestate, if saved",
+            schema::<SnapshotHandle>,
+        ),
+        handler: Handler::Session(take_snapshot),
+    },
+    Route {
+        path: "/sim/restore",
+        method: HttpMethod::Get,
+        summary: "Return the simulation and map edits to a previous snapshot",
+        params: &[
+            optional(
+                "id",
+                ParamType::Integer,
+                "The snapshot ID. Specify this or path.",
+            ),
+            optional(
+                "path",
+                ParamType::String,
+                "The path to a savestate. The map edits it refers to must be saved.",
+            ),
+        ],
+        body: None,
+        output: Output::Text("A confirmation"),
+        handler: Handler::Session(restore_snapshot),
+    },
+    Route {
+        path: "/sim/delete-snapshot",
+        method: HttpMethod::Get,
+        summary: "Forget a snapshot, freeing memory",
+        params: &[required("id", ParamType::Integer, "The snapshot ID")],
+        body: None,
+        output: Output::Text("A confirmation"),
+        handler: Handler::Session(delete_snapshot),
+    },
+    // Traffic signals
+    Route {
+        path: "/traffic-signals/get",
+        method: HttpMethod::Get,
+        summary: "Get the configuration of a traffic signal",
+        params: &[required("id", ParamType::Integer, "The intersection ID")],
+        body: None,
+        output: Output::Json("A ControlTrafficSignal", schema::<ControlTrafficSignal>),
+        handler: Handler::Session(get_traffic_signal),
+    },
+    Route {
+        path: "/traffic-signals/set",
+        method: HttpMethod::Post,
+        summary: "Change the configuration of a traffic signal",
+        params: &[],
+        body: Some(RequestBody {
+            description: "A ControlTrafficSignal",
+            required: true,
+            schema: schema::<ControlTrafficSignal>,
+        }),
+        output: Output::Text("A confirmation"),
+        handler: Handler::Session(set_traffic_signal),
+    },
+    Route {
+        path: "/traffic-signals/optimize-corridor-offsets",
+        method: HttpMethod::Post,
+        summary: "Coordinate the offsets of a chain of adjacent traffic signals to maximize the \
+                  green-wave bandwidth in both directions, using the plans currently in effect. \
+                  The new offsets are applied as map edits.",
+        params: &[required(
+            "intersections",
+            ParamType::IntegerList,
+            "The traffic signals along the corridor, in order",
+        )],
+        body: None,
+        output: Output::Json(
+            "The new offsets and the bandwidth before and after",
+            schema::<CorridorOffsets>,
+        ),
+        handler: Handler::Session(optimize_corridor_offsets),
+    },
+    Route {
+        path: "/traffic-signals/get-delays",
+        method: HttpMethod::Get,
+        summary: "Get the delays experienced by agents crossing a traffic signal",
+        params: &[
+            required("id", ParamType::Integer, "The intersection ID"),
+            required("t1", ParamType::Time, "Only include delays from this time"),
+            required("t2", ParamType::Time, "Only include delays until this time"),
+        ],
+        body: None,
+        output: Output::Json("Delays per movement", schema::<Delays>),
+        handler: Handler::Session(get_delays),
+    },
+    Route {
+        path: "/traffic-signals/get-cumulative-thruput",
+        method: HttpMethod::Get,
+        summary: "Get the number of agents that've crossed each movement of a traffic signal",
+        params: &[required("id", ParamType::Integer, "The intersection ID")],
+        body: None,
+        output: Output::Json("Throughput per movement", schema::<Throughput>),
+        handler: Handler::Session(get_cumulative_thruput),
+    },
+    Route {
+        path: "/traffic-signals/get-transit-priority",
+        method: HttpMethod::Get,
+        summary: "Get every time a bus or train extended or cut short a stage of a traffic \
+                  signal, with the estimated delay saved for transit and added for everybody else",
+        params: &[required("id", ParamType::Integer, "The intersection ID")],
+        body: None,
+        output: Output::Json(
+            "A list of transit priority requests",
+            schema::<Vec<TransitPriorityRequest>>,
+        ),
+        handler: Handler::Session(get_transit_priority),
+    },
+    Route {
+        path: "/traffic-signals/get-all-current-state",
+        method: HttpMethod::Get,
+        summary: "Get the current plan, stage, and waiting agents at every traffic signal",
+        params: &[],
+        body: None,
+        output: Output::Json(
+            "A map from intersection ID to its current state",
+            schema::<BTreeMap<IntersectionID, TrafficSignalState>>,
+        ),
+        handler: Handler::Session(get_all_traffic_signal_state),
+    },
+    Route {
+        path: "/traffic-signals/step",
+        method: HttpMethod::Post,
+        summary: "Force traffic signals to some stage, advance the simulation, and observe the \
+                  resulting state of every signal. Meant for external signal controllers.",
+        params: &[
+            optional(
+                "dt",
+                ParamType::Duration,
+                "How long to advance the simulation after applying the actions. If missing, just \
+                 observe the current state.",
+            ),
+            optional(
+                "intersections",
+                ParamType::IntegerList,
+                "Only observe these traffic signals. If missing, observe all of them.",
+            ),
+        ],
+        body: Some(RequestBody {
+            description: "A list of actions, each with the intersection `id` and the zero-based \
+                          `stage` of the signal's current plan to immediately switch to. The stage \
+                          lasts its usual duration, then the signal resumes its normal cycle. If \
+                          omitted, no signals are changed.",
+            required: false,
+            schema: schema::<Vec<SignalAction>>,
+        }),
+        output: Output::Json(
+            "The current time, and for each signal, the current plan and stage, remaining time in \
+             the stage, queue lengths and demand per movement, and waiting agents",
+            schema::<SignalObservations>,
+        ),
+        handler: Handler::Session(step_traffic_signals),
+    },
+    // Querying data
+    Route {
+        path: "/data/get-finished-trips",
+        method: HttpMethod::Get,
+        summary: "Get all finished or cancelled trips",
+        params: &[],
+        body: None,
+        output: Output::Json("A list of trips", schema::<Vec<FinishedTrip>>),
+        handler: Handler::Session(get_finished_trips),
+    },
+    Route {
+        path: "/data/get-agent-positions",
+        method: HttpMethod::Get,
+        summary: "Get the current position of every agent",
+        params: &[],
+        body: None,
+        output: Output::Json("A list of agent positions", schema::<AgentPositions>),
+        handler: Handler::Session(get_agent_positions),
+    },
+    Route {
+        path: "/data/get-road-thruput",
+        method: HttpMethod::Get,
+        summary: "Get the number of agents crossing each road per hour",
+        params: &[],
+        body: None,
+        output: Output::Json(
+            "A list of (road, agent type, hour, count)",
+            schema::<RoadThroughput>,
+        ),
+        handler: Handler::Session(get_road_thruput),
+    },
+    Route {
+        path: "/data/get-emissions",
+        method: HttpMethod::Get,
+        summary: "Get the estimated CO2 and NOx emitted so far, per road and intersection per hour",
+        params: &[],
+        body: None,
+        output: Output::Json(
+            "The total, and lists of (road or intersection, hour, emissions)",
+            schema::<EmissionsSummary>,
+        ),
+        handler: Handler::Session(get_emissions),
+    },
+    Route {
+        path: "/data/get-blocked-by-graph",
+        method: HttpMethod::Get,
+        summary: "Find agents that are stuck and what's blocking them",
+        params: &[],
+        body: None,
+        output: Output::Json(
+            "A map from agent to its delay and the cause",
+            schema::<BlockedByGraph>,
+        ),
+        handler: Handler::Session(get_blocked_by_graph),
+    },
+    Route {
+        path: "/data/trip-time-lower-bound",
+        method: HttpMethod::Get,
+        summary: "Estimate the fastest a trip could possibly finish",
+        params: &[required("id", ParamType::Integer, "The trip ID")],
+        body: None,
+        output: Output::Text("The duration in seconds"),
+        handler: Handler::Session(get_trip_time_lower_bound),
+    },
+    Route {
+        path: "/data/all-trip-time-lower-bounds",
+        method: HttpMethod::Get,
+        summary: "Estimate the fastest every trip could possibly finish",
+        params: &[],
+        body: None,
+        output: Output::Json(
+            "A map from trip ID to duration",
+            schema::<BTreeMap<TripID, Duration>>,
+        ),
+        handler: Handler::Session(get_all_trip_time_lower_bounds),
+    },
+    // Controlling the map
+    Route {
+        path: "/map/get-edits",
+        method: HttpMethod::Get,
+        summary: "Get the current map edits",
+        params: &[],
+        body: None,
+        output: Output::Json("PermanentMapEdits", schema::<PermanentMapEdits>),
+        handler: Handler::Session(get_edits),
+    },
+    Route {
+        path: "/map/get-edit-road-command",
+        method: HttpMethod::Get,
+        summary: "Get an edit command for a road that can be modified and applied",
+        params: &[required("id", ParamType::Integer, "The road ID")],
+        body: None,
+        output: Output::Json("A PermanentEditCmd", schema::<PermanentEditCmd>),
+        handler: Handler::Session(get_edit_road_command),
+    },
+    Route {
+        path: "/map/apply-edits",
+        method: HttpMethod::Post,
+        summary: "Edit the map without resetting the simulation",
+        params: &[],
+        body: Some(RequestBody {
+            description: "Either PermanentMapEdits to replace all current edits, or a list of \
+                          PermanentEditCmds to add to the current edits",
+            required: true,
+            schema: schema::<LiveEdits>,
+        }),
+        output: Output::Json(
+            "What changed in the map, the trips cancelled, and the number of parked cars \
+             displaced",
+            schema::<LiveEditResults>,
+        ),
+        handler: Handler::Session(apply_edits),
+    },
+    Route {
+        path: "/map/get-intersection-geometry",
+        method: HttpMethod::Get,
+        summary: "Get the geometry of an intersection and its roads, centered around it",
+        params: &[required("id", ParamType::Integer, "The intersection ID")],
+        body: None,
+        output: Output::Json("GeoJSON, with distances in meters", geojson_schema),
+        handler: Handler::Session(get_intersection_geometry),
+    },
+    Route {
+        path: "/map/get-all-geometry",
+        method: HttpMethod::Get,
+        summary: "Get the geometry of all intersections and roads",
+        params: &[],
+        body: None,
+        output: Output::Json("GeoJSON", geojson_schema),
+        handler: Handler::Session(get_all_geometry),
+    },
+];
+
+pub fn find_route(path: &str) -> Option<&'static Route> {
+    ROUTES.iter().find(|r| r.path == path)
+}
+
+impl Route {
+    /// Does this route operate on one session?
+    pub fn uses_session(&self) -> bool {
+        !matches!(self.handler, Handler::Global(_))
+    }
+
+    pub fn respond(&self, body: Body) -> Response<Body> {
+        Response::builder()
+            .header("Content-Type", self.output.content_type())
+            .body(body)
+            .unwrap()
+    }
+}
+
+#[derive(Serialize)]
+struct Error {
+    error: String,
+}
+
+/// Every failure is described by a JSON object with one `error` field.
+pub fn error_response(status: StatusCode, path: &str, err: anyhow::Error) -> Response<Body> {
+    error!("{}: {}", path, err);
+    Response::builder()
+        .status(status)
+        .header("Content-Type", "application/json")
+        .body(Body::from(abstutil::to_json(&Error {
+            error: err.to_string(),
+        })))
+        .unwrap()
+}
+
+/// Parses the query string of a request into a typed struct.
+pub fn parse_query<T: DeserializeOwned>(query: &str) -> Result<T> {
+    serde_urlencoded::from_str(query).map_err(|err| anyhow!("bad query parameters: {}", err))
+}
+
+/// Use with `#[serde(deserialize_with)]` to parse query parameters like "07:30:00".
+pub fn deserialize_time<'de, D: Deserializer<'de>>(d: D) -> Result<Time, D::Error> {
+    let x = String::deserialize(d)?;
+    Time::parse(&x).map_err(serde::de::Error::custom)
+}
+
+/// Use with `#[serde(deserialize_with)]` to parse optional query parameters like "1:30".
+pub fn deserialize_opt_duration<'de, D: Deserializer<'de>>(
+    d: D,
+) -> Result<Option<Duration>, D::Error> {
+    match Option::<String>::deserialize(d)? {
+        Some(x) => Duration::parse(&x)
+            .map(Some)
+            .map_err(serde::de::Error::custom),
+        None => Ok(None),
+    }
+}
+
+/// Use with `#[serde(deserialize_with)]` to parse query parameters like "1,2,3". Missing
+/// parameters become an empty list; use with `#[serde(default)]`.
+pub fn deserialize_ids<'de, D: Deserializer<'de>>(d: D) -> Result<Vec<usize>, D::Error> {
+    let x = String::deserialize(d)?;
+    x.split(',')
+        .map(|id| id.parse::<usize>().map_err(serde::de::Error::custom))
+        .collect()
+}
+
+/// Many routes just need one ID.
+#[derive(Deserialize)]
+pub struct IdQuery {
+    pub id: usize,
+}
+
+/// Generates an OpenAPI document describing every route.
+pub fn openapi() -> serde_json::Value {
+    let mut gen = SchemaSettings::openapi3().into_generator();
+    let mut paths = serde_json::Map::new();
+    for route in ROUTES {
+        let mut parameters: Vec<serde_json::Value> = route
+            .params
+            .iter()
+            .map(|p| {
+                serde_json::json!({
+                    "name": p.name,
+                    "in": "query",
+                    "required": p.required,
+                    "description": p.description,
+                    "schema": p.schema.to_json(),
+                })
+            })
+            .collect();
+        if route.uses_session() {
+            parameters.push(serde_json::json!({
+                "name": "session",
+                "in": "query",
+                "required": false,
+                "description": "The session ID. If omitted, uses the default session.",
+                "schema": ParamType::Integer.to_json(),
+            }));
+        }
+
+        let mut content = serde_json::Map::new();
+        content.insert(
+            route.output.content_type().to_string(),
+            match route.output {
+                Output::Text(_) => serde_json::json!({ "schema": {"type": "string"} }),
+                Output::Json(_, schema) | Output::JsonLines(_, schema) => {
+                    serde_json::json!({ "schema": schema(&mut gen) })
+                }
+            },
+        );
+        let mut operation = serde_json::json!({
+            "summary": route.summary,
+            "parameters": parameters,
+            "responses": {
+                "200": {
+                    "description": route.output.description(),
+                    "content": content,
+                },
+                "400": { "$ref": "#/components/responses/Error" },
+                "404": { "$ref": "#/components/responses/Error" },
+                "500": { "$ref": "#/components/responses/Error" },
+            },
+        });
+        if let Some(ref body) = route.body {
+            operation["requestBody"] = serde_json::json!({
+                "description": body.description,
+                "required": body.required,
+                "content": {
+                    "application/json": { "schema": (body.schema)(&mut gen) }
+                },
+            });
+        }
+
+        let mut item = serde_json::Map::new();
+        item.insert(route.method.lowercase().to_string(), operation);
+        paths.insert(
+            format!("{}{}", VERSION_PREFIX, route.path),
+            serde_json::Value::Object(item),
+        );
+    }
+
+    serde_json::json!({
+        "openapi": "3.0.3",
+        "info": {
+            "title": "A/B Street headless API",
+            "version": "1",
+        },
+        "paths": paths,
+        "components": {
+            "schemas": gen.definitions(),
+            "responses": {
+                "Error": {
+                    "description": "Something went wrong",
+                    "content": {
+                        "application/json": {
+                            "schema": {
+                                "type": "object",
+                                "properties": {
+                                    "error": { "type": "string" }
+                                },
+                                "required": ["error"],
+                            }
+                        }
+                    }
+                }
+            }
+        },
+    })
+}
+
+/// Describes a JSON object that isn't produced from any one Rust type.
+fn object_schema(description: &str) -> Schema {
+    Schema::Object(SchemaObject {
+        metadata: Some(Box::new(Metadata {
+            description: Some(description.to_string()),
+            ..Default::default()
+        })),
+        instance_type: Some(InstanceType::Object.into()),
+        ..Default::default()
+    })
+}
+
+fn geojson_schema(_: &mut SchemaGenerator) -> Schema {
+    object_schema("A GeoJSON FeatureCollection, as defined by RFC 7946")
+}
+
+fn openapi_schema(_: &mut SchemaGenerator) -> Schema {
+    object_schema("An OpenAPI 3 document")
+}
+
+impl ParamType {
+    fn to_json(&self) -> serde_json::Value {
+        match self {
+            ParamType::Integer => serde_json::json!({"type": "integer", "minimum": 0}),
+            ParamType::Boolean => serde_json::json!({"type": "boolean"}),
+            ParamType::String => serde_json::json!({"type": "string"}),
+            ParamType::Time => serde_json::json!({"type": "string", "example": "07:30:00"}),
+            ParamType::Duration => serde_json::json!({"type": "string", "example": "1:30"}),
+            ParamType::IntegerList => serde_json::json!({"type": "string", "example": "1,2,3"}),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use map_model::raw::OriginalRoad;
+    use map_model::{osm, LaneID, PermanentEditIntersection, RoadID, TurnID};
+
+    use super::*;
+    use crate::LiveEditResults;
+
+    #[test]
+    fn unique_paths() {
+        let mut paths = BTreeSet::new();
+        for route in ROUTES {
+            assert!(paths.insert(route.path), "{} is listed twice", route.path);
+            assert!(std::ptr::eq(find_route(route.path).unwrap(), route));
+        }
+    }
+
+    #[test]
+    fn refs_resolve() {
+        let doc = openapi();
+        let mut refs = Vec::new();
+        find_refs(&doc, &mut refs);
+        assert!(!refs.is_empty());
+        for r in refs {
+            let pointer = r
+                .strip_prefix('#')
+                .unwrap_or_else(|| panic!("{} isn't a local reference", r));
+            assert!(doc.pointer(pointer).is_some(), "{} doesn't resolve", r);
+        }
+    }
+
+    fn find_refs(value: &serde_json::Value, refs: &mut Vec<String>) {
+        match value {
+            serde_json::Value::Object(map) => {
+                for (k, v) in map {
+                    if k == "$ref" {
+                        refs.push(v.as_str().unwrap().to_string());
+                    } else {
+                        find_refs(v, refs);
+                    }
+                }
+            }
+            serde_json::Value::Array(list) => {
+                for v in list {
+                    find_refs(v, refs);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    #[test]
+    fn values_match_schemas() {
+        let lane = |road, offset| LaneID {
+            road: RoadID(road),
+            offset,
+        };
+        let results = LiveEditResults {
+            changed_roads: vec![RoadID(3)].into_iter().collect(),
+            deleted_lanes: vec![lane(3, 1)].into_iter().collect(),
+            changed_intersections: BTreeSet::new(),
+            added_turns: vec![TurnID {
+                parent: IntersectionID(2),
+                src: lane(3, 0),
+                dst: lane(4, 2),
+            }]
+            .into_iter()
+            .collect(),
+            deleted_turns: BTreeSet::new(),
+            changed_parking_lots: BTreeSet::new(),
+            cancelled_trips: BTreeSet::new(),
+            parked_cars_displaced: 0,
+        };
+        check_against_schema::<LiveEditResults>(&results);
+
+        let road = OriginalRoad {
+            osm_way_id: osm::WayID(10),
+            i1: osm::NodeID(1),
+            i2: osm::NodeID(2),
+        };
+        let cmd = PermanentEditCmd::ChangeIntersection {
+            i: osm::NodeID(2),
+            new: PermanentEditIntersection::StopSign {
+                must_stop: vec![(road, true)].into_iter().collect(),
+            },
+            old: PermanentEditIntersection::Closed,
+        };
+        check_against_schema::<Vec<PermanentEditCmd>>(&vec![cmd]);
+    }
+
+    fn check_against_schema<T: Serialize + JsonSchema>(value: &T) {
+        let mut gen = SchemaSettings::openapi3().into_generator();
+        let schema = serde_json::to_value(gen.subschema_for::<T>()).unwrap();
+        let definitions = serde_json::to_value(gen.definitions()).unwrap();
+        let value = serde_json::to_value(value).unwrap();
+        if let Err(err) = validate(&value, &schema, &definitions) {
+            panic!("{} doesn't match its schema: {}", value, err);
+        }
+    }
+
+    /// Checks the subset of JSON Schema that schemars produces.
+    fn validate(
+        value: &serde_json::Value,
+        schema: &serde_json::Value,
+        definitions: &serde_json::Value,
+    ) -> Result<(), String> {
+        use serde_json::Value;
+
+        if let Some(Value::String(r)) = schema.get("$ref") {
+            let name = r.rsplit('/').next().unwrap();
+            let schema = definitions
+                .get(name)
+                .ok_or_else(|| format!("{} isn't defined", r))?;
+            return validate(value, schema, definitions);
+        }
+        if value.is_null() && schema.get("nullable") == Some(&Value::Bool(true)) {
+            return Ok(());
+        }
+        if let Some(Value::Array(all)) = schema.get("allOf") {
+            for s in all {
+                validate(value, s, definitions)?;
+            }
+        }
+        for key in ["anyOf", "oneOf"] {
+            if let Some(Value::Array(options)) = schema.get(key) {
+                if !options
+                    .iter()
+                    .any(|s| validate(value, s, definitions).is_ok())
+                {
+                    return Err(format!("{} matches nothing in {}", value, key));
+                }
+            }
+        }
+        if let Some(Value::Array(choices)) = schema.get("enum") {
+            if !choices.contains(value) {
+                return Err(format!("{} isn't one of {:?}", value, choices));
+            }
+        }
+        let ok = match schema.get("type").and_then(|t| t.as_str()) {
+            None => true,
+            Some("integer") => value.is_i64() || value.is_u64(),
+            Some("number") => value.is_number(),
+            Some("string") => value.is_string(),
+            Some("boolean") => value.is_boolean(),
+            Some("array") => value.is_array(),
+            Some("object") => value.is_object(),
+            Some(x) => return Err(format!("unknown type {}", x)),
+        };
+        if !ok {
+            return Err(format!("{} isn't {}", value, schema["type"]));
+        }
+
+        if let Value::Object(fields) = value {
+            if let Some(Value::Array(required)) = schema.get("required") {
+                for key in required {
+                    if !fields.contains_key(key.as_str().unwrap()) {
+                        return Err(format!("{} is missing {}", value, key));
+                    }
+                }
+            }
+            let properties = schema.get("properties");
+            for (key, v) in fields {
+                match properties.and_then(|p| p.get(key)) {
+                    Some(s) => validate(v, s, definitions)?,
+                    None => match schema.get("additionalProperties") {
+                        Some(Value::Bool(false)) => {
+                            return Err(format!("{} has unexpected {}", value, key))
+                        }
+                        Some(s @ Value::Object(_)) => validate(v, s, definitions)?,
+                        _ => {}
+                    },
+                }
+            }
+        }
+        if let Value::Array(list) = value {
+            match schema.get("items") {
+                Some(Value::Array(tuple)) => {
+                    if tuple.len() != list.len() {
+                        return Err(format!("{} should have {} items", value, tuple.len()));
+                    }
+                    for (v, s) in list.iter().zip(tuple) {
+                        validate(v, s, definitions)?;
+                    }
+                }
+                Some(s) => {
+                    for v in list {
+                        validate(v, s, definitions)?;
+                    }
+                }
+                None => {}
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,329 @@
+//! Stream events from the simulation as it runs, so clients don't have to repeatedly poll for
+//! large blobs of data.
+//!
+//! > curl "http://localhost:1234/v1/sim/stream-events?t=08:00:00&kinds=TripFinished,BusArrivedAtStop"
+//!
+//! The simulation advances to time `t`, stopping every `step` (defaulting to one minute) to send
+//! all of the matching events that happened. Each line of the response is one JSON object with
+//! the `time` and the `event`. The optional filters are:
+//!
+//! - `kinds`: a comma-separated list of event types, like `TripFinished`
+//! - `intersections`, `roads`, `routes`, `trips`, `people`: comma-separated lists of numeric IDs.
+//!   If any of these are specified, only events involving at least one of the objects are sent.
+
+use std::collections::BTreeSet;
+use std::task::Poll;
+
+use anyhow::Result;
+use hyper::body::Sender;
+use hyper::Body;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tokio::runtime::Handle;
+
+use abstutil::Timer;
+use geom::{Duration, Time};
+use map_model::{BusRouteID, IntersectionID, RoadID, Traversable};
+use sim::{AlertLocation, Event, ParkingSpot, PersonID, Problem, TripID};
+
+use crate::{api, Session};
+
+/// Starts advancing the simulation in the background, returning the body of the response that'll
+/// receive events as they happen. The session is locked until the simulation reaches the end
+/// time or the client disconnects.
+pub fn stream_events(query: &str) -> Result<Body> {
+    let session = crate::find_session(query)?;
+    let args: StreamEventsQuery = api::parse_query(query)?;
+    let end_time = args.t;
+    let step = args.step.unwrap_or_else(|| Duration::minutes(1));
+    if step <= Duration::ZERO {
+        bail!("step must be positive");
+    }
+    let filter = EventFilter::new(args);
+
+    {
+        let now = crate::lock_session(&session)?.sim.time();
+        if end_time <= now {
+            bail!("{} is in the past. call /sim/reset first?", end_time);
+        }
+    }
+
+    let (mut sender, body) = Body::channel();
+    let runtime = Handle::current();
+    tokio::task::spawn_blocking(move || {
+        let mut session = match crate::lock_session(&session) {
+            Ok(session) => session,
+            Err(err) => {
+                error!("Can't stream events: {}", err);
+                return;
+            }
+        };
+        let Session { map, sim, .. } = &mut *session;
+
+        sim.start_buffering_events();
+        while sim.time() < end_time {
+            // Don't keep the session locked for nobody, even if no events have matched lately
+            if is_closed(&runtime, &mut sender) {
+                info!("Client stopped listening to events at {}", sim.time());
+                break;
+            }
+            let dt = step.min(end_time - sim.time());
+            sim.timed_step(map, dt, &mut None, &mut Timer::throwaway());
+
+            let mut chunk = String::new();
+            for (time, ev) in sim.drain_buffered_events() {
+                if let Some(line) = filter.to_json_line(time, &ev) {
+                    chunk.push_str(&line);
+                    chunk.push('\n');
+                }
+            }
+            if !chunk.is_empty() && runtime.block_on(sender.send_data(chunk.into())).is_err() {
+                info!("Client stopped listening to events at {}", sim.time());
+                break;
+            }
+        }
+        sim.stop_buffering_events();
+    });
+    Ok(body)
+}
+
+/// Checks if the client has disconnected, without waiting for it to want more data.
+fn is_closed(runtime: &Handle, sender: &mut Sender) -> bool {
+    runtime.block_on(std::future::poll_fn(|cx| {
+        Poll::Ready(matches!(sender.poll_ready(cx), Poll::Ready(Err(_))))
+    }))
+}
+
+struct EventFilter {
+    /// If None, send every kind of event
+    kinds: Option<BTreeSet<String>>,
+    intersections: BTreeSet<IntersectionID>,
+    roads: BTreeSet<RoadID>,
+    routes: BTreeSet<BusRouteID>,
+    trips: BTreeSet<TripID>,
+    people: BTreeSet<PersonID>,
+}
+
+/// One line of the response
+#[derive(Serialize, JsonSchema)]
+pub struct TimedEvent {
+    time: Time,
+    #[schemars(with = "Event")]
+    event: serde_json::Value,
+}
+
+#[derive(Deserialize)]
+struct StreamEventsQuery {
+    #[serde(deserialize_with = "api::deserialize_time")]
+    t: Time,
+    #[serde(default, deserialize_with = "api::deserialize_opt_duration")]
+    step: Option<Duration>,
+    kinds: Option<String>,
+    #[serde(default, deserialize_with = "api::deserialize_ids")]
+    intersections: Vec<usize>,
+    #[serde(default, deserialize_with = "api::deserialize_ids")]
+    roads: Vec<usize>,
+    #[serde(default, deserialize_with = "api::deserialize_ids")]
+    routes: Vec<usize>,
+    #[serde(default, deserialize_with = "api::deserialize_ids")]
+    trips: Vec<usize>,
+    #[serde(default, deserialize_with = "api::deserialize_ids")]
+    people: Vec<usize>,
+}
+
+impl EventFilter {
+    fn new(args: StreamEventsQuery) -> EventFilter {
+        EventFilter {
+            kinds: args
+                .kinds
+                .map(|x| x.split(',').map(|k| k.to_string()).collect()),
+            intersections: args.intersections.into_iter().map(IntersectionID).collect(),
+            roads: args.roads.into_iter().map(RoadID).collect(),
+            routes: args.routes.into_iter().map(BusRouteID).collect(),
+            trips: args.trips.into_iter().map(TripID).collect(),
+            people: args.people.into_iter().map(PersonID).collect(),
+        }
+    }
+
+    fn filters_objects(&self) -> bool {
+        !self.intersections.is_empty()
+            || !self.roads.is_empty()
+            || !self.routes.is_empty()
+            || !self.trips.is_empty()
+            || !self.people.is_empty()
+    }
+
+    /// Returns None if the event doesn't match the filter.
+    fn to_json_line(&self, time: Time, ev: &Event) -> Option<String> {
+        if self.filters_objects() && !self.involves_any_object(ev) {
+            return None;
+        }
+        // Events are serialized as an object with the name of the variant as the only key.
+        let event = serde_json::to_value(ev).ok()?;
+        if let Some(ref kinds) = self.kinds {
+            let kind = event.as_object()?.keys().next()?;
+            if !kinds.contains(kind) {
+                return None;
+            }
+        }
+        serde_json::to_string(&TimedEvent { time, event }).ok()
+    }
+
+    fn involves_any_object(&self, ev: &Event) -> bool {
+        let mut objects = Objects::default();
+        match ev {
+            Event::CarReachedParkingSpot(_, spot)
+            | Event::CarLeftParkingSpot(_, spot, _)
+            | Event::PedReachedParkingSpot(_, spot) => {
+                if let ParkingSpot::Onstreet(l, _) = spot {
+                    objects.roads.push(l.road);
+                }
+            }
+            Event::BusArrivedAtStop(_, r, stop)
+            | Event::BusDepartedFromStop(_, r, stop)
+            | Event::TransitBoardingDenied { route: r, stop, .. }
+            | Event::TransitVehicleLoad { route: r, stop, .. }
+            | Event::TransitScheduleDeviation { route: r, stop, .. } => {
+                objects.routes.push(*r);
+                objects.roads.push(stop.sidewalk.road);
+            }
+            Event::PassengerBoardsTransit(p, _, r, stop, _)
+            | Event::PassengerAlightsTransit(p, _, r, stop) => {
+                objects.people.push(*p);
+                objects.routes.push(*r);
+                objects.roads.push(stop.sidewalk.road);
+            }
+            Event::PersonEntersBuilding(p, _)
+            | Event::PersonLeavesBuilding(p, _)
+            | Event::PassengerEntersCar(p, _, _)
+            | Event::PassengerLeavesCar(p, _, _) => {
+                objects.people.push(*p);
+            }
+            Event::PersonLeavesMap(p, _, i) | Event::PersonEntersMap(p, _, i) => {
+                objects.people.push(*p);
+                objects.intersections.push(*i);
+            }
+            Event::BikeStoppedAtSidewalk(_, l) => {
+                objects.roads.push(l.road);
+            }
+            Event::ProblemEncountered(trip, problem) => {
+                objects.trips.push(*trip);
+                match problem {
+                    Problem::IntersectionDelay(i, _) | Problem::ComplexIntersectionCrossing(i) => {
+                        objects.intersections.push(*i);
+                    }
+                    Problem::ArterialIntersectionCrossing(t) => {
+                        objects.intersections.push(t.parent);
+                    }
+                    Problem::OvertakeDesired(on) => {
+                        objects.traversable(*on);
+                    }
+                }
+            }
+            Event::AgentEntersTraversable(_, trip, on, _) => {
+                objects.trips.extend(trip);
+                objects.traversable(*on);
+            }
+            Event::IntersectionDelayMeasured(trip, t, _, _) => {
+                objects.trips.push(*trip);
+                objects.intersections.push(t.parent);
+            }
+            Event::TransitSignalPriority { intersection, .. } => {
+                objects.intersections.push(*intersection);
+            }
+            Event::RideHailPickUp { rider, lane, .. }
+            | Event::RideHailDropOff { rider, lane, .. } => {
+                objects.trips.push(*rider);
+                objects.roads.push(lane.road);
+            }
+            Event::RideHailDistance { .. } | Event::RideHailFleetUtilization { .. } => {}
+            Event::TruckUnloading { trip, lane, .. } => {
+                objects.trips.push(*trip);
+                objects.roads.push(lane.road);
+            }
+            Event::TruckLeftLoadingZone { lane, .. } => {
+                objects.roads.push(lane.road);
+            }
+            Event::BikeShareUnlocked { trip, .. }
+            | Event::BikeShareReturned { trip, .. }
+            | Event::BikeShareDockEmpty { trip, .. }
+            | Event::BikeShareDockFull { trip, .. } => {
+                objects.trips.push(*trip);
+            }
+            Event::BikeShareRebalanced { .. } => {}
+            Event::TripFinished { trip, .. } | Event::TripCancelled(trip, _) => {
+                objects.trips.push(*trip);
+            }
+            Event::TripPhaseStarting(trip, p, _, _) => {
+                objects.trips.push(*trip);
+                objects.people.push(*p);
+            }
+            Event::PathAmended(_) | Event::CruisingForParking(_, _) | Event::Rerouted(_, _) => {}
+            Event::VehicleEmissions(_, on, _) => match on {
+                Traversable::Lane(l) => {
+                    objects.roads.push(l.road);
+                }
+                Traversable::Turn(t) => {
+                    objects.intersections.push(t.parent);
+                }
+            },
+            Event::VehicleCrossedLane(_, l, _) => {
+                objects.roads.push(l.road);
+            }
+            Event::BatteryDepleted(_)
+            | Event::ChargingSessionEnded { .. }
+            | Event::NoChargerAvailable(_, _) => {}
+            Event::Alert(loc, _) => match loc {
+                AlertLocation::Intersection(i) => {
+                    objects.intersections.push(*i);
+                }
+                AlertLocation::Person(p) => {
+                    objects.people.push(*p);
+                }
+                AlertLocation::Nil | AlertLocation::Building(_) => {}
+            },
+        }
+
+        objects
+            .intersections
+            .iter()
+            .any(|i| self.intersections.contains(i))
+            || objects.roads.iter().any(|r| self.roads.contains(r))
+            || objects.routes.iter().any(|r| self.routes.contains(r))
+            || objects.trips.iter().any(|t| self.trips.contains(t))
+            || objects.people.iter().any(|p| self.people.contains(p))
+    }
+}
+
+/// The map objects and agents that an event involves
+#[derive(Default)]
+struct Objects {
+    intersections: Vec<IntersectionID>,
+    roads: Vec<RoadID>,
+    routes: Vec<BusRouteID>,
+    trips: Vec<TripID>,
+    people: Vec<PersonID>,
+}
+
+impl Objects {
+    fn traversable(&mut self, on: Traversable) {
+        match on {
+            Traversable::Lane(l) => self.roads.push(l.road),
+            Traversable::Turn(t) => self.intersections.push(t.parent),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn notice_disconnect_without_sending() {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let (mut sender, body) = Body::channel();
+        assert!(!is_closed(runtime.handle(), &mut sender));
+        drop(body);
+        assert!(is_closed(runtime.handle(), &mut sender));
+    }
+}
@@ -1,26 +1,46 @@
 //! This runs a simulation without any graphics and serves a very basic API to control things. See
 //! https://a-b-street.github.io/docs/tech/dev/api.html for documentation. To run this:
 //!
+//! ```text
 //! > cd headless; cargo run -- --port=1234
-//! > curl http://localhost:1234/sim/get-time
+//! > curl http://localhost:1234/v1/sim/get-time
 //! 00:00:00.0
-//! > curl http://localhost:1234/sim/goto-time?t=01:01:00
+//! > curl http://localhost:1234/v1/sim/goto-time?t=01:01:00
 //! it's now 01:01:00.0
-//! > curl http://localhost:1234/data/get-road-thruput
+//! > curl http://localhost:1234/v1/data/get-road-thruput
 //! ... huge JSON blob
+//! ```
+//!
+//! Every route is described by an OpenAPI document at /openapi.json. Errors are returned as a JSON
+//! object with an `error` field.
+//!
+//! Multiple independent simulations can run at the same time. Each one is a session with its own
+//! map and sim. Every command accepts an optional `session` GET parameter; if it's missing, the
+//! default session (created at startup, with ID 0) is used.
+//!
+//! ```text
+//! > curl -X POST http://localhost:1234/v1/session/create
+//! 1
+//! > curl http://localhost:1234/v1/sim/goto-time?t=07:00:00&session=1
+//! it's now 07:00:00.0
+//! ```
 
 #[macro_use]
 extern crate anyhow;
 #[macro_use]
 extern crate log;
 
-use std::collections::{BTreeMap, BTreeSet, HashMap};
-use std::sync::RwLock;
+mod api;
+mod events;
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::{Arc, Mutex, MutexGuard, RwLock};
 
 use anyhow::Result;
 use hyper::{Body, Request, Response, Server, StatusCode};
 use rand::SeedableRng;
 use rand_xorshift::XorShiftRng;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use structopt::StructOpt;
 
@@ -28,28 +48,79 @@ use abstio::MapName;
 use abstutil::{serialize_btreemap, Timer};
 use geom::{Distance, Duration, LonLat, Time};
 use map_model::{
-    CompressedMovementID, ControlTrafficSignal, EditCmd, EditIntersection, IntersectionID, Map,
-    MovementID, PermanentMapEdits, RoadID, TurnID,
+    CompressedMovementID, ControlTrafficSignal, EditCmd, EditIntersection, Intersection,
+    IntersectionID, LaneID, Map, MapEdits, MovementID, ParkingLotID, PermanentEditCmd,
+    PermanentMapEdits, RoadID, TurnID,
 };
 use sim::{
-    AgentID, AgentType, DelayCause, ExternalPerson, PersonID, Scenario, ScenarioModifier, Sim,
-    SimFlags, SimOptions, TripID, TripMode, VehicleType,
+    AgentID, AgentType, CarID, DelayCause, Emissions, ExternalPerson, PersonID, Scenario,
+    ScenarioModifier, Sim, SimFlags, SimOptions, TripID, TripMode, VehicleType,
 };
 
+use crate::api::IdQuery;
+
 lazy_static::lazy_static! {
-    static ref MAP: RwLock<Map> = RwLock::new(Map::blank());
-    static ref SIM: RwLock<Sim> = RwLock::new(Sim::new(&Map::blank(), SimOptions::new("tmp")));
-    static ref LOAD: RwLock<LoadSim> = RwLock::new({
-        LoadSim {
+    static ref SESSIONS: RwLock<Sessions> = RwLock::new(Sessions {
+        sessions: BTreeMap::new(),
+        next_id: DEFAULT_SESSION,
+        default_load: LoadSim {
             scenario: abstio::path_scenario(&MapName::seattle("montlake"), "weekday"),
             modifiers: Vec::new(),
             edits: None,
             rng_seed: SimFlags::RNG_SEED,
             opts: SimOptions::default(),
-        }
+        },
     });
 }
 
+/// Commands that don't specify a session use this one, created at startup.
+const DEFAULT_SESSION: usize = 0;
+
+struct Sessions {
+    sessions: BTreeMap<usize, Arc<Mutex<Session>>>,
+    next_id: usize,
+    /// New sessions start from this, unless they specify their own scenario, modifiers, and
+    /// edits. The RNG seed and SimOptions always come from the command line.
+    default_load: LoadSim,
+}
+
+impl Sessions {
+    fn get(&self, id: usize) -> Result<Arc<Mutex<Session>>> {
+        self.sessions
+            .get(&id)
+            .cloned()
+            .ok_or_else(|| anyhow!("no session {}", id))
+    }
+}
+
+/// An independent simulation, with its own map (since edits may differ).
+pub struct Session {
+    map: Map,
+    sim: Sim,
+    load: LoadSim,
+    snapshots: BTreeMap<usize, Snapshot>,
+    next_snapshot_id: usize,
+}
+
+/// The state of a simulation at some point, kept in memory so it can be restored quickly.
+struct Snapshot {
+    sim: Sim,
+    edits: MapEdits,
+}
+
+impl Session {
+    fn new(load: LoadSim, timer: &mut Timer) -> Result<Session> {
+        let (map, sim) = load.setup(timer)?;
+        Ok(Session {
+            map,
+            sim,
+            load,
+            snapshots: BTreeMap::new(),
+            next_snapshot_id: 0,
+        })
+    }
+}
+
 #[derive(StructOpt)]
 #[structopt(
     name = "headless",
@@ -74,13 +145,19 @@ async fn main() {
     let args = Args::from_args();
 
     {
-        let mut load = LOAD.write().unwrap();
-        load.rng_seed = args.rng_seed;
-        load.opts = args.opts;
+        let mut sessions = SESSIONS.write().unwrap();
+        sessions.default_load.rng_seed = args.rng_seed;
+        sessions.default_load.opts = args.opts;
 
-        let (map, sim) = load.setup(&mut Timer::new("setup headless"));
-        *MAP.write().unwrap() = map;
-        *SIM.write().unwrap() = sim;
+        let session = Session::new(
+            sessions.default_load.clone(),
+            &mut Timer::new("setup headless"),
+        )
+        .unwrap();
+        sessions
+            .sessions
+            .insert(DEFAULT_SESSION, Arc::new(Mutex::new(session)));
+        sessions.next_id = DEFAULT_SESSION + 1;
     }
 
     let addr = std::net::SocketAddr::from(([127, 0, 0, 1], args.port));
@@ -94,306 +171,712 @@ async fn main() {
 }
 
 async fn serve_req(req: Request<Body>) -> Result<Response<Body>, hyper::Error> {
-    let path = req.uri().path().to_string();
-    // Url::parse needs an absolute URL
-    let params: HashMap<String, String> =
-        url::Url::parse(&format!("http://localhost{}", req.uri()))
-            .unwrap()
-            .query_pairs()
-            .map(|(k, v)| (k.to_string(), v.to_string()))
-            .collect();
+    let full_path = req.uri().path().to_string();
+    let query = req.uri().query().unwrap_or("").to_string();
+    let method = req.method().clone();
     let body = hyper::body::to_bytes(req).await?.to_vec();
-    info!("Handling {}", path);
-    Ok(
-        match handle_command(
-            &path,
-            &params,
-            &body,
-            &mut SIM.write().unwrap(),
-            &mut MAP.write().unwrap(),
-            &mut LOAD.write().unwrap(),
-        ) {
-            Ok(resp) => Response::new(Body::from(resp)),
-            Err(err) => {
-                error!("{}: {}", path, err);
-                Response::builder()
-                    .status(StatusCode::BAD_REQUEST)
-                    .body(Body::from(format!("Bad command {}: {}", path, err)))
-                    .unwrap()
-            }
-        },
-    )
-}
-
-fn handle_command(
-    path: &str,
-    params: &HashMap<String, String>,
-    body: &[u8],
-    sim: &mut Sim,
-    map: &mut Map,
-    load: &mut LoadSim,
-) -> Result<String> {
-    let get = |key: &str| {
-        params
-            .get(key)
-            .ok_or_else(|| anyhow!("missing GET parameter {}", key))
+    info!("Handling {}", full_path);
+
+    let (path, versioned) = match full_path.strip_prefix(api::VERSION_PREFIX) {
+        Some(path) => (path.to_string(), true),
+        None => (full_path.clone(), false),
     };
+    let route = match api::find_route(&path) {
+        Some(route) => route,
+        None => {
+            return Ok(api::error_response(
+                StatusCode::NOT_FOUND,
+                &full_path,
+                anyhow!("Unknown command"),
+            ));
+        }
+    };
+    if versioned && !route.method.matches(&method) {
+        return Ok(api::error_response(
+            StatusCode::METHOD_NOT_ALLOWED,
+            &full_path,
+            anyhow!("{} doesn't accept {}", full_path, method),
+        ));
+    }
+
+    if let api::Handler::Stream(handler) = route.handler {
+        // This one responds incrementally while the simulation advances
+        return Ok(match handler(&query) {
+            Ok(body) => route.respond(body),
+            Err(err) => api::error_response(StatusCode::BAD_REQUEST, &full_path, err),
+        });
+    }
 
-    match path {
-        // Controlling the simulation
-        "/sim/reset" => {
-            let (new_map, new_sim) = load.setup(&mut Timer::new("reset sim"));
-            *map = new_map;
-            *sim = new_sim;
-            Ok("sim reloaded".to_string())
+    // Simulating can take a long time. Don't block the async runtime, so that requests to other
+    // sessions can proceed in parallel. The outer error is our fault; the inner is the client's.
+    let result = tokio::task::spawn_blocking(move || -> Result<Result<String>> {
+        match route.handler {
+            api::Handler::Global(handler) => Ok(handler(&query, &body)),
+            api::Handler::Session(handler) => {
+                let session = match find_session(&query) {
+                    Ok(session) => session,
+                    Err(err) => return Ok(Err(err)),
+                };
+                let mut session = lock_session(&session)?;
+                Ok(handler(&mut session, &query, &body))
+            }
+            api::Handler::Stream(_) => unreachable!(),
         }
-        "/sim/load" => {
-            let args: LoadSim = abstutil::from_json(body)?;
+    })
+    .await;
+    Ok(match result {
+        Ok(Ok(Ok(resp))) => route.respond(Body::from(resp)),
+        Ok(Ok(Err(err))) => api::error_response(StatusCode::BAD_REQUEST, &full_path, err),
+        Ok(Err(err)) => api::error_response(StatusCode::INTERNAL_SERVER_ERROR, &full_path, err),
+        Err(err) => api::error_response(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            &full_path,
+            anyhow!("internal error: {}", err),
+        ),
+    })
+}
 
-            load.scenario = args.scenario;
-            load.modifiers = args.modifiers;
-            load.edits = args.edits;
+#[derive(Deserialize)]
+struct SessionQuery {
+    session: Option<usize>,
+}
 
-            // Also reset
-            let (new_map, new_sim) = load.setup(&mut Timer::new("reset sim"));
-            *map = new_map;
-            *sim = new_sim;
+/// Looks up the session specified by the optional `session` query parameter.
+fn find_session(query: &str) -> Result<Arc<Mutex<Session>>> {
+    let id = api::parse_query::<SessionQuery>(query)?
+        .session
+        .unwrap_or(DEFAULT_SESSION);
+    // Only hold the lock on all sessions long enough to find this one
+    SESSIONS.read().unwrap().get(id)
+}
 
-            Ok("flags changed and sim reloaded".to_string())
-        }
-        "/sim/get-time" => Ok(sim.time().to_string()),
-        "/sim/goto-time" => {
-            let t = Time::parse(get("t")?)?;
-            if t <= sim.time() {
-                bail!("{} is in the past. call /sim/reset first?", t)
-            } else {
-                let dt = t - sim.time();
-                sim.timed_step(map, dt, &mut None, &mut Timer::new("goto-time"));
-                Ok(format!("it's now {}", t))
-            }
+/// If a handler panicked while using this session, it may have been left half-modified, so
+/// refuse to use it again.
+fn lock_session(session: &Mutex<Session>) -> Result<MutexGuard<'_, Session>> {
+    session.lock().map_err(|_| {
+        anyhow!("this session broke during an earlier request; delete it and create a new one")
+    })
+}
+
+/// Looks up a traffic signal by its intersection ID, which comes straight from the client.
+fn get_signal_intersection(map: &Map, id: usize) -> Result<&Intersection> {
+    match map.maybe_get_i(IntersectionID(id)) {
+        Some(i) if i.is_traffic_signal() => Ok(i),
+        Some(i) => bail!("{} isn't a traffic signal", i.id),
+        None => bail!("no intersection {}", id),
+    }
+}
+
+// Each of these handles one route in api::ROUTES, taking the query string and the request body.
+
+fn get_openapi(_: &str, _: &[u8]) -> Result<String> {
+    Ok(abstutil::to_json(&api::openapi()))
+}
+
+// Managing sessions
+
+fn create_session(_: &str, body: &[u8]) -> Result<String> {
+    let mut load = SESSIONS.read().unwrap().default_load.clone();
+    // Optionally override the scenario, modifiers, and edits
+    if !body.is_empty() {
+        let args: LoadSim = abstutil::from_json(body)?;
+        load.scenario = args.scenario;
+        load.modifiers = args.modifiers;
+        load.edits = args.edits;
+    }
+
+    // Loading is slow, so don't hold the lock on all sessions while doing it
+    let session = Session::new(load, &mut Timer::new("create session"))?;
+
+    let mut sessions = SESSIONS.write().unwrap();
+    let id = sessions.next_id;
+    sessions.next_id += 1;
+    sessions.sessions.insert(id, Arc::new(Mutex::new(session)));
+    Ok(id.to_string())
+}
+
+fn list_sessions(_: &str, _: &[u8]) -> Result<String> {
+    let ids: Vec<usize> = SESSIONS.read().unwrap().sessions.keys().cloned().collect();
+    Ok(abstutil::to_json(&ids))
+}
+
+fn delete_session(query: &str, _: &[u8]) -> Result<String> {
+    let id = api::parse_query::<IdQuery>(query)?.id;
+    if SESSIONS.write().unwrap().sessions.remove(&id).is_none() {
+        bail!("no session {}", id);
+    }
+    Ok(format!("session {} deleted", id))
+}
+
+// Controlling the simulation
+
+fn reset_sim(session: &mut Session, _: &str, _: &[u8]) -> Result<String> {
+    let (map, sim) = session.load.setup(&mut Timer::new("reset sim"))?;
+    session.map = map;
+    session.sim = sim;
+    Ok("sim reloaded".to_string())
+}
+
+fn load_sim(session: &mut Session, _: &str, body: &[u8]) -> Result<String> {
+    let args: LoadSim = abstutil::from_json(body)?;
+
+    let mut load = session.load.clone();
+    load.scenario = args.scenario;
+    load.modifiers = args.modifiers;
+    load.edits = args.edits;
+
+    // Also reset. If the new scenario or edits are broken, keep the old ones.
+    let (map, sim) = load.setup(&mut Timer::new("reset sim"))?;
+    session.load = load;
+    session.map = map;
+    session.sim = sim;
+
+    Ok("flags changed and sim reloaded".to_string())
+}
+
+fn get_time(session: &mut Session, _: &str, _: &[u8]) -> Result<String> {
+    Ok(session.sim.time().to_string())
+}
+
+fn goto_time(session: &mut Session, query: &str, _: &[u8]) -> Result<String> {
+    let Session { map, sim, .. } = session;
+    let t = api::parse_query::<TimeQuery>(query)?.t;
+    if t <= sim.time() {
+        bail!("{} is in the past. call /sim/reset first?", t)
+    } else {
+        let dt = t - sim.time();
+        sim.timed_step(map, dt, &mut None, &mut Timer::new("goto-time"));
+        Ok(format!("it's now {}", t))
+    }
+}
+
+fn new_person(session: &mut Session, _: &str, body: &[u8]) -> Result<String> {
+    let Session { map, sim, load, .. } = session;
+    let input: ExternalPerson = abstutil::from_json(body)?;
+    for trip in &input.trips {
+        if trip.departure < sim.time() {
+            bail!(
+                "It's {} now, so you can't start a trip at {}",
+                sim.time(),
+                trip.departure
+            )
         }
-        "/sim/new-person" => {
-            let input: ExternalPerson = abstutil::from_json(body)?;
-            for trip in &input.trips {
-                if trip.departure < sim.time() {
-                    bail!(
-                        "It's {} now, so you can't start a trip at {}",
-                        sim.time(),
-                        trip.departure
-                    )
-                }
-            }
+    }
+
+    let mut scenario = Scenario::empty(map, "one-shot");
+    scenario.people = ExternalPerson::import(map, vec![input], false)?;
+    scenario.validate()?;
+    let mut rng = XorShiftRng::seed_from_u64(load.rng_seed);
+    scenario.instantiate(sim, map, &mut rng, &mut Timer::throwaway());
+    Ok(format!(
+        "{} created",
+        sim.get_all_people().last().unwrap().id
+    ))
+}
+
+fn take_snapshot(session: &mut Session, query: &str, _: &[u8]) -> Result<String> {
+    let Session {
+        map,
+        sim,
+        snapshots,
+        next_snapshot_id,
+        ..
+    } = session;
+    let args: SnapshotQuery = api::parse_query(query)?;
+    let id = *next_snapshot_id;
+    *next_snapshot_id += 1;
+    snapshots.insert(
+        id,
+        Snapshot {
+            sim: sim.clone(),
+            edits: map.get_edits().clone(),
+        },
+    );
+
+    // A savestate on disk only refers to the edits by name, so save them too.
+    let path = if args.save.unwrap_or(false) {
+        map.save_edits();
+        Some(sim.save())
+    } else {
+        None
+    };
+    Ok(abstutil::to_json(&SnapshotHandle {
+        id,
+        time: sim.time(),
+        path,
+    }))
+}
 
-            let mut scenario = Scenario::empty(map, "one-shot");
-            scenario.people = ExternalPerson::import(map, vec![input], false)?;
-            let mut rng = XorShiftRng::seed_from_u64(load.rng_seed);
-            scenario.instantiate(sim, map, &mut rng, &mut Timer::throwaway());
-            Ok(format!(
-                "{} created",
-                sim.get_all_people().last().unwrap().id
-            ))
+fn restore_snapshot(session: &mut Session, query: &str, _: &[u8]) -> Result<String> {
+    let Session {
+        map,
+        sim,
+        snapshots,
+        ..
+    } = session;
+    let args: RestoreQuery = api::parse_query(query)?;
+    let mut timer = Timer::new("restore snapshot");
+    let (new_sim, edits) = match (args.id, args.path) {
+        (Some(id), None) => {
+            let snapshot = snapshots
+                .get(&id)
+                .ok_or_else(|| anyhow!("no snapshot {}", id))?;
+            (snapshot.sim.clone(), snapshot.edits.clone())
         }
-        // Traffic signals
-        "/traffic-signals/get" => {
-            let i = IntersectionID(get("id")?.parse::<usize>()?);
-            if let Some(ts) = map.maybe_get_traffic_signal(i) {
-                Ok(abstutil::to_json(ts))
+        (None, Some(path)) => {
+            let new_sim = Sim::load_savestate(path, &mut timer)?;
+            let edits = if new_sim.get_edits_name() == map.get_edits().edits_name {
+                map.get_edits().clone()
             } else {
-                bail!("{} isn't a traffic signal", i)
-            }
+                MapEdits::load_from_file(
+                    map,
+                    abstio::path_edits(map.get_name(), new_sim.get_edits_name()),
+                    &mut timer,
+                )?
+            };
+            (new_sim, edits)
         }
-        "/traffic-signals/set" => {
-            let ts: ControlTrafficSignal = abstutil::from_json(body)?;
-            let id = ts.id;
+        _ => bail!("specify exactly one of id or path"),
+    };
+    if new_sim.get_map_name() != map.get_name() {
+        bail!(
+            "the snapshot is for {}, but the current map is {}",
+            new_sim.get_map_name().describe(),
+            map.get_name().describe()
+        );
+    }
 
-            // incremental_edit_traffic_signal is the cheap option, but since we may need to call
-            // get-edits later, go through the proper flow.
-            let mut edits = map.get_edits().clone();
-            edits.commands.push(EditCmd::ChangeIntersection {
-                i: id,
-                old: map.get_i_edit(id),
-                new: EditIntersection::TrafficSignal(ts.export(map)),
-            });
-            map.must_apply_edits(edits, &mut Timer::throwaway());
-            map.recalculate_pathfinding_after_edits(&mut Timer::throwaway());
+    if map.get_edits() != &edits {
+        map.must_apply_edits(edits, &mut timer);
+        map.recalculate_pathfinding_after_edits(&mut timer);
+    }
+    *sim = new_sim;
+    Ok(format!("restored to {}", sim.time()))
+}
 
-            Ok(format!("{} has been updated", id))
-        }
-        "/traffic-signals/get-delays" => {
-            let i = map.get_i(IntersectionID(get("id")?.parse::<usize>()?));
-            let t1 = Time::parse(get("t1")?)?;
-            let t2 = Time::parse(get("t2")?)?;
-            if !i.is_traffic_signal() {
-                bail!("{} isn't a traffic signal", i.id);
-            }
-            let movements: Vec<&MovementID> = i.movements.keys().collect();
+fn delete_snapshot(session: &mut Session, query: &str, _: &[u8]) -> Result<String> {
+    let id = api::parse_query::<IdQuery>(query)?.id;
+    if session.snapshots.remove(&id).is_none() {
+        bail!("no snapshot {}", id);
+    }
+    Ok(format!("snapshot {} deleted", id))
+}
 
-            let mut delays = Delays {
-                per_direction: BTreeMap::new(),
-            };
-            for m in i.movements.keys() {
-                delays.per_direction.insert(*m, Vec::new());
-            }
-            if let Some(list) = sim.get_analytics().intersection_delays.get(&i.id) {
-                for (idx, t, dt, _) in list {
-                    if *t >= t1 && *t <= t2 {
-                        delays
-                            .per_direction
-                            .get_mut(movements[*idx as usize])
-                            .unwrap()
-                            .push(*dt);
-                    }
-                }
+// Traffic signals
+
+fn get_traffic_signal(session: &mut Session, query: &str, _: &[u8]) -> Result<String> {
+    let i = IntersectionID(api::parse_query::<IdQuery>(query)?.id);
+    if let Some(ts) = session.map.maybe_get_traffic_signal(i) {
+        Ok(abstutil::to_json(ts))
+    } else {
+        bail!("{} isn't a traffic signal", i)
+    }
+}
+
+fn set_traffic_signal(session: &mut Session, _: &str, body: &[u8]) -> Result<String> {
+    let map = &mut session.map;
+    let ts: ControlTrafficSignal = abstutil::from_json(body)?;
+    let id = ts.id;
+
+    // incremental_edit_traffic_signal is the cheap option, but since we may need to call
+    // get-edits later, go through the proper flow.
+    let mut edits = map.get_edits().clone();
+    edits.commands.push(EditCmd::ChangeIntersection {
+        i: id,
+        old: map.get_i_edit(id),
+        new: EditIntersection::TrafficSignal(ts.export(map)),
+    });
+    map.must_apply_edits(edits, &mut Timer::throwaway());
+    map.recalculate_pathfinding_after_edits(&mut Timer::throwaway());
+
+    Ok(format!("{} has been updated", id))
+}
+
+fn optimize_corridor_offsets(session: &mut Session, query: &str, _: &[u8]) -> Result<String> {
+    let Session { map, sim, .. } = session;
+    let args: CorridorQuery = api::parse_query(query)?;
+    let corridor: Vec<IntersectionID> =
+        args.intersections.into_iter().map(IntersectionID).collect();
+    let result = map_model::optimize_corridor_offsets(map, &corridor, sim.time())?;
+
+    let mut edits = map.get_edits().clone();
+    edits.commands.extend(result.to_edit_cmds(map));
+    let mut timer = Timer::throwaway();
+    map.must_apply_edits(edits, &mut timer);
+    map.recalculate_pathfinding_after_edits(&mut timer);
+    sim.handle_live_edited_traffic_signals(map);
+
+    Ok(abstutil::to_json(&result))
+}
+
+fn get_delays(session: &mut Session, query: &str, _: &[u8]) -> Result<String> {
+    let Session { map, sim, .. } = session;
+    let DelaysQuery { id, t1, t2 } = api::parse_query(query)?;
+    let i = get_signal_intersection(map, id)?;
+    let movements: Vec<&MovementID> = i.movements.keys().collect();
+
+    let mut delays = Delays {
+        per_direction: BTreeMap::new(),
+    };
+    for m in i.movements.keys() {
+        delays.per_direction.insert(*m, Vec::new());
+    }
+    if let Some(list) = sim.get_analytics().intersection_delays.get(&i.id) {
+        for (idx, t, dt, _) in list {
+            if *t >= t1 && *t <= t2 {
+                delays
+                    .per_direction
+                    .get_mut(movements[*idx as usize])
+                    .unwrap()
+                    .push(*dt);
             }
-            Ok(abstutil::to_json(&delays))
         }
-        "/traffic-signals/get-cumulative-thruput" => {
-            let i = map.get_i(IntersectionID(get("id")?.parse::<usize>()?));
-            if !i.is_traffic_signal() {
-                bail!("{} isn't a traffic signal", i.id);
-            }
+    }
+    Ok(abstutil::to_json(&delays))
+}
 
-            let mut thruput = Throughput {
-                per_direction: BTreeMap::new(),
-            };
-            for (idx, m) in i.movements.keys().enumerate() {
-                thruput.per_direction.insert(
-                    *m,
-                    sim.get_analytics()
-                        .traffic_signal_thruput
-                        .total_for(CompressedMovementID {
-                            i: i.id,
-                            idx: u8::try_from(idx).unwrap(),
-                        }),
-                );
-            }
-            Ok(abstutil::to_json(&thruput))
+fn get_cumulative_thruput(session: &mut Session, query: &str, _: &[u8]) -> Result<String> {
+    let Session { map, sim, .. } = session;
+    let i = get_signal_intersection(map, api::parse_query::<IdQuery>(query)?.id)?;
+
+    let mut thruput = Throughput {
+        per_direction: BTreeMap::new(),
+    };
+    for (idx, m) in i.movements.keys().enumerate() {
+        thruput.per_direction.insert(
+            *m,
+            sim.get_analytics()
+                .traffic_signal_thruput
+                .total_for(CompressedMovementID {
+                    i: i.id,
+                    idx: u8::try_from(idx).unwrap(),
+                }),
+        );
+    }
+    Ok(abstutil::to_json(&thruput))
+}
+
+fn get_transit_priority(session: &mut Session, query: &str, _: &[u8]) -> Result<String> {
+    let Session { map, sim, .. } = session;
+    let i = get_signal_intersection(map, api::parse_query::<IdQuery>(query)?.id)?;
+    let mut requests = Vec::new();
+    if let Some(list) = sim.get_analytics().transit_signal_priority.get(&i.id) {
+        for (time, vehicle, transit_delay_saved, cross_street_delay_added) in list {
+            requests.push(TransitPriorityRequest {
+                time: *time,
+                vehicle: *vehicle,
+                transit_delay_saved: *transit_delay_saved,
+                cross_street_delay_added: *cross_street_delay_added,
+            });
         }
-        "/traffic-signals/get-all-current-state" => {
-            let mut all_state = BTreeMap::new();
-            for i in map.all_intersections() {
-                if !i.is_traffic_signal() {
-                    continue;
-                }
-                let (current_stage_idx, remaining_time) =
-                    sim.current_stage_and_remaining_time(i.id);
-                all_state.insert(
-                    i.id,
-                    TrafficSignalState {
-                        current_stage_idx,
-                        remaining_time,
-                        accepted: sim
-                            .get_accepted_agents(i.id)
-                            .into_iter()
-                            .map(|(a, _)| a)
-                            .collect(),
-                        waiting: sim.get_waiting_agents(i.id),
-                    },
-                );
-            }
-            Ok(abstutil::to_json(&all_state))
+    }
+    Ok(abstutil::to_json(&requests))
+}
+
+fn get_all_traffic_signal_state(session: &mut Session, _: &str, _: &[u8]) -> Result<String> {
+    let Session { map, sim, .. } = session;
+    let mut all_state = BTreeMap::new();
+    for i in map.all_intersections() {
+        if !i.is_traffic_signal() {
+            continue;
         }
-        // Querying data
-        "/data/get-finished-trips" => {
-            let mut trips = Vec::new();
-            for (_, id, mode, maybe_duration) in &sim.get_analytics().finished_trips {
-                let distance_crossed = if maybe_duration.is_some() {
-                    sim.finished_trip_details(*id).unwrap().2
-                } else {
-                    Distance::ZERO
-                };
-                trips.push(FinishedTrip {
-                    id: *id,
-                    person: sim.trip_to_person(*id).unwrap(),
-                    duration: *maybe_duration,
-                    distance_crossed,
-                    mode: *mode,
-                });
-            }
-            Ok(abstutil::to_json(&trips))
+        let (current_plan_idx, current_stage_idx, remaining_time) =
+            sim.current_plan_stage_and_remaining_time(i.id);
+        all_state.insert(
+            i.id,
+            TrafficSignalState {
+                current_plan_idx,
+                current_stage_idx,
+                remaining_time,
+                accepted: sim
+                    .get_accepted_agents(i.id)
+                    .into_iter()
+                    .map(|(a, _)| a)
+                    .collect(),
+                waiting: sim.get_waiting_agents(i.id),
+            },
+        );
+    }
+    Ok(abstutil::to_json(&all_state))
+}
+
+fn step_traffic_signals(session: &mut Session, query: &str, body: &[u8]) -> Result<String> {
+    let Session { map, sim, .. } = session;
+    let args: StepQuery = api::parse_query(query)?;
+    let actions: Vec<SignalAction> = if body.is_empty() {
+        Vec::new()
+    } else {
+        abstutil::from_json(body)?
+    };
+
+    // Validate everything before changing anything
+    for action in &actions {
+        let i = IntersectionID(action.id);
+        let ts = map
+            .maybe_get_traffic_signal(i)
+            .ok_or_else(|| anyhow!("{} isn't a traffic signal", i))?;
+        let (plan, _, _) = sim.current_plan_stage_and_remaining_time(i);
+        let num_stages = ts.get_stages(plan).len();
+        if action.stage >= num_stages {
+            bail!("{} only has {} stages in its current plan", i, num_stages);
         }
-        "/data/get-agent-positions" => Ok(abstutil::to_json(&AgentPositions {
-            agents: sim
-                .get_unzoomed_agents(map)
-                .into_iter()
-                .chain(sim.get_unzoomed_transit_riders(map))
-                .map(|a| AgentPosition {
-                    id: a.id,
-                    trip: sim.agent_to_trip(a.id),
-                    person: a.person,
-                    vehicle_type: a.id.to_vehicle_type(),
-                    pos: a.pos.to_gps(map.get_gps_bounds()),
-                    distance_crossed: sim.agent_properties(map, a.id).dist_crossed,
-                })
-                .collect(),
-        })),
-        "/data/get-road-thruput" => Ok(abstutil::to_json(&RoadThroughput {
-            counts: sim
-                .get_analytics()
-                .road_thruput
-                .counts
-                .iter()
-                .map(|((r, a, hr), cnt)| (*r, *a, *hr, *cnt))
-                .collect(),
-        })),
-        "/data/get-blocked-by-graph" => Ok(abstutil::to_json(&BlockedByGraph {
-            blocked_by: sim
-                .get_blocked_by_graph(map)
-                .into_iter()
-                .map(|(id, (delay, cause))| {
-                    (
-                        id,
-                        (delay, cause, sim.agent_to_trip(id), sim.agent_to_person(id)),
-                    )
-                })
-                .collect(),
-        })),
-        "/data/trip-time-lower-bound" => {
-            let id = TripID(get("id")?.parse::<usize>()?);
-            let duration = sim.get_trip_time_lower_bound(map, id)?;
-            Ok(duration.inner_seconds().to_string())
+    }
+    if let Some(dt) = args.dt {
+        if dt <= Duration::ZERO {
+            bail!("dt must be positive");
         }
-        "/data/all-trip-time-lower-bounds" => {
-            let results: BTreeMap<TripID, Duration> = Timer::throwaway()
-                .parallelize(
-                    "calculate all trip time lower bounds",
-                    sim.all_trip_info(),
-                    |(id, _)| {
-                        sim.get_trip_time_lower_bound(map, id)
-                            .ok()
-                            .map(|dt| (id, dt))
-                    },
-                )
-                .into_iter()
-                .flatten()
-                .collect();
-            Ok(abstutil::to_json(&results))
+    }
+
+    for action in actions {
+        sim.force_traffic_signal_stage(map, IntersectionID(action.id), action.stage)?;
+    }
+    if let Some(dt) = args.dt {
+        sim.timed_step(map, dt, &mut None, &mut Timer::throwaway());
+    }
+
+    let mut signals = BTreeMap::new();
+    for i in map.all_intersections() {
+        if !i.is_traffic_signal()
+            || (!args.intersections.is_empty() && !args.intersections.contains(&i.id.0))
+        {
+            continue;
         }
-        // Controlling the map
-        "/map/get-edits" => {
+        let (current_plan_idx, current_stage_idx, remaining_time) =
+            sim.current_plan_stage_and_remaining_time(i.id);
+        let demand = &sim.get_analytics().demand;
+        signals.insert(
+            i.id,
+            SignalObservation {
+                current_plan_idx,
+                current_stage_idx,
+                remaining_time,
+                queue_lengths: sim.get_queue_lengths_per_movement(map, i.id),
+                demand: i
+                    .movements
+                    .keys()
+                    .map(|m| (*m, demand.get(m).cloned().unwrap_or(0)))
+                    .collect(),
+                waiting: sim.get_waiting_agents(i.id),
+            },
+        );
+    }
+    Ok(abstutil::to_json(&SignalObservations {
+        time: sim.time(),
+        signals,
+    }))
+}
+
+// Querying data
+
+fn get_finished_trips(session: &mut Session, _: &str, _: &[u8]) -> Result<String> {
+    let sim = &session.sim;
+    let mut trips = Vec::new();
+    for (_, id, mode, maybe_duration) in &sim.get_analytics().finished_trips {
+        let distance_crossed = if maybe_duration.is_some() {
+            sim.finished_trip_details(*id).unwrap().2
+        } else {
+            Distance::ZERO
+        };
+        trips.push(FinishedTrip {
+            id: *id,
+            person: sim.trip_to_person(*id).unwrap(),
+            duration: *maybe_duration,
+            distance_crossed,
+            mode: *mode,
+        });
+    }
+    Ok(abstutil::to_json(&trips))
+}
+
+fn get_agent_positions(session: &mut Session, _: &str, _: &[u8]) -> Result<String> {
+    let Session { map, sim, .. } = session;
+    Ok(abstutil::to_json(&AgentPositions {
+        agents: sim
+            .get_unzoomed_agents(map)
+            .into_iter()
+            .chain(sim.get_unzoomed_transit_riders(map))
+            .map(|a| AgentPosition {
+                id: a.id,
+                trip: sim.agent_to_trip(a.id),
+                person: a.person,
+                vehicle_type: a.id.to_vehicle_type(),
+                pos: a.pos.to_gps(map.get_gps_bounds()),
+                distance_crossed: sim.agent_properties(map, a.id).dist_crossed,
+            })
+            .collect(),
+    }))
+}
+
+fn get_road_thruput(session: &mut Session, _: &str, _: &[u8]) -> Result<String> {
+    Ok(abstutil::to_json(&RoadThroughput {
+        counts: session
+            .sim
+            .get_analytics()
+            .road_thruput
+            .counts
+            .iter()
+            .map(|((r, a, hr), cnt)| (*r, *a, *hr, *cnt))
+            .collect(),
+    }))
+}
+
+fn get_emissions(session: &mut Session, _: &str, _: &[u8]) -> Result<String> {
+    let sim = &session.sim;
+    let analytics = sim.get_analytics();
+    Ok(abstutil::to_json(&EmissionsSummary {
+        total: analytics.total_emissions(sim.time()),
+        roads: analytics
+            .road_emissions
+            .iter()
+            .map(|((r, hr), e)| (*r, *hr, *e))
+            .collect(),
+        intersections: analytics
+            .intersection_emissions
+            .iter()
+            .map(|((i, hr), e)| (*i, *hr, *e))
+            .collect(),
+    }))
+}
+
+fn get_blocked_by_graph(session: &mut Session, _: &str, _: &[u8]) -> Result<String> {
+    let Session { map, sim, .. } = session;
+    Ok(abstutil::to_json(&BlockedByGraph {
+        blocked_by: sim
+            .get_blocked_by_graph(map)
+            .into_iter()
+            .map(|(id, (delay, cause))| {
+                (
+                    id,
+                    (delay, cause, sim.agent_to_trip(id), sim.agent_to_person(id)),
+                )
+            })
+            .collect(),
+    }))
+}
+
+fn get_trip_time_lower_bound(session: &mut Session, query: &str, _: &[u8]) -> Result<String> {
+    let id = TripID(api::parse_query::<IdQuery>(query)?.id);
+    let duration = session.sim.get_trip_time_lower_bound(&session.map, id)?;
+    Ok(duration.inner_seconds().to_string())
+}
+
+fn get_all_trip_time_lower_bounds(session: &mut Session, _: &str, _: &[u8]) -> Result<String> {
+    let Session { map, sim, .. } = session;
+    let results: BTreeMap<TripID, Duration> = Timer::throwaway()
+        .parallelize(
+            "calculate all trip time lower bounds",
+            sim.all_trip_info(),
+            |(id, _)| {
+                sim.get_trip_time_lower_bound(map, id)
+                    .ok()
+                    .map(|dt| (id, dt))
+            },
+        )
+        .into_iter()
+        .flatten()
+        .collect();
+    Ok(abstutil::to_json(&results))
+}
+
+// Controlling the map
+
+fn get_edits(session: &mut Session, _: &str, _: &[u8]) -> Result<String> {
+    let map = &session.map;
+    let mut edits = map.get_edits().clone();
+    edits.commands.clear();
+    edits.compress(map);
+    Ok(abstutil::to_json(&edits.to_permanent(map)))
+}
+
+fn get_edit_road_command(session: &mut Session, query: &str, _: &[u8]) -> Result<String> {
+    let map = &session.map;
+    let r = RoadID(api::parse_query::<IdQuery>(query)?.id);
+    if map.maybe_get_r(r).is_none() {
+        bail!("no road {}", r);
+    }
+    Ok(abstutil::to_json(
+        &map.edit_road_cmd(r, |_| {}).to_perma(map),
+    ))
+}
+
+fn apply_edits(session: &mut Session, _: &str, body: &[u8]) -> Result<String> {
+    let Session { map, sim, .. } = session;
+    let edits = match abstutil::from_json::<LiveEdits>(body)? {
+        LiveEdits::Full(perma) => perma.into_edits(map)?,
+        LiveEdits::Commands(cmds) => {
             let mut edits = map.get_edits().clone();
-            edits.commands.clear();
-            edits.compress(map);
-            Ok(abstutil::to_json(&edits.to_permanent(map)))
-        }
-        "/map/get-edit-road-command" => {
-            let r = RoadID(get("id")?.parse::<usize>()?);
-            Ok(abstutil::to_json(
-                &map.edit_road_cmd(r, |_| {}).to_perma(map),
-            ))
-        }
-        "/map/get-intersection-geometry" => {
-            let i = IntersectionID(get("id")?.parse::<usize>()?);
-            Ok(abstutil::to_json(&export_geometry(map, i)))
+            for cmd in cmds {
+                edits.commands.push(cmd.into_cmd(map)?);
+            }
+            edits
         }
-        "/map/get-all-geometry" => Ok(abstutil::to_json(&export_all_geometry(map))),
-        _ => Err(anyhow!("Unknown command")),
+    };
+    let mut timer = Timer::new("apply live edits");
+    let effects = map.must_apply_edits(edits, &mut timer);
+    map.recalculate_pathfinding_after_edits(&mut timer);
+    sim.handle_live_edited_traffic_signals(map);
+    let (cancelled_trips, parked_cars_displaced) = sim.handle_live_edits(map, &mut timer);
+
+    Ok(abstutil::to_json(&LiveEditResults {
+        changed_roads: effects.changed_roads,
+        deleted_lanes: effects.deleted_lanes,
+        changed_intersections: effects.changed_intersections,
+        added_turns: effects.added_turns,
+        deleted_turns: effects.deleted_turns,
+        changed_parking_lots: effects.changed_parking_lots,
+        cancelled_trips,
+        parked_cars_displaced,
+    }))
+}
+
+fn get_intersection_geometry(session: &mut Session, query: &str, _: &[u8]) -> Result<String> {
+    let i = IntersectionID(api::parse_query::<IdQuery>(query)?.id);
+    if session.map.maybe_get_i(i).is_none() {
+        bail!("no intersection {}", i);
     }
+    Ok(abstutil::to_json(&export_geometry(&session.map, i)))
+}
+
+fn get_all_geometry(session: &mut Session, _: &str, _: &[u8]) -> Result<String> {
+    Ok(abstutil::to_json(&export_all_geometry(&session.map)))
 }
 
-// TODO I think specifying the API with protobufs or similar will be a better idea.
+#[derive(Deserialize)]
+struct SnapshotQuery {
+    save: Option<bool>,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct SnapshotHandle {
+    /// Pass to /sim/restore
+    id: usize,
+    time: Time,
+    /// If the snapshot was also saved to disk, this is the path of the savestate
+    path: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct RestoreQuery {
+    id: Option<usize>,
+    path: Option<String>,
+}
 
-#[derive(Serialize)]
+#[derive(Deserialize)]
+struct TimeQuery {
+    #[serde(deserialize_with = "api::deserialize_time")]
+    t: Time,
+}
+
+#[derive(Deserialize)]
+struct DelaysQuery {
+    id: usize,
+    #[serde(deserialize_with = "api::deserialize_time")]
+    t1: Time,
+    #[serde(deserialize_with = "api::deserialize_time")]
+    t2: Time,
+}
+
+#[derive(Serialize, JsonSchema)]
 struct FinishedTrip {
     id: TripID,
     person: PersonID,
@@ -402,24 +885,34 @@ struct FinishedTrip {
     mode: TripMode,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, JsonSchema)]
 struct Delays {
     #[serde(serialize_with = "serialize_btreemap")]
+    #[schemars(with = "Vec<(MovementID, Vec<Duration>)>")]
     per_direction: BTreeMap<MovementID, Vec<Duration>>,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, JsonSchema)]
 struct Throughput {
     #[serde(serialize_with = "serialize_btreemap")]
+    #[schemars(with = "Vec<(MovementID, usize)>")]
     per_direction: BTreeMap<MovementID, usize>,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, JsonSchema)]
+struct TransitPriorityRequest {
+    time: Time,
+    vehicle: CarID,
+    transit_delay_saved: Duration,
+    cross_street_delay_added: Duration,
+}
+
+#[derive(Serialize, JsonSchema)]
 struct AgentPositions {
     agents: Vec<AgentPosition>,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, JsonSchema)]
 struct AgentPosition {
     /// The agent's ID
     id: AgentID,
@@ -445,14 +938,25 @@ struct AgentPosition {
     distance_crossed: Distance,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, JsonSchema)]
 struct RoadThroughput {
     // (road, agent type, hour since midnight, throughput for that one hour period)
     counts: Vec<(RoadID, AgentType, usize, usize)>,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, JsonSchema)]
+struct EmissionsSummary {
+    /// In grams
+    total: Emissions,
+    // (road, hour since midnight, emissions for that one hour period)
+    roads: Vec<(RoadID, usize, Emissions)>,
+    intersections: Vec<(IntersectionID, usize, Emissions)>,
+}
+
+#[derive(Serialize, JsonSchema)]
 struct TrafficSignalState {
+    /// Zero-based index into the signal's time-of-day plans
+    current_plan_idx: usize,
     current_stage_idx: usize,
     remaining_time: Duration,
     accepted: BTreeSet<AgentID>,
@@ -460,34 +964,107 @@ struct TrafficSignalState {
     waiting: Vec<(AgentID, TurnID, Time)>,
 }
 
-#[derive(Serialize)]
+#[derive(Deserialize)]
+struct CorridorQuery {
+    #[serde(deserialize_with = "api::deserialize_ids")]
+    intersections: Vec<usize>,
+}
+
+#[derive(Deserialize)]
+struct StepQuery {
+    #[serde(default, deserialize_with = "api::deserialize_opt_duration")]
+    dt: Option<Duration>,
+    #[serde(default, deserialize_with = "api::deserialize_ids")]
+    intersections: Vec<usize>,
+}
+
+/// Immediately switch a traffic signal to a stage
+#[derive(Deserialize, JsonSchema)]
+struct SignalAction {
+    /// The intersection ID
+    id: usize,
+    /// Zero-based index into the stages of the signal's current plan
+    stage: usize,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct SignalObservations {
+    time: Time,
+    #[serde(serialize_with = "serialize_btreemap")]
+    #[schemars(with = "Vec<(IntersectionID, SignalObservation)>")]
+    signals: BTreeMap<IntersectionID, SignalObservation>,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct SignalObservation {
+    current_plan_idx: usize,
+    current_stage_idx: usize,
+    remaining_time: Duration,
+    /// Vehicles queued on incoming lanes for each movement, plus pedestrians waiting to cross
+    #[serde(serialize_with = "serialize_btreemap")]
+    #[schemars(with = "Vec<(MovementID, usize)>")]
+    queue_lengths: BTreeMap<MovementID, usize>,
+    /// How many agents on the map still have each movement somewhere ahead in their path
+    #[serde(serialize_with = "serialize_btreemap")]
+    #[schemars(with = "Vec<(MovementID, usize)>")]
+    demand: BTreeMap<MovementID, usize>,
+    // Some agent has been waiting to start a turn since some time
+    waiting: Vec<(AgentID, TurnID, Time)>,
+}
+
+#[derive(Serialize, JsonSchema)]
 struct BlockedByGraph {
     /// Each entry indicates that some agent has been stuck in one place for some amount of time,
     /// due to being blocked by another agent or because they're waiting at an intersection. Unless
     /// the agent is a bus, then the TripID and PersonID will also be filled out.
     #[serde(serialize_with = "serialize_btreemap")]
+    #[schemars(with = "Vec<(AgentID, (Duration, DelayCause, Option<TripID>, Option<PersonID>))>")]
     blocked_by: BTreeMap<AgentID, (Duration, DelayCause, Option<TripID>, Option<PersonID>)>,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, JsonSchema)]
+#[serde(untagged)]
+enum LiveEdits {
+    /// Replace all of the current edits
+    Full(PermanentMapEdits),
+    /// Add these commands to the current edits
+    Commands(Vec<PermanentEditCmd>),
+}
+
+#[derive(Serialize, JsonSchema)]
+struct LiveEditResults {
+    changed_roads: BTreeSet<RoadID>,
+    deleted_lanes: BTreeSet<LaneID>,
+    changed_intersections: BTreeSet<IntersectionID>,
+    added_turns: BTreeSet<TurnID>,
+    deleted_turns: BTreeSet<TurnID>,
+    changed_parking_lots: BTreeSet<ParkingLotID>,
+    /// Trips that were interrupted by the edits
+    cancelled_trips: BTreeSet<TripID>,
+    parked_cars_displaced: usize,
+}
+
+#[derive(Clone, Deserialize, JsonSchema)]
 struct LoadSim {
     scenario: String,
     modifiers: Vec<ScenarioModifier>,
     edits: Option<PermanentMapEdits>,
     // These are fixed from the initial command line flags
     #[serde(skip_deserializing)]
+    #[schemars(skip)]
     rng_seed: u64,
     #[serde(skip_deserializing)]
+    #[schemars(skip)]
     opts: SimOptions,
 }
 
 impl LoadSim {
-    fn setup(&self, timer: &mut Timer) -> (Map, Sim) {
+    fn setup(&self, timer: &mut Timer) -> Result<(Map, Sim)> {
         let mut scenario: Scenario = abstio::must_read_object(self.scenario.clone(), timer);
 
         let mut map = Map::load_synchronously(scenario.map_name.path(), timer);
         if let Some(perma) = self.edits.clone() {
-            let edits = perma.into_edits(&map).unwrap();
+            let edits = perma.into_edits(&map)?;
             map.must_apply_edits(edits, timer);
             map.recalculate_pathfinding_after_edits(timer);
         }
@@ -495,12 +1072,13 @@ impl LoadSim {
         for m in &self.modifiers {
             scenario = m.apply(&map, scenario);
         }
+        scenario.validate()?;
 
         let mut rng = XorShiftRng::seed_from_u64(self.rng_seed);
         let mut sim = Sim::new(&map, self.opts.clone());
         scenario.instantiate(&mut sim, &map, &mut rng, timer);
 
-        (map, sim)
+        Ok((map, sim))
     }
 }
 
@@ -29,6 +29,10 @@ pub struct GenericCityImporter {
     pub include_railroads: bool,
     /// If provided, read polygons from this GeoJSON file and add them to the RawMap as buildings.
     pub extra_buildings: Option<String>,
+    /// If provided, import real transit schedules from this GTFS feed. Like `osm_url`, this can be
+    /// a URL to a .zip file or a local path to one.
+    #[serde(default)]
+    pub gtfs_url: Option<String>,
 }
 
 impl GenericCityImporter {
@@ -85,6 +89,11 @@ impl GenericCityImporter {
             timer,
         );
         map.save();
+
+        if let Some(ref url) = self.gtfs_url {
+            crate::gtfs::fetch(config, &name.city, url).await;
+        }
+
         map
     }
 }
@@ -0,0 +1,410 @@
+//! Import real transit timetables from GTFS (https://developers.google.com/transit/gtfs/reference).
+//! Transit routes and stops still come from OSM relations. This just matches GTFS stops to the
+//! map's stops, matches GTFS trips to routes, and replaces each route's synthetic schedule.
+//!
+//! Two simplifications:
+//!
+//! - The map stores one schedule offset per stop for a whole route, so every trip on a route gets
+//!   the median time from the start of the trip to each stop. Trips that run slower at rush hour
+//!   lose that variation.
+//! - shapes.txt isn't read. A trip's `shape_id` is only compared to the GTFS marker that OSM route
+//!   relations sometimes carry; the route geometry always comes from OSM.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use std::process::Command;
+
+use anyhow::Result;
+use serde::Deserialize;
+
+use abstio::CityName;
+use abstutil::{must_run_cmd, Timer};
+use geom::{Distance, Duration, FindClosest, LonLat, Time};
+use map_model::{BusRouteID, BusStopID, Map};
+
+use crate::configuration::ImporterConfiguration;
+use crate::utils::download;
+
+/// GTFS stops farther than this from every stop in the map are ignored.
+const MAX_DIST_TO_STOP: Distance = Distance::const_meters(50.0);
+
+/// Where the unzipped GTFS feed for a city lives
+pub fn input_dir(city: &CityName) -> String {
+    city.input_path("gtfs/")
+}
+
+/// Download a GTFS zip file, or use a local one, and unzip it into the city's input directory.
+pub async fn fetch(config: &ImporterConfiguration, city: &CityName, url_or_path: &str) {
+    let output = input_dir(city);
+    if url_or_path.starts_with("http") {
+        download(config, output, url_or_path).await;
+    } else if Path::new(&output).exists() {
+        println!("- {} already exists", output);
+    } else {
+        println!("- Unzipping {} into {}", url_or_path, output);
+        std::fs::create_dir_all(&output).expect("Creating GTFS dir failed");
+        must_run_cmd(
+            Command::new(&config.unzip)
+                .arg(url_or_path)
+                .arg("-d")
+                .arg(&output),
+        );
+    }
+}
+
+/// Replace the schedule of every route that matches some GTFS trips with the departure times and
+/// per-stop times from the feed. Only trips running on a typical weekday (Wednesday) are used.
+pub fn add_schedules(map: &mut Map, dir: &str, timer: &mut Timer) -> Result<()> {
+    timer.start("read GTFS");
+    let stops = match_stops(map, dir)?;
+    let calendar_path = format!("{}/calendar.txt", dir);
+    let services = if Path::new(&calendar_path).exists() {
+        Some(read_weekday_services(File::open(calendar_path)?)?)
+    } else {
+        None
+    };
+    let trips = read_trips(File::open(format!("{}/trips.txt", dir))?, &services)?;
+    let stop_times = read_stop_times(
+        File::open(format!("{}/stop_times.txt", dir))?,
+        &stops,
+        &trips,
+    )?;
+    timer.stop("read GTFS");
+
+    // Many trips follow the same sequence of stops, so only match each sequence to a route once.
+    let mut pattern_to_route: HashMap<Vec<BusStopID>, Option<(BusRouteID, Vec<usize>)>> =
+        HashMap::new();
+    // Per route, every trip's time at each stop
+    let mut route_to_trips: BTreeMap<BusRouteID, Vec<Vec<Time>>> = BTreeMap::new();
+    timer.start_iter("match GTFS trips to routes", stop_times.len());
+    for (trip_id, times) in stop_times {
+        timer.next();
+        let pattern: Vec<BusStopID> = times.iter().map(|(stop, _)| *stop).collect();
+        let matched = pattern_to_route
+            .entry(pattern.clone())
+            .or_insert_with(|| match_route(map, trips[&trip_id].shape_id.as_ref(), &pattern));
+        if let Some((route, indices)) = matched {
+            route_to_trips
+                .entry(*route)
+                .or_default()
+                .push(indices.iter().map(|idx| times[*idx].1).collect());
+        }
+    }
+
+    let mut num_routes = 0;
+    for (route, trips) in route_to_trips {
+        num_routes += 1;
+        let mut departures = Vec::new();
+        let mut offsets_per_stop = vec![Vec::new(); map.get_br(route).stops.len()];
+        for times in trips {
+            for (idx, t) in times.iter().enumerate() {
+                offsets_per_stop[idx].push(*t - times[0]);
+            }
+            // The simulation only covers one day, so wrap trips starting after midnight
+            let mut departure = times[0];
+            if departure >= Time::START_OF_DAY + Duration::hours(24) {
+                departure = departure - Duration::hours(24);
+            }
+            departures.push(departure);
+        }
+        departures.sort();
+        departures.dedup();
+
+        // Trips run slower at rush hour, but the map just stores one typical offset per stop.
+        // Pick the median, so a few unusual trips don't skew it.
+        let offsets = offsets_per_stop
+            .into_iter()
+            .map(|mut offsets| {
+                offsets.sort();
+                offsets[offsets.len() / 2]
+            })
+            .collect();
+        map.hack_override_timetable(route, departures, offsets);
+    }
+    info!(
+        "Imported GTFS schedules for {} of {} routes",
+        num_routes,
+        map.all_bus_routes().len()
+    );
+    map.save();
+    Ok(())
+}
+
+/// Map each GTFS stop_id to the closest stop in the map, if there is one.
+fn match_stops(map: &Map, dir: &str) -> Result<HashMap<String, BusStopID>> {
+    let mut closest: FindClosest<BusStopID> = FindClosest::new(map.get_bounds());
+    for stop in map.all_bus_stops().values() {
+        closest.add(
+            stop.id,
+            &[stop.sidewalk_pos.pt(map), stop.driving_pos.pt(map)],
+        );
+    }
+
+    let mut stops = HashMap::new();
+    for rec in csv::Reader::from_reader(File::open(format!("{}/stops.txt", dir))?).deserialize() {
+        let rec: StopRecord = rec?;
+        let gps = LonLat::new(rec.stop_lon, rec.stop_lat);
+        if !map.get_gps_bounds().contains(gps) {
+            continue;
+        }
+        if let Some((id, _)) = closest.closest_pt(gps.to_pt(map.get_gps_bounds()), MAX_DIST_TO_STOP)
+        {
+            stops.insert(rec.stop_id, id);
+        }
+    }
+    Ok(stops)
+}
+
+/// Returns the services running on Wednesdays, from calendar.txt.
+fn read_weekday_services<R: Read>(calendar: R) -> Result<HashSet<String>> {
+    let mut services = HashSet::new();
+    for rec in csv::Reader::from_reader(calendar).deserialize() {
+        let rec: CalendarRecord = rec?;
+        if rec.wednesday == 1 {
+            services.insert(rec.service_id);
+        }
+    }
+    Ok(services)
+}
+
+/// Read trips.txt, keeping trips that run on one of the services. If the feed has no calendar,
+/// all trips are used.
+fn read_trips<R: Read>(
+    trips_txt: R,
+    services: &Option<HashSet<String>>,
+) -> Result<HashMap<String, TripRecord>> {
+    let mut trips = HashMap::new();
+    for rec in csv::Reader::from_reader(trips_txt).deserialize() {
+        let rec: TripRecord = rec?;
+        if services
+            .as_ref()
+            .map(|s| s.contains(&rec.service_id))
+            .unwrap_or(true)
+        {
+            trips.insert(rec.trip_id.clone(), rec);
+        }
+    }
+    Ok(trips)
+}
+
+/// For every trip, the matched stops it visits in order, and when, from stop_times.txt. Stops
+/// without a scheduled time are skipped. Times past midnight (like 25:10:00) are kept as-is.
+fn read_stop_times<R: Read>(
+    stop_times_txt: R,
+    stops: &HashMap<String, BusStopID>,
+    trips: &HashMap<String, TripRecord>,
+) -> Result<BTreeMap<String, Vec<(BusStopID, Time)>>> {
+    let mut per_trip: BTreeMap<String, Vec<(usize, BusStopID, Time)>> = BTreeMap::new();
+    for rec in csv::Reader::from_reader(stop_times_txt).deserialize() {
+        let rec: StopTimeRecord = rec?;
+        if !trips.contains_key(&rec.trip_id) || rec.arrival_time.is_empty() {
+            continue;
+        }
+        if let Some(stop) = stops.get(&rec.stop_id) {
+            per_trip.entry(rec.trip_id).or_default().push((
+                rec.stop_sequence,
+                *stop,
+                Time::parse(&rec.arrival_time)?,
+            ));
+        }
+    }
+
+    Ok(per_trip
+        .into_iter()
+        .map(|(trip, mut times)| {
+            times.sort_by_key(|(seq, _, _)| *seq);
+            (
+                trip,
+                times
+                    .into_iter()
+                    .map(|(_, stop, time)| (stop, time))
+                    .collect(),
+            )
+        })
+        .collect())
+}
+
+/// Find the route that visits every one of its stops in order somewhere along a GTFS trip's
+/// stops. If the trip's shape matches a route's GTFS marker from OSM, only that route is
+/// considered. Otherwise the route with the most stops wins. Returns the route and the index into
+/// the trip's stops for each of the route's stops.
+fn match_route(
+    map: &Map,
+    shape_id: Option<&String>,
+    pattern: &[BusStopID],
+) -> Option<(BusRouteID, Vec<usize>)> {
+    let marked_route = shape_id.and_then(|shape| {
+        map.all_bus_routes().iter().find(|r| {
+            r.gtfs_trip_marker
+                .as_ref()
+                .map(|m| m.split(':').next().unwrap() == shape)
+                .unwrap_or(false)
+        })
+    });
+    let candidates = match marked_route {
+        Some(r) => vec![r],
+        None => map.all_bus_routes().iter().collect(),
+    };
+
+    let mut best: Option<(BusRouteID, Vec<usize>)> = None;
+    for route in candidates {
+        if let Some(indices) = find_subsequence(&route.stops, pattern) {
+            if best
+                .as_ref()
+                .map(|(_, x)| indices.len() > x.len())
+                .unwrap_or(true)
+            {
+                best = Some((route.id, indices));
+            }
+        }
+    }
+    best
+}
+
+/// If every stop along a route appears in order in a trip, return the index into the trip for each
+/// one.
+fn find_subsequence<T: PartialEq>(route: &[T], trip: &[T]) -> Option<Vec<usize>> {
+    let mut indices = Vec::new();
+    let mut next = 0;
+    for stop in route {
+        let idx = next + trip[next..].iter().position(|s| s == stop)?;
+        indices.push(idx);
+        next = idx + 1;
+    }
+    Some(indices)
+}
+
+#[derive(Debug, Deserialize)]
+struct StopRecord {
+    stop_id: String,
+    stop_lat: f64,
+    stop_lon: f64,
+}
+
+#[derive(Debug, Deserialize)]
+struct CalendarRecord {
+    service_id: String,
+    wednesday: usize,
+}
+
+#[derive(Debug, Deserialize)]
+struct TripRecord {
+    service_id: String,
+    trip_id: String,
+    shape_id: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct StopTimeRecord {
+    trip_id: String,
+    arrival_time: String,
+    stop_id: String,
+    stop_sequence: usize,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_subsequence() {
+        // The trip makes extra stops before, between, and after the route's
+        assert_eq!(
+            find_subsequence(&[1, 3], &[0, 1, 2, 3, 4]),
+            Some(vec![1, 3])
+        );
+        // A stop visited twice matches the first visit after the previous stop
+        assert_eq!(find_subsequence(&[1, 2], &[2, 1, 2]), Some(vec![1, 2]));
+        // Out of order
+        assert_eq!(find_subsequence(&[3, 1], &[1, 2, 3]), None);
+        // The trip skips one of the route's stops
+        assert_eq!(find_subsequence(&[1, 4], &[1, 2, 3]), None);
+        assert_eq!(find_subsequence(&[], &[0]), Some(Vec::new()));
+    }
+
+    #[test]
+    fn test_read_trips() {
+        let calendar = "service_id,monday,tuesday,wednesday,thursday,friday,saturday,sunday,start_date,end_date
+weekday,1,1,1,1,1,0,0,20200101,20201231
+weekend,0,0,0,0,0,1,1,20200101,20201231
+";
+        let services = read_weekday_services(calendar.as_bytes()).unwrap();
+        assert_eq!(services, vec!["weekday".to_string()].into_iter().collect());
+
+        // shape_id is optional
+        let trips_txt = "route_id,service_id,trip_id,shape_id
+r1,weekday,t1,s1
+r1,weekend,t2,s1
+r2,weekday,t3,
+";
+        let trips = read_trips(trips_txt.as_bytes(), &Some(services)).unwrap();
+        assert_eq!(trips.len(), 2);
+        assert_eq!(trips["t1"].shape_id, Some("s1".to_string()));
+        assert_eq!(trips["t3"].shape_id, None);
+
+        // Without a calendar, keep everything
+        let trips = read_trips(trips_txt.as_bytes(), &None).unwrap();
+        assert_eq!(trips.len(), 3);
+    }
+
+    #[test]
+    fn test_read_stop_times() {
+        let stops: HashMap<String, BusStopID> = vec![
+            ("a".to_string(), stop(0)),
+            ("b".to_string(), stop(1)),
+            ("c".to_string(), stop(2)),
+        ]
+        .into_iter()
+        .collect();
+        let trips = read_trips(
+            "service_id,trip_id\nweekday,t1\nweekday,t2\n".as_bytes(),
+            &None,
+        )
+        .unwrap();
+        // Rows out of order, a stop outside the map, a stop without a time, a trip that isn't
+        // running, and times past midnight
+        let stop_times_txt = "trip_id,arrival_time,departure_time,stop_id,stop_sequence
+t1,08:05:30,08:05:30,b,2
+t1,08:00:00,08:00:00,a,1
+t1,08:07:00,08:07:00,elsewhere,3
+t1,,,c,4
+t1,08:15:00,08:15:00,c,5
+t2,24:50:00,24:50:00,a,1
+t2,25:10:00,25:10:00,c,2
+t3,09:00:00,09:00:00,a,1
+";
+        let stop_times = read_stop_times(stop_times_txt.as_bytes(), &stops, &trips).unwrap();
+        assert_eq!(stop_times.len(), 2);
+        assert_eq!(
+            stop_times["t1"],
+            vec![
+                (stop(0), time(8, 0, 0)),
+                (stop(1), time(8, 5, 30)),
+                (stop(2), time(8, 15, 0)),
+            ]
+        );
+        assert_eq!(
+            stop_times["t2"],
+            vec![(stop(0), time(24, 50, 0)), (stop(2), time(25, 10, 0))]
+        );
+    }
+
+    fn stop(road: usize) -> BusStopID {
+        BusStopID::new(
+            map_model::LaneID {
+                road: map_model::RoadID(road),
+                offset: 0,
+            },
+            0,
+        )
+    }
+
+    fn time(hours: usize, minutes: usize, seconds: usize) -> Time {
+        Time::START_OF_DAY
+            + Duration::hours(hours)
+            + Duration::minutes(minutes)
+            + Duration::seconds(seconds as f64)
+    }
+}
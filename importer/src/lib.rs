@@ -22,6 +22,7 @@ mod berlin;
 mod geneva;
 mod configuration;
 mod generic;
+mod gtfs;
 mod seattle;
 mod soundcast;
 mod uk;
@@ -278,6 +279,16 @@ impl Job {
                     geneva::distribute_residents(&mut map)
                 }
 
+                // Seattle's GTFS feed is still special-cased above
+                let gtfs_dir = gtfs::input_dir(&name.city);
+                if name.city != CityName::seattle() && abstio::file_exists(&gtfs_dir) {
+                    timer.start(format!("add GTFS schedules for {}", name.describe()));
+                    if let Err(err) = gtfs::add_schedules(&mut map, &gtfs_dir, timer) {
+                        error!("Couldn't import GTFS for {}: {}", name.describe(), err);
+                    }
+                    timer.stop(format!("add GTFS schedules for {}", name.describe()));
+                }
+
                 Some(map)
             } else if self.scenario {
                 Some(map_model::Map::load_synchronously(name.path(), timer))
@@ -88,6 +88,7 @@ fn import_trips(huge_map: &Map, timer: &mut Timer) -> Vec<OrigTrip> {
         prettyprint_usize(total_records - trips.len())
     );
 
+    let mut trips = merge_park_and_ride(trips);
     trips.sort_by_key(|t| t.depart_at);
 
     // Dump debug info about parcels. ALL trips are counted here, but parcels are filtered.
@@ -112,6 +113,40 @@ fn import_trips(huge_map: &Map, timer: &mut Timer) -> Vec<OrigTrip> {
     trips
 }
 
+// Soundcast splits park-and-ride trips in two at the lot where the mode changes; the first part
+// has the ParkAndRideTransfer purpose. Glue these back together, so the simulation can decide where
+// to switch modes. If one part got filtered out, the other is left alone.
+fn merge_park_and_ride(mut input: Vec<OrigTrip>) -> Vec<OrigTrip> {
+    input.sort_by_key(|t| (t.person, t.seq));
+    let mut trips: Vec<OrigTrip> = Vec::new();
+    let mut merged = 0;
+    for mut trip in input {
+        if let Some(prev) = trips.last_mut() {
+            if matches!(prev.purpose, TripPurpose::ParkAndRideTransfer)
+                && prev.person == trip.person
+                && prev.seq.0 == trip.seq.0
+                && prev.seq.1 == trip.seq.1
+                && prev.seq.2 + 1 == trip.seq.2
+            {
+                prev.to = trip.to;
+                prev.mode = TripMode::ParkAndRide;
+                prev.purpose = trip.purpose;
+                prev.trip_time += trip.trip_time;
+                prev.trip_dist += trip.trip_dist;
+                merged += 1;
+                continue;
+            }
+        }
+        // A lone half of a park-and-ride trip just rides transit
+        if trip.mode == TripMode::ParkAndRide {
+            trip.mode = TripMode::Transit;
+        }
+        trips.push(trip);
+    }
+    info!("Merged {} park-and-ride trips", prettyprint_usize(merged));
+    trips
+}
+
 // TODO Do we also need the zone ID, or is parcel ID globally unique?
 // Keyed by parcel ID
 #[cfg(feature = "scenarios")]
@@ -257,8 +292,9 @@ fn get_mode(code: &str) -> TripMode {
         "1.0" => TripMode::Walk,
         "2.0" => TripMode::Bike,
         "3.0" | "4.0" | "5.0" => TripMode::Drive,
-        // TODO Park-and-ride and school bus as walk-to-transit is a little weird.
-        "6.0" | "7.0" | "8.0" => TripMode::Transit,
+        "7.0" => TripMode::ParkAndRide,
+        // TODO School bus as walk-to-transit is a little weird.
+        "6.0" | "8.0" => TripMode::Transit,
         // TODO Invalid code, what's this one mean? I only see a few examples, so just default to
         // walking.
         "0.0" => TripMode::Walk,
@@ -204,8 +204,12 @@ fn clip_trips(
                 &osm_id_to_bldg,
                 borders.for_mode(orig.mode),
                 match orig.mode {
-                    TripMode::Walk | TripMode::Transit => PathConstraints::Pedestrian,
-                    TripMode::Drive => PathConstraints::Car,
+                    TripMode::Walk | TripMode::Transit | TripMode::BikeShare => {
+                        PathConstraints::Pedestrian
+                    }
+                    TripMode::Drive | TripMode::RideHail | TripMode::ParkAndRide => {
+                        PathConstraints::Car
+                    }
                     TripMode::Bike => PathConstraints::Bike,
                 },
                 maybe_huge_map.as_ref(),
@@ -296,6 +300,9 @@ pub fn make_scenario(
         map_name: map.get_name().clone(),
         people,
         only_seed_buses: None,
+        ride_hail_fleet: 0,
+        pct_electric_cars: 0,
+        driver_behavior: None,
     }
     .remove_weird_schedules()
 }
@@ -78,10 +78,10 @@ impl AppLike for App {
     fn sim_time(&self) -> geom::Time {
         unreachable!()
     }
-    fn current_stage_and_remaining_time(
+    fn current_plan_stage_and_remaining_time(
         &self,
         _: map_model::IntersectionID,
-    ) -> (usize, geom::Duration) {
+    ) -> (usize, usize, geom::Duration) {
         unreachable!()
     }
 }
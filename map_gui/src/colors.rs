@@ -115,7 +115,11 @@ pub struct ColorScheme {
 
     // Unzoomed dynamic elements
     pub unzoomed_car: Color,
+    pub unzoomed_ride_hail: Color,
+    pub unzoomed_truck: Color,
     pub unzoomed_bike: Color,
+    pub unzoomed_bike_share: Color,
+    pub unzoomed_park_and_ride: Color,
     pub unzoomed_bus: Color,
     pub unzoomed_pedestrian: Color,
 
@@ -244,7 +248,11 @@ impl ColorScheme {
 
             // Unzoomed dynamic elements
             unzoomed_car: hex("#FE5f55"),
+            unzoomed_ride_hail: hex("#9B5DE5"),
+            unzoomed_truck: hex("#8D6A4F"),
             unzoomed_bike: hex("#90BE6D"),
+            unzoomed_bike_share: hex("#43AA8B"),
+            unzoomed_park_and_ride: hex("#F3722C"),
             unzoomed_bus: hex("#FFD166"),
             unzoomed_pedestrian: hex("#457B9D"),
 
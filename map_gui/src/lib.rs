@@ -58,8 +58,12 @@ pub trait AppLike {
     fn sim_time(&self) -> Time {
         self.sim().time()
     }
-    fn current_stage_and_remaining_time(&self, id: IntersectionID) -> (usize, Duration) {
-        self.sim().current_stage_and_remaining_time(id)
+    /// Returns the current plan, stage within that plan, and time remaining in the stage.
+    fn current_plan_stage_and_remaining_time(
+        &self,
+        id: IntersectionID,
+    ) -> (usize, usize, Duration) {
+        self.sim().current_plan_stage_and_remaining_time(id)
     }
 
     /// Change the color scheme. Idempotent. Return true if there was a change.
@@ -97,7 +101,7 @@ impl ID {
         match id {
             AgentID::Car(id) => ID::Car(id),
             AgentID::Pedestrian(id) => ID::Pedestrian(id),
-            AgentID::BusPassenger(_, bus) => ID::Car(bus),
+            AgentID::BusPassenger(_, car) | AgentID::CarPassenger(_, car) => ID::Car(car),
         }
     }
 
@@ -210,6 +210,14 @@ impl UnzoomedAgents {
                     None
                 }
             }
+            // Trucks are shown or hidden along with cars
+            Some(VehicleType::Truck) => {
+                if self.cars {
+                    Some(color_scheme.unzoomed_truck)
+                } else {
+                    None
+                }
+            }
             Some(VehicleType::Bike) => {
                 if self.bikes {
                     Some(color_scheme.unzoomed_bike)
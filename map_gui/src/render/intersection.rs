@@ -222,11 +222,11 @@ impl Renderable for DrawIntersection {
                     .map(|(t, _)| *t != app.sim_time())
                     .unwrap_or(true);
                 if recalc {
-                    let (idx, remaining) = app.current_stage_and_remaining_time(self.id);
+                    let (plan, idx, remaining) = app.current_plan_stage_and_remaining_time(self.id);
                     let mut batch = GeomBatch::new();
                     traffic_signal::draw_signal_stage(
                         g.prerender,
-                        &signal.stages[idx],
+                        &signal.get_stages(plan)[idx],
                         idx,
                         self.id,
                         Some(remaining),
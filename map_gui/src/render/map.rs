@@ -496,6 +496,22 @@ impl DrawMap {
         self.roads[road.id.0] = draw;
     }
 
+    /// Bus stops can be created or removed by map edits.
+    pub fn recreate_bus_stop(
+        &mut self,
+        ctx: &EventCtx,
+        id: BusStopID,
+        map: &Map,
+        cs: &ColorScheme,
+    ) {
+        if let Some(stop) = map.maybe_get_bs(id) {
+            self.bus_stops
+                .insert(id, DrawBusStop::new(ctx, stop, map, cs));
+        } else {
+            self.bus_stops.remove(&id);
+        }
+    }
+
     pub fn free_memory(&mut self) {
         // Clear the lazily evaluated zoomed-in details
         for r in &mut self.roads {
@@ -41,7 +41,8 @@ pub fn draw_signal_stage(
                 if stage.stage_type.simple_duration() > Duration::ZERO {
                     (
                         t <= Duration::seconds(5.0),
-                        (t / stage.stage_type.simple_duration()) as f32,
+                        // Actuated stages may last longer than their minimum
+                        (t / stage.stage_type.simple_duration()).min(1.0) as f32,
                     )
                 } else {
                     (true, 1.0)
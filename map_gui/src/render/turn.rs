@@ -22,10 +22,11 @@ impl DrawMovement {
         map: &Map,
         cs: &ColorScheme,
         i: IntersectionID,
+        plan: usize,
         idx: usize,
     ) -> Vec<(DrawMovement, GeomBatch)> {
         let signal = map.get_traffic_signal(i);
-        let stage = &signal.stages[idx];
+        let stage = &signal.get_stages(plan)[idx];
 
         // TODO Sort by angle here if we want some consistency
         let mut offset_per_lane: HashMap<LaneID, usize> = HashMap::new();
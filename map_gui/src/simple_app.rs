@@ -278,12 +278,16 @@ impl<T: 'static> AppLike for SimpleApp<T> {
         self.time
     }
 
-    fn current_stage_and_remaining_time(&self, id: IntersectionID) -> (usize, Duration) {
+    fn current_plan_stage_and_remaining_time(
+        &self,
+        id: IntersectionID,
+    ) -> (usize, usize, Duration) {
         let signal = self.map.get_traffic_signal(id);
-        let mut time_left = (self.time - Time::START_OF_DAY) % signal.simple_cycle_duration();
-        for (idx, stage) in signal.stages.iter().enumerate() {
+        let plan = signal.plan_idx_at(self.time);
+        let mut time_left = (self.time - Time::START_OF_DAY) % signal.simple_cycle_duration(plan);
+        for (idx, stage) in signal.get_stages(plan).iter().enumerate() {
             if time_left < stage.stage_type.simple_duration() {
-                return (idx, time_left);
+                return (plan, idx, time_left);
             }
             time_left -= stage.stage_type.simple_duration();
         }
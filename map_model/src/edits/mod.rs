@@ -5,19 +5,22 @@
 use std::collections::{BTreeMap, BTreeSet, HashSet};
 
 use anyhow::Result;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 use abstutil::Timer;
-use geom::{Distance, HashablePt2D, Line, Speed, Time};
+use geom::{Distance, Duration, HashablePt2D, Line, Speed, Time};
 
-pub use self::perma::PermanentMapEdits;
+pub use self::perma::{PermanentEditCmd, PermanentEditIntersection, PermanentMapEdits};
 use crate::make::initial::lane_specs::get_lane_specs_ltr;
-use crate::make::{match_points_to_lanes, snap_driveway, trim_path};
+use crate::make::{
+    default_spawn_times, match_points_to_lanes, pick_start_lane, snap_driveway, trim_path,
+};
 use crate::{
-    connectivity, AccessRestrictions, BuildingID, BusRouteID, ControlStopSign,
-    ControlTrafficSignal, Direction, IntersectionID, IntersectionType, LaneID, LaneSpec, LaneType,
-    Map, MapConfig, Movement, ParkingLotID, PathConstraints, Pathfinder, Road, RoadID, TurnID,
-    Zone,
+    connectivity, AccessRestrictions, BuildingID, BusRoute, BusRouteID, BusStop, BusStopID,
+    ControlStopSign, ControlTrafficSignal, Direction, IntersectionID, IntersectionType, LaneID,
+    LaneSpec, LaneType, Map, MapConfig, Movement, ParkingLotID, ParkingPolicy, PathConstraints,
+    PathRequest, Pathfinder, Position, Road, RoadID, TurnID, Zone,
 };
 
 mod compat;
@@ -40,6 +43,7 @@ pub struct MapEdits {
     pub changed_roads: BTreeSet<RoadID>,
     pub original_intersections: BTreeMap<IntersectionID, EditIntersection>,
     pub changed_routes: BTreeSet<BusRouteID>,
+    pub changed_parking_lots: BTreeSet<ParkingLotID>,
 
     /// Some edits are included in the game by default, in data/system/proposals, as "community
     /// proposals." They require a description and may have a link to a write-up.
@@ -56,11 +60,14 @@ pub enum EditIntersection {
     Closed,
 }
 
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub struct EditRoad {
     pub lanes_ltr: Vec<LaneSpec>,
     pub speed_limit: Speed,
     pub access_restrictions: AccessRestrictions,
+    /// Edits from before parking policies existed won't have this
+    #[serde(default)]
+    pub parking_policy: ParkingPolicy,
 }
 
 impl EditRoad {
@@ -69,6 +76,7 @@ impl EditRoad {
             lanes_ltr: get_lane_specs_ltr(&r.osm_tags, cfg),
             speed_limit: r.speed_limit_from_osm(),
             access_restrictions: r.access_restrictions_from_osm(),
+            parking_policy: ParkingPolicy::default(),
         }
     }
 
@@ -111,6 +119,9 @@ impl EditRoad {
         if self.access_restrictions != other.access_restrictions {
             changes.push("access restrictions".to_string());
         }
+        if self.parking_policy != other.parking_policy {
+            changes.push("parking policy".to_string());
+        }
         changes
     }
 
@@ -135,6 +146,7 @@ impl EditRoad {
                 .collect(),
             speed_limit: Speed::ZERO,
             access_restrictions: AccessRestrictions::new(),
+            parking_policy: ParkingPolicy::default(),
         }
     }
 
@@ -173,6 +185,29 @@ impl EditRoad {
     }
 }
 
+/// Everything about a transit route that can be edited. Stops must exist before the route uses
+/// them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EditBusRoute {
+    pub full_name: String,
+    pub short_name: String,
+    pub route_type: PathConstraints,
+    pub stops: Vec<BusStopID>,
+    pub start: LaneID,
+    pub end_border: Option<LaneID>,
+    pub spawn_times: Vec<Time>,
+    pub scheduled_offsets: Vec<Duration>,
+}
+
+/// A bus stop created by map edits. Its driving position is snapped to the closest lane buses can
+/// use on the same road.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct EditBusStop {
+    pub name: String,
+    pub dist_along: Distance,
+}
+
+#[allow(clippy::large_enum_variant)]
 #[derive(Debug, Clone, PartialEq)]
 pub enum EditCmd {
     ChangeRoad {
@@ -190,6 +225,23 @@ pub enum EditCmd {
         old: Vec<Time>,
         new: Vec<Time>,
     },
+    ChangeParkingLot {
+        id: ParkingLotID,
+        old: ParkingPolicy,
+        new: ParkingPolicy,
+    },
+    /// None means the stop doesn't exist. Only stops created by edits can be removed again.
+    ChangeBusStop {
+        id: BusStopID,
+        old: Option<EditBusStop>,
+        new: Option<EditBusStop>,
+    },
+    /// An old value of None creates a new route, and a new value of None deletes it.
+    ChangeRoute {
+        id: BusRouteID,
+        old: Option<EditBusRoute>,
+        new: Option<EditBusRoute>,
+    },
 }
 
 pub struct EditEffects {
@@ -199,6 +251,10 @@ pub struct EditEffects {
     pub added_turns: BTreeSet<TurnID>,
     pub deleted_turns: BTreeSet<TurnID>,
     pub changed_parking_lots: BTreeSet<ParkingLotID>,
+    /// Created or removed
+    pub changed_bus_stops: BTreeSet<BusStopID>,
+    /// Created, rerouted, or deleted
+    pub changed_routes: BTreeSet<BusRouteID>,
     modified_lanes: BTreeSet<LaneID>,
 }
 
@@ -214,6 +270,7 @@ impl MapEdits {
             changed_roads: BTreeSet::new(),
             original_intersections: BTreeMap::new(),
             changed_routes: BTreeSet::new(),
+            changed_parking_lots: BTreeSet::new(),
         }
     }
 
@@ -274,6 +331,7 @@ impl MapEdits {
         self.changed_roads.clear();
         self.original_intersections.clear();
         self.changed_routes.clear();
+        self.changed_parking_lots.clear();
 
         for cmd in &self.commands {
             match cmd {
@@ -288,6 +346,11 @@ impl MapEdits {
                 EditCmd::ChangeRouteSchedule { id, .. } => {
                     self.changed_routes.insert(*id);
                 }
+                EditCmd::ChangeParkingLot { id, .. } => {
+                    self.changed_parking_lots.insert(*id);
+                }
+                // These aren't derived from the map; compress keeps the commands as they are.
+                EditCmd::ChangeBusStop { .. } | EditCmd::ChangeRoute { .. } => {}
             }
         }
 
@@ -297,9 +360,12 @@ impl MapEdits {
         self.original_intersections
             .retain(|i, orig| map.get_i_edit(*i) != orig.clone());
         self.changed_routes.retain(|br| {
-            let r = map.get_br(*br);
-            r.spawn_times != r.orig_spawn_times
+            map.maybe_get_br(*br)
+                .map(|r| !r.is_deleted() && r.spawn_times != r.orig_spawn_times)
+                .unwrap_or(false)
         });
+        self.changed_parking_lots
+            .retain(|pl| !map.get_pl(*pl).parking_policy.is_unrestricted());
     }
 
     /// Assumes update_derived has been called.
@@ -318,6 +384,17 @@ impl MapEdits {
                 new: map.get_i_edit(*i),
             });
         }
+        // Stops and routes can't be reconstructed from the map, since there's nothing to compare
+        // against, so keep every command in order. Schedule changes may refer to created routes,
+        // so these come first.
+        for cmd in &map.get_edits().commands {
+            if matches!(
+                cmd,
+                EditCmd::ChangeBusStop { .. } | EditCmd::ChangeRoute { .. }
+            ) {
+                self.commands.push(cmd.clone());
+            }
+        }
         for r in &self.changed_routes {
             let r = map.get_br(*r);
             self.commands.push(EditCmd::ChangeRouteSchedule {
@@ -326,6 +403,13 @@ impl MapEdits {
                 old: r.orig_spawn_times.clone(),
             });
         }
+        for pl in &self.changed_parking_lots {
+            self.commands.push(EditCmd::ChangeParkingLot {
+                id: *pl,
+                old: ParkingPolicy::default(),
+                new: map.get_pl(*pl).parking_policy.clone(),
+            });
+        }
     }
 
     /// Pick apart changed_roads and figure out if an entire road was edited, or just a few lanes.
@@ -339,6 +423,7 @@ impl MapEdits {
             // What exactly changed?
             if r.speed_limit != orig.speed_limit
                 || r.access_restrictions != orig.access_restrictions
+                || r.parking_policy != orig.parking_policy
                 // If a lane was added or deleted, figuring out if any were modified is kind of
                 // unclear -- just mark the entire road.
                 || r.lanes.len() != orig.lanes_ltr.len()
@@ -398,6 +483,23 @@ impl EditCmd {
             EditCmd::ChangeRouteSchedule { id, .. } => {
                 format!("reschedule route {}", map.get_br(*id).short_name)
             }
+            EditCmd::ChangeParkingLot { id, new, .. } => {
+                details.push(new.describe());
+                format!("parking lot #{}", id.0)
+            }
+            EditCmd::ChangeBusStop { id, new, .. } => match new {
+                Some(stop) => format!("new bus stop {}", stop.name),
+                None => format!("remove bus stop on {}", id.sidewalk),
+            },
+            EditCmd::ChangeRoute { old, new, .. } => match (old, new) {
+                (None, Some(new)) => format!("create route {}", new.short_name),
+                (Some(_), Some(new)) => {
+                    details.push(format!("{} stops", new.stops.len()));
+                    format!("reroute {}", new.short_name)
+                }
+                (Some(old), None) => format!("delete route {}", old.short_name),
+                (None, None) => unreachable!(),
+            },
         };
         (summary, details)
     }
@@ -414,6 +516,7 @@ impl EditCmd {
                 let road = &mut map.roads[r.0];
                 road.speed_limit = new.speed_limit;
                 road.access_restrictions = new.access_restrictions.clone();
+                road.parking_policy = new.parking_policy.clone();
 
                 effects.changed_roads.insert(road.id);
                 for i in [road.src_i, road.dst_i] {
@@ -474,6 +577,105 @@ impl EditCmd {
             EditCmd::ChangeRouteSchedule { id, new, .. } => {
                 map.bus_routes[id.0].spawn_times = new.clone();
             }
+            EditCmd::ChangeParkingLot { id, new, .. } => {
+                map.parking_lots[id.0].parking_policy = new.clone();
+                effects.changed_parking_lots.insert(*id);
+            }
+            EditCmd::ChangeBusStop { id, new, .. } => {
+                match new {
+                    Some(new) => {
+                        if map.bus_stops.contains_key(id) {
+                            return;
+                        }
+                        let sidewalk_pos = Position::new(id.sidewalk, new.dist_along);
+                        // PermanentEditCmd::into_cmd checks this, but an earlier command in the
+                        // same batch could've changed the road
+                        let driving_lane = match map
+                            .get_parent(id.sidewalk)
+                            .find_closest_lane(id.sidewalk, |l| {
+                                PathConstraints::Bus.can_use(l, map)
+                            }) {
+                            Some(l) => l,
+                            None => {
+                                warn!("Not creating {}; buses can't reach it", id);
+                                return;
+                            }
+                        };
+                        let driving_pos = sidewalk_pos.equiv_pos(driving_lane, map);
+                        map.bus_stops.insert(
+                            *id,
+                            BusStop {
+                                id: *id,
+                                name: new.name.clone(),
+                                driving_pos,
+                                sidewalk_pos,
+                                is_train_stop: false,
+                            },
+                        );
+                        map.mut_lane(id.sidewalk).bus_stops.insert(*id);
+                    }
+                    None => {
+                        if map.bus_stops.remove(id).is_none() {
+                            return;
+                        }
+                        map.mut_lane(id.sidewalk).bus_stops.remove(id);
+                    }
+                }
+                effects.changed_bus_stops.insert(*id);
+            }
+            EditCmd::ChangeRoute { id, new, .. } => {
+                if map.get_br_edit(*id) == new.clone() {
+                    return;
+                }
+                // Deleted routes are never removed, just left with no stops, so that route IDs
+                // stay stable while undoing and redoing.
+                while map.bus_routes.len() <= id.0 {
+                    let id = map.new_bus_route_id();
+                    let osm_rel_id = perma::route_rel_id(id, map);
+                    map.bus_routes.push(BusRoute {
+                        id,
+                        full_name: String::new(),
+                        short_name: String::new(),
+                        gtfs_trip_marker: None,
+                        osm_rel_id,
+                        stops: Vec::new(),
+                        // Unused, since the route has no stops
+                        start: LaneID {
+                            road: RoadID(0),
+                            offset: 0,
+                        },
+                        end_border: None,
+                        route_type: PathConstraints::Bus,
+                        spawn_times: Vec::new(),
+                        orig_spawn_times: Vec::new(),
+                        scheduled_offsets: Vec::new(),
+                    });
+                }
+                let route = &mut map.bus_routes[id.0];
+                match new {
+                    Some(new) => {
+                        route.full_name = new.full_name.clone();
+                        route.short_name = new.short_name.clone();
+                        route.route_type = new.route_type;
+                        route.stops = new.stops.clone();
+                        route.start = new.start;
+                        route.end_border = new.end_border;
+                        route.spawn_times = new.spawn_times.clone();
+                        route.scheduled_offsets = new.scheduled_offsets.clone();
+                        if route.osm_rel_id.0 < 0 {
+                            // Created routes have nothing else to reset a schedule to
+                            route.orig_spawn_times = new.spawn_times.clone();
+                        }
+                    }
+                    None => {
+                        route.stops.clear();
+                        route.end_border = None;
+                        route.spawn_times.clear();
+                        route.scheduled_offsets.clear();
+                    }
+                }
+                effects.changed_routes.insert(*id);
+            }
         }
     }
 
@@ -494,6 +696,21 @@ impl EditCmd {
                 old: new,
                 new: old,
             },
+            EditCmd::ChangeParkingLot { id, old, new } => EditCmd::ChangeParkingLot {
+                id,
+                old: new,
+                new: old,
+            },
+            EditCmd::ChangeBusStop { id, old, new } => EditCmd::ChangeBusStop {
+                id,
+                old: new,
+                new: old,
+            },
+            EditCmd::ChangeRoute { id, old, new } => EditCmd::ChangeRoute {
+                id,
+                old: new,
+                new: old,
+            },
         }
     }
 }
@@ -784,6 +1001,7 @@ impl Map {
             lanes_ltr: r.lane_specs(),
             speed_limit: r.speed_limit,
             access_restrictions: r.access_restrictions.clone(),
+            parking_policy: r.parking_policy.clone(),
         }
     }
 
@@ -794,6 +1012,113 @@ impl Map {
         EditCmd::ChangeRoad { r, old, new }
     }
 
+    /// None if the route doesn't exist or has been deleted.
+    pub fn get_br_edit(&self, id: BusRouteID) -> Option<EditBusRoute> {
+        let r = self.maybe_get_br(id)?;
+        if r.is_deleted() {
+            return None;
+        }
+        Some(EditBusRoute {
+            full_name: r.full_name.clone(),
+            short_name: r.short_name.clone(),
+            route_type: r.route_type,
+            stops: r.stops.clone(),
+            start: r.start,
+            end_border: r.end_border,
+            spawn_times: r.spawn_times.clone(),
+            scheduled_offsets: r.scheduled_offsets.clone(),
+        })
+    }
+
+    /// Describes the route serving these stops in order, either rerouting an existing route or
+    /// creating a new bus route with the default schedule. Fails if vehicles can't drive between
+    /// the stops.
+    pub fn br_edit_with_stops(
+        &self,
+        id: BusRouteID,
+        stops: Vec<BusStopID>,
+    ) -> Result<EditBusRoute> {
+        if stops.len() < 2 {
+            bail!("A route needs at least two stops");
+        }
+        let first_stop = self.get_bs(stops[0]).driving_pos;
+        let mut route = match self.get_br_edit(id) {
+            Some(route) => route,
+            None => EditBusRoute {
+                full_name: format!("New route {}", id.0),
+                short_name: format!("New route {}", id.0),
+                route_type: PathConstraints::Bus,
+                stops: Vec::new(),
+                start: first_stop.lane(),
+                end_border: None,
+                spawn_times: default_spawn_times(),
+                scheduled_offsets: Vec::new(),
+            },
+        };
+        if route.stops.first() != stops.first() {
+            route.start = pick_start_lane(first_stop, route.route_type, self)?;
+        }
+        // Only keep ending at a border if the last stop is the same
+        if route.stops.last() != stops.last() {
+            route.end_border = None;
+        }
+        // A timetable only makes sense for the original stops
+        if route.stops != stops {
+            route.scheduled_offsets.clear();
+        }
+        route.stops = stops;
+
+        // Make sure the route is connected
+        let mut steps = vec![PathRequest::vehicle(
+            Position::start(route.start),
+            first_stop,
+            route.route_type,
+        )];
+        for pair in route.stops.windows(2) {
+            steps.push(PathRequest::vehicle(
+                self.get_bs(pair[0]).driving_pos,
+                self.get_bs(pair[1]).driving_pos,
+                route.route_type,
+            ));
+        }
+        if let Some(l) = route.end_border {
+            steps.push(PathRequest::vehicle(
+                self.get_bs(*route.stops.last().unwrap()).driving_pos,
+                Position::end(l, self),
+                route.route_type,
+            ));
+        }
+        for req in steps {
+            if req.start.lane() == req.end.lane() && req.start.dist_along() > req.end.dist_along() {
+                bail!(
+                    "Two stops are out of order on {}",
+                    self.get_parent(req.start.lane()).get_name(None)
+                );
+            }
+            if let Err(err) = self.pathfind(req) {
+                bail!("No path between stops: {}", err);
+            }
+        }
+        Ok(route)
+    }
+
+    /// The ID that the next route created by edits will get.
+    pub fn new_bus_route_id(&self) -> BusRouteID {
+        BusRouteID(self.bus_routes.len())
+    }
+
+    /// An unused ID for a new bus stop on this sidewalk.
+    pub fn new_bus_stop_id(&self, sidewalk: LaneID) -> BusStopID {
+        let idx = self
+            .bus_stops
+            .keys()
+            .filter(|id| id.sidewalk == sidewalk)
+            .map(|id| id.idx + 1)
+            .max()
+            .unwrap_or(0);
+        BusStopID { sidewalk, idx }
+    }
+
     /// Panics on borders
     pub fn get_i_edit(&self, i: IntersectionID) -> EditIntersection {
         match self.get_i(i).intersection_type {
@@ -841,6 +1166,8 @@ impl Map {
             added_turns: BTreeSet::new(),
             deleted_turns: BTreeSet::new(),
             changed_parking_lots: BTreeSet::new(),
+            changed_bus_stops: BTreeSet::new(),
+            changed_routes: BTreeSet::new(),
             modified_lanes: BTreeSet::new(),
         };
 
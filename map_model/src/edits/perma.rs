@@ -1,20 +1,24 @@
 use std::collections::{BTreeMap, BTreeSet};
 
 use anyhow::{Context, Result};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 use abstio::MapName;
 use abstutil::{deserialize_btreemap, serialize_btreemap};
-use geom::Time;
+use geom::{Duration, Time};
 
-use crate::edits::{EditCmd, EditIntersection, EditRoad, MapEdits};
+use crate::edits::{EditBusRoute, EditBusStop, EditCmd, EditIntersection, EditRoad, MapEdits};
 use crate::raw::OriginalRoad;
-use crate::{osm, ControlStopSign, IntersectionID, Map};
+use crate::{
+    osm, BusRouteID, BusStopID, ControlStopSign, IntersectionID, LaneID, Map, ParkingPolicy,
+    PathConstraints,
+};
 
 /// MapEdits are converted to this before serializing. Referencing things like LaneID in a Map won't
 /// work if the basemap is rebuilt from new OSM data, so instead we use stabler OSM IDs that're less
 /// likely to change.
-#[derive(Serialize, Deserialize, Clone)]
+#[derive(Serialize, Deserialize, Clone, JsonSchema)]
 pub struct PermanentMapEdits {
     pub map_name: MapName,
     pub edits_name: String,
@@ -31,21 +35,47 @@ pub struct PermanentMapEdits {
     pub proposal_link: Option<String>,
 }
 
-#[derive(Serialize, Deserialize, Clone)]
+#[derive(Serialize, Deserialize, Clone, JsonSchema)]
 pub enum PermanentEditIntersection {
     StopSign {
         #[serde(
             serialize_with = "serialize_btreemap",
             deserialize_with = "deserialize_btreemap"
         )]
+        #[schemars(with = "Vec<(OriginalRoad, bool)>")]
         must_stop: BTreeMap<OriginalRoad, bool>,
     },
     TrafficSignal(traffic_signal_data::TrafficSignal),
     Closed,
 }
 
-#[allow(clippy::enum_variant_names)]
-#[derive(Serialize, Deserialize, Clone)]
+#[derive(Serialize, Deserialize, Clone, Copy, JsonSchema)]
+pub struct PermanentLane {
+    r: OriginalRoad,
+    offset: usize,
+}
+
+/// Bus stops created by edits don't exist in OSM, so refer to them by lane.
+#[derive(Serialize, Deserialize, Clone, Copy, JsonSchema)]
+pub struct PermanentBusStop {
+    sidewalk: PermanentLane,
+    idx: usize,
+}
+
+#[derive(Serialize, Deserialize, Clone, JsonSchema)]
+pub struct PermanentEditBusRoute {
+    full_name: String,
+    short_name: String,
+    route_type: PathConstraints,
+    stops: Vec<PermanentBusStop>,
+    start: PermanentLane,
+    end_border: Option<PermanentLane>,
+    spawn_times: Vec<Time>,
+    scheduled_offsets: Vec<Duration>,
+}
+
+#[allow(clippy::enum_variant_names, clippy::large_enum_variant)]
+#[derive(Serialize, Deserialize, Clone, JsonSchema)]
 pub enum PermanentEditCmd {
     ChangeRoad {
         r: OriginalRoad,
@@ -62,6 +92,22 @@ pub enum PermanentEditCmd {
         old: Vec<Time>,
         new: Vec<Time>,
     },
+    ChangeParkingLot {
+        osm_id: osm::OsmID,
+        old: ParkingPolicy,
+        new: ParkingPolicy,
+    },
+    ChangeBusStop {
+        stop: PermanentBusStop,
+        old: Option<EditBusStop>,
+        new: Option<EditBusStop>,
+    },
+    /// Routes created by edits have negative IDs.
+    ChangeRoute {
+        osm_rel_id: osm::RelationID,
+        old: Option<PermanentEditBusRoute>,
+        new: Option<PermanentEditBusRoute>,
+    },
 }
 
 impl EditCmd {
@@ -84,6 +130,21 @@ impl EditCmd {
                     new: new.clone(),
                 }
             }
+            EditCmd::ChangeParkingLot { id, old, new } => PermanentEditCmd::ChangeParkingLot {
+                osm_id: map.get_pl(*id).osm_id,
+                old: old.clone(),
+                new: new.clone(),
+            },
+            EditCmd::ChangeBusStop { id, old, new } => PermanentEditCmd::ChangeBusStop {
+                stop: PermanentBusStop::new(*id, map),
+                old: old.clone(),
+                new: new.clone(),
+            },
+            EditCmd::ChangeRoute { id, old, new } => PermanentEditCmd::ChangeRoute {
+                osm_rel_id: route_rel_id(*id, map),
+                old: old.as_ref().map(|r| r.to_permanent(map)),
+                new: new.as_ref().map(|r| r.to_permanent(map)),
+            },
         }
     }
 }
@@ -123,11 +184,44 @@ impl PermanentEditCmd {
                 old,
                 new,
             } => {
-                let id = map
-                    .find_br(osm_rel_id)
-                    .ok_or_else(|| anyhow!("can't find {}", osm_rel_id))?;
+                let id = find_route(map, osm_rel_id)?;
                 Ok(EditCmd::ChangeRouteSchedule { id, old, new })
             }
+            PermanentEditCmd::ChangeParkingLot { osm_id, old, new } => {
+                let id = map
+                    .find_pl_by_osm_id(osm_id)
+                    .ok_or_else(|| anyhow!("can't find parking lot {}", osm_id))?;
+                Ok(EditCmd::ChangeParkingLot { id, old, new })
+            }
+            PermanentEditCmd::ChangeBusStop { stop, old, new } => {
+                let id = stop.into_id(map)?;
+                if new.is_some() {
+                    if !map.get_l(id.sidewalk).is_sidewalk() {
+                        bail!("{} isn't on a sidewalk", id);
+                    }
+                    if map
+                        .get_parent(id.sidewalk)
+                        .find_closest_lane(id.sidewalk, |l| PathConstraints::Bus.can_use(l, map))
+                        .is_none()
+                    {
+                        bail!("{} isn't on a road buses can use", id);
+                    }
+                } else if let Some(route) =
+                    map.all_bus_routes().iter().find(|r| r.stops.contains(&id))
+                {
+                    bail!("can't delete {}; {} still stops there", id, route.full_name);
+                }
+                Ok(EditCmd::ChangeBusStop { id, old, new })
+            }
+            PermanentEditCmd::ChangeRoute {
+                osm_rel_id,
+                old,
+                new,
+            } => Ok(EditCmd::ChangeRoute {
+                id: find_route(map, osm_rel_id)?,
+                old: old.map(|r| r.into_edit(map)).transpose()?,
+                new: new.map(|r| r.into_edit(map)).transpose()?,
+            }),
         }
     }
 }
@@ -139,7 +233,7 @@ impl MapEdits {
             map_name: map.get_name().clone(),
             edits_name: self.edits_name.clone(),
             // Increase this every time there's a schema change
-            version: 11,
+            version: 12,
             proposal_description: self.proposal_description.clone(),
             proposal_link: self.proposal_link.clone(),
             commands: self.commands.iter().map(|cmd| cmd.to_perma(map)).collect(),
@@ -166,6 +260,7 @@ impl PermanentMapEdits {
             changed_roads: BTreeSet::new(),
             original_intersections: BTreeMap::new(),
             changed_routes: BTreeSet::new(),
+            changed_parking_lots: BTreeSet::new(),
         };
         edits.update_derived(map);
         Ok(edits)
@@ -194,6 +289,7 @@ impl PermanentMapEdits {
             changed_roads: BTreeSet::new(),
             original_intersections: BTreeMap::new(),
             changed_routes: BTreeSet::new(),
+            changed_parking_lots: BTreeSet::new(),
         };
         edits.update_derived(map);
         edits
@@ -261,3 +357,276 @@ impl PermanentEditIntersection {
         }
     }
 }
+
+/// Routes from OSM are found by relation ID. Routes created by edits are numbered after all of
+/// the OSM routes, and might not exist yet, since earlier commands create them.
+fn find_route(map: &Map, osm_rel_id: osm::RelationID) -> Result<BusRouteID> {
+    if osm_rel_id.0 >= 0 {
+        return map
+            .find_br(osm_rel_id)
+            .ok_or_else(|| anyhow!("can't find {}", osm_rel_id));
+    }
+    Ok(BusRouteID(
+        num_osm_routes(map) + (-osm_rel_id.0 - 1) as usize,
+    ))
+}
+
+/// The inverse of find_route. Routes created by edits might not exist yet, or anymore.
+pub(crate) fn route_rel_id(id: BusRouteID, map: &Map) -> osm::RelationID {
+    let num_osm_routes = num_osm_routes(map);
+    if id.0 < num_osm_routes {
+        map.get_br(id).osm_rel_id
+    } else {
+        osm::RelationID(-1 - ((id.0 - num_osm_routes) as i64))
+    }
+}
+
+fn num_osm_routes(map: &Map) -> usize {
+    map.all_bus_routes()
+        .iter()
+        .filter(|r| r.osm_rel_id.0 >= 0)
+        .count()
+}
+
+impl PermanentLane {
+    fn new(l: LaneID, map: &Map) -> PermanentLane {
+        PermanentLane {
+            r: map.get_r(l.road).orig_id,
+            offset: l.offset,
+        }
+    }
+
+    fn into_id(self, map: &Map) -> Result<LaneID> {
+        let road = map.find_r_by_osm_id(self.r)?;
+        if self.offset >= map.get_r(road).lanes.len() {
+            bail!("{} doesn't have a lane at offset {}", self.r, self.offset);
+        }
+        Ok(LaneID {
+            road,
+            offset: self.offset,
+        })
+    }
+}
+
+impl PermanentBusStop {
+    fn new(id: BusStopID, map: &Map) -> PermanentBusStop {
+        PermanentBusStop {
+            sidewalk: PermanentLane::new(id.sidewalk, map),
+            idx: id.idx,
+        }
+    }
+
+    fn into_id(self, map: &Map) -> Result<BusStopID> {
+        Ok(BusStopID {
+            sidewalk: self.sidewalk.into_id(map)?,
+            idx: self.idx,
+        })
+    }
+}
+
+impl EditBusRoute {
+    fn to_permanent(&self, map: &Map) -> PermanentEditBusRoute {
+        PermanentEditBusRoute {
+            full_name: self.full_name.clone(),
+            short_name: self.short_name.clone(),
+            route_type: self.route_type,
+            stops: self
+                .stops
+                .iter()
+                .map(|bs| PermanentBusStop::new(*bs, map))
+                .collect(),
+            start: PermanentLane::new(self.start, map),
+            end_border: self.end_border.map(|l| PermanentLane::new(l, map)),
+            spawn_times: self.spawn_times.clone(),
+            scheduled_offsets: self.scheduled_offsets.clone(),
+        }
+    }
+}
+
+impl PermanentEditBusRoute {
+    fn into_edit(self, map: &Map) -> Result<EditBusRoute> {
+        Ok(EditBusRoute {
+            full_name: self.full_name,
+            short_name: self.short_name,
+            route_type: self.route_type,
+            stops: self
+                .stops
+                .into_iter()
+                .map(|bs| bs.into_id(map))
+                .collect::<Result<Vec<_>>>()?,
+            start: self.start.into_id(map)?,
+            end_border: self.end_border.map(|l| l.into_id(map)).transpose()?,
+            spawn_times: self.spawn_times,
+            scheduled_offsets: self.scheduled_offsets,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use abstutil::Timer;
+    use geom::Distance;
+
+    use super::*;
+    use crate::toy::{road, sidewalk, toy_map};
+    use crate::{IntersectionType, LaneType};
+
+    /// A street long enough for a few stops between a and b. The side streets keep a and b from
+    /// being merged away.
+    fn street() -> Map {
+        toy_map(
+            &[
+                ("w", 0.0, 100.0, IntersectionType::Border),
+                ("a", 100.0, 100.0, IntersectionType::StopSign),
+                ("b", 700.0, 100.0, IntersectionType::StopSign),
+                ("e", 800.0, 100.0, IntersectionType::Border),
+                ("na", 100.0, 0.0, IntersectionType::Border),
+                ("nb", 700.0, 0.0, IntersectionType::Border),
+            ],
+            &[
+                ("w", "a"),
+                ("a", "b"),
+                ("b", "e"),
+                ("a", "na"),
+                ("b", "nb"),
+            ],
+        )
+    }
+
+    fn new_stop(map: &Map, idx: usize, dist: f64) -> (BusStopID, EditCmd) {
+        let id = BusStopID {
+            sidewalk: sidewalk(road(map, "a", "b")),
+            idx,
+        };
+        let cmd = EditCmd::ChangeBusStop {
+            id,
+            old: None,
+            new: Some(EditBusStop {
+                name: format!("stop {}", idx),
+                dist_along: Distance::meters(dist),
+            }),
+        };
+        (id, cmd)
+    }
+
+    /// Save the edits, load them again, make sure nothing changed, and apply them
+    fn round_trip(map: &mut Map, edits: MapEdits) {
+        let json = abstutil::to_json(&edits.to_permanent(map));
+        let loaded = abstutil::from_json::<PermanentMapEdits>(json.as_bytes())
+            .unwrap()
+            .into_edits(map)
+            .unwrap();
+        assert_eq!(loaded.commands, edits.commands);
+        let mut timer = Timer::throwaway();
+        map.must_apply_edits(loaded, &mut timer);
+        map.recalculate_pathfinding_after_edits(&mut timer);
+    }
+
+    #[test]
+    fn create_reroute_delete_undo() {
+        let mut map = street();
+        let mut edits = map.get_edits().clone();
+
+        // Create three stops and a route serving the first two
+        let mut stops = Vec::new();
+        for (idx, dist) in [50.0, 200.0, 350.0].into_iter().enumerate() {
+            let (id, cmd) = new_stop(&map, idx, dist);
+            edits.commands.push(cmd);
+            stops.push(id);
+        }
+        round_trip(&mut map, edits.clone());
+        let route = map.new_bus_route_id();
+        let created = map
+            .br_edit_with_stops(route, vec![stops[0], stops[1]])
+            .unwrap();
+        edits.commands.push(EditCmd::ChangeRoute {
+            id: route,
+            old: None,
+            new: Some(created.clone()),
+        });
+        round_trip(&mut map, edits.clone());
+        assert_eq!(map.get_br(route).stops, vec![stops[0], stops[1]]);
+        assert_eq!(map.get_bs(stops[1]).name, "stop 1");
+
+        // Skip the middle stop
+        let rerouted = map
+            .br_edit_with_stops(route, vec![stops[0], stops[2]])
+            .unwrap();
+        edits.commands.push(EditCmd::ChangeRoute {
+            id: route,
+            old: Some(created),
+            new: Some(rerouted.clone()),
+        });
+        round_trip(&mut map, edits.clone());
+        assert_eq!(map.get_br(route).stops, vec![stops[0], stops[2]]);
+        let after_reroute = edits.clone();
+
+        // Then delete it, and the stop nothing uses anymore
+        edits.commands.push(EditCmd::ChangeRoute {
+            id: route,
+            old: Some(rerouted),
+            new: None,
+        });
+        edits.commands.push(EditCmd::ChangeBusStop {
+            id: stops[1],
+            old: Some(EditBusStop {
+                name: "stop 1".to_string(),
+                dist_along: Distance::meters(200.0),
+            }),
+            new: None,
+        });
+        round_trip(&mut map, edits);
+        assert!(map.get_br_edit(route).is_none());
+        assert!(map.maybe_get_bs(stops[1]).is_none());
+        assert!(map.maybe_get_bs(stops[0]).is_some());
+
+        // Undo the deletions, then everything
+        round_trip(&mut map, after_reroute);
+        assert_eq!(map.get_br(route).stops, vec![stops[0], stops[2]]);
+        assert!(map.maybe_get_bs(stops[1]).is_some());
+        round_trip(&mut map, MapEdits::new());
+        assert!(map.get_br_edit(route).is_none());
+        for id in stops {
+            assert!(map.maybe_get_bs(id).is_none());
+        }
+    }
+
+    #[test]
+    fn reject_broken_stops() {
+        let mut map = street();
+        let mut edits = map.get_edits().clone();
+        let (stop1, cmd) = new_stop(&map, 0, 50.0);
+        edits.commands.push(cmd);
+        let (stop2, cmd) = new_stop(&map, 1, 200.0);
+        edits.commands.push(cmd);
+        round_trip(&mut map, edits.clone());
+        let route = map.new_bus_route_id();
+        edits.commands.push(EditCmd::ChangeRoute {
+            id: route,
+            old: None,
+            new: Some(map.br_edit_with_stops(route, vec![stop1, stop2]).unwrap()),
+        });
+        round_trip(&mut map, edits.clone());
+
+        // The route still uses the stop
+        let delete = EditCmd::ChangeBusStop {
+            id: stop1,
+            old: None,
+            new: None,
+        };
+        assert!(delete.to_perma(&map).into_cmd(&map).is_err());
+
+        // Buses can't reach a stop on a road under construction
+        let r = road(&map, "a", "b").id;
+        edits.commands.push(map.edit_road_cmd(r, |new| {
+            for spec in &mut new.lanes_ltr {
+                if spec.lt == LaneType::Driving {
+                    spec.lt = LaneType::Construction;
+                }
+            }
+        }));
+        round_trip(&mut map, edits);
+        let (_, create) = new_stop(&map, 2, 350.0);
+        assert!(create.to_perma(&map).into_cmd(&map).is_err());
+    }
+}
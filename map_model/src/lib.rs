@@ -38,34 +38,43 @@ use geom::{Bounds, GPSBounds, Polygon};
 
 pub use crate::city::City;
 pub use crate::edits::{
-    EditCmd, EditEffects, EditIntersection, EditRoad, MapEdits, PermanentMapEdits,
+    EditBusRoute, EditBusStop, EditCmd, EditEffects, EditIntersection, EditRoad, MapEdits,
+    PermanentEditCmd, PermanentEditIntersection, PermanentMapEdits,
 };
+pub use crate::make::traffic_signals::{optimize_corridor_offsets, CorridorOffsets};
 pub use crate::make::RawToMapOptions;
 pub use crate::map::{DrivingSide, MapConfig};
 pub use crate::objects::area::{Area, AreaID, AreaType};
+pub use crate::objects::bike_share_dock::{BikeShareDock, BikeShareDockID};
 pub use crate::objects::block::{Block, Perimeter};
 pub use crate::objects::building::{
     Amenity, AmenityType, Building, BuildingID, BuildingType, NamePerLanguage, OffstreetParking,
 };
 pub use crate::objects::bus_stop::{BusRoute, BusRouteID, BusStop, BusStopID};
+pub use crate::objects::charging_station::{ChargingStation, ChargingStationID};
 pub use crate::objects::intersection::{Intersection, IntersectionID, IntersectionType};
 pub use crate::objects::lane::{
     BufferType, Lane, LaneID, LaneSpec, LaneType, NORMAL_LANE_THICKNESS, PARKING_LOT_SPOT_LENGTH,
     SIDEWALK_THICKNESS,
 };
 pub use crate::objects::movement::{CompressedMovementID, Movement, MovementID};
-pub use crate::objects::parking_lot::{ParkingLot, ParkingLotID};
+pub use crate::objects::parking_lot::{ParkingLot, ParkingLotID, ParkingPolicy};
 pub use crate::objects::road::{DirectedRoadID, Direction, Road, RoadID, RoadSideID, SideOfRoad};
 pub use crate::objects::stop_signs::{ControlStopSign, RoadWithStopSign};
-pub use crate::objects::traffic_signals::{ControlTrafficSignal, Stage, StageType};
+pub use crate::objects::traffic_signals::{
+    ControlTrafficSignal, SignalPlan, Stage, StageType, TransitSignalPriority,
+};
 pub use crate::objects::turn::{Turn, TurnID, TurnPriority, TurnType};
 pub use crate::objects::zone::{AccessRestrictions, Zone};
 pub use crate::pathfind::uber_turns::{IntersectionCluster, UberTurn};
 use crate::pathfind::Pathfinder;
 pub use crate::pathfind::{
     Path, PathConstraints, PathRequest, PathStep, PathStepV2, PathV2, RoutingParams,
+    TimeDependentPathfinder, TransitItinerary, TransitLeg,
+};
+pub use crate::traversable::{
+    Position, Traversable, MAX_BIKE_SPEED, MAX_TRUCK_SPEED, MAX_WALKING_SPEED,
 };
-pub use crate::traversable::{Position, Traversable, MAX_BIKE_SPEED, MAX_WALKING_SPEED};
 
 mod city;
 pub mod connectivity;
@@ -76,6 +85,7 @@ mod objects;
 pub mod osm;
 mod pathfind;
 pub mod raw;
+pub mod toy;
 mod traversable;
 
 // The map used by the simulation and UI. This struct is declared here so that the rest of the
@@ -93,6 +103,8 @@ pub struct Map {
     bus_routes: Vec<BusRoute>,
     areas: Vec<Area>,
     parking_lots: Vec<ParkingLot>,
+    bike_share_docks: Vec<BikeShareDock>,
+    charging_stations: Vec<ChargingStation>,
     boundary_polygon: Polygon,
 
     // Note that border nodes belong in neither!
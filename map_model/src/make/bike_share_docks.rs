@@ -0,0 +1,54 @@
+use std::collections::HashSet;
+
+use abstutil::Timer;
+use geom::{Distance, HashablePt2D};
+
+use crate::make::match_points_to_lanes;
+use crate::raw::RawBikeShareDock;
+use crate::{BikeShareDock, BikeShareDockID, Map};
+
+/// Snap each dock to the nearest sidewalk. Docks that're too far from any sidewalk, or that can't
+/// reach a lane where bikes are allowed, are skipped.
+pub fn make_all_bike_share_docks(
+    input: &[RawBikeShareDock],
+    map: &Map,
+    timer: &mut Timer,
+) -> Vec<BikeShareDock> {
+    timer.start("convert bike-share docks");
+    let query: HashSet<HashablePt2D> = input.iter().map(|d| d.pt.to_hashable()).collect();
+    let sidewalk_pts = match_points_to_lanes(
+        map,
+        query,
+        |l| l.is_walkable(),
+        Distance::ZERO,
+        Distance::meters(50.0),
+        timer,
+    );
+
+    let mut results = Vec::new();
+    for orig in input {
+        if let Some(sidewalk_pos) = sidewalk_pts.get(&orig.pt.to_hashable()) {
+            let dock = BikeShareDock {
+                id: BikeShareDockID(results.len()),
+                osm_id: orig.osm_id,
+                name: orig.name.clone(),
+                pt: orig.pt,
+                capacity: orig.capacity,
+                scooters: orig.scooters,
+                sidewalk_pos: *sidewalk_pos,
+            };
+            if dock.biking_connection(map).is_some() {
+                results.push(dock);
+            } else {
+                warn!("Skipping bike-share dock {}, nowhere to bike", orig.osm_id);
+            }
+        } else {
+            warn!(
+                "Skipping bike-share dock {}, no sidewalk nearby",
+                orig.osm_id
+            );
+        }
+    }
+    timer.stop("convert bike-share docks");
+    results
+}
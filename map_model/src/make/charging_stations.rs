@@ -0,0 +1,48 @@
+use std::collections::HashSet;
+
+use abstutil::Timer;
+use geom::{Distance, HashablePt2D};
+
+use crate::make::match_points_to_lanes;
+use crate::raw::RawChargingStation;
+use crate::{ChargingStation, ChargingStationID, Map};
+
+/// Snap each charging station to the nearest sidewalk. Stations that're too far from any sidewalk
+/// are skipped.
+pub fn make_all_charging_stations(
+    input: &[RawChargingStation],
+    map: &Map,
+    timer: &mut Timer,
+) -> Vec<ChargingStation> {
+    timer.start("convert charging stations");
+    let query: HashSet<HashablePt2D> = input.iter().map(|c| c.pt.to_hashable()).collect();
+    let sidewalk_pts = match_points_to_lanes(
+        map,
+        query,
+        |l| l.is_walkable(),
+        Distance::ZERO,
+        Distance::meters(50.0),
+        timer,
+    );
+
+    let mut results = Vec::new();
+    for orig in input {
+        if let Some(sidewalk_pos) = sidewalk_pts.get(&orig.pt.to_hashable()) {
+            results.push(ChargingStation {
+                id: ChargingStationID(results.len()),
+                osm_id: orig.osm_id,
+                name: orig.name.clone(),
+                pt: orig.pt,
+                capacity: orig.capacity,
+                sidewalk_pos: *sidewalk_pos,
+            });
+        } else {
+            warn!(
+                "Skipping charging station {}, no sidewalk nearby",
+                orig.osm_id
+            );
+        }
+    }
+    timer.stop("convert charging stations");
+    results
+}
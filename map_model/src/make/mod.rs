@@ -9,16 +9,19 @@ use abstutil::{MultiMap, Timer};
 use geom::{Distance, FindClosest, HashablePt2D, Line, Polygon, Speed, EPSILON_DIST};
 
 pub use self::parking_lots::snap_driveway;
+pub use self::transit::{default_spawn_times, pick_start_lane};
 use crate::pathfind::{CreateEngine, Pathfinder};
 use crate::raw::{OriginalRoad, RawMap};
 use crate::{
     connectivity, osm, AccessRestrictions, Area, AreaID, ControlStopSign, ControlTrafficSignal,
-    Intersection, IntersectionID, IntersectionType, Lane, LaneID, Map, MapEdits, PathConstraints,
-    Position, Road, RoadID, RoutingParams, Zone,
+    Intersection, IntersectionID, IntersectionType, Lane, LaneID, Map, MapEdits, ParkingPolicy,
+    PathConstraints, Position, Road, RoadID, RoutingParams, Zone,
 };
 
+mod bike_share_docks;
 mod bridges;
 mod buildings;
+mod charging_stations;
 pub mod collapse_intersections;
 pub mod initial;
 pub mod merge_intersections;
@@ -63,6 +66,8 @@ impl Map {
             bus_routes: Vec::new(),
             areas: Vec::new(),
             parking_lots: Vec::new(),
+            bike_share_docks: Vec::new(),
+            charging_stations: Vec::new(),
             zones: Vec::new(),
             boundary_polygon: raw.boundary_polygon.clone(),
             stop_signs: BTreeMap::new(),
@@ -152,6 +157,7 @@ impl Map {
                 speed_limit: Speed::ZERO,
                 zorder: raw_road.get_zorder(),
                 access_restrictions: AccessRestrictions::new(),
+                parking_policy: ParkingPolicy::default(),
                 percent_incline: raw_road.percent_incline,
             };
             road.speed_limit = road.speed_limit_from_osm();
@@ -239,6 +245,11 @@ impl Map {
             timer,
         );
 
+        map.bike_share_docks =
+            bike_share_docks::make_all_bike_share_docks(&raw.bike_share_docks, &map, timer);
+        map.charging_stations =
+            charging_stations::make_all_charging_stations(&raw.charging_stations, &map, timer);
+
         map.zones = Zone::make_all(&map);
 
         for a in &raw.areas {
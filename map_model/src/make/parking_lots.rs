@@ -8,8 +8,8 @@ use geom::{Angle, Distance, FindClosest, HashablePt2D, Line, PolyLine, Polygon,
 use crate::make::{match_points_to_lanes, trim_path};
 use crate::raw::RawParkingLot;
 use crate::{
-    osm, Map, ParkingLot, ParkingLotID, PathConstraints, Position, NORMAL_LANE_THICKNESS,
-    PARKING_LOT_SPOT_LENGTH,
+    osm, Map, ParkingLot, ParkingLotID, ParkingPolicy, PathConstraints, Position,
+    NORMAL_LANE_THICKNESS, PARKING_LOT_SPOT_LENGTH,
 };
 
 /// Take in parking lots from OSM and all parking aisle roads. Match parking lots to the nearest
@@ -59,6 +59,8 @@ pub fn make_all_parking_lots(
                     driving_pos,
                     sidewalk_line,
                     sidewalk_pos,
+
+                    parking_policy: ParkingPolicy::default(),
                 });
             }
             Err(err) => {
@@ -0,0 +1,382 @@
+//! Coordinate the offsets of traffic signals along a corridor, so that vehicles traveling at the
+//! speed limit hit a "green wave" in both directions.
+//!
+//! This uses a simple bandwidth calculation, not simulation. The bandwidth in one direction is how
+//! much of the cycle a platoon could depart from the first signal and make it through every other
+//! signal without stopping.
+
+use std::collections::BTreeSet;
+
+use anyhow::Result;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use geom::{Duration, Time};
+
+use crate::{DirectedRoadID, EditCmd, EditIntersection, IntersectionID, Map, MovementID, RoadID};
+
+// Give up searching after this many passes over the corridor, even if it's still improving
+const MAX_PASSES: usize = 10;
+
+/// The result of optimizing offsets along a corridor.
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+pub struct CorridorOffsets {
+    /// Every signal along the corridor shares this cycle length.
+    pub cycle_length: Duration,
+    /// In order along the corridor, each signal, the plan that was changed, and its new offset.
+    /// The first signal's offset doesn't change.
+    pub offsets: Vec<(IntersectionID, usize, Duration)>,
+    pub forward_bandwidth_before: Duration,
+    pub backward_bandwidth_before: Duration,
+    pub forward_bandwidth_after: Duration,
+    pub backward_bandwidth_after: Duration,
+}
+
+impl CorridorOffsets {
+    /// Produce the edits that write the new offsets into the map.
+    pub fn to_edit_cmds(&self, map: &Map) -> Vec<EditCmd> {
+        let mut cmds = Vec::new();
+        for (i, plan, offset) in &self.offsets {
+            let mut ts = map.get_traffic_signal(*i).clone();
+            if ts.get_offset(*plan) == *offset {
+                continue;
+            }
+            ts.set_offset(*plan, *offset);
+            cmds.push(EditCmd::ChangeIntersection {
+                i: *i,
+                old: map.get_i_edit(*i),
+                new: EditIntersection::TrafficSignal(ts.export(map)),
+            });
+        }
+        cmds
+    }
+}
+
+/// Find offsets for a chain of adjacent traffic signals that maximize the combined bandwidth in
+/// both directions. Each signal uses whichever plan is in effect at `time`, and all of those plans
+/// must have the same cycle length. Stages that aren't fixed are treated as lasting their minimum
+/// duration.
+pub fn optimize_corridor_offsets(
+    map: &Map,
+    corridor: &[IntersectionID],
+    time: Time,
+) -> Result<CorridorOffsets> {
+    if corridor.len() < 2 {
+        bail!("A corridor needs at least two traffic signals");
+    }
+    if corridor.iter().collect::<BTreeSet<_>>().len() != corridor.len() {
+        bail!("A corridor can't visit the same intersection twice");
+    }
+
+    let mut plans = Vec::new();
+    let mut cycle_length = None;
+    for i in corridor {
+        let ts = map
+            .maybe_get_traffic_signal(*i)
+            .ok_or_else(|| anyhow!("{} isn't a traffic signal", i))?;
+        let plan = ts.plan_idx_at(time);
+        let cycle = ts.simple_cycle_duration(plan);
+        if let Some(expected) = cycle_length {
+            if cycle != expected {
+                bail!(
+                    "{} has a cycle length of {}, but {} has {}. Coordinated signals need the \
+                     same cycle length.",
+                    i,
+                    cycle,
+                    corridor[0],
+                    expected
+                );
+            }
+        }
+        cycle_length = Some(cycle);
+        plans.push(plan);
+    }
+    let cycle_length = cycle_length.unwrap();
+    // Work in whole seconds; offsets are exported that way anyway
+    let cycle = cycle_length.inner_seconds().round() as usize;
+    if cycle == 0 {
+        bail!("The signals along the corridor have an empty cycle");
+    }
+
+    // The road between each consecutive pair of signals, and how long it takes to drive
+    let mut roads: Vec<RoadID> = Vec::new();
+    let mut travel_times: Vec<usize> = Vec::new();
+    for pair in corridor.windows(2) {
+        let r = map
+            .get_i(pair[0])
+            .roads
+            .iter()
+            .find(|r| map.get_r(**r).other_endpt(pair[0]) == pair[1])
+            .cloned()
+            .ok_or_else(|| anyhow!("{} and {} aren't connected by a road", pair[0], pair[1]))?;
+        let road = map.get_r(r);
+        roads.push(r);
+        travel_times.push((road.length() / road.speed_limit).inner_seconds().round() as usize);
+    }
+
+    // When is each signal green for traffic continuing along the corridor?
+    let mut forward_green = Vec::new();
+    let mut backward_green = Vec::new();
+    for (idx, i) in corridor.iter().enumerate() {
+        let prev = if idx == 0 { None } else { Some(roads[idx - 1]) };
+        let next = roads.get(idx).cloned();
+        forward_green.push(green_pattern(
+            map,
+            *i,
+            plans[idx],
+            cycle,
+            prev.map(|r| map.get_r(r).directed_id_to(*i)),
+            next.map(|r| map.get_r(r).directed_id_from(*i)),
+        )?);
+        backward_green.push(green_pattern(
+            map,
+            *i,
+            plans[idx],
+            cycle,
+            next.map(|r| map.get_r(r).directed_id_to(*i)),
+            prev.map(|r| map.get_r(r).directed_id_from(*i)),
+        )?);
+    }
+
+    // How long after departing the first signal does a vehicle reach each signal? And the same
+    // from the last signal going backwards.
+    let mut forward_arrival = vec![0];
+    for dt in &travel_times {
+        forward_arrival.push(forward_arrival.last().unwrap() + dt);
+    }
+    let total = *forward_arrival.last().unwrap();
+    let backward_arrival: Vec<usize> = forward_arrival.iter().map(|t| total - t).collect();
+    let signals = Corridor {
+        cycle,
+        forward_green,
+        backward_green,
+        forward_arrival,
+        backward_arrival,
+    };
+
+    let offsets: Vec<usize> = corridor
+        .iter()
+        .zip(plans.iter())
+        .map(|(i, plan)| {
+            (map.get_traffic_signal(*i)
+                .get_offset(*plan)
+                .inner_seconds()
+                .round() as usize)
+                % cycle
+        })
+        .collect();
+    let before = signals.score(&offsets);
+    let offsets = signals.optimize(offsets);
+    let after = signals.score(&offsets);
+
+    Ok(CorridorOffsets {
+        cycle_length,
+        offsets: corridor
+            .iter()
+            .zip(plans.iter())
+            .zip(offsets)
+            .map(|((i, plan), offset)| (*i, *plan, Duration::seconds(offset as f64)))
+            .collect(),
+        forward_bandwidth_before: Duration::seconds(before.0 as f64),
+        backward_bandwidth_before: Duration::seconds(before.1 as f64),
+        forward_bandwidth_after: Duration::seconds(after.0 as f64),
+        backward_bandwidth_after: Duration::seconds(after.1 as f64),
+    })
+}
+
+/// When each signal along a corridor is green and how long it takes to reach it, all in whole
+/// seconds
+struct Corridor {
+    cycle: usize,
+    /// For each signal, is it green in each second of the cycle, before applying its offset?
+    forward_green: Vec<Vec<bool>>,
+    backward_green: Vec<Vec<bool>>,
+    /// How long after departing the first signal a vehicle reaches each signal, and the same from
+    /// the last signal going backwards
+    forward_arrival: Vec<usize>,
+    backward_arrival: Vec<usize>,
+}
+
+impl Corridor {
+    /// How many seconds of the cycle a vehicle could depart and make it through every signal
+    fn bandwidth(&self, green: &[Vec<bool>], arrival: &[usize], offsets: &[usize]) -> usize {
+        (0..self.cycle)
+            .filter(|depart| {
+                green
+                    .iter()
+                    .zip(arrival)
+                    .zip(offsets)
+                    .all(|((green, arrival), offset)| {
+                        green[(depart + arrival + offset) % self.cycle]
+                    })
+            })
+            .count()
+    }
+
+    /// (forward, backward) bandwidth through the first `num_signals`
+    fn score_prefix(&self, offsets: &[usize], num_signals: usize) -> (usize, usize) {
+        (
+            self.bandwidth(
+                &self.forward_green[..num_signals],
+                &self.forward_arrival[..num_signals],
+                &offsets[..num_signals],
+            ),
+            self.bandwidth(
+                &self.backward_green[..num_signals],
+                &self.backward_arrival[..num_signals],
+                &offsets[..num_signals],
+            ),
+        )
+    }
+
+    /// (forward, backward) bandwidth
+    fn score(&self, offsets: &[usize]) -> (usize, usize) {
+        self.score_prefix(offsets, offsets.len())
+    }
+
+    /// Coordinate descent: holding the others fixed, try every offset for one signal at a time.
+    /// The first signal stays put as a reference point.
+    fn optimize(&self, mut offsets: Vec<usize>) -> Vec<usize> {
+        // If no single change helps, the descent is stuck immediately. So also try lining up each
+        // signal with the ones before it, and start from whichever is better.
+        let mut lined_up = offsets.clone();
+        for idx in 1..lined_up.len() {
+            lined_up[idx] = (0..self.cycle)
+                .max_by_key(|offset| {
+                    let mut candidate = lined_up.clone();
+                    candidate[idx] = *offset;
+                    let (fwd, back) = self.score_prefix(&candidate, idx + 1);
+                    fwd + back
+                })
+                .unwrap();
+        }
+        let total = |offsets: &[usize]| {
+            let (fwd, back) = self.score(offsets);
+            fwd + back
+        };
+        if total(&lined_up) > total(&offsets) {
+            offsets = lined_up;
+        }
+
+        let mut best_total = total(&offsets);
+        for _ in 0..MAX_PASSES {
+            let mut improved = false;
+            for idx in 1..offsets.len() {
+                let mut candidate = offsets.clone();
+                for offset in 0..self.cycle {
+                    candidate[idx] = offset;
+                    let (fwd, back) = self.score(&candidate);
+                    if fwd + back > best_total {
+                        best_total = fwd + back;
+                        offsets[idx] = offset;
+                        improved = true;
+                    }
+                }
+            }
+            if !improved {
+                break;
+            }
+        }
+        offsets
+    }
+}
+
+/// For each second of the cycle (before the offset is applied), is some movement from `from` to
+/// `to` allowed? Either side may be unspecified at the ends of the corridor.
+fn green_pattern(
+    map: &Map,
+    i: IntersectionID,
+    plan: usize,
+    cycle: usize,
+    from: Option<DirectedRoadID>,
+    to: Option<DirectedRoadID>,
+) -> Result<Vec<bool>> {
+    let intersection = map.get_i(i);
+    let movements: Vec<MovementID> = intersection
+        .movements
+        .keys()
+        .filter(|m| {
+            !m.crosswalk
+                && from.map(|r| m.from == r).unwrap_or(true)
+                && to.map(|r| m.to == r).unwrap_or(true)
+        })
+        .cloned()
+        .collect();
+    if movements.is_empty() {
+        bail!("Vehicles can't continue along the corridor through {}", i);
+    }
+
+    let mut pattern = Vec::new();
+    for stage in map.get_traffic_signal(i).get_stages(plan) {
+        let allowed = movements
+            .iter()
+            .any(|m| stage.protected_movements.contains(m) || stage.yield_movements.contains(m));
+        let dt = stage.stage_type.simple_duration().inner_seconds().round() as usize;
+        pattern.resize(pattern.len() + dt, allowed);
+    }
+    // Rounding each stage could make this slightly off
+    pattern.resize(cycle, false);
+    Ok(pattern)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Green for the first `green` seconds of the cycle
+    fn pattern(cycle: usize, green: usize) -> Vec<bool> {
+        (0..cycle).map(|t| t < green).collect()
+    }
+
+    #[test]
+    fn one_way_green_wave() {
+        // Only care about one direction; the other way is always green
+        let corridor = Corridor {
+            cycle: 60,
+            forward_green: vec![pattern(60, 30); 3],
+            backward_green: vec![vec![true; 60]; 3],
+            forward_arrival: vec![0, 20, 45],
+            backward_arrival: vec![45, 25, 0],
+        };
+        assert_eq!(corridor.score(&[0, 0, 0]), (0, 60));
+
+        // Each signal should turn green as the platoon from the first one arrives
+        let offsets = corridor.optimize(vec![0, 0, 0]);
+        assert_eq!(offsets, vec![0, 40, 15]);
+        assert_eq!(corridor.score(&offsets), (30, 60));
+    }
+
+    #[test]
+    fn two_way_tradeoff() {
+        // Both directions are green at the same time. Signals half a cycle apart in travel time
+        // can serve both directions perfectly.
+        let corridor = Corridor {
+            cycle: 60,
+            forward_green: vec![pattern(60, 30); 2],
+            backward_green: vec![pattern(60, 30); 2],
+            forward_arrival: vec![0, 30],
+            backward_arrival: vec![30, 0],
+        };
+        let offsets = corridor.optimize(vec![0, 0]);
+        assert_eq!(offsets[0], 0);
+        assert_eq!(corridor.score(&offsets), (30, 30));
+    }
+
+    #[test]
+    fn never_worse() {
+        let corridor = Corridor {
+            cycle: 90,
+            forward_green: vec![pattern(90, 40), pattern(90, 50), pattern(90, 35)],
+            backward_green: vec![pattern(90, 20), pattern(90, 30), pattern(90, 25)],
+            forward_arrival: vec![0, 17, 41],
+            backward_arrival: vec![41, 24, 0],
+        };
+        for start in [vec![0, 0, 0], vec![5, 70, 33], vec![89, 1, 45]] {
+            let (fwd, back) = corridor.score(&start);
+            let offsets = corridor.optimize(start.clone());
+            assert_eq!(offsets[0], start[0]);
+            let (new_fwd, new_back) = corridor.score(&offsets);
+            assert!(new_fwd + new_back >= fwd + back);
+        }
+    }
+}
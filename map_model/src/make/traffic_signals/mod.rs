@@ -13,6 +13,9 @@ use crate::{
 };
 use geom::Duration;
 
+pub use self::corridor::{optimize_corridor_offsets, CorridorOffsets};
+
+mod corridor;
 mod lagging_green;
 
 /// Applies a bunch of heuristics to a single intersection, returning the valid results in
@@ -85,15 +88,17 @@ pub fn get_possible_policies(
 
     // Make sure all possible policies have a minimum crosswalk time enforced
     for (_, signal) in &mut results {
-        for stage in &mut signal.stages {
-            let crosswalks: Vec<MovementID> = stage
-                .protected_movements
-                .iter()
-                .filter(|id| id.crosswalk)
-                .cloned()
-                .collect();
-            for id in crosswalks {
-                stage.enforce_minimum_crosswalk_time(&i.movements[&id]);
+        for plan in 0..signal.num_plans() {
+            for stage in signal.get_stages_mut(plan) {
+                let crosswalks: Vec<MovementID> = stage
+                    .protected_movements
+                    .iter()
+                    .filter(|id| id.crosswalk)
+                    .cloned()
+                    .collect();
+                for id in crosswalks {
+                    stage.enforce_minimum_crosswalk_time(&i.movements[&id]);
+                }
             }
         }
     }
@@ -107,6 +112,8 @@ fn new(id: IntersectionID) -> ControlTrafficSignal {
         id,
         stages: Vec::new(),
         offset: Duration::ZERO,
+        later_plans: Vec::new(),
+        transit_priority: None,
     }
 }
 
@@ -141,6 +141,7 @@ fn make_route(
         end_border,
         spawn_times: default_spawn_times(),
         orig_spawn_times: default_spawn_times(),
+        scheduled_offsets: Vec::new(),
     };
 
     let mut debug_route = "All parts of the route:".to_string();
@@ -307,7 +308,7 @@ impl Matcher {
     }
 }
 
-fn pick_start_lane(
+pub fn pick_start_lane(
     first_stop: Position,
     constraints: PathConstraints,
     map: &Map,
@@ -337,7 +338,7 @@ fn pick_start_lane(
     )
 }
 
-fn default_spawn_times() -> Vec<Time> {
+pub fn default_spawn_times() -> Vec<Time> {
     // Hourly spawning from midnight to 7, then every 30 minutes till 7, then hourly again
     let mut times = Vec::new();
     for i in 0..24 {
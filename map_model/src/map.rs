@@ -12,11 +12,13 @@ use geom::{Bounds, Distance, Duration, GPSBounds, Polygon, Pt2D, Ring, Time};
 
 use crate::raw::{OriginalRoad, RawMap};
 use crate::{
-    osm, Area, AreaID, AreaType, Building, BuildingID, BuildingType, BusRoute, BusRouteID, BusStop,
-    BusStopID, CompressedMovementID, ControlStopSign, ControlTrafficSignal, DirectedRoadID,
-    Direction, Intersection, IntersectionID, Lane, LaneID, LaneType, Map, MapEdits, Movement,
-    MovementID, OffstreetParking, ParkingLot, ParkingLotID, Path, PathConstraints, PathRequest,
-    PathV2, Pathfinder, Position, Road, RoadID, RoutingParams, Turn, TurnID, TurnType, Zone,
+    osm, Area, AreaID, AreaType, BikeShareDock, BikeShareDockID, Building, BuildingID,
+    BuildingType, BusRoute, BusRouteID, BusStop, BusStopID, ChargingStation, ChargingStationID,
+    CompressedMovementID, ControlStopSign, ControlTrafficSignal, DirectedRoadID, Direction,
+    Intersection, IntersectionID, Lane, LaneID, LaneType, Map, MapEdits, Movement, MovementID,
+    OffstreetParking, ParkingLot, ParkingLotID, Path, PathConstraints, PathRequest, PathV2,
+    Pathfinder, Position, Road, RoadID, RoutingParams, TransitItinerary, Turn, TurnID, TurnType,
+    Zone,
 };
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -121,6 +123,16 @@ impl Map {
                     self.parking_lots.len(),
                     serialized_size_bytes(&self.parking_lots),
                 ),
+                (
+                    "bike-share docks",
+                    self.bike_share_docks.len(),
+                    serialized_size_bytes(&self.bike_share_docks),
+                ),
+                (
+                    "charging stations",
+                    self.charging_stations.len(),
+                    serialized_size_bytes(&self.charging_stations),
+                ),
                 (
                     "zones",
                     self.zones.len(),
@@ -156,6 +168,8 @@ impl Map {
             bus_routes: Vec::new(),
             areas: Vec::new(),
             parking_lots: Vec::new(),
+            bike_share_docks: Vec::new(),
+            charging_stations: Vec::new(),
             zones: Vec::new(),
             boundary_polygon: Ring::must_new(vec![
                 Pt2D::new(0.0, 0.0),
@@ -212,6 +226,14 @@ impl Map {
         &self.parking_lots
     }
 
+    pub fn all_bike_share_docks(&self) -> &Vec<BikeShareDock> {
+        &self.bike_share_docks
+    }
+
+    pub fn all_charging_stations(&self) -> &Vec<ChargingStation> {
+        &self.charging_stations
+    }
+
     pub fn all_zones(&self) -> &Vec<Zone> {
         &self.zones
     }
@@ -247,6 +269,14 @@ impl Map {
         self.parking_lots.get(id.0)
     }
 
+    pub fn maybe_get_bike_share_dock(&self, id: BikeShareDockID) -> Option<&BikeShareDock> {
+        self.bike_share_docks.get(id.0)
+    }
+
+    pub fn maybe_get_charging_station(&self, id: ChargingStationID) -> Option<&ChargingStation> {
+        self.charging_stations.get(id.0)
+    }
+
     pub fn maybe_get_a(&self, id: AreaID) -> Option<&Area> {
         self.areas.get(id.0)
     }
@@ -304,6 +334,14 @@ impl Map {
         &self.parking_lots[id.0]
     }
 
+    pub fn get_bike_share_dock(&self, id: BikeShareDockID) -> &BikeShareDock {
+        &self.bike_share_docks[id.0]
+    }
+
+    pub fn get_charging_station(&self, id: ChargingStationID) -> &ChargingStation {
+        &self.charging_stations[id.0]
+    }
+
     pub fn get_stop_sign(&self, id: IntersectionID) -> &ControlStopSign {
         &self.stop_signs[&id]
     }
@@ -464,7 +502,9 @@ impl Map {
     }
 
     pub fn get_bus_route(&self, name: &str) -> Option<&BusRoute> {
-        self.bus_routes.iter().find(|r| r.full_name == name)
+        self.bus_routes
+            .iter()
+            .find(|r| r.full_name == name && !r.is_deleted())
     }
 
     pub fn get_routes_serving_stop(&self, stop: BusStopID) -> Vec<&BusRoute> {
@@ -596,6 +636,21 @@ impl Map {
             .pathfind_with_params(req.clone(), params, cache_custom, self)
             .ok_or_else(|| anyhow!("can't fulfill {}", req))
     }
+    /// Like `pathfind_v2`, but adds some extra cost to each movement, like live delays at
+    /// intersections. This is much slower, since it can't use the contraction hierarchies.
+    pub fn pathfind_v2_with_extra_costs<F: Fn(MovementID) -> Duration>(
+        &self,
+        req: PathRequest,
+        extra_cost: F,
+    ) -> Result<PathV2> {
+        crate::pathfind::pathfind_with_extra_costs(
+            req.clone(),
+            &self.routing_params,
+            self,
+            extra_cost,
+        )
+        .ok_or_else(|| anyhow!("can't fulfill {}", req))
+    }
     pub fn should_use_transit(
         &self,
         start: Position,
@@ -605,6 +660,25 @@ impl Map {
         self.pathfinder.should_use_transit(self, start, end)
     }
 
+    /// Plan a trip riding transit, possibly with transfers, using the real schedule of each route.
+    /// Returns None if just walking is at least as fast.
+    pub fn plan_transit_trip(
+        &self,
+        start: Position,
+        end: Position,
+        depart: Time,
+    ) -> Option<TransitItinerary> {
+        assert!(!self.pathfinder_dirty);
+        self.pathfinder.plan_transit_trip(self, start, end, depart)
+    }
+
+    /// Estimate how long it takes to walk from start to end, riding transit if that helps. This
+    /// optimistically assumes no waiting at stops.
+    pub fn estimate_transit_duration(&self, start: Position, end: Position) -> Option<Duration> {
+        assert!(!self.pathfinder_dirty);
+        self.pathfinder.estimate_transit_duration(self, start, end)
+    }
+
     /// Return the cost of a single path, and also a mapping from every directed road to the cost
     /// of getting there from the same start. This can be used to understand why an alternative
     /// route wasn't chosen.
@@ -655,6 +729,15 @@ impl Map {
         None
     }
 
+    pub fn find_pl_by_osm_id(&self, id: osm::OsmID) -> Option<ParkingLotID> {
+        for pl in self.all_parking_lots() {
+            if pl.osm_id == id {
+                return Some(pl.id);
+            }
+        }
+        None
+    }
+
     pub fn find_br(&self, id: osm::RelationID) -> Option<BusRouteID> {
         for br in self.all_bus_routes() {
             if br.osm_rel_id == id {
@@ -688,6 +771,19 @@ impl Map {
         self.bus_routes[br.0].spawn_times = times;
     }
 
+    /// Replace a route's synthetic schedule with a real timetable: when vehicles begin the route,
+    /// and how long after that they're scheduled to reach each stop.
+    pub fn hack_override_timetable(
+        &mut self,
+        br: BusRouteID,
+        times: Vec<Time>,
+        offsets: Vec<Duration>,
+    ) {
+        assert_eq!(offsets.len(), self.bus_routes[br.0].stops.len());
+        self.hack_override_orig_spawn_times(br, times);
+        self.bus_routes[br.0].scheduled_offsets = offsets;
+    }
+
     pub fn hack_add_area(&mut self, area_type: AreaType, polygon: Polygon, osm_tags: Tags) {
         self.areas.push(Area {
             id: AreaID(self.areas.len()),
@@ -0,0 +1,52 @@
+use std::fmt;
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use abstutil::{deserialize_usize, serialize_usize};
+use geom::Pt2D;
+
+use crate::objects::building::sidewalk_to_bike;
+use crate::{osm, LaneID, Map, Position};
+
+#[derive(
+    Clone, Copy, Debug, Eq, Hash, PartialEq, PartialOrd, Ord, Serialize, Deserialize, JsonSchema,
+)]
+pub struct BikeShareDockID(
+    #[serde(
+        serialize_with = "serialize_usize",
+        deserialize_with = "deserialize_usize"
+    )]
+    pub usize,
+);
+
+impl fmt::Display for BikeShareDockID {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Bike-share dock #{}", self.0)
+    }
+}
+
+/// A station where people can rent a shared bike (or kick-scooter) and return it at any other
+/// dock. It sits on a sidewalk next to some lane that bikes can use.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct BikeShareDock {
+    pub id: BikeShareDockID,
+    pub osm_id: osm::NodeID,
+    pub name: Option<String>,
+    pub pt: Pt2D,
+    pub capacity: usize,
+    pub scooters: bool,
+    pub sidewalk_pos: Position,
+}
+
+impl BikeShareDock {
+    pub fn sidewalk(&self) -> LaneID {
+        self.sidewalk_pos.lane()
+    }
+
+    /// Returns (bike position, sidewalk position). Like for buildings, this can change with map
+    /// edits, or even disappear if the road no longer has anywhere to bike.
+    pub fn biking_connection(&self, map: &Map) -> Option<(Position, Position)> {
+        sidewalk_to_bike(self.sidewalk_pos, map)
+    }
+}
@@ -1,6 +1,7 @@
 use std::collections::{BTreeMap, HashSet, VecDeque};
 use std::fmt;
 
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use strum::IntoEnumIterator;
 use strum_macros::{Display, EnumIter, EnumString};
@@ -12,7 +13,9 @@ use geom::{Distance, PolyLine, Polygon, Pt2D};
 
 use crate::{osm, LaneID, Map, PathConstraints, Position};
 
-#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, PartialOrd, Ord, Serialize, Deserialize)]
+#[derive(
+    Clone, Copy, Debug, Eq, Hash, PartialEq, PartialOrd, Ord, Serialize, Deserialize, JsonSchema,
+)]
 pub struct BuildingID(
     #[serde(
         serialize_with = "serialize_usize",
@@ -214,7 +217,7 @@ impl Building {
     }
 }
 
-fn sidewalk_to_bike(sidewalk_pos: Position, map: &Map) -> Option<(Position, Position)> {
+pub(crate) fn sidewalk_to_bike(sidewalk_pos: Position, map: &Map) -> Option<(Position, Position)> {
     let lane = map
         .get_parent(sidewalk_pos.lane())
         .find_closest_lane(sidewalk_pos.lane(), |l| {
@@ -3,14 +3,17 @@
 
 use std::fmt;
 
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 use abstutil::{deserialize_usize, serialize_usize};
-use geom::Time;
+use geom::{Duration, Time};
 
 use crate::{osm, LaneID, Map, PathConstraints, PathRequest, Position};
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[derive(
+    Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize, JsonSchema,
+)]
 pub struct BusStopID {
     pub sidewalk: LaneID,
     /// As long as this is unique per lane, this value is otherwise meaningless. Not contiguous or
@@ -18,13 +21,22 @@ pub struct BusStopID {
     pub(crate) idx: usize,
 }
 
+impl BusStopID {
+    /// Real stops are created by the map, so this is mostly useful for tests.
+    pub fn new(sidewalk: LaneID, idx: usize) -> BusStopID {
+        BusStopID { sidewalk, idx }
+    }
+}
+
 impl fmt::Display for BusStopID {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "BusStopID({0}, {1})", self.sidewalk, self.idx)
     }
 }
 
-#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, PartialOrd, Ord, Serialize, Deserialize)]
+#[derive(
+    Clone, Copy, Debug, Eq, Hash, PartialEq, PartialOrd, Ord, Serialize, Deserialize, JsonSchema,
+)]
 pub struct BusRouteID(
     #[serde(
         serialize_with = "serialize_usize",
@@ -56,21 +68,32 @@ pub struct BusRoute {
     pub full_name: String,
     pub short_name: String,
     pub gtfs_trip_marker: Option<String>,
+    /// Routes created by map edits don't exist in OSM, so they get negative IDs.
     pub osm_rel_id: osm::RelationID,
+    /// Empty if the route has been deleted by map edits. The route keeps its ID, so it can be
+    /// restored later.
     pub stops: Vec<BusStopID>,
     /// May be a border or not. If not, is long enough for buses to spawn fully.
     pub start: LaneID,
     pub end_border: Option<LaneID>,
     pub route_type: PathConstraints,
-    /// Non-empty, times in order for one day when a vehicle should begin at start.
+    /// Times in order for one day when a vehicle should begin at start. Only empty if the route
+    /// is deleted.
     pub spawn_times: Vec<Time>,
     /// Explicitly store whatever the original was, since this can't be reconstructed without side
     /// input.
     pub orig_spawn_times: Vec<Time>,
+    /// If imported from a real timetable, how long after beginning at start a vehicle is
+    /// scheduled to reach each stop. Otherwise empty.
+    #[serde(default)]
+    pub scheduled_offsets: Vec<Duration>,
 }
 
 impl BusRoute {
     pub fn all_steps(&self, map: &Map) -> Vec<PathRequest> {
+        if self.is_deleted() {
+            return Vec::new();
+        }
         let mut steps = vec![PathRequest::vehicle(
             Position::start(self.start),
             map.get_bs(self.stops[0]).driving_pos,
@@ -93,6 +116,20 @@ impl BusRoute {
         steps
     }
 
+    /// If this route has a real timetable, when is a vehicle that began at `departure` scheduled
+    /// to reach the stop at this index?
+    pub fn scheduled_arrival(&self, departure: Time, stop_idx: usize) -> Option<Time> {
+        self.scheduled_offsets
+            .get(stop_idx)
+            .map(|offset| departure + *offset)
+    }
+
+    /// Routes deleted by map edits stay around with no stops, so that the IDs of other routes
+    /// don't change. Most callers should skip these.
+    pub fn is_deleted(&self) -> bool {
+        self.stops.is_empty()
+    }
+
     pub fn plural_noun(&self) -> &'static str {
         if self.route_type == PathConstraints::Bus {
             "buses"
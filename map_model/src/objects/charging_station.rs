@@ -0,0 +1,45 @@
+use std::fmt;
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use abstutil::{deserialize_usize, serialize_usize};
+use geom::Pt2D;
+
+use crate::{osm, LaneID, Position};
+
+#[derive(
+    Clone, Copy, Debug, Eq, Hash, PartialEq, PartialOrd, Ord, Serialize, Deserialize, JsonSchema,
+)]
+pub struct ChargingStationID(
+    #[serde(
+        serialize_with = "serialize_usize",
+        deserialize_with = "deserialize_usize"
+    )]
+    pub usize,
+);
+
+impl fmt::Display for ChargingStationID {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Charging station #{}", self.0)
+    }
+}
+
+/// A place where electric vehicles can plug in while they're parked. It sits on a sidewalk; any
+/// car parked close enough to it can use one of its chargers.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ChargingStation {
+    pub id: ChargingStationID,
+    pub osm_id: osm::NodeID,
+    pub name: Option<String>,
+    pub pt: Pt2D,
+    /// How many vehicles can charge here at once
+    pub capacity: usize,
+    pub sidewalk_pos: Position,
+}
+
+impl ChargingStation {
+    pub fn sidewalk(&self) -> LaneID {
+        self.sidewalk_pos.lane()
+    }
+}
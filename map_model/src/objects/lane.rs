@@ -1,6 +1,9 @@
 use std::collections::BTreeSet;
 use std::fmt;
 
+use schemars::gen::SchemaGenerator;
+use schemars::schema::{InstanceType, Metadata, Schema, SchemaObject};
+use schemars::JsonSchema;
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
 use abstutil::Tags;
@@ -78,7 +81,30 @@ impl<'de> Deserialize<'de> for LaneID {
     }
 }
 
-#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, PartialOrd, Ord, Serialize, Deserialize)]
+impl JsonSchema for LaneID {
+    fn schema_name() -> String {
+        "LaneID".to_string()
+    }
+
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        Schema::Object(SchemaObject {
+            metadata: Some(Box::new(Metadata {
+                description: Some(
+                    "The road ID multiplied by 32, plus the lane's offset from the left"
+                        .to_string(),
+                ),
+                ..Default::default()
+            })),
+            instance_type: Some(InstanceType::Integer.into()),
+            format: Some("uint32".to_string()),
+            ..Default::default()
+        })
+    }
+}
+
+#[derive(
+    Clone, Copy, Debug, Eq, Hash, PartialEq, PartialOrd, Ord, Serialize, Deserialize, JsonSchema,
+)]
 pub enum LaneType {
     Driving,
     Parking,
@@ -94,7 +120,9 @@ pub enum LaneType {
     Buffer(BufferType),
 }
 
-#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, PartialOrd, Ord, Serialize, Deserialize)]
+#[derive(
+    Clone, Copy, Debug, Eq, Hash, PartialEq, PartialOrd, Ord, Serialize, Deserialize, JsonSchema,
+)]
 pub enum BufferType {
     /// Just paint!
     Stripes,
@@ -256,7 +284,7 @@ pub struct Lane {
     pub biking_blackhole: bool,
 }
 
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub struct LaneSpec {
     pub lt: LaneType,
     pub dir: Direction,
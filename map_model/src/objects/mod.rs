@@ -1,7 +1,9 @@
 pub mod area;
+pub mod bike_share_dock;
 pub mod block;
 pub mod building;
 pub mod bus_stop;
+pub mod charging_station;
 pub mod intersection;
 pub mod lane;
 pub mod movement;
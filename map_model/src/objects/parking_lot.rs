@@ -1,16 +1,19 @@
 use std::fmt;
 
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 use abstutil::{deserialize_usize, serialize_usize};
-use geom::{Angle, Line, PolyLine, Polygon, Pt2D};
+use geom::{Angle, Duration, Line, PolyLine, Polygon, Pt2D};
 
 use crate::{osm, Position};
 
 // TODO For now, ignore the mapped roads linking things and just use the same driveway approach
 // that buildings use.
 
-#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, PartialOrd, Ord, Serialize, Deserialize)]
+#[derive(
+    Clone, Copy, Debug, Eq, Hash, PartialEq, PartialOrd, Ord, Serialize, Deserialize, JsonSchema,
+)]
 pub struct ParkingLotID(
     #[serde(
         serialize_with = "serialize_usize",
@@ -47,6 +50,8 @@ pub struct ParkingLot {
     /// Lot to sidewalk
     pub sidewalk_line: Line,
     pub sidewalk_pos: Position,
+
+    pub parking_policy: ParkingPolicy,
 }
 
 impl ParkingLot {
@@ -54,3 +59,46 @@ impl ParkingLot {
         self.spots.len() + self.extra_spots
     }
 }
+
+/// What it costs to park somewhere, and for how long somebody may stay. This applies to a parking
+/// lot or to all of the parking lanes along a road. Offstreet parking in buildings is always free.
+/// OSM doesn't consistently tag fees or time limits yet, so everything starts free and unlimited;
+/// only map edits change this.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct ParkingPolicy {
+    /// Cents, to keep prices exact. 0 means free.
+    pub cents_per_hour: usize,
+    /// None means there's no limit.
+    pub max_duration: Option<Duration>,
+}
+
+impl ParkingPolicy {
+    pub fn is_unrestricted(&self) -> bool {
+        self.cents_per_hour == 0 && self.max_duration.is_none()
+    }
+
+    /// How many cents to park for some amount of time, rounded to the nearest cent
+    pub fn cost(&self, stay: Duration) -> usize {
+        (self.cents_per_hour as f64 * (stay / Duration::hours(1))).round() as usize
+    }
+
+    pub fn allows_stay(&self, stay: Duration) -> bool {
+        self.max_duration.map(|max| stay <= max).unwrap_or(true)
+    }
+
+    pub fn describe(&self) -> String {
+        let price = if self.cents_per_hour == 0 {
+            "free".to_string()
+        } else {
+            format!(
+                "${}.{:02}/hour",
+                self.cents_per_hour / 100,
+                self.cents_per_hour % 100
+            )
+        };
+        match self.max_duration {
+            Some(max) => format!("{}, {} maximum", price, max),
+            None => price,
+        }
+    }
+}
@@ -3,6 +3,7 @@ use std::fmt;
 
 use anyhow::Result;
 use enumset::EnumSet;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 use abstutil::{deserialize_usize, serialize_usize, Tags};
@@ -11,10 +12,12 @@ use geom::{Distance, PolyLine, Polygon, Speed};
 use crate::raw::{OriginalRoad, RestrictionType};
 use crate::{
     osm, AccessRestrictions, BusStopID, DrivingSide, IntersectionID, Lane, LaneID, LaneSpec,
-    LaneType, Map, PathConstraints, Zone,
+    LaneType, Map, ParkingPolicy, PathConstraints, Zone,
 };
 
-#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, PartialOrd, Ord, Serialize, Deserialize)]
+#[derive(
+    Clone, Copy, Debug, Eq, Hash, PartialEq, PartialOrd, Ord, Serialize, Deserialize, JsonSchema,
+)]
 pub struct RoadID(
     #[serde(
         serialize_with = "serialize_usize",
@@ -44,7 +47,9 @@ impl RoadID {
     }
 }
 
-#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, PartialOrd, Ord, Serialize, Deserialize)]
+#[derive(
+    Clone, Copy, Debug, Eq, Hash, PartialEq, PartialOrd, Ord, Serialize, Deserialize, JsonSchema,
+)]
 pub enum Direction {
     Fwd,
     Back,
@@ -68,7 +73,9 @@ impl fmt::Display for Direction {
     }
 }
 
-#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, PartialOrd, Ord, Serialize, Deserialize)]
+#[derive(
+    Clone, Copy, Debug, Eq, Hash, PartialEq, PartialOrd, Ord, Serialize, Deserialize, JsonSchema,
+)]
 pub struct DirectedRoadID {
     pub id: RoadID,
     pub dir: Direction,
@@ -165,6 +172,8 @@ pub struct Road {
     pub orig_id: OriginalRoad,
     pub speed_limit: Speed,
     pub access_restrictions: AccessRestrictions,
+    /// Applies to every parking lane along the road
+    pub parking_policy: ParkingPolicy,
     pub zorder: isize,
     /// [-1.0, 1.0] theoretically, but in practice, about [-0.25, 0.25]. 0 is flat,
     /// positive is uphill from src_i -> dst_i, negative is downhill.
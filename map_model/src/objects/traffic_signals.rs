@@ -1,9 +1,10 @@
 use std::collections::{BTreeMap, BTreeSet};
 
 use anyhow::Result;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-use geom::{Distance, Duration, Speed};
+use geom::{Distance, Duration, Speed, Time};
 
 use crate::make::traffic_signals::get_possible_policies;
 use crate::raw::OriginalRoad;
@@ -19,14 +20,56 @@ const CROSSWALK_PACE: Speed = Speed::const_meters_per_second(1.4);
 /// A traffic signal consists of a sequence of Stages that repeat in a cycle. Most Stages last for a
 /// fixed duration. During a single Stage, some movements are protected (can proceed with the
 /// highest priority), while others are permitted (have to yield before proceeding).
-#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+///
+/// Some signals switch to a different plan, with different stages, at certain times of day. Plans
+/// are indexed from 0, which is `stages` and `offset`. Plan `idx` after that is `later_plans[idx -
+/// 1]`.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
 pub struct ControlTrafficSignal {
     pub id: IntersectionID,
+    /// The stages of the plan starting at midnight
     pub stages: Vec<Stage>,
+    /// The offset of the plan starting at midnight
     pub offset: Duration,
+    /// Plans that take effect later in the day, in order of start time. Each plan lasts until the
+    /// next one starts, or until midnight. Most signals don't have any.
+    #[serde(default)]
+    pub later_plans: Vec<SignalPlan>,
+    /// If present, buses and trains approaching the signal can extend or cut short the current
+    /// stage, in every plan.
+    #[serde(default)]
+    pub transit_priority: Option<TransitSignalPriority>,
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+/// Limits on how much an approaching bus or train can change a traffic signal's timing.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct TransitSignalPriority {
+    /// If a bus won't make it through before its green ends, hold the green for up to this long.
+    pub max_extension: Duration,
+    /// If a bus is approaching a red, end the current stage up to this much earlier. Stages are
+    /// never cut shorter than the time needed for pedestrians to finish crossing.
+    pub max_truncation: Duration,
+}
+
+impl TransitSignalPriority {
+    pub fn default_limits() -> TransitSignalPriority {
+        TransitSignalPriority {
+            max_extension: Duration::seconds(10.0),
+            max_truncation: Duration::seconds(10.0),
+        }
+    }
+}
+
+/// How a traffic signal is configured, starting at some time of day.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct SignalPlan {
+    /// Measured from midnight
+    pub start_time: Duration,
+    pub stages: Vec<Stage>,
+    pub offset: Duration,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, JsonSchema)]
 pub struct Stage {
     pub protected_movements: BTreeSet<MovementID>,
     pub yield_movements: BTreeSet<MovementID>,
@@ -35,13 +78,26 @@ pub struct Stage {
     pub stage_type: StageType,
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, JsonSchema)]
 pub enum StageType {
     Fixed(Duration),
     /// Minimum is the minimum duration, 0 allows cycle to be skipped if no demand.
     /// Delay is the elapsed time with no demand that ends a cycle.
     /// Additional is the additional duration for an extended cycle.
     Variable(Duration, Duration, Duration),
+    /// Detector-actuated control. Minimum green, passage time, maximum green.
+    /// After the minimum, the stage extends while vehicles for its protected movements keep
+    /// arriving less than the passage time apart. When every protected movement goes longer than
+    /// that without a vehicle, the stage "gaps out"; it always ends at the maximum ("maxes out").
+    /// The stage is skipped if nobody is waiting for any of its movements. Crosswalks only get a
+    /// walk signal if a pedestrian pushed the button before the stage started.
+    Actuated(Duration, Duration, Duration),
+    /// Adaptive max-pressure control. Minimum, maximum.
+    /// Every time the minimum elapses, the signal picks whichever stage has the most pressure:
+    /// vehicles queued for its protected movements minus vehicles already on the destination
+    /// lanes, plus pedestrians waiting to cross. The current stage can't be picked again once it's
+    /// lasted the maximum. The order of stages doesn't matter.
+    MaxPressure(Duration, Duration),
 }
 
 impl StageType {
@@ -50,6 +106,8 @@ impl StageType {
         match self {
             StageType::Fixed(d) => *d,
             StageType::Variable(duration, _, _) => *duration,
+            StageType::Actuated(min, _, _) => *min,
+            StageType::MaxPressure(min, _) => *min,
         }
     }
 }
@@ -82,24 +140,109 @@ impl ControlTrafficSignal {
         get_possible_policies(map, id, false)
     }
 
-    pub fn get_min_crossing_time(&self, idx: usize, i: &Intersection) -> Duration {
-        let mut max_distance = Distance::meters(0.0);
-        for movement in &self.stages[idx].protected_movements {
-            if movement.crosswalk {
-                max_distance = max_distance.max(i.movements[movement].geom.length());
-            }
+    /// How many plans the signal has, including the one starting at midnight.
+    pub fn num_plans(&self) -> usize {
+        1 + self.later_plans.len()
+    }
+
+    /// Which plan is in effect at some time. Plans repeat every day.
+    pub fn plan_idx_at(&self, time: Time) -> usize {
+        let time_of_day = (time - Time::START_OF_DAY) % Duration::hours(24);
+        self.later_plans
+            .iter()
+            .take_while(|plan| plan.start_time <= time_of_day)
+            .count()
+    }
+
+    /// When a plan starts, measured from midnight
+    pub fn get_plan_start_time(&self, plan: usize) -> Duration {
+        if plan == 0 {
+            Duration::ZERO
+        } else {
+            self.later_plans[plan - 1].start_time
+        }
+    }
+
+    pub fn get_stages(&self, plan: usize) -> &Vec<Stage> {
+        if plan == 0 {
+            &self.stages
+        } else {
+            &self.later_plans[plan - 1].stages
+        }
+    }
+
+    pub fn get_stages_mut(&mut self, plan: usize) -> &mut Vec<Stage> {
+        if plan == 0 {
+            &mut self.stages
+        } else {
+            &mut self.later_plans[plan - 1].stages
         }
-        let time = max_distance / CROSSWALK_PACE;
-        assert!(time >= Duration::ZERO);
-        // Round up because it is converted to a usize elsewhere
-        Duration::seconds(time.inner_seconds().ceil())
+    }
+
+    pub fn get_offset(&self, plan: usize) -> Duration {
+        if plan == 0 {
+            self.offset
+        } else {
+            self.later_plans[plan - 1].offset
+        }
+    }
+
+    pub fn set_offset(&mut self, plan: usize, offset: Duration) {
+        if plan == 0 {
+            self.offset = offset;
+        } else {
+            self.later_plans[plan - 1].offset = offset;
+        }
+    }
+
+    /// Copy an existing plan, making it take effect at some time of day. Returns the index of the
+    /// new plan. If another plan already starts at that time, it's replaced.
+    pub fn add_plan(&mut self, copy_from: usize, start_time: Duration) -> usize {
+        assert!(start_time > Duration::ZERO && start_time < Duration::hours(24));
+        let plan = SignalPlan {
+            start_time,
+            stages: self.get_stages(copy_from).clone(),
+            offset: self.get_offset(copy_from),
+        };
+        self.later_plans.retain(|p| p.start_time != start_time);
+        let idx = self
+            .later_plans
+            .iter()
+            .take_while(|p| p.start_time < start_time)
+            .count();
+        self.later_plans.insert(idx, plan);
+        idx + 1
+    }
+
+    /// The plan starting at midnight can't be removed.
+    pub fn remove_plan(&mut self, plan: usize) {
+        assert!(plan != 0);
+        self.later_plans.remove(plan - 1);
     }
 
     pub fn validate(&self, i: &Intersection) -> Result<()> {
+        let mut last_start_time = Duration::ZERO;
+        for plan in &self.later_plans {
+            if plan.start_time <= last_start_time || plan.start_time >= Duration::hours(24) {
+                bail!(
+                    "Traffic signal {} has plans starting at bad times; they must be in order and \
+                     during the day",
+                    self.id
+                );
+            }
+            last_start_time = plan.start_time;
+        }
+        for plan in 0..self.num_plans() {
+            self.validate_stages(self.get_stages(plan), i)?;
+        }
+        Ok(())
+    }
+
+    fn validate_stages(&self, stages: &[Stage], i: &Intersection) -> Result<()> {
         // Does the assignment cover the correct set of movements?
         let expected_movements: BTreeSet<MovementID> = i.movements.keys().cloned().collect();
         let mut actual_movements: BTreeSet<MovementID> = BTreeSet::new();
-        for stage in &self.stages {
+        for stage in stages {
             actual_movements.extend(stage.protected_movements.iter());
             actual_movements.extend(stage.yield_movements.iter());
         }
@@ -117,7 +260,7 @@ impl ControlTrafficSignal {
                     .collect::<Vec<_>>()
             );
         }
-        for (stage_index, stage) in self.stages.iter().enumerate() {
+        for (stage_index, stage) in stages.iter().enumerate() {
             // Do any of the priority movements in one stage conflict?
             for m1 in stage.protected_movements.iter().map(|m| &i.movements[m]) {
                 for m2 in stage.protected_movements.iter().map(|m| &i.movements[m]) {
@@ -136,29 +279,59 @@ impl ControlTrafficSignal {
             for m in stage.yield_movements.iter().map(|m| &i.movements[m]) {
                 assert!(m.turn_type != TurnType::Crosswalk);
             }
-            // Is there enough time in each stage to walk across the crosswalk
-            let min_crossing_time = self.get_min_crossing_time(stage_index, i);
-            if stage.stage_type.simple_duration() < min_crossing_time {
+            // Is there enough time in each stage to walk across the crosswalk? Actuated stages
+            // stretch the minimum when a pedestrian calls, as long as the maximum allows it.
+            let min_crossing_time = stage.get_min_crossing_time(i);
+            let time_given = match stage.stage_type {
+                StageType::Actuated(_, _, max) => max,
+                _ => stage.stage_type.simple_duration(),
+            };
+            if time_given < min_crossing_time {
                 bail!(
                     "Traffic signal does not allow enough time in stage to complete the \
                      crosswalk\nStage Index{}\nStage : {:?}\nTime Required: {}\nTime Given: {}",
                     stage_index,
                     stage,
                     min_crossing_time,
-                    stage.stage_type.simple_duration()
+                    time_given
                 );
             }
+            if let StageType::Actuated(min, passage, max) = stage.stage_type {
+                if min > max || passage == Duration::ZERO {
+                    bail!(
+                        "Stage {} has a bad actuated timing {:?}",
+                        stage_index,
+                        stage
+                    );
+                }
+            }
+            if let StageType::MaxPressure(min, max) = stage.stage_type {
+                if min == Duration::ZERO || min > max {
+                    bail!(
+                        "Stage {} has a bad max-pressure timing {:?}",
+                        stage_index,
+                        stage
+                    );
+                }
+                if stages.len() < 2 {
+                    bail!(
+                        "Stage {} uses max-pressure, but there's no other stage to switch to",
+                        stage_index
+                    );
+                }
+            }
         }
         Ok(())
     }
 
-    /// Move crosswalks from stages, adding them to an all-walk as last stage. This may promote
-    /// yields to protected. True is returned if any stages were added or modified.
+    /// Move crosswalks from stages, adding them to an all-walk as last stage, in every plan. This
+    /// may promote yields to protected. True is returned if any stages were added or modified.
     pub fn convert_to_ped_scramble(&mut self, i: &Intersection) -> bool {
         self.internal_convert_to_ped_scramble(true, i)
     }
-    /// Move crosswalks from stages, adding them to an all-walk as last stage. This does not promote
-    /// yields to protected. True is returned if any stages were added or modified.
+    /// Move crosswalks from stages, adding them to an all-walk as last stage, in every plan. This
+    /// does not promote yields to protected. True is returned if any stages were added or
+    /// modified.
     pub fn convert_to_ped_scramble_without_promotion(&mut self, i: &Intersection) -> bool {
         self.internal_convert_to_ped_scramble(false, i)
     }
@@ -177,53 +350,55 @@ impl ControlTrafficSignal {
             }
         }
 
-        // Remove Crosswalk movements from existing stages.
-        let mut replaced = std::mem::take(&mut self.stages);
-        let mut has_all_walk = false;
-        for stage in replaced.iter_mut() {
-            if !has_all_walk && stage == &all_walk_stage {
-                has_all_walk = true;
-                continue;
-            }
+        for plan in 0..self.num_plans() {
+            // Remove Crosswalk movements from existing stages.
+            let stages = self.get_stages_mut(plan);
+            let mut has_all_walk = false;
+            for stage in stages.iter_mut() {
+                if !has_all_walk && stage == &all_walk_stage {
+                    has_all_walk = true;
+                    continue;
+                }
 
-            // Crosswalks are only in protected_movements.
-            stage
-                .protected_movements
-                .retain(|m| i.movements[m].turn_type != TurnType::Crosswalk);
-            if promote_yield_to_protected {
-                // Blindly try to promote yield movements to protected, now that crosswalks are
-                // gone.
-                let mut promoted = Vec::new();
-                for m in &stage.yield_movements {
-                    if stage.could_be_protected(*m, i) {
-                        stage.protected_movements.insert(*m);
-                        promoted.push(*m);
+                // Crosswalks are only in protected_movements.
+                stage
+                    .protected_movements
+                    .retain(|m| i.movements[m].turn_type != TurnType::Crosswalk);
+                if promote_yield_to_protected {
+                    // Blindly try to promote yield movements to protected, now that crosswalks
+                    // are gone.
+                    let mut promoted = Vec::new();
+                    for m in &stage.yield_movements {
+                        if stage.could_be_protected(*m, i) {
+                            stage.protected_movements.insert(*m);
+                            promoted.push(*m);
+                        }
+                    }
+                    for m in promoted {
+                        stage.yield_movements.remove(&m);
                     }
-                }
-                for m in promoted {
-                    stage.yield_movements.remove(&m);
                 }
             }
-        }
-        self.stages = replaced;
 
-        if !has_all_walk {
-            self.stages.push(all_walk_stage);
+            if !has_all_walk {
+                stages.push(all_walk_stage.clone());
+            }
         }
         self != &orig
     }
 
-    /// Modifies the fixed timing of all stages, applying either a major or minor duration,
-    /// depending on the relative rank of the roads involved in the intersection. If this
+    /// Modifies the fixed timing of all stages in one plan, applying either a major or minor
+    /// duration, depending on the relative rank of the roads involved in the intersection. If this
     /// transformation couldn't be applied, returns an error. Even if an error is returned, the
     /// signal may have been changed -- so only call this on a cloned signal.
     pub fn adjust_major_minor_timing(
         &mut self,
+        plan: usize,
         major: Duration,
         minor: Duration,
         map: &Map,
     ) -> Result<()> {
-        if self.stages.len() != 2 {
+        if self.get_stages(plan).len() != 2 {
             bail!("This intersection doesn't have 2 stages.");
         }
 
@@ -242,7 +417,7 @@ impl ControlTrafficSignal {
 
         // Try to apply the transformation
         let orig = self.clone();
-        for stage in &mut self.stages {
+        for stage in self.get_stages_mut(plan) {
             match stage.stage_type {
                 StageType::Fixed(_) => {}
                 _ => bail!("This intersection doesn't use fixed timing."),
@@ -259,7 +434,7 @@ impl ControlTrafficSignal {
             }
         }
 
-        if self.simple_cycle_duration() != major + minor {
+        if self.simple_cycle_duration(plan) != major + minor {
             bail!("This intersection didn't already group major/minor roads together.");
         }
 
@@ -270,9 +445,9 @@ impl ControlTrafficSignal {
         Ok(())
     }
 
-    pub fn missing_turns(&self, i: &Intersection) -> BTreeSet<MovementID> {
+    pub fn missing_turns(&self, plan: usize, i: &Intersection) -> BTreeSet<MovementID> {
         let mut missing: BTreeSet<MovementID> = i.movements.keys().cloned().collect();
-        for stage in &self.stages {
+        for stage in self.get_stages(plan) {
             for m in &stage.protected_movements {
                 missing.remove(m);
             }
@@ -283,10 +458,10 @@ impl ControlTrafficSignal {
         missing
     }
 
-    /// How long a full cycle of the signal lasts, assuming no actuated timings.
-    pub fn simple_cycle_duration(&self) -> Duration {
+    /// How long a full cycle of one plan lasts, assuming no actuated timings.
+    pub fn simple_cycle_duration(&self, plan: usize) -> Duration {
         let mut total = Duration::ZERO;
-        for s in &self.stages {
+        for s in self.get_stages(plan) {
             total += s.stage_type.simple_duration();
         }
         total
@@ -303,6 +478,19 @@ impl Stage {
         }
     }
 
+    pub fn get_min_crossing_time(&self, i: &Intersection) -> Duration {
+        let mut max_distance = Distance::meters(0.0);
+        for movement in &self.protected_movements {
+            if movement.crosswalk {
+                max_distance = max_distance.max(i.movements[movement].geom.length());
+            }
+        }
+        let time = max_distance / CROSSWALK_PACE;
+        assert!(time >= Duration::ZERO);
+        // Round up because it is converted to a usize elsewhere
+        Duration::seconds(time.inner_seconds().ceil())
+    }
+
     pub fn could_be_protected(&self, m1: MovementID, i: &Intersection) -> bool {
         let movement1 = &i.movements[&m1];
         for m2 in &self.protected_movements {
@@ -361,6 +549,11 @@ impl Stage {
                 StageType::Variable(_, delay, additional) => {
                     StageType::Variable(time, delay, additional)
                 }
+                // Only stretch the minimum when a pedestrian calls
+                StageType::Actuated(min, passage, max) => {
+                    StageType::Actuated(min, passage, max.max(time))
+                }
+                StageType::MaxPressure(_, max) => StageType::MaxPressure(time, max.max(time)),
             };
         }
     }
@@ -390,104 +583,157 @@ impl ControlTrafficSignal {
     pub fn export(&self, map: &Map) -> traffic_signal_data::TrafficSignal {
         traffic_signal_data::TrafficSignal {
             intersection_osm_node_id: map.get_i(self.id).orig_id.0,
-            plans: vec![traffic_signal_data::Plan {
-                start_time_seconds: 0,
-                stages: self
-                    .stages
-                    .iter()
-                    .map(|s| traffic_signal_data::Stage {
-                        protected_turns: s
-                            .protected_movements
-                            .iter()
-                            .map(|t| export_movement(t, map))
-                            .collect(),
-                        permitted_turns: s
-                            .yield_movements
-                            .iter()
-                            .map(|t| export_movement(t, map))
-                            .collect(),
-                        stage_type: match s.stage_type {
-                            StageType::Fixed(d) => {
-                                traffic_signal_data::StageType::Fixed(d.inner_seconds() as usize)
-                            }
-                            StageType::Variable(min, delay, additional) => {
-                                traffic_signal_data::StageType::Variable(
-                                    min.inner_seconds() as usize,
-                                    delay.inner_seconds() as usize,
-                                    additional.inner_seconds() as usize,
-                                )
-                            }
-                        },
-                    })
-                    .collect(),
-                offset_seconds: self.offset.inner_seconds() as usize,
-            }],
+            plans: (0..self.num_plans())
+                .map(|plan| traffic_signal_data::Plan {
+                    start_time_seconds: self.get_plan_start_time(plan).inner_seconds() as usize,
+                    stages: self
+                        .get_stages(plan)
+                        .iter()
+                        .map(|s| export_stage(s, map))
+                        .collect(),
+                    offset_seconds: self.get_offset(plan).inner_seconds() as usize,
+                })
+                .collect(),
+            transit_priority: self.transit_priority.as_ref().map(|tsp| {
+                traffic_signal_data::TransitPriority {
+                    max_extension_seconds: tsp.max_extension.inner_seconds() as usize,
+                    max_truncation_seconds: tsp.max_truncation.inner_seconds() as usize,
+                }
+            }),
         }
     }
 
     pub(crate) fn import(
-        mut raw: traffic_signal_data::TrafficSignal,
+        raw: traffic_signal_data::TrafficSignal,
         id: IntersectionID,
         map: &Map,
     ) -> Result<ControlTrafficSignal> {
-        // TODO Only import the first plan. Will import all of them later.
-        let plan = raw.plans.remove(0);
-        let mut stages = Vec::new();
-        for s in plan.stages {
-            let mut errors = Vec::new();
-            let mut protected_movements = BTreeSet::new();
-            for t in s.protected_turns {
-                match import_movement(t, map) {
-                    Ok(mvmnt) => {
-                        protected_movements.insert(mvmnt);
-                    }
-                    Err(err) => {
-                        errors.push(err.to_string());
-                    }
-                }
-            }
-            let mut permitted_movements = BTreeSet::new();
-            for t in s.permitted_turns {
-                match import_movement(t, map) {
-                    Ok(mvmnt) => {
-                        permitted_movements.insert(mvmnt);
-                    }
-                    Err(err) => {
-                        errors.push(err.to_string());
-                    }
-                }
-            }
-            if errors.is_empty() {
-                stages.push(Stage {
-                    protected_movements,
-                    yield_movements: permitted_movements,
-                    stage_type: match s.stage_type {
-                        traffic_signal_data::StageType::Fixed(d) => {
-                            StageType::Fixed(Duration::seconds(d as f64))
-                        }
-                        traffic_signal_data::StageType::Variable(min, delay, additional) => {
-                            StageType::Variable(
-                                Duration::seconds(min as f64),
-                                Duration::seconds(delay as f64),
-                                Duration::seconds(additional as f64),
-                            )
-                        }
-                    },
-                });
-            } else {
-                bail!("{}", errors.join("; "));
+        let mut plans = Vec::new();
+        for plan in raw.plans {
+            let mut stages = Vec::new();
+            for s in plan.stages {
+                stages.push(import_stage(s, map)?);
             }
+            plans.push(SignalPlan {
+                start_time: Duration::seconds(plan.start_time_seconds as f64),
+                stages,
+                offset: Duration::seconds(plan.offset_seconds as f64),
+            });
+        }
+        if plans.is_empty() {
+            bail!("Traffic signal {} has no plans", id);
+        }
+        let first = plans.remove(0);
+        if first.start_time != Duration::ZERO {
+            bail!(
+                "The first plan for traffic signal {} doesn't start at midnight",
+                id
+            );
         }
         let ts = ControlTrafficSignal {
             id,
-            stages,
-            offset: Duration::seconds(plan.offset_seconds as f64),
+            stages: first.stages,
+            offset: first.offset,
+            later_plans: plans,
+            transit_priority: raw.transit_priority.map(|tsp| TransitSignalPriority {
+                max_extension: Duration::seconds(tsp.max_extension_seconds as f64),
+                max_truncation: Duration::seconds(tsp.max_truncation_seconds as f64),
+            }),
         };
         ts.validate(map.get_i(id))?;
         Ok(ts)
     }
 }
 
+fn export_stage(s: &Stage, map: &Map) -> traffic_signal_data::Stage {
+    traffic_signal_data::Stage {
+        protected_turns: s
+            .protected_movements
+            .iter()
+            .map(|t| export_movement(t, map))
+            .collect(),
+        permitted_turns: s
+            .yield_movements
+            .iter()
+            .map(|t| export_movement(t, map))
+            .collect(),
+        stage_type: match s.stage_type {
+            StageType::Fixed(d) => {
+                traffic_signal_data::StageType::Fixed(d.inner_seconds() as usize)
+            }
+            StageType::Variable(min, delay, additional) => {
+                traffic_signal_data::StageType::Variable(
+                    min.inner_seconds() as usize,
+                    delay.inner_seconds() as usize,
+                    additional.inner_seconds() as usize,
+                )
+            }
+            StageType::Actuated(min, passage, max) => traffic_signal_data::StageType::Actuated(
+                min.inner_seconds() as usize,
+                passage.inner_seconds() as usize,
+                max.inner_seconds() as usize,
+            ),
+            StageType::MaxPressure(min, max) => traffic_signal_data::StageType::MaxPressure(
+                min.inner_seconds() as usize,
+                max.inner_seconds() as usize,
+            ),
+        },
+    }
+}
+
+fn import_stage(s: traffic_signal_data::Stage, map: &Map) -> Result<Stage> {
+    let mut errors = Vec::new();
+    let mut protected_movements = BTreeSet::new();
+    for t in s.protected_turns {
+        match import_movement(t, map) {
+            Ok(mvmnt) => {
+                protected_movements.insert(mvmnt);
+            }
+            Err(err) => {
+                errors.push(err.to_string());
+            }
+        }
+    }
+    let mut permitted_movements = BTreeSet::new();
+    for t in s.permitted_turns {
+        match import_movement(t, map) {
+            Ok(mvmnt) => {
+                permitted_movements.insert(mvmnt);
+            }
+            Err(err) => {
+                errors.push(err.to_string());
+            }
+        }
+    }
+    if !errors.is_empty() {
+        bail!("{}", errors.join("; "));
+    }
+    Ok(Stage {
+        protected_movements,
+        yield_movements: permitted_movements,
+        stage_type: match s.stage_type {
+            traffic_signal_data::StageType::Fixed(d) => {
+                StageType::Fixed(Duration::seconds(d as f64))
+            }
+            traffic_signal_data::StageType::Variable(min, delay, additional) => {
+                StageType::Variable(
+                    Duration::seconds(min as f64),
+                    Duration::seconds(delay as f64),
+                    Duration::seconds(additional as f64),
+                )
+            }
+            traffic_signal_data::StageType::Actuated(min, passage, max) => StageType::Actuated(
+                Duration::seconds(min as f64),
+                Duration::seconds(passage as f64),
+                Duration::seconds(max as f64),
+            ),
+            traffic_signal_data::StageType::MaxPressure(min, max) => {
+                StageType::MaxPressure(Duration::seconds(min as f64), Duration::seconds(max as f64))
+            }
+        },
+    })
+}
+
 fn export_movement(id: &MovementID, map: &Map) -> traffic_signal_data::Turn {
     let from = map.get_r(id.from.id).orig_id;
     let to = map.get_r(id.to.id).orig_id;
@@ -532,3 +778,40 @@ fn find_r(id: traffic_signal_data::DirectedRoad, map: &Map) -> Result<DirectedRo
         },
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plan_idx_at() {
+        let plan = |hours| SignalPlan {
+            start_time: Duration::hours(hours),
+            stages: Vec::new(),
+            offset: Duration::ZERO,
+        };
+        let signal = ControlTrafficSignal {
+            id: IntersectionID(0),
+            stages: Vec::new(),
+            offset: Duration::ZERO,
+            later_plans: vec![plan(7), plan(19)],
+            transit_priority: None,
+        };
+        let at = |hours, minutes| {
+            signal.plan_idx_at(
+                Time::START_OF_DAY + Duration::hours(hours) + Duration::minutes(minutes),
+            )
+        };
+
+        assert_eq!(at(0, 0), 0);
+        assert_eq!(at(6, 59), 0);
+        assert_eq!(at(7, 0), 1);
+        assert_eq!(at(18, 59), 1);
+        assert_eq!(at(19, 0), 2);
+        assert_eq!(at(23, 59), 2);
+        // Plans repeat the next day
+        assert_eq!(at(24, 0), 0);
+        assert_eq!(at(31, 0), 1);
+        assert_eq!(at(43, 0), 2);
+    }
+}
@@ -1,6 +1,7 @@
 use std::collections::BTreeSet;
 use std::fmt;
 
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 use geom::{Angle, PolyLine};
@@ -10,7 +11,9 @@ use crate::{Intersection, IntersectionID, LaneID, Map, MovementID, PathConstrain
 
 /// Turns are uniquely identified by their (src, dst) lanes and their parent intersection.
 /// Intersection is needed to distinguish crosswalks that exist at two ends of a sidewalk.
-#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, PartialOrd, Ord, Serialize, Deserialize)]
+#[derive(
+    Clone, Copy, Debug, Eq, Hash, PartialEq, PartialOrd, Ord, Serialize, Deserialize, JsonSchema,
+)]
 pub struct TurnID {
     pub parent: IntersectionID,
     /// src and dst must both belong to parent. No guarantees that src is incoming and dst is
@@ -8,12 +8,14 @@
 use std::collections::BTreeSet;
 
 use enumset::EnumSet;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 use crate::{IntersectionID, Map, PathConstraints, RoadID};
 
-#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, JsonSchema)]
 pub struct AccessRestrictions {
+    #[schemars(with = "u64")]
     pub allow_through_traffic: EnumSet<PathConstraints>,
 }
 
@@ -0,0 +1,110 @@
+//! Most vehicle pathfinding uses contraction hierarchies, prepared ahead of time with fixed costs.
+//! When costs change constantly, like live delays at intersections, just run Dijkstra's algorithm
+//! over the graph of roads instead.
+
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
+
+use geom::{Duration, Time};
+
+use crate::pathfind::{vehicle_cost, zone_cost};
+use crate::{DirectedRoadID, Map, MovementID, PathConstraints, PathRequest, PathV2, RoutingParams};
+
+/// Finds a vehicle path, adding some extra cost to each movement on top of the usual cost.
+/// Uber-turns aren't handled; the resulting path just uses individual movements.
+pub fn pathfind_with_extra_costs<F: Fn(MovementID) -> Duration>(
+    req: PathRequest,
+    params: &RoutingParams,
+    map: &Map,
+    extra_cost: F,
+) -> Option<PathV2> {
+    pathfind_time_dependent(req, params, map, Time::START_OF_DAY, |mvmnt, _| {
+        extra_cost(mvmnt)
+    })
+}
+
+/// Finds a vehicle path departing at some time, adding some extra cost to each movement that
+/// depends on when the movement is reached. As long as reaching a movement later never means
+/// finishing it sooner, this finds the fastest path.
+pub fn pathfind_time_dependent<F: Fn(MovementID, Time) -> Duration>(
+    req: PathRequest,
+    params: &RoutingParams,
+    map: &Map,
+    depart: Time,
+    extra_cost: F,
+) -> Option<PathV2> {
+    assert!(req.constraints != PathConstraints::Pedestrian);
+    let end = map.get_l(req.end.lane()).get_directed_parent();
+
+    let mut queue: BinaryHeap<Item> = BinaryHeap::new();
+    queue.push(Item {
+        cost: Duration::ZERO,
+        node: map.get_l(req.start.lane()).get_directed_parent(),
+        prev: None,
+    });
+    if let Some((pos, cost)) = req.alt_start {
+        queue.push(Item {
+            cost,
+            node: map.get_l(pos.lane()).get_directed_parent(),
+            prev: None,
+        });
+    }
+
+    let mut backrefs: HashMap<DirectedRoadID, Option<DirectedRoadID>> = HashMap::new();
+    while let Some(current) = queue.pop() {
+        if backrefs.contains_key(&current.node) {
+            continue;
+        }
+        backrefs.insert(current.node, current.prev);
+
+        if current.node == end {
+            let mut roads = vec![end];
+            while let Some(prev) = backrefs[roads.last().unwrap()] {
+                roads.push(prev);
+            }
+            roads.reverse();
+            return Some(PathV2::from_roads(
+                roads,
+                req,
+                current.cost,
+                Vec::new(),
+                map,
+            ));
+        }
+
+        for mvmnt in map.get_movements_for(current.node, req.constraints) {
+            queue.push(Item {
+                cost: current.cost
+                    + vehicle_cost(mvmnt.from, mvmnt, req.constraints, params, map)
+                    + zone_cost(mvmnt, req.constraints, map)
+                    + extra_cost(mvmnt, depart + current.cost),
+                node: mvmnt.to,
+                prev: Some(current.node),
+            });
+        }
+    }
+    None
+}
+
+#[derive(PartialEq, Eq)]
+struct Item {
+    cost: Duration,
+    node: DirectedRoadID,
+    prev: Option<DirectedRoadID>,
+}
+impl PartialOrd for Item {
+    fn partial_cmp(&self, other: &Item) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Item {
+    fn cmp(&self, other: &Item) -> Ordering {
+        // BinaryHeap is a max-heap, so reverse the comparison to get smallest costs first.
+        let ord = other.cost.cmp(&self.cost);
+        if ord != Ordering::Equal {
+            return ord;
+        }
+        (self.node, self.prev).cmp(&(other.node, other.prev))
+    }
+}
@@ -1,21 +1,28 @@
 //! Everything related to pathfinding through a map for different types of agents.
 
 use enumset::EnumSetType;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 use geom::Duration;
 
+pub use self::dijkstra::pathfind_with_extra_costs;
 pub use self::engine::CreateEngine;
 pub use self::pathfinder::Pathfinder;
+pub use self::time_dependent::TimeDependentPathfinder;
+pub use self::transit::{TransitItinerary, TransitLeg};
 pub use self::v1::{Path, PathRequest, PathStep};
 pub use self::v2::{PathStepV2, PathV2};
 pub use self::vehicles::vehicle_cost;
 pub use self::walking::WalkingNode;
 use crate::{osm, Lane, LaneID, LaneType, Map, MovementID, TurnType};
 
+mod dijkstra;
 mod engine;
 mod node_map;
 mod pathfinder;
+mod time_dependent;
+mod transit;
 // TODO tmp
 pub mod uber_turns;
 mod v1;
@@ -25,13 +32,16 @@ mod walking;
 
 /// Who's asking for a path?
 // TODO This is an awful name.
-#[derive(Debug, Serialize, Deserialize, PartialOrd, Ord, EnumSetType)]
+#[derive(Debug, Serialize, Deserialize, PartialOrd, Ord, EnumSetType, JsonSchema)]
 pub enum PathConstraints {
     Pedestrian,
     Car,
     Bike,
     Bus,
     Train,
+    /// Delivery vans and other heavy goods vehicles. They use the same lanes as cars, except where
+    /// OSM says `hgv=no`.
+    Truck,
 }
 
 impl PathConstraints {
@@ -42,6 +52,7 @@ impl PathConstraints {
             PathConstraints::Bike,
             PathConstraints::Bus,
             PathConstraints::Train,
+            PathConstraints::Truck,
         ]
     }
 
@@ -85,12 +96,15 @@ impl PathConstraints {
             PathConstraints::Train => {
                 return lane.is_light_rail();
             }
+            PathConstraints::Truck => {
+                lane.is_driving() && !map.get_r(lane.id.road).osm_tags.is("hgv", "no")
+            }
         };
         if result {
             return true;
         }
-        // Second chance for cars and bikes trying to use a bus-only lane that also happens to be a
-        // turn lane.
+        // Second chance for cars, bikes, and trucks trying to use a bus-only lane that also happens
+        // to be a turn lane.
         //
         // TODO This check could be made stricter in two ways:
         // 1) Verify that the bus-only lanes are the ONLY way to make this movement; if there's a
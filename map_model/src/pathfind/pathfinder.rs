@@ -5,9 +5,10 @@ use serde::{Deserialize, Serialize};
 use thread_local::ThreadLocal;
 
 use abstutil::{Timer, VecMap};
-use geom::Duration;
+use geom::{Duration, Time};
 
 use crate::pathfind::engine::CreateEngine;
+use crate::pathfind::transit::{TransitItinerary, TransitRouter};
 use crate::pathfind::vehicles::VehiclePathfinder;
 use crate::pathfind::walking::SidewalkPathfinder;
 use crate::{
@@ -21,8 +22,10 @@ pub struct Pathfinder {
     bike_graph: VehiclePathfinder,
     bus_graph: VehiclePathfinder,
     train_graph: VehiclePathfinder,
+    truck_graph: VehiclePathfinder,
     walking_graph: SidewalkPathfinder,
     walking_with_transit_graph: SidewalkPathfinder,
+    transit_router: TransitRouter,
 
     // These params cover the main graphs
     params: RoutingParams,
@@ -41,8 +44,10 @@ impl Clone for Pathfinder {
             bike_graph: self.bike_graph.clone(),
             bus_graph: self.bus_graph.clone(),
             train_graph: self.train_graph.clone(),
+            truck_graph: self.truck_graph.clone(),
             walking_graph: self.walking_graph.clone(),
             walking_with_transit_graph: self.walking_with_transit_graph.clone(),
+            transit_router: self.transit_router.clone(),
             params: self.params.clone(),
             cached_alternatives: ThreadLocal::new(),
         }
@@ -58,8 +63,10 @@ impl Pathfinder {
             bike_graph: VehiclePathfinder::empty(),
             bus_graph: VehiclePathfinder::empty(),
             train_graph: VehiclePathfinder::empty(),
+            truck_graph: VehiclePathfinder::empty(),
             walking_graph: SidewalkPathfinder::empty(),
             walking_with_transit_graph: SidewalkPathfinder::empty(),
+            transit_router: TransitRouter::empty(),
             params: RoutingParams::default(),
             cached_alternatives: ThreadLocal::new(),
         }
@@ -101,6 +108,16 @@ impl Pathfinder {
         );
         timer.stop("prepare pathfinding for trains");
 
+        // Trucks are banned from a few roads, but otherwise route like cars
+        timer.start("prepare pathfinding for trucks");
+        let truck_graph = VehiclePathfinder::new(
+            map,
+            PathConstraints::Truck,
+            &params,
+            &car_graph.engine.reuse_ordering(),
+        );
+        timer.stop("prepare pathfinding for trucks");
+
         timer.start("prepare pathfinding for pedestrians");
         let walking_graph = SidewalkPathfinder::new(map, None, &engine);
         timer.stop("prepare pathfinding for pedestrians");
@@ -110,13 +127,19 @@ impl Pathfinder {
             SidewalkPathfinder::new(map, Some((&bus_graph, &train_graph)), &engine);
         timer.stop("prepare pathfinding for pedestrians using transit");
 
+        timer.start("prepare transit schedules");
+        let transit_router = TransitRouter::new(map, &bus_graph, &train_graph);
+        timer.stop("prepare transit schedules");
+
         Pathfinder {
             car_graph,
             bike_graph,
             bus_graph,
             train_graph,
+            truck_graph,
             walking_graph,
             walking_with_transit_graph,
+            transit_router,
 
             params,
             cached_alternatives: ThreadLocal::new(),
@@ -150,6 +173,9 @@ impl Pathfinder {
                 PathConstraints::Train => {
                     p.train_graph = VehiclePathfinder::new(map, constraints, &params, &engine);
                 }
+                PathConstraints::Truck => {
+                    p.truck_graph = VehiclePathfinder::new(map, constraints, &params, &engine);
+                }
             }
             timer.stop(format!("prepare pathfinding for just {:?}", constraints));
         }
@@ -180,6 +206,7 @@ impl Pathfinder {
                 PathConstraints::Bike => self.bike_graph.pathfind(req, map),
                 PathConstraints::Bus => self.bus_graph.pathfind(req, map),
                 PathConstraints::Train => self.train_graph.pathfind(req, map),
+                PathConstraints::Truck => self.truck_graph.pathfind(req, map),
             };
         }
 
@@ -223,6 +250,7 @@ impl Pathfinder {
             PathConstraints::Pedestrian => self.walking_graph.all_costs_from(req.start, map),
             PathConstraints::Car => self.car_graph.all_costs_from(req.start, map),
             PathConstraints::Bike => self.bike_graph.all_costs_from(req.start, map),
+            PathConstraints::Truck => self.truck_graph.all_costs_from(req.start, map),
             PathConstraints::Bus | PathConstraints::Train => unreachable!(),
         };
         Some((req_cost, all_costs))
@@ -239,6 +267,26 @@ impl Pathfinder {
             .should_use_transit(map, start, end)
     }
 
+    pub fn plan_transit_trip(
+        &self,
+        map: &Map,
+        start: Position,
+        end: Position,
+        depart: Time,
+    ) -> Option<TransitItinerary> {
+        self.transit_router.plan(map, start, end, depart)
+    }
+
+    pub fn estimate_transit_duration(
+        &self,
+        map: &Map,
+        start: Position,
+        end: Position,
+    ) -> Option<Duration> {
+        self.walking_with_transit_graph
+            .estimate_transit_duration(map, start, end)
+    }
+
     pub fn apply_edits(&mut self, map: &Map, timer: &mut Timer) {
         timer.start("apply edits to car pathfinding");
         self.car_graph.apply_edits(map);
@@ -256,6 +304,10 @@ impl Pathfinder {
         self.train_graph.apply_edits(map);
         timer.stop("apply edits to train pathfinding");
 
+        timer.start("apply edits to truck pathfinding");
+        self.truck_graph.apply_edits(map);
+        timer.stop("apply edits to truck pathfinding");
+
         timer.start("apply edits to pedestrian pathfinding");
         self.walking_graph.apply_edits(map, None);
         timer.stop("apply edits to pedestrian pathfinding");
@@ -264,5 +316,9 @@ impl Pathfinder {
         self.walking_with_transit_graph
             .apply_edits(map, Some((&self.bus_graph, &self.train_graph)));
         timer.stop("apply edits to pedestrian using transit pathfinding");
+
+        timer.start("apply edits to transit schedules");
+        self.transit_router = TransitRouter::new(map, &self.bus_graph, &self.train_graph);
+        timer.stop("apply edits to transit schedules");
     }
 }
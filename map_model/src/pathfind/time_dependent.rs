@@ -0,0 +1,348 @@
+//! Vehicle pathfinding where the cost of crossing a road depends on the time of day, using travel
+//! times observed in a previous simulation. Congested roads are avoided when they're congested.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use abstutil::{deserialize_btreemap, serialize_btreemap};
+use geom::{Duration, Time};
+
+use crate::pathfind::dijkstra::pathfind_time_dependent;
+use crate::{
+    DirectedRoadID, Map, PathConstraints, PathRequest, PathStepV2, PathV2, Position, Traversable,
+};
+
+const HOUR: Duration = Duration::const_seconds(3600.0);
+/// Paths are planned as if the vehicle departs at the start of one of these windows, so that
+/// vehicles leaving around the same time can share the result.
+const CACHE_WINDOW: Duration = Duration::const_seconds(15.0 * 60.0);
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TimeDependentPathfinder {
+    /// (Road, hour of the day) -> how long vehicles typically took to cross the road, including
+    /// waiting at the end of it. Missing hours mean nobody was delayed.
+    #[serde(
+        serialize_with = "serialize_btreemap",
+        deserialize_with = "deserialize_btreemap"
+    )]
+    travel_times: BTreeMap<(DirectedRoadID, usize), Duration>,
+
+    /// The roads of every path found during `cache_window`, with their cost. None if there's no
+    /// path.
+    #[serde(skip_serializing, skip_deserializing)]
+    cache: BTreeMap<(DirectedRoadID, DirectedRoadID, PathConstraints), Option<CachedPath>>,
+    #[serde(skip_serializing, skip_deserializing)]
+    cache_window: usize,
+}
+
+type CachedPath = (Vec<DirectedRoadID>, Duration);
+
+impl TimeDependentPathfinder {
+    pub fn new(mut travel_times: BTreeMap<(DirectedRoadID, usize), Duration>) -> Self {
+        // Dijkstra's algorithm only finds the fastest path if entering a road later never means
+        // leaving it sooner. Travel times are interpolated between hours, so they can't drop by
+        // more than an hour from one hour to the next; raise any that do. The day wraps around,
+        // so go through it twice.
+        let roads: BTreeSet<DirectedRoadID> = travel_times.keys().map(|(dr, _)| *dr).collect();
+        for dr in roads {
+            for hour in 1..48 {
+                let prev = travel_times
+                    .get(&(dr, (hour - 1) % 24))
+                    .cloned()
+                    .unwrap_or(Duration::ZERO);
+                if prev - HOUR > Duration::ZERO {
+                    let current = travel_times
+                        .entry((dr, hour % 24))
+                        .or_insert(Duration::ZERO);
+                    *current = (*current).max(prev - HOUR);
+                }
+            }
+        }
+        Self {
+            travel_times,
+            cache: BTreeMap::new(),
+            cache_window: 0,
+        }
+    }
+
+    /// Finds a vehicle path departing at some time. Each road costs however much longer than
+    /// usual vehicles took to cross it around when it's reached. This is much slower than regular
+    /// pathfinding, since it can't use the contraction hierarchies, so results are shared by
+    /// vehicles departing between the same roads in the same 15 minutes.
+    pub fn pathfind(&mut self, req: PathRequest, depart: Time, map: &Map) -> Result<PathV2> {
+        let window = ((depart - Time::START_OF_DAY) / CACHE_WINDOW).floor() as usize;
+        if window != self.cache_window {
+            self.cache.clear();
+            self.cache_window = window;
+        }
+        let depart = Time::START_OF_DAY + CACHE_WINDOW * (window as f64);
+
+        // The path only depends on the roads at each end, unless there's a second way to start
+        if req.alt_start.is_some() {
+            return self
+                .calculate(req.clone(), depart, map)
+                .ok_or_else(|| anyhow!("can't fulfill {}", req));
+        }
+        let key = (
+            directed_road(req.start, map),
+            directed_road(req.end, map),
+            req.constraints,
+        );
+        if !self.cache.contains_key(&key) {
+            let path = self.calculate(req.clone(), depart, map).map(|path| {
+                let roads = path
+                    .get_steps()
+                    .iter()
+                    .filter_map(|step| match step {
+                        PathStepV2::Along(dr) => Some(*dr),
+                        _ => None,
+                    })
+                    .collect();
+                (roads, path.get_cost())
+            });
+            self.cache.insert(key, path);
+        }
+        match self.cache[&key] {
+            Some((ref roads, cost)) => Ok(PathV2::from_roads(
+                roads.clone(),
+                req,
+                cost,
+                Vec::new(),
+                map,
+            )),
+            None => bail!("can't fulfill {}", req),
+        }
+    }
+
+    /// Forget all of the paths found so far. Call this after the map is edited, since they might
+    /// use roads that aren't usable anymore.
+    pub fn invalidate(&mut self) {
+        self.cache.clear();
+    }
+
+    fn calculate(&self, req: PathRequest, depart: Time, map: &Map) -> Option<PathV2> {
+        let constraints = req.constraints;
+        pathfind_time_dependent(req, map.routing_params(), map, depart, |mvmnt, time| {
+            self.delay(mvmnt.from, time, constraints, map)
+        })
+    }
+
+    /// How much longer than the free-flow time it took to cross a road around some time
+    fn delay(
+        &self,
+        dr: DirectedRoadID,
+        time: Time,
+        constraints: PathConstraints,
+        map: &Map,
+    ) -> Duration {
+        let observed = self.travel_time(dr, time);
+        if observed == Duration::ZERO {
+            return Duration::ZERO;
+        }
+        let max_speed = match constraints {
+            PathConstraints::Bike => Some(crate::MAX_BIKE_SPEED),
+            PathConstraints::Truck => Some(crate::MAX_TRUCK_SPEED),
+            _ => None,
+        };
+        let free_flow = map.get_r(dr.id).length()
+            / Traversable::max_speed_along_road(dr, max_speed, constraints, map).0;
+        (observed - free_flow).max(Duration::ZERO)
+    }
+
+    /// How long vehicles typically took to cross a road at some time of day, or zero if nobody
+    /// was delayed. Each hour's observation applies to the middle of the hour; in between,
+    /// interpolate linearly.
+    fn travel_time(&self, dr: DirectedRoadID, time: Time) -> Duration {
+        let time_of_day = (time - Time::START_OF_DAY) % Duration::hours(24);
+        let mut hours = (time_of_day - HOUR / 2.0) / HOUR;
+        if hours < 0.0 {
+            hours += 24.0;
+        }
+        let hour = hours.floor() as usize;
+        let get = |hour: usize| {
+            self.travel_times
+                .get(&(dr, hour % 24))
+                .cloned()
+                .unwrap_or(Duration::ZERO)
+        };
+        let (before, after) = (get(hour), get(hour + 1));
+        before + (after - before) * (hours - hours.floor())
+    }
+}
+
+fn directed_road(pos: Position, map: &Map) -> DirectedRoadID {
+    map.get_l(pos.lane()).get_directed_parent()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::toy::{node, toy_map};
+    use abstutil::Timer;
+
+    use crate::{Direction, IntersectionType, LaneType, RoadID};
+
+    #[test]
+    fn later_arrivals_never_finish_sooner() {
+        let dr = DirectedRoadID {
+            id: RoadID(0),
+            dir: Direction::Fwd,
+        };
+        let mut travel_times = BTreeMap::new();
+        // Gridlock followed by nothing
+        travel_times.insert((dr, 7), Duration::hours(3));
+        travel_times.insert((dr, 8), Duration::minutes(1));
+        travel_times.insert((dr, 17), Duration::minutes(20));
+        travel_times.insert((dr, 23), Duration::minutes(90));
+        let pathfinder = TimeDependentPathfinder::new(travel_times);
+
+        let mut last_finish = Time::START_OF_DAY;
+        let mut time = Time::START_OF_DAY;
+        while time < Time::START_OF_DAY + Duration::hours(48) {
+            let finish = time + pathfinder.travel_time(dr, time);
+            assert!(
+                finish + Duration::seconds(0.01) >= last_finish,
+                "entering at {} finishes at {}, before {}",
+                time,
+                finish,
+                last_finish
+            );
+            last_finish = finish;
+            time += Duration::minutes(1);
+        }
+    }
+
+    #[test]
+    fn hours_wrap_around() {
+        let dr = DirectedRoadID {
+            id: RoadID(0),
+            dir: Direction::Fwd,
+        };
+        let mut travel_times = BTreeMap::new();
+        travel_times.insert((dr, 0), Duration::minutes(5));
+        travel_times.insert((dr, 23), Duration::minutes(3));
+        let pathfinder = TimeDependentPathfinder::new(travel_times);
+
+        let at = |hours, minutes| {
+            pathfinder.travel_time(
+                dr,
+                Time::START_OF_DAY + Duration::hours(hours) + Duration::minutes(minutes),
+            )
+        };
+        assert_eq!(at(0, 30), Duration::minutes(5));
+        assert_eq!(at(23, 30), Duration::minutes(3));
+        assert_eq!(at(24, 0), Duration::minutes(4));
+        assert_eq!(at(24, 30), Duration::minutes(5));
+        assert_eq!(at(47, 30), Duration::minutes(3));
+        assert_eq!(at(12, 0), Duration::ZERO);
+    }
+
+    #[test]
+    fn avoid_congested_road() {
+        // A direct road from west to east, and a longer detour around the north
+        //
+        //  n1        n2
+        //  |          |
+        //  nw ------ ne
+        //  |          |
+        //  w -------- e
+        //  |          |
+        //  sw        se
+        let mut map = toy_map(
+            &[
+                ("sw", 0.0, 500.0, IntersectionType::Border),
+                ("w", 0.0, 300.0, IntersectionType::StopSign),
+                ("nw", 0.0, 100.0, IntersectionType::StopSign),
+                ("n1", 0.0, 0.0, IntersectionType::Border),
+                ("n2", 400.0, 0.0, IntersectionType::Border),
+                ("ne", 400.0, 100.0, IntersectionType::StopSign),
+                ("e", 400.0, 300.0, IntersectionType::StopSign),
+                ("se", 400.0, 500.0, IntersectionType::Border),
+            ],
+            &[
+                ("sw", "w"),
+                ("w", "e"),
+                ("w", "nw"),
+                ("nw", "n1"),
+                ("nw", "ne"),
+                ("ne", "n2"),
+                ("ne", "e"),
+                ("e", "se"),
+            ],
+        );
+        // Find the road between two intersections, and the direction heading to the second
+        let road = |from: &str, to: &str| {
+            for r in map.all_roads() {
+                let src = map.get_i(r.src_i).orig_id;
+                let dst = map.get_i(r.dst_i).orig_id;
+                if (src, dst) == (node(from), node(to)) {
+                    return DirectedRoadID {
+                        id: r.id,
+                        dir: Direction::Fwd,
+                    };
+                }
+                if (src, dst) == (node(to), node(from)) {
+                    return DirectedRoadID {
+                        id: r.id,
+                        dir: Direction::Back,
+                    };
+                }
+            }
+            panic!("no road from {} to {}", from, to);
+        };
+        let lane = |dr: DirectedRoadID| dr.lanes(PathConstraints::Car, &map)[0];
+        let req = PathRequest::vehicle(
+            Position::start(lane(road("sw", "w"))),
+            Position::end(lane(road("e", "se")), &map),
+            PathConstraints::Car,
+        );
+        let direct = road("w", "e");
+        let uses_direct = |path: &PathV2| {
+            path.get_steps()
+                .iter()
+                .any(|step| matches!(step, PathStepV2::Along(dr) if *dr == direct))
+        };
+
+        // Without any congestion, go straight
+        let mut pathfinder = TimeDependentPathfinder::new(BTreeMap::new());
+        let rush_hour = Time::START_OF_DAY + Duration::hours(8);
+        assert!(uses_direct(
+            &pathfinder.pathfind(req.clone(), rush_hour, &map).unwrap()
+        ));
+
+        // When the direct road is jammed during rush hour, take the detour then, but not later
+        let mut travel_times = BTreeMap::new();
+        for hour in 7..10 {
+            travel_times.insert((direct, hour), Duration::minutes(30));
+        }
+        let mut pathfinder = TimeDependentPathfinder::new(travel_times);
+        assert!(!uses_direct(
+            &pathfinder.pathfind(req.clone(), rush_hour, &map).unwrap()
+        ));
+        assert!(uses_direct(
+            &pathfinder
+                .pathfind(req.clone(), Time::START_OF_DAY + Duration::hours(14), &map)
+                .unwrap()
+        ));
+
+        // Close the direct road. Paths found before that shouldn't be reused.
+        let mut edits = map.get_edits().clone();
+        edits.commands.push(map.edit_road_cmd(direct.id, |new| {
+            for spec in &mut new.lanes_ltr {
+                if spec.lt == LaneType::Driving {
+                    spec.lt = LaneType::Construction;
+                }
+            }
+        }));
+        map.must_apply_edits(edits, &mut Timer::throwaway());
+        map.recalculate_pathfinding_after_edits(&mut Timer::throwaway());
+        pathfinder.invalidate();
+        assert!(!uses_direct(
+            &pathfinder
+                .pathfind(req, Time::START_OF_DAY + Duration::hours(14), &map)
+                .unwrap()
+        ));
+    }
+}
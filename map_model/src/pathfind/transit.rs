@@ -0,0 +1,574 @@
+//! Plan trips using public transit with real schedules. This is a variation of RAPTOR
+//! (https://www.microsoft.com/en-us/research/wp-content/uploads/2012/01/raptor_alenex.pdf): each
+//! round rides one more vehicle, so the itinerary with the earliest arrival and the fewest
+//! transfers wins.
+//!
+//! Walking to, from, and between stops is estimated from straight-line distance. The people
+//! following the itinerary pathfind for real when they walk each leg.
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use abstutil::{deserialize_btreemap, serialize_btreemap};
+use geom::{Distance, Duration, Pt2D, Time};
+
+use crate::pathfind::vehicles::VehiclePathfinder;
+use crate::pathfind::WalkingNode;
+use crate::{BusRouteID, BusStopID, Map, PathConstraints, Position};
+
+/// Don't consider walking farther than this to or from a stop.
+const MAX_WALK_TO_STOP: Distance = Distance::const_meters(1000.0);
+/// Don't consider walking farther than this to transfer between two stops.
+const MAX_TRANSFER_WALK: Distance = Distance::const_meters(400.0);
+/// Sidewalks rarely go in a straight line, so inflate straight-line walking distances.
+const WALKING_DETOUR: f64 = 1.3;
+/// Itineraries ride at most this many vehicles.
+const MAX_RIDES: usize = 4;
+
+/// One ride in a transit itinerary.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TransitLeg {
+    pub route: BusRouteID,
+    pub stop1: BusStopID,
+    /// None means ride off the map. Only the last leg can do this.
+    pub maybe_stop2: Option<BusStopID>,
+    /// When the vehicle is expected to reach stop1
+    pub board_at: Time,
+    /// How long the person is expected to wait at stop1 after walking there
+    pub expected_wait: Duration,
+    /// When the vehicle is expected to reach stop2 or the border
+    pub alight_at: Time,
+}
+
+/// A plan to reach somewhere using transit, possibly with transfers.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TransitItinerary {
+    pub legs: Vec<TransitLeg>,
+    /// When the person is expected to reach their destination
+    pub arrival: Time,
+}
+
+impl TransitItinerary {
+    /// The total time spent waiting at stops
+    pub fn total_wait(&self) -> Duration {
+        self.legs.iter().map(|leg| leg.expected_wait).sum()
+    }
+}
+
+/// Everything about the transit network that's expensive to calculate per trip. Schedules aren't
+/// stored here; they're read from each route when planning, so schedule edits take effect
+/// immediately.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct TransitRouter {
+    /// Indexed by BusRouteID. For each route, how long after departing from the start a vehicle
+    /// reaches each stop, ignoring traffic and time spent at stops. The last entry is the border,
+    /// if the route ends at one. None if the route is deleted or some part of it can't be driven.
+    driving_offsets: Vec<Option<Vec<Duration>>>,
+    #[serde(
+        serialize_with = "serialize_btreemap",
+        deserialize_with = "deserialize_btreemap"
+    )]
+    stop_pts: BTreeMap<BusStopID, Pt2D>,
+    /// For each stop, the routes and the index along their stops
+    #[serde(
+        serialize_with = "serialize_btreemap",
+        deserialize_with = "deserialize_btreemap"
+    )]
+    serving: BTreeMap<BusStopID, Vec<(BusRouteID, usize)>>,
+    /// For each stop, the nearby stops and how long it takes to walk there
+    #[serde(
+        serialize_with = "serialize_btreemap",
+        deserialize_with = "deserialize_btreemap"
+    )]
+    transfers: BTreeMap<BusStopID, Vec<(BusStopID, Duration)>>,
+}
+
+/// How a stop was reached in some round
+#[derive(Clone, Copy)]
+enum Reached {
+    /// Walking from the start
+    Start,
+    /// Riding a route, boarding at some stop at some time
+    Ride {
+        route: BusRouteID,
+        board: BusStopID,
+        board_at: Time,
+    },
+    /// Riding to another stop in the same round, then walking
+    Transfer(BusStopID),
+}
+
+#[derive(Clone, Copy)]
+struct Label {
+    arrive: Time,
+    how: Reached,
+}
+
+/// The best way found so far to reach the destination
+struct Target {
+    round: usize,
+    /// Where the last ride starts, if it rides off the map. Otherwise, where the person walks
+    /// from at the end.
+    stop: BusStopID,
+    /// The route to ride off the map, and when the vehicle departs from the start
+    ride_off_map: Option<(BusRouteID, Time)>,
+}
+
+impl TransitRouter {
+    pub fn empty() -> TransitRouter {
+        TransitRouter {
+            driving_offsets: Vec::new(),
+            stop_pts: BTreeMap::new(),
+            serving: BTreeMap::new(),
+            transfers: BTreeMap::new(),
+        }
+    }
+
+    pub fn new(
+        map: &Map,
+        bus_graph: &VehiclePathfinder,
+        train_graph: &VehiclePathfinder,
+    ) -> TransitRouter {
+        let mut driving_offsets = Vec::new();
+        let mut serving: BTreeMap<BusStopID, Vec<(BusRouteID, usize)>> = BTreeMap::new();
+        for route in map.all_bus_routes() {
+            let mut offsets = Some(Vec::new());
+            let mut total = Duration::ZERO;
+            for req in route.all_steps(map) {
+                let graph = match route.route_type {
+                    PathConstraints::Bus => bus_graph,
+                    PathConstraints::Train => train_graph,
+                    _ => unreachable!(),
+                };
+                match graph.pathfind(req, map) {
+                    Some(path) => {
+                        total += path.get_cost();
+                        offsets.as_mut().unwrap().push(total);
+                    }
+                    None => {
+                        offsets = None;
+                        break;
+                    }
+                }
+            }
+            if offsets.is_some() {
+                for (idx, stop) in route.stops.iter().enumerate() {
+                    serving.entry(*stop).or_default().push((route.id, idx));
+                }
+            }
+            driving_offsets.push(offsets);
+        }
+
+        let stop_pts: BTreeMap<BusStopID, Pt2D> = map
+            .all_bus_stops()
+            .values()
+            .map(|stop| (stop.id, stop.sidewalk_pos.pt(map)))
+            .collect();
+        let mut transfers = BTreeMap::new();
+        for (stop1, pt1) in &stop_pts {
+            if !serving.contains_key(stop1) {
+                continue;
+            }
+            let mut nearby = Vec::new();
+            for (stop2, pt2) in &stop_pts {
+                if stop1 != stop2
+                    && serving.contains_key(stop2)
+                    && pt1.dist_to(*pt2) <= MAX_TRANSFER_WALK
+                {
+                    nearby.push((*stop2, walking_time(*pt1, *pt2)));
+                }
+            }
+            transfers.insert(*stop1, nearby);
+        }
+
+        TransitRouter {
+            driving_offsets,
+            stop_pts,
+            serving,
+            transfers,
+        }
+    }
+
+    /// Find the transit itinerary that arrives earliest, departing from the start at some time.
+    /// Returns None if just walking is at least as fast.
+    pub fn plan(
+        &self,
+        map: &Map,
+        start: Position,
+        end: Position,
+        depart: Time,
+    ) -> Option<TransitItinerary> {
+        let start_pt = start.pt(map);
+        let end_pt = end.pt(map);
+        let leave_map = match WalkingNode::end_transit(end, map) {
+            WalkingNode::LeaveMap(i) => Some(i),
+            _ => None,
+        };
+        let egress: BTreeMap<BusStopID, Duration> = self
+            .stop_pts
+            .iter()
+            .filter(|(_, pt)| pt.dist_to(end_pt) <= MAX_WALK_TO_STOP)
+            .map(|(stop, pt)| (*stop, walking_time(*pt, end_pt)))
+            .collect();
+        if egress.is_empty() && leave_map.is_none() {
+            return None;
+        }
+
+        // Only bother with itineraries that beat walking
+        let mut best_target = depart + walking_time(start_pt, end_pt);
+        let mut target: Option<Target> = None;
+
+        let mut best: BTreeMap<BusStopID, Time> = BTreeMap::new();
+        let mut rounds: Vec<BTreeMap<BusStopID, Label>> = vec![BTreeMap::new()];
+        for (stop, pt) in &self.stop_pts {
+            if self.serving.contains_key(stop) && pt.dist_to(start_pt) <= MAX_WALK_TO_STOP {
+                let arrive = depart + walking_time(start_pt, *pt);
+                best.insert(*stop, arrive);
+                rounds[0].insert(
+                    *stop,
+                    Label {
+                        arrive,
+                        how: Reached::Start,
+                    },
+                );
+            }
+        }
+
+        for k in 1..=MAX_RIDES {
+            // Which routes could somebody board this round, and where's the first stop they could
+            // board at?
+            let mut queue: BTreeMap<BusRouteID, usize> = BTreeMap::new();
+            for stop in rounds[k - 1].keys() {
+                for (route, idx) in &self.serving[stop] {
+                    let first = queue.entry(*route).or_insert(*idx);
+                    *first = (*first).min(*idx);
+                }
+            }
+
+            let mut rides: BTreeMap<BusStopID, Label> = BTreeMap::new();
+            for (route_id, first_idx) in queue {
+                let route = map.get_br(route_id);
+                let offsets = self.offsets(map, route_id);
+                // (When the vehicle departs from the start, where the person boards, when they
+                // board)
+                let mut trip: Option<(Time, BusStopID, Time)> = None;
+                for (idx, stop) in route.stops.iter().cloned().enumerate().skip(first_idx) {
+                    if let Some((departure, board, board_at)) = trip {
+                        let arrive = departure + offsets[idx];
+                        if arrive < best_target
+                            && best.get(&stop).map(|t| arrive < *t).unwrap_or(true)
+                        {
+                            best.insert(stop, arrive);
+                            rides.insert(
+                                stop,
+                                Label {
+                                    arrive,
+                                    how: Reached::Ride {
+                                        route: route_id,
+                                        board,
+                                        board_at,
+                                    },
+                                },
+                            );
+                        }
+                    }
+
+                    // Can we catch an earlier vehicle here?
+                    if let Some(ready) = rounds[k - 1].get(&stop).map(|l| l.arrive) {
+                        let next = route
+                            .spawn_times
+                            .partition_point(|t| *t + offsets[idx] < ready);
+                        if let Some(departure) = route.spawn_times.get(next) {
+                            if trip.map(|(t, _, _)| *departure < t).unwrap_or(true) {
+                                trip = Some((*departure, stop, *departure + offsets[idx]));
+                            }
+                        }
+                    }
+                }
+
+                // Ride off the map?
+                if let Some((departure, board, _)) = trip {
+                    if leave_map.is_some()
+                        && route.end_border.map(|l| map.get_l(l).dst_i) == leave_map
+                    {
+                        let arrive = departure + offsets[route.stops.len()];
+                        if arrive < best_target {
+                            best_target = arrive;
+                            target = Some(Target {
+                                round: k,
+                                stop: board,
+                                ride_off_map: Some((route_id, departure)),
+                            });
+                        }
+                    }
+                }
+            }
+
+            // Walk to other stops after riding
+            let mut labels = rides.clone();
+            for (stop1, ride) in &rides {
+                for (stop2, walk) in &self.transfers[stop1] {
+                    let arrive = ride.arrive + *walk;
+                    if arrive < best_target && best.get(stop2).map(|t| arrive < *t).unwrap_or(true)
+                    {
+                        best.insert(*stop2, arrive);
+                        labels.insert(
+                            *stop2,
+                            Label {
+                                arrive,
+                                how: Reached::Transfer(*stop1),
+                            },
+                        );
+                    }
+                }
+            }
+
+            for (stop, label) in &labels {
+                if let Some(walk) = egress.get(stop) {
+                    if label.arrive + *walk < best_target {
+                        best_target = label.arrive + *walk;
+                        target = Some(Target {
+                            round: k,
+                            stop: *stop,
+                            ride_off_map: None,
+                        });
+                    }
+                }
+            }
+
+            // Later rounds only board where this round improved something
+            let done = labels.is_empty();
+            rounds.push(labels);
+            if done {
+                break;
+            }
+        }
+
+        let Target {
+            round: mut k,
+            mut stop,
+            ride_off_map,
+        } = target?;
+        let mut legs = Vec::new();
+        if let Some((route, departure)) = ride_off_map {
+            let idx = self.serving[&stop]
+                .iter()
+                .find(|(r, _)| *r == route)
+                .unwrap()
+                .1;
+            let board_at = departure + self.offsets(map, route)[idx];
+            legs.push(TransitLeg {
+                route,
+                stop1: stop,
+                maybe_stop2: None,
+                board_at,
+                expected_wait: board_at - rounds[k - 1][&stop].arrive,
+                alight_at: best_target,
+            });
+            k -= 1;
+        }
+        loop {
+            let label = rounds[k][&stop];
+            match label.how {
+                Reached::Start => break,
+                Reached::Transfer(from) => {
+                    stop = from;
+                }
+                Reached::Ride {
+                    route,
+                    board,
+                    board_at,
+                } => {
+                    legs.push(TransitLeg {
+                        route,
+                        stop1: board,
+                        maybe_stop2: Some(stop),
+                        board_at,
+                        expected_wait: board_at - rounds[k - 1][&board].arrive,
+                        alight_at: label.arrive,
+                    });
+                    stop = board;
+                    k -= 1;
+                }
+            }
+        }
+        legs.reverse();
+
+        Some(TransitItinerary {
+            legs,
+            arrival: best_target,
+        })
+    }
+
+    /// How long after departing from the start a vehicle reaches each stop, and then the border if
+    /// the route ends at one. Real timetables are used when the route has one.
+    fn offsets(&self, map: &Map, id: BusRouteID) -> Vec<Duration> {
+        let route = map.get_br(id);
+        let driving = self.driving_offsets[id.0].as_ref().unwrap();
+        if route.scheduled_offsets.len() != route.stops.len() {
+            return driving.clone();
+        }
+        let mut offsets = route.scheduled_offsets.clone();
+        if route.end_border.is_some() {
+            let last = route.stops.len() - 1;
+            offsets.push(offsets[last] + (driving[last + 1] - driving[last]));
+        }
+        offsets
+    }
+}
+
+fn walking_time(pt1: Pt2D, pt2: Pt2D) -> Duration {
+    pt1.dist_to(pt2) * WALKING_DETOUR / crate::MAX_WALKING_SPEED
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::toy::{add_bus_route, road, sidewalk, toy_map};
+    use crate::IntersectionType;
+
+    /// A long street running east, with a side street at each intersection. Two routes run along
+    /// it, overlapping between b and c:
+    ///
+    ///  A: s1 (between a and b) to s2 (between b and c)
+    ///  B: s3 (just after s2) to s4 (between c and d)
+    struct Corridor {
+        map: Map,
+        routes: Vec<BusRouteID>,
+        stops: Vec<BusStopID>,
+    }
+
+    impl Corridor {
+        fn new() -> Corridor {
+            let mut map = toy_map(
+                &[
+                    ("w", 0.0, 100.0, IntersectionType::Border),
+                    ("a", 100.0, 100.0, IntersectionType::StopSign),
+                    ("b", 1600.0, 100.0, IntersectionType::StopSign),
+                    ("c", 3100.0, 100.0, IntersectionType::StopSign),
+                    ("d", 4600.0, 100.0, IntersectionType::StopSign),
+                    ("e", 4700.0, 100.0, IntersectionType::Border),
+                    ("na", 100.0, 0.0, IntersectionType::Border),
+                    ("nb", 1600.0, 0.0, IntersectionType::Border),
+                    ("nc", 3100.0, 0.0, IntersectionType::Border),
+                    ("nd", 4600.0, 0.0, IntersectionType::Border),
+                ],
+                &[
+                    ("w", "a"),
+                    ("a", "b"),
+                    ("b", "c"),
+                    ("c", "d"),
+                    ("d", "e"),
+                    ("a", "na"),
+                    ("b", "nb"),
+                    ("c", "nc"),
+                    ("d", "nd"),
+                ],
+            );
+
+            let (route_a, mut stops) = add_bus_route(
+                &mut map,
+                "A",
+                &[("a", "b", 100.0), ("b", "c", 1300.0)],
+                Duration::minutes(10),
+            );
+            let (route_b, more_stops) = add_bus_route(
+                &mut map,
+                "B",
+                &[("b", "c", 1400.0), ("c", "d", 1400.0)],
+                Duration::minutes(15),
+            );
+            stops.extend(more_stops);
+            Corridor {
+                map,
+                routes: vec![route_a, route_b],
+                stops,
+            }
+        }
+
+        fn plan(
+            &self,
+            start: (&str, &str, f64),
+            end: (&str, &str, f64),
+            depart: Time,
+        ) -> Option<TransitItinerary> {
+            let pos = |(from, to, dist): (&str, &str, f64)| {
+                Position::new(sidewalk(road(&self.map, from, to)), Distance::meters(dist))
+            };
+            self.map.plan_transit_trip(pos(start), pos(end), depart)
+        }
+    }
+
+    fn time(hours: usize, minutes: usize) -> Time {
+        Time::START_OF_DAY + Duration::hours(hours) + Duration::minutes(minutes)
+    }
+
+    #[test]
+    fn one_ride() {
+        let corridor = Corridor::new();
+        let depart = time(8, 3);
+        let itinerary = corridor
+            .plan(("a", "b", 50.0), ("b", "c", 1350.0), depart)
+            .unwrap();
+        assert_eq!(itinerary.legs.len(), 1);
+        let leg = &itinerary.legs[0];
+        assert_eq!(leg.route, corridor.routes[0]);
+        assert_eq!(leg.stop1, corridor.stops[0]);
+        assert_eq!(leg.maybe_stop2, Some(corridor.stops[1]));
+
+        // The 8:00 vehicle is already gone by the time they reach the stop, so they catch the
+        // 8:10 one
+        assert!(leg.board_at >= time(8, 10));
+        assert!(leg.board_at < time(8, 11));
+        assert!(leg.board_at - leg.expected_wait > depart);
+        assert!(leg.alight_at > leg.board_at);
+        assert!(itinerary.arrival > leg.alight_at);
+        assert!(itinerary.arrival < time(8, 20));
+    }
+
+    #[test]
+    fn transfer() {
+        let corridor = Corridor::new();
+        let itinerary = corridor
+            .plan(("a", "b", 50.0), ("c", "d", 1450.0), time(8, 3))
+            .unwrap();
+        assert_eq!(
+            itinerary
+                .legs
+                .iter()
+                .map(|leg| (leg.route, leg.stop1, leg.maybe_stop2))
+                .collect::<Vec<_>>(),
+            vec![
+                (
+                    corridor.routes[0],
+                    corridor.stops[0],
+                    Some(corridor.stops[1])
+                ),
+                (
+                    corridor.routes[1],
+                    corridor.stops[2],
+                    Some(corridor.stops[3])
+                ),
+            ]
+        );
+        // Walking between the two stops takes some time
+        assert!(
+            itinerary.legs[1].board_at - itinerary.legs[1].expected_wait
+                > itinerary.legs[0].alight_at
+        );
+    }
+
+    #[test]
+    fn walk_when_nothing_runs() {
+        let corridor = Corridor::new();
+        // The last vehicle leaves at 22:00
+        assert!(corridor
+            .plan(("a", "b", 50.0), ("b", "c", 1350.0), time(23, 0))
+            .is_none());
+        // Walking a short distance beats waiting
+        assert!(corridor
+            .plan(("a", "b", 50.0), ("a", "b", 150.0), time(8, 3))
+            .is_none());
+    }
+}
@@ -4,6 +4,7 @@
 use std::collections::{BTreeMap, BTreeSet};
 
 use petgraph::graphmap::UnGraphMap;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 use geom::{Distance, PolyLine};
@@ -17,7 +18,7 @@ pub struct IntersectionCluster {
     pub uber_turns: Vec<UberTurn>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, JsonSchema)]
 pub struct UberTurn {
     pub path: Vec<TurnID>,
 }
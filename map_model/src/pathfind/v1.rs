@@ -2,13 +2,16 @@ use std::collections::VecDeque;
 use std::fmt;
 
 use anyhow::Result;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 use geom::{Distance, Duration, PolyLine, Speed, EPSILON_DIST};
 
 use crate::{BuildingID, LaneID, Map, PathConstraints, Position, Traversable, TurnID, UberTurn};
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[derive(
+    Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash, PartialOrd, Ord, JsonSchema,
+)]
 pub enum PathStep {
     /// Original direction
     Lane(LaneID),
@@ -130,7 +133,7 @@ impl PathStep {
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, JsonSchema)]
 pub struct Path {
     steps: VecDeque<PathStep>,
     // The original request used to produce this path. Calling shift(), add(), modify_step(), etc
@@ -346,6 +349,22 @@ impl Path {
         }
     }
 
+    /// Replace everything after the current step with a different path. The new path must start
+    /// with the current step and end in the same place.
+    pub fn replace_remaining(&mut self, new: Path, map: &Map) {
+        assert!(self.currently_inside_ut.is_none());
+        assert_eq!(self.steps[0], new.steps[0]);
+        assert_eq!(self.orig_req.end, new.orig_req.end);
+
+        // The old steps might not exist in the map anymore, so don't look at them.
+        self.total_length = self.crossed_so_far;
+        for step in &new.steps {
+            self.total_length += self.dist_crossed_from_step(map, step);
+        }
+        self.steps = new.steps;
+        self.uber_turns = new.uber_turns;
+    }
+
     pub fn current_step(&self) -> PathStep {
         self.steps[0]
     }
@@ -449,6 +468,13 @@ impl Path {
         Some(pts_so_far.unwrap())
     }
 
+    /// If the rest of this path enters an access-restricted zone that doesn't allow its mode and
+    /// isn't where the path starts or ends, returns the turn entering that zone. Pathfinding
+    /// avoids this, but map edits can change restrictions after a path is calculated.
+    pub fn find_illegal_zone_entrance(&self, map: &Map) -> Option<TurnID> {
+        find_illegal_zone_entrance(map, self.steps.iter(), &self.orig_req)
+    }
+
     pub fn get_steps(&self) -> &VecDeque<PathStep> {
         &self.steps
     }
@@ -520,7 +546,7 @@ impl Path {
     }
 }
 
-#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct PathRequest {
     pub start: Position,
     pub end: Position,
@@ -561,7 +587,7 @@ impl PathRequest {
         let (start, end) = match constraints {
             PathConstraints::Pedestrian => (from.sidewalk_pos, to.sidewalk_pos),
             PathConstraints::Bike => (from.biking_connection(map)?.0, to.biking_connection(map)?.0),
-            PathConstraints::Car => (
+            PathConstraints::Car | PathConstraints::Truck => (
                 from.driving_connection(map)?.0,
                 to.driving_connection(map)?.0,
             ),
@@ -570,7 +596,7 @@ impl PathRequest {
             // train to travel between buildings.
             PathConstraints::Bus | PathConstraints::Train => unimplemented!(),
         };
-        if constraints == PathConstraints::Car {
+        if constraints == PathConstraints::Car || constraints == PathConstraints::Truck {
             Some(PathRequest::leave_from_driveway(
                 start,
                 end,
@@ -710,6 +736,19 @@ fn validate_restrictions(map: &Map, steps: &[PathStep]) {
 }
 
 fn validate_zones(map: &Map, steps: &[PathStep], req: &PathRequest) {
+    if let Some(t) = find_illegal_zone_entrance(map, steps.iter(), req) {
+        // TODO There are lots of false positive here that occur when part of the graph is
+        // separated from the rest by access-restricted roads. Could maybe detect that here, or
+        // ideally even extend the zone at map construction time (or edit time) when that happens.
+        panic!("{} causes illegal entrance into a zone at {}", req, t);
+    }
+}
+
+fn find_illegal_zone_entrance<'a, I: Iterator<Item = &'a PathStep>>(
+    map: &Map,
+    steps: I,
+    req: &PathRequest,
+) -> Option<TurnID> {
     let z1 = map.get_parent(req.start.lane()).get_zone(map);
     let z2 = map.get_parent(req.end.lane()).get_zone(map);
 
@@ -729,13 +768,10 @@ fn validate_zones(map: &Map, steps: &[PathStep], req: &PathRequest) {
                 // Entering our destination zone is fine
                 let into_zone = map.get_parent(t.dst).get_zone(map);
                 if into_zone != z1 && into_zone != z2 {
-                    // TODO There are lots of false positive here that occur when part of the graph
-                    // is separated from the rest by access-restricted roads. Could maybe detect
-                    // that here, or ideally even extend the zone at map construction time (or edit
-                    // time) when that happens.
-                    panic!("{} causes illegal entrance into a zone at {}", req, t);
+                    return Some(*t);
                 }
             }
         }
     }
+    None
 }
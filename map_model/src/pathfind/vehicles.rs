@@ -283,6 +283,7 @@ pub fn vehicle_cost(
     let max_speed = match constraints {
         PathConstraints::Car | PathConstraints::Bus | PathConstraints::Train => None,
         PathConstraints::Bike => Some(crate::MAX_BIKE_SPEED),
+        PathConstraints::Truck => Some(crate::MAX_TRUCK_SPEED),
         PathConstraints::Pedestrian => unreachable!(),
     };
     let t1 = map.get_r(dr.id).length()
@@ -291,7 +292,7 @@ pub fn vehicle_cost(
         / Traversable::max_speed_along_movement(mvmnt, max_speed, constraints, map);
 
     let base = match constraints {
-        PathConstraints::Car | PathConstraints::Train => t1 + t2,
+        PathConstraints::Car | PathConstraints::Train | PathConstraints::Truck => t1 + t2,
         PathConstraints::Bike => {
             // TODO If we're on a driving lane, higher speed limit is worse.
             // TODO Bike lanes next to parking is dangerous.
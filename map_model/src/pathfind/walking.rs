@@ -42,7 +42,7 @@ impl WalkingNode {
         WalkingNode::SidewalkEndpoint(lane.get_directed_parent(), dst_i)
     }
 
-    fn end_transit(pos: Position, map: &Map) -> WalkingNode {
+    pub(crate) fn end_transit(pos: Position, map: &Map) -> WalkingNode {
         let l = map.get_l(pos.lane());
         if map.get_i(l.src_i).is_outgoing_border() && pos.dist_along() == Distance::ZERO {
             return WalkingNode::LeaveMap(l.src_i);
@@ -108,8 +108,27 @@ impl SidewalkPathfinder {
             return;
         }
 
+        // Map edits may create new bus stops. The node ordering can't be reused once the graph
+        // has more nodes, so prepare from scratch in that case.
+        let mut new_nodes = false;
+        if use_transit.is_some() {
+            for bs in map.all_bus_stops().keys() {
+                if !self.nodes.contains(WalkingNode::RideBus(*bs)) {
+                    self.nodes.get_or_insert(WalkingNode::RideBus(*bs));
+                    new_nodes = true;
+                }
+            }
+        }
+
         let input_graph = make_input_graph(&self.nodes, use_transit, map);
-        let engine = self.engine.reuse_ordering().create(input_graph);
+        let engine = if !new_nodes {
+            self.engine.reuse_ordering()
+        } else if self.engine.is_dijkstra() {
+            CreateEngine::Dijkstra
+        } else {
+            CreateEngine::CH
+        }
+        .create(input_graph);
         self.engine = engine;
     }
 
@@ -231,6 +250,27 @@ impl SidewalkPathfinder {
         None
     }
 
+    /// Estimate how long it takes to walk between two positions, riding transit if that helps.
+    /// Like the transit graph itself, this optimistically assumes no waiting at stops.
+    pub fn estimate_transit_duration(
+        &self,
+        map: &Map,
+        start: Position,
+        end: Position,
+    ) -> Option<Duration> {
+        if matches!(self.engine, PathfindEngine::Empty) {
+            return None;
+        }
+
+        assert!(self.use_transit);
+
+        let (raw_weight, _) = self.engine.calculate_path(
+            self.nodes.get(WalkingNode::closest(start, map)),
+            self.nodes.get(WalkingNode::end_transit(end, map)),
+        )?;
+        Some(unround(raw_weight))
+    }
+
     pub fn all_costs_from(&self, start: Position, map: &Map) -> HashMap<DirectedRoadID, Duration> {
         if matches!(self.engine, PathfindEngine::Empty) {
             return HashMap::new();
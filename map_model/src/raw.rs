@@ -7,6 +7,7 @@ use std::fmt;
 
 use anyhow::{Context, Result};
 use petgraph::graphmap::DiGraphMap;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 use abstio::{CityName, MapName};
@@ -40,6 +41,8 @@ pub struct RawMap {
     pub areas: Vec<RawArea>,
     pub parking_lots: Vec<RawParkingLot>,
     pub parking_aisles: Vec<(osm::WayID, Vec<Pt2D>)>,
+    pub bike_share_docks: Vec<RawBikeShareDock>,
+    pub charging_stations: Vec<RawChargingStation>,
 
     pub boundary_polygon: Polygon,
     pub gps_bounds: GPSBounds,
@@ -53,7 +56,7 @@ pub struct RawMap {
 //   checks.
 // - TODO Look at some stable ID standard like linear referencing
 // (https://github.com/opentraffic/architecture/issues/1).
-#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, JsonSchema)]
 pub struct OriginalRoad {
     pub osm_way_id: osm::WayID,
     pub i1: osm::NodeID,
@@ -116,6 +119,8 @@ impl RawMap {
             areas: Vec::new(),
             parking_lots: Vec::new(),
             parking_aisles: Vec::new(),
+            bike_share_docks: Vec::new(),
+            charging_stations: Vec::new(),
             // Some nonsense thing
             boundary_polygon: Polygon::rectangle(1.0, 1.0),
             gps_bounds: GPSBounds::new(),
@@ -692,6 +697,26 @@ pub struct RawParkingLot {
     pub osm_tags: Tags,
 }
 
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RawBikeShareDock {
+    pub osm_id: osm::NodeID,
+    pub pt: Pt2D,
+    pub name: Option<String>,
+    /// How many bikes or scooters can be docked here at once
+    pub capacity: usize,
+    /// Kick-scooters instead of bikes
+    pub scooters: bool,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RawChargingStation {
+    pub osm_id: osm::NodeID,
+    pub pt: Pt2D,
+    pub name: Option<String>,
+    /// How many vehicles can charge here at once
+    pub capacity: usize,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum RestrictionType {
     BanTurns,
@@ -0,0 +1,301 @@
+//! Tiny handcrafted maps for unit tests, here and in other crates
+
+use std::collections::BTreeMap;
+
+use abstutil::Timer;
+use geom::{Distance, Duration, LonLat, Polygon, Pt2D, Time};
+
+use crate::raw::{
+    OriginalRoad, RawBikeShareDock, RawBuilding, RawChargingStation, RawIntersection, RawMap,
+    RawRoad,
+};
+use crate::{
+    osm, BikeShareDockID, BuildingID, BusRouteID, BusStopID, ChargingStationID, Direction,
+    EditBusRoute, EditBusStop, EditCmd, IntersectionID, IntersectionType, LaneID, LaneType, Map,
+    PathConstraints, RawToMapOptions, Road,
+};
+
+/// The ID of a named intersection. This is a hash of the name (FNV-1a), so it's stable across
+/// runs; `toy_map` checks that no two names collide.
+pub fn node(name: &str) -> osm::NodeID {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for b in name.bytes() {
+        hash ^= b as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    // Real OSM IDs are positive
+    osm::NodeID((hash >> 1) as i64)
+}
+
+/// Build a map from named intersections (name, x, y, type) and two-way roads between them.
+/// Positions are in meters.
+pub fn toy_map(
+    intersections: &[(&str, f64, f64, IntersectionType)],
+    roads: &[(&str, &str)],
+) -> Map {
+    build(toy_raw_map(intersections, roads))
+}
+
+/// Like `toy_map`, but stops before building the map, so buildings and other things can be added
+/// first. Call `build` afterwards.
+pub fn toy_raw_map(
+    intersections: &[(&str, f64, f64, IntersectionType)],
+    roads: &[(&str, &str)],
+) -> RawMap {
+    let mut raw = RawMap::blank(abstio::MapName::new("zz", "test", "toy"));
+    for (name, x, y, intersection_type) in intersections {
+        let old = raw.intersections.insert(
+            node(name),
+            RawIntersection {
+                point: Pt2D::new(*x, *y),
+                intersection_type: *intersection_type,
+                elevation: Distance::ZERO,
+                trim_roads_for_merging: BTreeMap::new(),
+            },
+        );
+        assert!(
+            old.is_none(),
+            "{} is listed twice or collides with another intersection's ID",
+            name
+        );
+    }
+    for (idx, (i1, i2)) in roads.iter().enumerate() {
+        let id = OriginalRoad::new(idx as i64 + 1, (node(i1).0, node(i2).0));
+        let mut osm_tags = abstutil::Tags::empty();
+        osm_tags.insert(osm::HIGHWAY, "residential");
+        osm_tags.insert(osm::SIDEWALK, "both");
+        osm_tags.insert("lanes", "2");
+        osm_tags.insert(osm::ENDPT_FWD, "true");
+        osm_tags.insert(osm::ENDPT_BACK, "true");
+        osm_tags.insert(osm::OSM_WAY_ID, id.osm_way_id.to_string());
+        osm_tags.insert(osm::MAXSPEED, "25 mph");
+        raw.roads.insert(
+            id,
+            RawRoad {
+                center_points: vec![
+                    raw.intersections[&node(i1)].point,
+                    raw.intersections[&node(i2)].point,
+                ],
+                osm_tags,
+                turn_restrictions: Vec::new(),
+                complicated_turn_restrictions: Vec::new(),
+                percent_incline: 0.0,
+            },
+        );
+    }
+    raw
+}
+
+/// Add a small named building centered somewhere, with some private parking spots. It'll be
+/// connected to the closest sidewalk.
+pub fn add_building(raw: &mut RawMap, name: &str, x: f64, y: f64, parking_spots: usize) {
+    let mut osm_tags = abstutil::Tags::empty();
+    osm_tags.insert("building", "house");
+    let old = raw.buildings.insert(
+        building_osm_id(name),
+        RawBuilding {
+            polygon: Polygon::rectangle_centered(
+                Pt2D::new(x, y),
+                Distance::meters(10.0),
+                Distance::meters(10.0),
+            ),
+            osm_tags,
+            public_garage_name: None,
+            num_parking_spots: parking_spots,
+            amenities: Vec::new(),
+        },
+    );
+    assert!(old.is_none(), "building {} is listed twice", name);
+}
+
+/// Add a named bike-share dock, which'll be snapped to the closest sidewalk
+pub fn add_bike_share_dock(raw: &mut RawMap, name: &str, x: f64, y: f64, capacity: usize) {
+    raw.bike_share_docks.push(RawBikeShareDock {
+        osm_id: node(name),
+        pt: Pt2D::new(x, y),
+        name: Some(name.to_string()),
+        capacity,
+        scooters: false,
+    });
+}
+
+/// Add a named charging station, which'll be snapped to the closest sidewalk
+pub fn add_charging_station(raw: &mut RawMap, name: &str, x: f64, y: f64, capacity: usize) {
+    raw.charging_stations.push(RawChargingStation {
+        osm_id: node(name),
+        pt: Pt2D::new(x, y),
+        name: Some(name.to_string()),
+        capacity,
+    });
+}
+
+/// Finish a map started by `toy_raw_map`.
+pub fn build(mut raw: RawMap) -> Map {
+    let mut pts: Vec<Pt2D> = raw.intersections.values().map(|i| i.point).collect();
+    pts.extend(raw.buildings.values().map(|b| b.polygon.center()));
+    let bounds = geom::Bounds::from(&pts);
+    raw.boundary_polygon = Polygon::rectangle_two_corners(
+        Pt2D::new(bounds.min_x - 50.0, bounds.min_y - 50.0),
+        Pt2D::new(bounds.max_x + 50.0, bounds.max_y + 50.0),
+    )
+    .unwrap();
+    // Lookups by position only work inside the map's bounds, which come from these. A degree of
+    // longitude is about 75km this far north, and a degree of latitude about 111km; round down to
+    // leave some slack.
+    raw.gps_bounds.update(LonLat::new(-122.3, 47.6));
+    raw.gps_bounds.update(LonLat::new(
+        -122.3 + (0.01_f64).max((bounds.max_x + 50.0) / 70_000.0),
+        47.6 + (0.01_f64).max((bounds.max_y + 50.0) / 100_000.0),
+    ));
+    let mut timer = Timer::throwaway();
+    let mut map = Map::create_from_raw(raw, RawToMapOptions::default(), &mut timer);
+    // Fill in the same things as a map loaded from a file, like which buildings are on each road
+    map.map_loaded_directly(&mut timer);
+    map
+}
+
+/// A building added by `add_building`
+pub fn building(map: &Map, name: &str) -> BuildingID {
+    map.all_buildings()
+        .iter()
+        .find(|b| b.orig_id == building_osm_id(name))
+        .unwrap_or_else(|| panic!("no building {}", name))
+        .id
+}
+
+/// A dock added by `add_bike_share_dock`
+pub fn bike_share_dock(map: &Map, name: &str) -> BikeShareDockID {
+    map.all_bike_share_docks()
+        .iter()
+        .find(|d| d.osm_id == node(name))
+        .unwrap_or_else(|| panic!("no bike-share dock {}", name))
+        .id
+}
+
+/// A station added by `add_charging_station`
+pub fn charging_station(map: &Map, name: &str) -> ChargingStationID {
+    map.all_charging_stations()
+        .iter()
+        .find(|s| s.osm_id == node(name))
+        .unwrap_or_else(|| panic!("no charging station {}", name))
+        .id
+}
+
+fn building_osm_id(name: &str) -> osm::OsmID {
+    osm::OsmID::Way(osm::WayID(node(name).0))
+}
+
+/// A named intersection
+pub fn intersection(map: &Map, name: &str) -> IntersectionID {
+    map.all_intersections()
+        .iter()
+        .find(|i| i.orig_id == node(name))
+        .unwrap_or_else(|| panic!("no intersection {}", name))
+        .id
+}
+
+/// The road from one named intersection to another
+pub fn road<'a>(map: &'a Map, from: &str, to: &str) -> &'a Road {
+    map.all_roads()
+        .iter()
+        .find(|r| {
+            map.get_i(r.src_i).orig_id == node(from) && map.get_i(r.dst_i).orig_id == node(to)
+        })
+        .unwrap_or_else(|| panic!("no road from {} to {}", from, to))
+}
+
+/// The sidewalk on the side of traffic heading forwards
+pub fn sidewalk(road: &Road) -> LaneID {
+    road.lanes
+        .iter()
+        .find(|l| l.lane_type == LaneType::Sidewalk && l.dir == Direction::Fwd)
+        .unwrap()
+        .id
+}
+
+/// Add a bus route, stopping at each (from, to, distance along the sidewalk) in order. Buses
+/// appear at the start of the first stop's road every `headway` from 6am to 10pm, and vanish after
+/// the last stop. Returns the route and its new stops.
+pub fn add_bus_route(
+    map: &mut Map,
+    name: &str,
+    stops: &[(&str, &str, f64)],
+    headway: Duration,
+) -> (BusRouteID, Vec<BusStopID>) {
+    let mut edits = map.get_edits().clone();
+    let mut stop_ids = Vec::new();
+    for (from, to, dist) in stops {
+        let sidewalk = sidewalk(road(map, from, to));
+        let idx = map.get_l(sidewalk).bus_stops.len()
+            + stop_ids
+                .iter()
+                .filter(|id: &&BusStopID| id.sidewalk == sidewalk)
+                .count();
+        let id = BusStopID::new(sidewalk, idx);
+        edits.commands.push(EditCmd::ChangeBusStop {
+            id,
+            old: None,
+            new: Some(EditBusStop {
+                name: format!("{} stop {}", name, stop_ids.len() + 1),
+                dist_along: Distance::meters(*dist),
+            }),
+        });
+        stop_ids.push(id);
+    }
+
+    let mut spawn_times = Vec::new();
+    let mut t = Time::START_OF_DAY + Duration::hours(6);
+    while t <= Time::START_OF_DAY + Duration::hours(22) {
+        spawn_times.push(t);
+        t += headway;
+    }
+    let id = map.new_bus_route_id();
+    edits.commands.push(EditCmd::ChangeRoute {
+        id,
+        old: None,
+        new: Some(EditBusRoute {
+            full_name: name.to_string(),
+            short_name: name.to_string(),
+            route_type: PathConstraints::Bus,
+            stops: stop_ids.clone(),
+            start: road(map, stops[0].0, stops[0].1)
+                .lanes
+                .iter()
+                .find(|l| l.lane_type == LaneType::Driving && l.dir == Direction::Fwd)
+                .unwrap()
+                .id,
+            end_border: None,
+            spawn_times,
+            scheduled_offsets: Vec::new(),
+        }),
+    });
+
+    let mut timer = Timer::throwaway();
+    map.must_apply_edits(edits, &mut timer);
+    map.recalculate_pathfinding_after_edits(&mut timer);
+    (id, stop_ids)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_dont_collide() {
+        assert_ne!(node("ab"), node("ba"));
+        assert_ne!(node("ac"), node("bb"));
+        assert_eq!(node("ab"), node("ab"));
+    }
+
+    #[test]
+    #[should_panic(expected = "listed twice")]
+    fn duplicate_names() {
+        toy_map(
+            &[
+                ("a", 0.0, 0.0, IntersectionType::Border),
+                ("a", 100.0, 0.0, IntersectionType::Border),
+            ],
+            &[],
+        );
+    }
+}
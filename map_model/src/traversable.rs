@@ -1,5 +1,6 @@
 use std::fmt;
 
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 use geom::{Angle, Distance, PolyLine, Pt2D, Speed};
@@ -7,7 +8,9 @@ use geom::{Angle, Distance, PolyLine, Pt2D, Speed};
 use crate::{DirectedRoadID, Direction, LaneID, Map, MovementID, PathConstraints, TurnID};
 
 /// Represents a specific point some distance along a lane.
-#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[derive(
+    Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, JsonSchema,
+)]
 pub struct Position {
     // Don't let callers construct a Position directly, so it's easy to find callers of new().
     lane: LaneID,
@@ -131,7 +134,9 @@ impl Position {
 
 /// Either a lane or a turn, where most movement happens.
 // TODO Consider adding building and parking lot driveways here.
-#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, PartialOrd, Ord, Serialize, Deserialize)]
+#[derive(
+    Clone, Copy, Debug, Eq, Hash, PartialEq, PartialOrd, Ord, Serialize, Deserialize, JsonSchema,
+)]
 pub enum Traversable {
     Lane(LaneID),
     Turn(TurnID),
@@ -216,8 +221,11 @@ impl Traversable {
             // We assume every pedestrian has a max_speed defined.
             walking_speed_on_incline(max_speed_on_flat_ground.unwrap(), percent_incline)
         } else {
-            debug_assert!(max_speed_on_flat_ground.is_none());
-            // Incline doesn't affect cars, buses, or trains
+            // Trucks are the only other vehicles that may be slower than the speed limit
+            debug_assert!(
+                max_speed_on_flat_ground.is_none() || constraints == PathConstraints::Truck
+            );
+            // Incline doesn't affect cars, buses, trains, or trucks
             road.speed_limit
         };
 
@@ -252,6 +260,8 @@ impl Traversable {
 
 // 10 mph
 pub const MAX_BIKE_SPEED: Speed = Speed::const_meters_per_second(4.4704);
+// 55 mph
+pub const MAX_TRUCK_SPEED: Speed = Speed::const_meters_per_second(24.5872);
 // 3 mph
 pub const MAX_WALKING_SPEED: Speed = Speed::const_meters_per_second(1.34112);
 
@@ -3,17 +3,20 @@ use std::fs::File;
 use std::io::Write;
 
 use anyhow::Result;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 use abstutil::Counter;
-use geom::{Duration, Time};
+use geom::{Distance, Duration, Pt2D, Time};
 use map_model::{
-    BusRouteID, BusStopID, CompressedMovementID, IntersectionID, LaneID, Map, MovementID,
-    ParkingLotID, Path, PathRequest, RoadID, Traversable, TurnID,
+    BikeShareDockID, BuildingID, BusRouteID, BusStopID, ChargingStationID, CompressedMovementID,
+    DirectedRoadID, IntersectionID, LaneID, Map, MovementID, ParkingLotID, Path, PathRequest,
+    RoadID, Traversable, TurnID,
 };
 
 use crate::{
-    AgentID, AgentType, AlertLocation, CarID, Event, ParkingSpot, TripID, TripMode, TripPhaseType,
+    spot_policy, AgentID, AgentType, AlertLocation, CarID, Emissions, Event, ParkingSpot, TripID,
+    TripMode, TripPhaseType, VehicleType,
 };
 
 /// As a simulation runs, different pieces emit Events. The Analytics object listens to these,
@@ -39,9 +42,47 @@ pub struct Analytics {
 
     // TODO Reconsider this one
     pub bus_arrivals: Vec<(Time, CarID, BusRouteID, BusStopID)>,
+    /// For routes with a real timetable, how late did each vehicle reach each stop? Negative means
+    /// early.
+    pub schedule_deviations: BTreeMap<BusRouteID, Vec<(Time, BusStopID, Duration)>>,
     /// For each passenger boarding, how long did they wait at the stop?
     pub passengers_boarding: BTreeMap<BusStopID, Vec<(Time, BusRouteID, Duration)>>,
     pub passengers_alighting: BTreeMap<BusStopID, Vec<(Time, BusRouteID)>>,
+    /// Each time a full transit vehicle left people waiting at a stop, how many?
+    pub denied_boardings: BTreeMap<BusStopID, Vec<(Time, BusRouteID, usize)>>,
+    /// Per route, each time a vehicle left a stop (beginning the segment to the next stop), how
+    /// many passengers were riding, out of its capacity?
+    pub transit_loads: BTreeMap<BusRouteID, Vec<(Time, BusStopID, usize, usize)>>,
+    /// For cars carrying carpool passengers or ride-hailing riders, how many people (including the
+    /// driver) are inside after each pick-up or drop-off
+    pub car_occupancy: BTreeMap<CarID, Vec<(Time, usize)>>,
+
+    /// For each ride-hailing pick-up, how long did the rider wait for the vehicle?
+    pub ride_hail_waits: Vec<(Time, TripID, Duration)>,
+    /// Per driving lane, when does a ride-hailing vehicle stop at the curb to pick up (true) or
+    /// drop off (false) somebody?
+    pub ride_hail_curb_stops: BTreeMap<LaneID, Vec<(Time, bool)>>,
+    /// How far has the ride-hailing fleet driven empty, including going to park?
+    pub ride_hail_empty_distance: Distance,
+    /// How far has the ride-hailing fleet driven with a rider on board?
+    pub ride_hail_revenue_distance: Distance,
+    /// Whenever it changes, how many ride-hailing vehicles are busy, and how many are in the fleet
+    pub ride_hail_utilization: Vec<(Time, usize, usize)>,
+
+    /// When did a truck stop to unload at a building?
+    pub deliveries: Vec<(Time, TripID, BuildingID)>,
+    /// Per driving lane, when did a truck finish unloading, how long was it blocking the lane, and
+    /// how many vehicles were stuck directly behind it at the end?
+    pub loading_zone_stops: BTreeMap<LaneID, Vec<(Time, Duration, usize)>>,
+
+    /// Per bike-share dock, how many bikes are docked after every change
+    pub bike_share_inventory: BTreeMap<BikeShareDockID, Vec<(Time, usize)>>,
+    /// When did somebody find a dock empty and have to go elsewhere?
+    pub bike_share_empty_docks: Vec<(Time, TripID, BikeShareDockID)>,
+    /// When did somebody find a dock full and have to go elsewhere?
+    pub bike_share_full_docks: Vec<(Time, TripID, BikeShareDockID)>,
+    /// When did the operator move bikes, from where, to where, and how many?
+    pub bike_share_rebalancing: Vec<(Time, BikeShareDockID, BikeShareDockID, usize)>,
 
     pub started_trips: BTreeMap<TripID, Time>,
     /// Finish time, ID, mode, trip duration if successful (or None if cancelled)
@@ -56,10 +97,39 @@ pub struct Analytics {
     // TODO Transit riders aren't represented here yet, just the vehicle they're riding.
     /// Only for traffic signals. The u8 is the movement index from a CompressedMovementID.
     pub intersection_delays: BTreeMap<IntersectionID, Vec<(u8, Time, Duration, AgentType)>>,
+    /// Every time a bus or train changed a traffic signal's timing: the transit vehicle's
+    /// estimated delay saved, and the estimated delay added for everybody else.
+    pub transit_signal_priority: BTreeMap<IntersectionID, Vec<(Time, CarID, Duration, Duration)>>,
 
     /// Per parking lane or lot, when does a spot become filled (true) or free (false)
     pub parking_lane_changes: BTreeMap<LaneID, Vec<(Time, bool)>>,
     pub parking_lot_changes: BTreeMap<ParkingLotID, Vec<(Time, bool)>>,
+    /// Every time a car leaves a spot with a price or time limit: how much it paid (in cents), and
+    /// how long it stayed past the limit
+    pub parking_payments: Vec<(Time, ParkingSpot, usize, Duration)>,
+    /// When did a driver have to search away from their destination for parking, and how far did
+    /// they drive looking?
+    pub parking_cruising: Vec<(Time, CarID, Distance)>,
+
+    /// When did a driver switch routes partway through their trip, and how much time did they
+    /// expect to save? None means they were going around live map edits.
+    pub reroutes: Vec<(Time, CarID, Option<Duration>)>,
+
+    /// Per charging station, when did a car unplug, how long did it charge, and how many kWh did
+    /// it get?
+    pub charging_sessions: BTreeMap<ChargingStationID, Vec<(Time, Duration, f64)>>,
+    /// Where did electric vehicles with a low battery fail to find a free charger? Useful for
+    /// deciding where new stations should go.
+    pub unmet_charging_demand: Vec<(Time, CarID, Pt2D)>,
+    pub battery_depletions: Vec<(Time, CarID)>,
+
+    /// (Road or intersection, hour block) -> total emissions for that hour
+    pub road_emissions: BTreeMap<(RoadID, usize), Emissions>,
+    pub intersection_emissions: BTreeMap<(IntersectionID, usize), Emissions>,
+
+    /// (Directed road, hour block when entering it) -> the total time cars and trucks took to cross
+    /// a lane of the road, and how many did. Later simulations can plan routes around this congestion.
+    pub road_travel_times: BTreeMap<(DirectedRoadID, usize), (Duration, usize)>,
 
     pub(crate) alerts: Vec<(Time, AlertLocation, String)>,
 
@@ -67,7 +137,7 @@ pub struct Analytics {
     record_anything: bool,
 }
 
-#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub enum Problem {
     /// A vehicle waited >30s, or a pedestrian waited >15s.
     IntersectionDelay(IntersectionID, Duration),
@@ -87,15 +157,40 @@ impl Analytics {
             traffic_signal_thruput: TimeSeriesCount::new(),
             demand: BTreeMap::new(),
             bus_arrivals: Vec::new(),
+            schedule_deviations: BTreeMap::new(),
             passengers_boarding: BTreeMap::new(),
             passengers_alighting: BTreeMap::new(),
+            denied_boardings: BTreeMap::new(),
+            transit_loads: BTreeMap::new(),
+            car_occupancy: BTreeMap::new(),
+            ride_hail_waits: Vec::new(),
+            ride_hail_curb_stops: BTreeMap::new(),
+            ride_hail_empty_distance: Distance::ZERO,
+            ride_hail_revenue_distance: Distance::ZERO,
+            ride_hail_utilization: Vec::new(),
+            deliveries: Vec::new(),
+            loading_zone_stops: BTreeMap::new(),
+            bike_share_inventory: BTreeMap::new(),
+            bike_share_empty_docks: Vec::new(),
+            bike_share_full_docks: Vec::new(),
+            bike_share_rebalancing: Vec::new(),
             started_trips: BTreeMap::new(),
             finished_trips: Vec::new(),
             problems_per_trip: BTreeMap::new(),
             trip_log: Vec::new(),
             intersection_delays: BTreeMap::new(),
+            transit_signal_priority: BTreeMap::new(),
             parking_lane_changes: BTreeMap::new(),
             parking_lot_changes: BTreeMap::new(),
+            parking_payments: Vec::new(),
+            parking_cruising: Vec::new(),
+            reroutes: Vec::new(),
+            charging_sessions: BTreeMap::new(),
+            unmet_charging_demand: Vec::new(),
+            battery_depletions: Vec::new(),
+            road_emissions: BTreeMap::new(),
+            intersection_emissions: BTreeMap::new(),
+            road_travel_times: BTreeMap::new(),
             alerts: Vec::new(),
             record_anything,
         }
@@ -108,24 +203,24 @@ impl Analytics {
 
         // Throughput
         if let Event::AgentEntersTraversable(a, _, to, passengers) = ev {
+            let rider_type = if a.to_type() == AgentType::Car {
+                AgentType::CarPassenger
+            } else {
+                AgentType::TransitRider
+            };
             match to {
                 Traversable::Lane(l) => {
                     self.road_thruput.record(time, l.road, a.to_type(), 1);
                     if let Some(n) = passengers {
-                        self.road_thruput
-                            .record(time, l.road, AgentType::TransitRider, n);
+                        self.road_thruput.record(time, l.road, rider_type, n);
                     }
                 }
                 Traversable::Turn(t) => {
                     self.intersection_thruput
                         .record(time, t.parent, a.to_type(), 1);
                     if let Some(n) = passengers {
-                        self.intersection_thruput.record(
-                            time,
-                            t.parent,
-                            AgentType::TransitRider,
-                            n,
-                        );
+                        self.intersection_thruput
+                            .record(time, t.parent, rider_type, n);
                     }
 
                     if let Some((id, compressed)) = map.get_movement_for_traffic_signal(t) {
@@ -133,12 +228,8 @@ impl Analytics {
                         self.traffic_signal_thruput
                             .record(time, compressed, a.to_type(), 1);
                         if let Some(n) = passengers {
-                            self.traffic_signal_thruput.record(
-                                time,
-                                compressed,
-                                AgentType::TransitRider,
-                                n,
-                            );
+                            self.traffic_signal_thruput
+                                .record(time, compressed, rider_type, n);
                         }
                     }
                 }
@@ -159,6 +250,18 @@ impl Analytics {
         if let Event::BusArrivedAtStop(bus, route, stop) = ev {
             self.bus_arrivals.push((time, bus, route, stop));
         }
+        if let Event::TransitScheduleDeviation {
+            route,
+            stop,
+            late_by,
+            ..
+        } = ev
+        {
+            self.schedule_deviations
+                .entry(route)
+                .or_default()
+                .push((time, stop, late_by));
+        }
 
         // Passengers boarding/alighting
         if let Event::PassengerBoardsTransit(_, _, route, stop, waiting) = ev {
@@ -173,6 +276,140 @@ impl Analytics {
                 .or_insert_with(Vec::new)
                 .push((time, route));
         }
+        if let Event::TransitBoardingDenied {
+            route,
+            stop,
+            left_behind,
+            ..
+        } = ev
+        {
+            self.denied_boardings
+                .entry(stop)
+                .or_default()
+                .push((time, route, left_behind));
+        }
+        if let Event::TransitVehicleLoad {
+            route,
+            stop,
+            passengers,
+            capacity,
+            ..
+        } = ev
+        {
+            self.transit_loads
+                .entry(route)
+                .or_default()
+                .push((time, stop, passengers, capacity));
+        }
+
+        // Car occupancy
+        if let Event::PassengerEntersCar(_, car, _) | Event::PassengerLeavesCar(_, car, _) = ev {
+            let changes = self.car_occupancy.entry(car).or_default();
+            // Just the driver to start
+            let before = changes.last().map(|(_, n)| *n).unwrap_or(1);
+            let after = if matches!(ev, Event::PassengerEntersCar(_, _, _)) {
+                before + 1
+            } else {
+                before - 1
+            };
+            changes.push((time, after));
+        }
+
+        // Ride-hailing
+        match ev {
+            Event::RideHailPickUp {
+                rider,
+                lane,
+                waited,
+                ..
+            } => {
+                self.ride_hail_waits.push((time, rider, waited));
+                self.ride_hail_curb_stops
+                    .entry(lane)
+                    .or_default()
+                    .push((time, true));
+            }
+            Event::RideHailDropOff { lane, .. } => {
+                self.ride_hail_curb_stops
+                    .entry(lane)
+                    .or_default()
+                    .push((time, false));
+            }
+            Event::RideHailDistance {
+                distance,
+                with_rider,
+                ..
+            } => {
+                if with_rider {
+                    self.ride_hail_revenue_distance += distance;
+                } else {
+                    self.ride_hail_empty_distance += distance;
+                }
+            }
+            Event::RideHailFleetUtilization { busy, total } => {
+                self.ride_hail_utilization.push((time, busy, total));
+            }
+            _ => {}
+        }
+
+        // Deliveries
+        match ev {
+            Event::TruckUnloading { trip, building, .. } => {
+                self.deliveries.push((time, trip, building));
+            }
+            Event::TruckLeftLoadingZone {
+                lane,
+                blocked_for,
+                vehicles_behind,
+                ..
+            } => {
+                self.loading_zone_stops.entry(lane).or_default().push((
+                    time,
+                    blocked_for,
+                    vehicles_behind,
+                ));
+            }
+            _ => {}
+        }
+
+        // Bike-sharing
+        match ev {
+            Event::BikeShareUnlocked {
+                dock, inventory, ..
+            }
+            | Event::BikeShareReturned {
+                dock, inventory, ..
+            } => {
+                self.bike_share_inventory
+                    .entry(dock)
+                    .or_default()
+                    .push((time, inventory));
+            }
+            Event::BikeShareDockEmpty { trip, dock } => {
+                self.bike_share_empty_docks.push((time, trip, dock));
+            }
+            Event::BikeShareDockFull { trip, dock } => {
+                self.bike_share_full_docks.push((time, trip, dock));
+            }
+            Event::BikeShareRebalanced {
+                from,
+                to,
+                bikes,
+                from_inventory,
+                to_inventory,
+            } => {
+                self.bike_share_rebalancing.push((time, from, to, bikes));
+                self.bike_share_inventory
+                    .entry(from)
+                    .or_default()
+                    .push((time, from_inventory));
+                self.bike_share_inventory
+                    .entry(to)
+                    .or_default()
+                    .push((time, to_inventory));
+            }
+            _ => {}
+        }
 
         // Started trips
         if let Event::TripPhaseStarting(id, _, _, _) = ev {
@@ -200,7 +437,7 @@ impl Analytics {
                 AgentID::Car(_) => Duration::seconds(30.0),
                 AgentID::Pedestrian(_) => Duration::seconds(15.0),
                 // Don't record for riders
-                AgentID::BusPassenger(_, _) => Duration::hours(24),
+                AgentID::BusPassenger(_, _) | AgentID::CarPassenger(_, _) => Duration::hours(24),
             };
             if delay > threshold {
                 self.problems_per_trip
@@ -219,6 +456,20 @@ impl Analytics {
             }
         }
 
+        if let Event::TransitSignalPriority {
+            intersection,
+            vehicle,
+            transit_delay_saved,
+            cross_street_delay_added,
+            ..
+        } = ev
+        {
+            self.transit_signal_priority
+                .entry(intersection)
+                .or_default()
+                .push((time, vehicle, transit_delay_saved, cross_street_delay_added));
+        }
+
         // Parking spot changes
         if let Event::CarReachedParkingSpot(_, spot) = ev {
             if let ParkingSpot::Onstreet(l, _) = spot {
@@ -233,7 +484,7 @@ impl Analytics {
                     .push((time, true));
             }
         }
-        if let Event::CarLeftParkingSpot(_, spot) = ev {
+        if let Event::CarLeftParkingSpot(_, spot, parked_since) = ev {
             if let ParkingSpot::Onstreet(l, _) = spot {
                 self.parking_lane_changes
                     .entry(l)
@@ -245,6 +496,79 @@ impl Analytics {
                     .or_insert_with(Vec::new)
                     .push((time, false));
             }
+
+            // Use the policy in effect when the car leaves, even if it changed during the stay
+            let policy = spot_policy(spot, map);
+            if !policy.is_unrestricted() {
+                let stay = time - parked_since;
+                let overstay = match policy.max_duration {
+                    Some(max) if stay > max => stay - max,
+                    _ => Duration::ZERO,
+                };
+                self.parking_payments
+                    .push((time, spot, policy.cost(stay), overstay));
+            }
+        }
+        if let Event::CruisingForParking(car, dist) = ev {
+            self.parking_cruising.push((time, car, dist));
+        }
+        if let Event::Rerouted(car, saved) = ev {
+            self.reroutes.push((time, car, saved));
+        }
+
+        // Emissions
+        if let Event::VehicleEmissions(_, on, emissions) = ev {
+            match on {
+                Traversable::Lane(l) => {
+                    *self
+                        .road_emissions
+                        .entry((l.road, time.get_hours()))
+                        .or_insert(Emissions::ZERO) += emissions;
+                }
+                Traversable::Turn(t) => {
+                    *self
+                        .intersection_emissions
+                        .entry((t.parent, time.get_hours()))
+                        .or_insert(Emissions::ZERO) += emissions;
+                }
+            }
+        }
+
+        // Travel times
+        if let Event::VehicleCrossedLane(car, l, duration) = ev {
+            if matches!(car.vehicle_type, VehicleType::Car | VehicleType::Truck) {
+                let entry = self
+                    .road_travel_times
+                    .entry((
+                        map.get_l(l).get_directed_parent(),
+                        (time - duration).get_hours(),
+                    ))
+                    .or_insert((Duration::ZERO, 0));
+                entry.0 += duration;
+                entry.1 += 1;
+            }
+        }
+
+        // Electric vehicles
+        match ev {
+            Event::ChargingSessionEnded {
+                station,
+                duration,
+                kwh,
+                ..
+            } => {
+                self.charging_sessions
+                    .entry(station)
+                    .or_default()
+                    .push((time, duration, kwh));
+            }
+            Event::NoChargerAvailable(car, pt) => {
+                self.unmet_charging_demand.push((time, car, pt));
+            }
+            Event::BatteryDepleted(car) => {
+                self.battery_depletions.push((time, car));
+            }
+            _ => {}
         }
 
         // Safety metrics
@@ -473,6 +797,110 @@ impl Analytics {
         }
     }
 
+    /// For each hour of the day so far, the most onstreet and parking lot spots filled at once.
+    pub fn parking_occupancy_per_hour(&self, now: Time) -> Vec<usize> {
+        // The changes for each lane or lot are already sorted, and a stable sort keeps a car
+        // reaching and leaving a spot at the same time in order.
+        let mut changes: Vec<(Time, bool)> = self
+            .parking_lane_changes
+            .values()
+            .chain(self.parking_lot_changes.values())
+            .flatten()
+            .filter(|(t, _)| *t <= now)
+            .cloned()
+            .collect();
+        changes.sort_by_key(|(t, _)| *t);
+
+        let mut peaks = vec![0; now.get_hours() + 1];
+        let mut cnt = 0;
+        let mut hour = 0;
+        for (t, filled) in changes {
+            // Hours without any changes still have the cars parked before
+            while hour < t.get_hours() {
+                hour += 1;
+                peaks[hour] = cnt;
+            }
+            if filled {
+                cnt += 1;
+            } else {
+                cnt -= 1;
+            }
+            peaks[hour] = peaks[hour].max(cnt);
+        }
+        for peak in peaks.iter_mut().skip(hour + 1) {
+            *peak = cnt;
+        }
+        peaks
+    }
+
+    /// Total parking revenue so far, in cents
+    pub fn parking_revenue(&self) -> usize {
+        self.parking_payments
+            .iter()
+            .map(|(_, _, cents, _)| *cents)
+            .sum()
+    }
+
+    /// For each segment of a route, identified by the stop where it begins, how full were vehicles
+    /// on average and at worst? Measured as a fraction of capacity, so above 1 isn't possible.
+    pub fn crowding_per_segment(&self, route: BusRouteID) -> BTreeMap<BusStopID, (f64, f64)> {
+        let mut per_stop: BTreeMap<BusStopID, Vec<f64>> = BTreeMap::new();
+        for (_, stop, passengers, capacity) in self.transit_loads.get(&route).unwrap_or(&Vec::new())
+        {
+            per_stop
+                .entry(*stop)
+                .or_default()
+                .push((*passengers as f64) / (*capacity as f64));
+        }
+        per_stop
+            .into_iter()
+            .map(|(stop, loads)| {
+                let avg = loads.iter().sum::<f64>() / (loads.len() as f64);
+                let max = loads.into_iter().fold(0.0, f64::max);
+                (stop, (avg, max))
+            })
+            .collect()
+    }
+
+    /// Total emissions per road, up to some time
+    pub fn emissions_per_road(&self, now: Time) -> BTreeMap<RoadID, Emissions> {
+        let mut per_road = BTreeMap::new();
+        for ((r, hour), emissions) in &self.road_emissions {
+            if *hour <= now.get_hours() {
+                *per_road.entry(*r).or_insert(Emissions::ZERO) += *emissions;
+            }
+        }
+        per_road
+    }
+
+    /// The average time cars and trucks took to cross each road, per hour of the day
+    pub fn hourly_travel_times(&self) -> BTreeMap<(DirectedRoadID, usize), Duration> {
+        self.road_travel_times
+            .iter()
+            .map(|(key, (total, count))| (*key, *total / (*count as f64)))
+            .collect()
+    }
+
+    /// Total emissions everywhere, up to some time
+    pub fn total_emissions(&self, now: Time) -> Emissions {
+        let mut total = Emissions::ZERO;
+        let hours = self
+            .road_emissions
+            .iter()
+            .map(|((_, hour), e)| (*hour, *e))
+            .chain(
+                self.intersection_emissions
+                    .iter()
+                    .map(|((_, hour), e)| (*hour, *e)),
+            );
+        for (hour, emissions) in hours {
+            if hour <= now.get_hours() {
+                total += emissions;
+            }
+        }
+        total
+    }
+
     fn parking_spot_availability(
         now: Time,
         changes: &[(Time, bool)],
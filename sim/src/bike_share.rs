@@ -0,0 +1,234 @@
+//! Shared bikes (or kick-scooters) that don't belong to anybody. Somebody walks to the nearest
+//! dock, unlocks a bike, rides to the dock nearest their destination, and walks the rest of the
+//! way. Docks can run empty or fill up; an operator periodically moves bikes between docks to even
+//! things out.
+//!
+//! This just tracks where each bike is; TripManager drives the state changes, since they always
+//! involve somebody's trip.
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use abstutil::{deserialize_btreemap, serialize_btreemap};
+use geom::{Distance, Pt2D};
+use map_model::{BikeShareDockID, Map};
+
+use crate::{CarID, Vehicle};
+
+/// Nobody will walk further than this (in a straight line) to reach a dock.
+const MAX_DIST_TO_DOCK: Distance = Distance::const_meters(1000.0);
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct BikeShareSystem {
+    #[serde(
+        serialize_with = "serialize_btreemap",
+        deserialize_with = "deserialize_btreemap"
+    )]
+    docks: BTreeMap<BikeShareDockID, Dock>,
+    /// Bikes somebody is riding right now, and the dock they're headed for
+    #[serde(
+        serialize_with = "serialize_btreemap",
+        deserialize_with = "deserialize_btreemap"
+    )]
+    in_use: BTreeMap<CarID, (Vehicle, BikeShareDockID)>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct Dock {
+    capacity: usize,
+    bikes: Vec<Vehicle>,
+}
+
+impl BikeShareSystem {
+    pub fn new() -> BikeShareSystem {
+        BikeShareSystem {
+            docks: BTreeMap::new(),
+            in_use: BTreeMap::new(),
+        }
+    }
+
+    pub fn add_dock(&mut self, dock: BikeShareDockID, capacity: usize, bikes: Vec<Vehicle>) {
+        self.docks.insert(dock, Dock { capacity, bikes });
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.docks.is_empty()
+    }
+
+    /// How many bikes are docked here right now? Docks that weren't stocked at the start of the
+    /// simulation have none.
+    pub fn num_bikes(&self, dock: BikeShareDockID) -> usize {
+        self.docks.get(&dock).map(|d| d.bikes.len()).unwrap_or(0)
+    }
+
+    pub fn has_room(&self, dock: BikeShareDockID) -> bool {
+        self.docks
+            .get(&dock)
+            .map(|d| d.bikes.len() < d.capacity)
+            .unwrap_or(false)
+    }
+
+    /// All docks, with the number of bikes currently docked and the capacity
+    pub fn inventory(&self) -> impl Iterator<Item = (BikeShareDockID, usize, usize)> + '_ {
+        self.docks
+            .iter()
+            .map(|(id, dock)| (*id, dock.bikes.len(), dock.capacity))
+    }
+
+    pub fn is_in_use(&self, bike: CarID) -> bool {
+        self.in_use.contains_key(&bike)
+    }
+
+    pub fn get_vehicle(&self, bike: CarID) -> Vehicle {
+        self.in_use[&bike].0.clone()
+    }
+
+    /// Take any bike from a dock, if there is one. The rider promises to return it to the
+    /// destination.
+    pub fn unlock(&mut self, dock: BikeShareDockID, destination: BikeShareDockID) -> Option<CarID> {
+        let bike = self.docks.get_mut(&dock)?.bikes.pop()?;
+        let id = bike.id;
+        self.in_use.insert(id, (bike, destination));
+        Some(id)
+    }
+
+    /// The rider is headed somewhere else, because their destination filled up.
+    pub fn change_destination(&mut self, bike: CarID, destination: BikeShareDockID) {
+        self.in_use.get_mut(&bike).unwrap().1 = destination;
+    }
+
+    /// Lock the bike at a dock, even if it's full. Returns the number of bikes there now.
+    pub fn return_bike(&mut self, bike: CarID, dock: BikeShareDockID) -> usize {
+        let (vehicle, _) = self.in_use.remove(&bike).unwrap();
+        let bikes = &mut self.docks.get_mut(&dock).unwrap().bikes;
+        bikes.push(vehicle);
+        bikes.len()
+    }
+
+    /// The rider's trip was cancelled, so magically return the bike to wherever it was headed.
+    /// Returns that dock and the number of bikes there now, if the bike was in use.
+    pub fn abandon(&mut self, bike: CarID) -> Option<(BikeShareDockID, usize)> {
+        let dock = self.in_use.get(&bike)?.1;
+        Some((dock, self.return_bike(bike, dock)))
+    }
+
+    /// Move bikes from the fullest docks to the emptiest ones, aiming to leave every dock involved
+    /// half full. Returns (from, to, number of bikes) for every move.
+    pub fn rebalance(&mut self) -> Vec<(BikeShareDockID, BikeShareDockID, usize)> {
+        let mut surplus: Vec<(BikeShareDockID, usize)> = Vec::new();
+        let mut deficit: Vec<(BikeShareDockID, usize)> = Vec::new();
+        for (id, dock) in &self.docks {
+            let target = dock.capacity / 2;
+            if dock.bikes.len() * 4 > dock.capacity * 3 {
+                surplus.push((*id, dock.bikes.len() - target));
+            } else if dock.bikes.len() * 4 < dock.capacity {
+                deficit.push((*id, target - dock.bikes.len()));
+            }
+        }
+        // Help the neediest docks first
+        surplus.sort_by_key(|(_, n)| *n);
+        deficit.sort_by_key(|(_, n)| *n);
+
+        let mut moves = Vec::new();
+        while let (Some((from, have)), Some((to, need))) = (surplus.pop(), deficit.pop()) {
+            let n = have.min(need);
+            let from_bikes = &mut self.docks.get_mut(&from).unwrap().bikes;
+            let moved = from_bikes.split_off(from_bikes.len() - n);
+            self.docks.get_mut(&to).unwrap().bikes.extend(moved);
+            moves.push((from, to, n));
+
+            if have > n {
+                surplus.push((from, have - n));
+            }
+            if need > n {
+                deficit.push((to, need - n));
+            }
+        }
+        moves
+    }
+}
+
+/// Find the dock closest to a point, as long as it's not too far away, bikes can reach it, and it
+/// passes the filter.
+pub(crate) fn closest_dock<F: Fn(BikeShareDockID) -> bool>(
+    pt: Pt2D,
+    map: &Map,
+    filter: F,
+) -> Option<BikeShareDockID> {
+    map.all_bike_share_docks()
+        .iter()
+        .filter(|d| {
+            d.pt.dist_to(pt) <= MAX_DIST_TO_DOCK
+                && filter(d.id)
+                && d.biking_connection(map).is_some()
+        })
+        .min_by_key(|d| d.pt.dist_to(pt))
+        .map(|d| d.id)
+}
+
+#[cfg(test)]
+mod tests {
+    use geom::{Duration, Time};
+    use map_model::toy::{
+        add_bike_share_dock, add_building, bike_share_dock, build, building, toy_raw_map,
+    };
+    use map_model::IntersectionType;
+
+    use crate::testing;
+    use crate::{Event, IndividTrip, TripEndpoint, TripMode, TripPurpose};
+
+    #[test]
+    fn empty_dock_redirects_rider() {
+        let mut raw = toy_raw_map(
+            &[
+                ("w", 0.0, 0.0, IntersectionType::Border),
+                ("e", 1200.0, 0.0, IntersectionType::Border),
+            ],
+            &[("w", "e")],
+        );
+        add_building(&mut raw, "home", 100.0, 20.0, 0);
+        add_building(&mut raw, "shop", 1050.0, 20.0, 0);
+        // Docks start half full, so this one has no bikes
+        add_bike_share_dock(&mut raw, "empty", 150.0, 10.0, 1);
+        add_bike_share_dock(&mut raw, "stocked", 300.0, 10.0, 4);
+        add_bike_share_dock(&mut raw, "downtown", 1000.0, 10.0, 10);
+        let map = build(raw);
+
+        let trip = IndividTrip::new(
+            Time::START_OF_DAY + Duration::hours(7),
+            TripPurpose::Shopping,
+            TripEndpoint::Bldg(building(&map, "home")),
+            TripEndpoint::Bldg(building(&map, "shop")),
+            TripMode::BikeShare,
+        );
+        let scenario = testing::scenario(&map, vec![vec![trip]]);
+        let (sim, events) = testing::run(&map, &scenario, Duration::hours(8));
+        assert_eq!(sim.num_trips(), (1, 0));
+
+        let docks: Vec<Event> = events
+            .into_iter()
+            .filter(|ev| {
+                matches!(
+                    ev,
+                    Event::BikeShareDockEmpty { .. }
+                        | Event::BikeShareUnlocked { .. }
+                        | Event::BikeShareReturned { .. }
+                )
+            })
+            .collect();
+        assert_eq!(docks.len(), 3, "{:?}", docks);
+        let dock = |name| bike_share_dock(&map, name);
+        assert!(
+            matches!(docks[0], Event::BikeShareDockEmpty { dock: d, .. } if d == dock("empty"))
+        );
+        assert!(matches!(
+            docks[1],
+            Event::BikeShareUnlocked { dock: d, inventory: 1, .. } if d == dock("stocked")
+        ));
+        assert!(matches!(
+            docks[2],
+            Event::BikeShareReturned { dock: d, inventory: 6, .. } if d == dock("downtown")
+        ));
+    }
+}
@@ -0,0 +1,264 @@
+//! Electric vehicles carry a battery that drains as they drive, faster when climbing hills. When
+//! a driver expects to arrive with a low battery, they park near a charging station close to their
+//! destination instead, plug in, and walk the rest of the way. The car charges for as long as it
+//! stays parked there.
+//!
+//! This just tracks which cars are plugged in where; TripManager decides when to charge, since it
+//! always involves somebody's trip.
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use abstutil::{deserialize_btreemap, serialize_btreemap};
+use geom::{Distance, Duration, Pt2D, Time};
+use map_model::{BuildingID, ChargingStationID, Lane, Map, Path};
+
+use crate::CarID;
+
+/// Typical for a small electric car on city streets
+const KWH_PER_KM: f64 = 0.18;
+/// Lifting a car up a hill costs extra. Regenerative braking on the way down is ignored.
+const KWH_PER_METER_CLIMBED: f64 = 0.005;
+/// A common public charger
+const CHARGER_KW: f64 = 7.2;
+/// Drivers look for a charger when they'd arrive with less than this fraction of a full battery.
+const LOW_CHARGE: f64 = 0.3;
+/// Nobody will walk further than this (in a straight line) from a charger to their destination.
+const MAX_DIST_TO_CHARGER: Distance = Distance::const_meters(800.0);
+/// A car parked this close to a charging station can plug in.
+const CHARGER_REACH: Distance = Distance::const_meters(100.0);
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Battery {
+    /// In kWh
+    pub capacity: f64,
+    /// In kWh, between 0 and capacity
+    pub charge: f64,
+}
+
+impl Battery {
+    pub fn new(capacity: f64, charge: f64) -> Battery {
+        Battery {
+            capacity,
+            charge: charge.min(capacity),
+        }
+    }
+
+    pub fn percent(&self) -> f64 {
+        100.0 * self.charge / self.capacity
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.charge <= 0.0
+    }
+
+    /// Would the battery be low after using this much energy?
+    pub fn low_after(&self, kwh: f64) -> bool {
+        self.charge - kwh < LOW_CHARGE * self.capacity
+    }
+
+    /// The battery never goes below empty; a car that runs out keeps going, so it doesn't get
+    /// stranded in traffic.
+    pub fn drain(&mut self, kwh: f64) {
+        self.charge = (self.charge - kwh).max(0.0);
+    }
+
+    /// Charge for some amount of time, returning the kWh added.
+    pub fn charge_for(&mut self, duration: Duration) -> f64 {
+        let added = (CHARGER_KW * (duration / Duration::hours(1))).min(self.capacity - self.charge);
+        self.charge += added;
+        added
+    }
+}
+
+/// How much energy it takes to drive the full length of a lane, in kWh.
+pub fn energy_to_cross(lane: &Lane, map: &Map) -> f64 {
+    let climb = map.get_i(lane.dst_i).elevation - map.get_i(lane.src_i).elevation;
+    energy_used(lane.length(), climb.max(Distance::ZERO))
+}
+
+/// How much energy it takes to follow a path, in kWh.
+pub fn estimate_energy(path: &Path, map: &Map) -> f64 {
+    energy_used(path.total_length(), path.get_total_elevation_change(map).0)
+}
+
+fn energy_used(dist: Distance, climb: Distance) -> f64 {
+    KWH_PER_KM * dist.inner_meters() / 1000.0 + KWH_PER_METER_CLIMBED * climb.inner_meters()
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct ChargingNetwork {
+    /// Per station, the cars plugged in and when they started charging
+    #[serde(
+        serialize_with = "serialize_btreemap",
+        deserialize_with = "deserialize_btreemap"
+    )]
+    plugged_in: BTreeMap<ChargingStationID, BTreeMap<CarID, Time>>,
+    /// Cars driving somewhere to charge
+    #[serde(
+        serialize_with = "serialize_btreemap",
+        deserialize_with = "deserialize_btreemap"
+    )]
+    heading_to: BTreeMap<CarID, ChargingStationID>,
+}
+
+impl ChargingNetwork {
+    pub fn new() -> ChargingNetwork {
+        ChargingNetwork {
+            plugged_in: BTreeMap::new(),
+            heading_to: BTreeMap::new(),
+        }
+    }
+
+    fn has_room(&self, station: ChargingStationID, map: &Map) -> bool {
+        self.plugged_in
+            .get(&station)
+            .map(|cars| cars.len())
+            .unwrap_or(0)
+            < map.get_charging_station(station).capacity
+    }
+
+    /// Pick a charging station near a destination, preferring ones with a free charger right now,
+    /// and a building to park near.
+    pub fn find_charger(
+        &self,
+        destination: Pt2D,
+        map: &Map,
+    ) -> Option<(ChargingStationID, BuildingID)> {
+        let station = map
+            .all_charging_stations()
+            .iter()
+            .filter(|s| s.pt.dist_to(destination) <= MAX_DIST_TO_CHARGER)
+            .min_by_key(|s| (!self.has_room(s.id, map), s.pt.dist_to(destination)))?;
+        // Park near the building closest to the station
+        let bldg = map
+            .road_to_buildings(station.sidewalk().road)
+            .iter()
+            .min_by_key(|b| map.get_b(**b).sidewalk_pos.pt(map).dist_to(station.pt))
+            .cloned()?;
+        Some((station.id, bldg))
+    }
+
+    /// The driver remembers the station and tries to plug in there after parking.
+    pub fn head_to(&mut self, car: CarID, station: ChargingStationID) {
+        self.heading_to.insert(car, station);
+    }
+
+    /// A car that went looking for a charger just parked. Plug into the station it was heading
+    /// for, or any other station within reach with a free charger. Returns the station, or None if
+    /// the car wasn't looking for a charger. The inner None means every nearby charger was busy.
+    pub fn car_parked(
+        &mut self,
+        now: Time,
+        car: CarID,
+        pt: Pt2D,
+        map: &Map,
+    ) -> Option<Option<ChargingStationID>> {
+        let wanted = self.heading_to.remove(&car)?;
+        let station = if self.has_room(wanted, map)
+            && map.get_charging_station(wanted).pt.dist_to(pt) <= CHARGER_REACH
+        {
+            Some(wanted)
+        } else {
+            map.all_charging_stations()
+                .iter()
+                .filter(|s| s.pt.dist_to(pt) <= CHARGER_REACH && self.has_room(s.id, map))
+                .min_by_key(|s| s.pt.dist_to(pt))
+                .map(|s| s.id)
+        };
+        if let Some(station) = station {
+            self.plugged_in.entry(station).or_default().insert(car, now);
+        }
+        Some(station)
+    }
+
+    /// The car is leaving. If it was plugged in, returns the station and how long it charged.
+    pub fn unplug(&mut self, now: Time, car: CarID) -> Option<(ChargingStationID, Duration)> {
+        self.heading_to.remove(&car);
+        for (station, cars) in &mut self.plugged_in {
+            if let Some(since) = cars.remove(&car) {
+                return Some((*station, now - since));
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use geom::{Duration, Pt2D, Time};
+    use map_model::toy::{
+        add_building, add_charging_station, build, building, charging_station, toy_raw_map,
+    };
+    use map_model::IntersectionType;
+
+    use super::{Battery, ChargingNetwork};
+    use crate::{CarID, VehicleType};
+
+    #[test]
+    fn cars_share_chargers() {
+        // One street with two single chargers 100m apart, and a cafe by the first
+        let mut raw = toy_raw_map(
+            &[
+                ("w", 0.0, 0.0, IntersectionType::Border),
+                ("e", 400.0, 0.0, IntersectionType::Border),
+            ],
+            &[("w", "e")],
+        );
+        add_building(&mut raw, "cafe", 150.0, 30.0, 0);
+        add_charging_station(&mut raw, "first", 150.0, 10.0, 1);
+        add_charging_station(&mut raw, "second", 250.0, 10.0, 1);
+        let map = build(raw);
+        let cafe = building(&map, "cafe");
+        let first = charging_station(&map, "first");
+        let second = charging_station(&map, "second");
+        let car = |id| CarID {
+            id,
+            vehicle_type: VehicleType::Car,
+        };
+        let cafe_pt = Pt2D::new(150.0, 30.0);
+        let start = Time::START_OF_DAY + Duration::hours(9);
+
+        let mut network = ChargingNetwork::new();
+        assert_eq!(network.find_charger(cafe_pt, &map), Some((first, cafe)));
+        network.head_to(car(0), first);
+        assert_eq!(
+            network.car_parked(start, car(0), Pt2D::new(150.0, 5.0), &map),
+            Some(Some(first))
+        );
+
+        // The first charger is taken, so the next driver aims for the second one
+        assert_eq!(network.find_charger(cafe_pt, &map).unwrap().0, second);
+        // Somebody who didn't notice plugs into the second one, since it's within reach
+        network.head_to(car(1), first);
+        assert_eq!(
+            network.car_parked(start, car(1), Pt2D::new(200.0, 5.0), &map),
+            Some(Some(second))
+        );
+        // Now both are taken
+        network.head_to(car(2), first);
+        assert_eq!(
+            network.car_parked(start, car(2), Pt2D::new(150.0, 5.0), &map),
+            Some(None)
+        );
+        // Cars that didn't come to charge are ignored
+        assert_eq!(
+            network.car_parked(start, car(3), Pt2D::new(150.0, 5.0), &map),
+            None
+        );
+
+        // After two hours, the first car leaves with a fuller battery, and frees up its charger
+        let (station, duration) = network.unplug(start + Duration::hours(2), car(0)).unwrap();
+        assert_eq!(station, first);
+        assert_eq!(duration, Duration::hours(2));
+        let mut battery = Battery::new(60.0, 10.0);
+        assert!((battery.charge_for(duration) - 14.4).abs() < 1e-6);
+        assert_eq!(network.find_charger(cafe_pt, &map), Some((first, cafe)));
+        // Batteries don't overfill
+        battery.charge_for(Duration::hours(10));
+        assert_eq!(battery.charge, battery.capacity);
+        // Nobody's left plugged in at the busy charger
+        assert_eq!(network.unplug(start + Duration::hours(3), car(2)), None);
+    }
+}
@@ -0,0 +1,342 @@
+//! Estimates tailpipe emissions from how vehicles actually move. Every time a vehicle finishes a
+//! lane or turn, an `EmissionsModel` looks at how far it went, how long it took, how its speed
+//! changed from the previous step, and how much it climbed. The results are aggregated per road
+//! and intersection in `Analytics`.
+//!
+//! These are rough models in the spirit of COPERT or a simplified MOVES, good for comparing
+//! proposals against each other, not for an official inventory.
+
+use std::ops::{Add, AddAssign, Sub};
+
+use enum_dispatch::enum_dispatch;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use geom::{Distance, Duration, Speed};
+
+use crate::{Vehicle, VehicleType};
+
+/// How a vehicle moved over one lane or turn.
+#[derive(Clone, Copy, Debug)]
+pub struct DrivingSegment {
+    pub dist: Distance,
+    pub duration: Duration,
+    /// The average speed over the previous lane or turn, or zero if the vehicle just started
+    pub prev_speed: Speed,
+    /// Only uphill; going downhill doesn't save anything
+    pub climb: Distance,
+}
+
+impl DrivingSegment {
+    pub fn avg_speed(&self) -> Speed {
+        if self.duration == Duration::ZERO {
+            Speed::ZERO
+        } else {
+            Speed::from_dist_time(self.dist, self.duration)
+        }
+    }
+}
+
+/// In grams
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct Emissions {
+    pub co2: f64,
+    pub nox: f64,
+}
+
+impl Emissions {
+    pub const ZERO: Emissions = Emissions { co2: 0.0, nox: 0.0 };
+}
+
+impl Add for Emissions {
+    type Output = Emissions;
+
+    fn add(self, other: Emissions) -> Emissions {
+        Emissions {
+            co2: self.co2 + other.co2,
+            nox: self.nox + other.nox,
+        }
+    }
+}
+
+impl AddAssign for Emissions {
+    fn add_assign(&mut self, other: Emissions) {
+        *self = *self + other;
+    }
+}
+
+impl Sub for Emissions {
+    type Output = Emissions;
+
+    fn sub(self, other: Emissions) -> Emissions {
+        Emissions {
+            co2: self.co2 - other.co2,
+            nox: self.nox - other.nox,
+        }
+    }
+}
+
+/// Estimates emissions for one vehicle over one segment of its path. There are two
+/// implementations:
+/// - SpeedCurveModel uses emission factors that depend on average speed, plus extra for
+///   accelerating and climbing hills
+/// - FlatRateModel just uses a fixed rate per kilometer, for a quick inventory
+#[enum_dispatch(EmissionsModelKind)]
+pub trait EmissionsModel {
+    fn emissions(&self, vehicle: &Vehicle, segment: DrivingSegment) -> Emissions;
+}
+
+#[enum_dispatch]
+#[derive(Clone, Serialize, Deserialize)]
+pub enum EmissionsModelKind {
+    SpeedCurve(SpeedCurveModel),
+    FlatRate(FlatRateModel),
+}
+
+/// Grams of CO2 released per megajoule of work done by an engine, assuming gasoline at about 25%
+/// efficiency
+const CO2_PER_MJ_OF_WORK: f64 = 270.0;
+/// Below this, a vehicle is basically idling
+const IDLING_SPEED: Speed = Speed::const_meters_per_second(1.5);
+
+/// The parameters for one class of vehicle.
+struct VehicleClass {
+    /// A U-shaped curve of grams CO2 per km, given the average speed `v` in km/h:
+    /// `a / v + b + c * v^2`. Slow stop-and-go traffic and fast highways are both worse than
+    /// steady city speeds.
+    a: f64,
+    b: f64,
+    c: f64,
+    /// Grams CO2 per second
+    idling: f64,
+    /// In kg
+    mass: f64,
+    /// Grams NOx per gram CO2. Diesel engines are much worse.
+    nox_ratio: f64,
+}
+
+/// None means no tailpipe emissions at all.
+fn vehicle_class(vehicle: &Vehicle) -> Option<VehicleClass> {
+    match vehicle.vehicle_type {
+        VehicleType::Car => {
+            if vehicle.battery.is_some() {
+                return None;
+            }
+            Some(VehicleClass {
+                a: 1500.0,
+                b: 110.0,
+                c: 0.006,
+                idling: 0.6,
+                mass: 1500.0,
+                nox_ratio: 0.0004,
+            })
+        }
+        VehicleType::Truck => Some(VehicleClass {
+            a: 4000.0,
+            b: 250.0,
+            c: 0.012,
+            idling: 1.5,
+            mass: 5000.0,
+            nox_ratio: 0.003,
+        }),
+        VehicleType::Bus => Some(VehicleClass {
+            a: 9000.0,
+            b: 800.0,
+            c: 0.03,
+            idling: 2.5,
+            mass: 13000.0,
+            nox_ratio: 0.003,
+        }),
+        // Trains are assumed to be electric
+        VehicleType::Train | VehicleType::Bike => None,
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SpeedCurveModel;
+
+impl EmissionsModel for SpeedCurveModel {
+    fn emissions(&self, vehicle: &Vehicle, segment: DrivingSegment) -> Emissions {
+        let class = match vehicle_class(vehicle) {
+            Some(c) => c,
+            None => {
+                return Emissions::ZERO;
+            }
+        };
+        let speed = segment.avg_speed();
+        let mut co2 = if speed < IDLING_SPEED {
+            class.idling * segment.duration.inner_seconds()
+        } else {
+            let v = speed.inner_meters_per_second() * 3.6;
+            let per_km = class.a / v + class.b + class.c * v * v;
+            // Even moving slowly, the engine burns at least as much as idling.
+            (per_km * segment.dist.inner_meters() / 1000.0)
+                .max(class.idling * segment.duration.inner_seconds())
+        };
+
+        // Speeding up and climbing take extra work. Slowing down and going downhill don't give
+        // anything back.
+        let v1 = segment.prev_speed.inner_meters_per_second();
+        let v2 = speed.inner_meters_per_second();
+        let kinetic = 0.5 * class.mass * (v2 * v2 - v1 * v1).max(0.0);
+        let potential = class.mass * 9.81 * segment.climb.inner_meters();
+        co2 += CO2_PER_MJ_OF_WORK * (kinetic + potential) / 1_000_000.0;
+
+        Emissions {
+            co2,
+            nox: class.nox_ratio * co2,
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct FlatRateModel;
+
+impl EmissionsModel for FlatRateModel {
+    fn emissions(&self, vehicle: &Vehicle, segment: DrivingSegment) -> Emissions {
+        match vehicle_class(vehicle) {
+            Some(class) => {
+                // Use the rate at a typical 40km/h
+                let per_km = class.a / 40.0 + class.b + class.c * 40.0 * 40.0;
+                let co2 = per_km * segment.dist.inner_meters() / 1000.0;
+                Emissions {
+                    co2,
+                    nox: class.nox_ratio * co2,
+                }
+            }
+            None => Emissions::ZERO,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CarID, DriverBehavior};
+
+    fn vehicle(vehicle_type: VehicleType) -> Vehicle {
+        Vehicle {
+            id: CarID {
+                id: 0,
+                vehicle_type,
+            },
+            owner: None,
+            vehicle_type,
+            length: Distance::meters(5.0),
+            max_speed: None,
+            battery: None,
+            behavior: DriverBehavior::default(),
+        }
+    }
+
+    /// Cruising at a steady speed on flat ground
+    fn cruise(dist: Distance, speed: Speed) -> DrivingSegment {
+        DrivingSegment {
+            dist,
+            duration: dist / speed,
+            prev_speed: speed,
+            climb: Distance::ZERO,
+        }
+    }
+
+    fn co2_per_km(model: &dyn EmissionsModel, vehicle: &Vehicle, kmph: f64) -> f64 {
+        model
+            .emissions(
+                vehicle,
+                cruise(Distance::meters(1000.0), Speed::km_per_hour(kmph)),
+            )
+            .co2
+    }
+
+    #[test]
+    fn no_tailpipe() {
+        let segment = cruise(Distance::meters(500.0), Speed::km_per_hour(30.0));
+        let mut ev = vehicle(VehicleType::Car);
+        ev.battery = Some(crate::Battery::new(60.0, 30.0));
+        for v in [ev, vehicle(VehicleType::Bike), vehicle(VehicleType::Train)] {
+            assert_eq!(SpeedCurveModel.emissions(&v, segment), Emissions::ZERO);
+            assert_eq!(FlatRateModel.emissions(&v, segment), Emissions::ZERO);
+        }
+    }
+
+    #[test]
+    fn speed_curve() {
+        let car = vehicle(VehicleType::Car);
+        // Stop-and-go traffic and highways are both worse than city speeds
+        let crawling = co2_per_km(&SpeedCurveModel, &car, 10.0);
+        let city = co2_per_km(&SpeedCurveModel, &car, 50.0);
+        let highway = co2_per_km(&SpeedCurveModel, &car, 130.0);
+        assert!(city < crawling);
+        assert!(city < highway);
+
+        // Bigger vehicles are worse
+        assert!(city < co2_per_km(&SpeedCurveModel, &vehicle(VehicleType::Truck), 50.0));
+        assert!(
+            co2_per_km(&SpeedCurveModel, &vehicle(VehicleType::Truck), 50.0)
+                < co2_per_km(&SpeedCurveModel, &vehicle(VehicleType::Bus), 50.0)
+        );
+    }
+
+    #[test]
+    fn idling() {
+        let car = vehicle(VehicleType::Car);
+        let stopped = DrivingSegment {
+            dist: Distance::ZERO,
+            duration: Duration::minutes(1),
+            prev_speed: Speed::ZERO,
+            climb: Distance::ZERO,
+        };
+        let emissions = SpeedCurveModel.emissions(&car, stopped);
+        assert!((emissions.co2 - 36.0).abs() < 1e-6);
+        assert!((emissions.nox - 36.0 * 0.0004).abs() < 1e-9);
+        // Waiting longer is worse
+        let longer = DrivingSegment {
+            duration: Duration::minutes(2),
+            ..stopped
+        };
+        assert!(SpeedCurveModel.emissions(&car, longer).co2 > emissions.co2);
+    }
+
+    #[test]
+    fn accelerating_and_climbing() {
+        let car = vehicle(VehicleType::Car);
+        let steady = cruise(Distance::meters(200.0), Speed::km_per_hour(50.0));
+        let base = SpeedCurveModel.emissions(&car, steady).co2;
+
+        let from_stop = DrivingSegment {
+            prev_speed: Speed::ZERO,
+            ..steady
+        };
+        assert!(SpeedCurveModel.emissions(&car, from_stop).co2 > base);
+
+        let uphill = DrivingSegment {
+            climb: Distance::meters(10.0),
+            ..steady
+        };
+        // m * g * h of extra work
+        let extra = CO2_PER_MJ_OF_WORK * 1500.0 * 9.81 * 10.0 / 1_000_000.0;
+        assert!((SpeedCurveModel.emissions(&car, uphill).co2 - base - extra).abs() < 1e-6);
+
+        // Braking doesn't give anything back
+        let slowing = DrivingSegment {
+            prev_speed: Speed::km_per_hour(80.0),
+            ..steady
+        };
+        assert_eq!(SpeedCurveModel.emissions(&car, slowing).co2, base);
+    }
+
+    #[test]
+    fn flat_rate() {
+        let car = vehicle(VehicleType::Car);
+        // Only distance matters
+        let slow = co2_per_km(&FlatRateModel, &car, 10.0);
+        let fast = co2_per_km(&FlatRateModel, &car, 100.0);
+        assert_eq!(slow, fast);
+        assert!((slow - co2_per_km(&SpeedCurveModel, &car, 40.0)).abs() < 1e-3);
+        let twice = FlatRateModel.emissions(
+            &car,
+            cruise(Distance::meters(2000.0), Speed::km_per_hour(40.0)),
+        );
+        assert!((twice.co2 - 2.0 * slow).abs() < 1e-6);
+    }
+}
@@ -1,31 +1,62 @@
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-use geom::Duration;
+use geom::{Distance, Duration, Pt2D, Time};
 use map_model::{
-    BuildingID, BusRouteID, BusStopID, IntersectionID, LaneID, Map, Path, PathRequest, Traversable,
-    TurnID,
+    BikeShareDockID, BuildingID, BusRouteID, BusStopID, ChargingStationID, IntersectionID, LaneID,
+    Map, Path, PathRequest, Traversable, TurnID,
 };
 
-use crate::{AgentID, CarID, ParkingSpot, PedestrianID, PersonID, Problem, TripID, TripMode};
+use crate::{
+    AgentID, CarID, Emissions, ParkingSpot, PedestrianID, PersonID, Problem, TripID, TripMode,
+};
 
 /// As a simulation runs, different systems emit Events. This cleanly separates the internal
 /// mechanics of the simulation from consumers that just want to know what's happening.
 ///
 /// An Event always occurs at a particular time, plumbed separately to consumers.
 ///
+/// Code outside this crate can observe events with `Sim::start_buffering_events`.
+///
 /// Many of these were created for a test framework that's been abandoned. They could be removed or
 /// have their API adjusted, but it's not urgent; publishing an event that's not used by Analytics
 /// has no performance impact.
-#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize, JsonSchema)]
 pub enum Event {
     CarReachedParkingSpot(CarID, ParkingSpot),
-    CarLeftParkingSpot(CarID, ParkingSpot),
+    /// The car had been parked there since the given time
+    CarLeftParkingSpot(CarID, ParkingSpot, Time),
 
     BusArrivedAtStop(CarID, BusRouteID, BusStopID),
     BusDepartedFromStop(CarID, BusRouteID, BusStopID),
+    /// A full transit vehicle left some people behind at a stop.
+    TransitBoardingDenied {
+        bus: CarID,
+        route: BusRouteID,
+        stop: BusStopID,
+        left_behind: usize,
+    },
+    /// A transit vehicle left a stop carrying this many passengers.
+    TransitVehicleLoad {
+        bus: CarID,
+        route: BusRouteID,
+        stop: BusStopID,
+        passengers: usize,
+        capacity: usize,
+    },
+    /// A vehicle on a route with a real timetable reached a stop. Negative means early.
+    TransitScheduleDeviation {
+        bus: CarID,
+        route: BusRouteID,
+        stop: BusStopID,
+        late_by: Duration,
+    },
     /// How long waiting at the stop?
     PassengerBoardsTransit(PersonID, CarID, BusRouteID, BusStopID, Duration),
     PassengerAlightsTransit(PersonID, CarID, BusRouteID, BusStopID),
+    /// A carpool passenger or ride-hailing rider was picked up or dropped off at a building
+    PassengerEntersCar(PersonID, CarID, BuildingID),
+    PassengerLeavesCar(PersonID, CarID, BuildingID),
 
     PersonEntersBuilding(PersonID, BuildingID),
     PersonLeavesBuilding(PersonID, BuildingID),
@@ -39,11 +70,99 @@ pub enum Event {
 
     ProblemEncountered(TripID, Problem),
 
-    /// If the agent is a transit vehicle, then include a count of how many passengers are on
-    /// board.
+    /// If the agent is a transit vehicle or a car with passengers, then include a count of how many
+    /// passengers are on board.
     AgentEntersTraversable(AgentID, Option<TripID>, Traversable, Option<usize>),
     /// TripID, TurnID (Where the delay was encountered), Time spent waiting at that turn
     IntersectionDelayMeasured(TripID, TurnID, AgentID, Duration),
+    /// A bus or train approaching a traffic signal extended the current stage (if `extended`) or
+    /// cut it short. The delays are rough estimates made at that moment.
+    TransitSignalPriority {
+        intersection: IntersectionID,
+        vehicle: CarID,
+        extended: bool,
+        change: Duration,
+        /// How much sooner the transit vehicle gets through
+        transit_delay_saved: Duration,
+        /// Total extra waiting for everybody else, summed over agents
+        cross_street_delay_added: Duration,
+    },
+
+    /// A ride-hailing vehicle stopped along a driving lane to pick somebody up
+    RideHailPickUp {
+        vehicle: CarID,
+        rider: TripID,
+        lane: LaneID,
+        /// How long since the rider asked for a ride
+        waited: Duration,
+    },
+    RideHailDropOff {
+        vehicle: CarID,
+        rider: TripID,
+        lane: LaneID,
+    },
+    /// A ride-hailing vehicle finished driving somewhere, either empty or carrying a rider
+    RideHailDistance {
+        vehicle: CarID,
+        distance: Distance,
+        with_rider: bool,
+    },
+    /// Whenever the number of busy ride-hailing vehicles (anything besides parked and waiting for
+    /// a rider) changes
+    RideHailFleetUtilization {
+        busy: usize,
+        total: usize,
+    },
+
+    /// A truck stopped in a driving lane to unload a delivery at a building
+    TruckUnloading {
+        truck: CarID,
+        trip: TripID,
+        building: BuildingID,
+        lane: LaneID,
+    },
+    /// A truck finished unloading and drove off. Only vehicles stuck directly behind the truck on
+    /// the same lane at that moment are counted.
+    TruckLeftLoadingZone {
+        truck: CarID,
+        lane: LaneID,
+        blocked_for: Duration,
+        vehicles_behind: usize,
+    },
+
+    /// Somebody took a shared bike from a dock. `inventory` is how many bikes are left there.
+    BikeShareUnlocked {
+        trip: TripID,
+        dock: BikeShareDockID,
+        bike: CarID,
+        inventory: usize,
+    },
+    /// Somebody returned a shared bike to a dock. `inventory` is how many bikes are there now,
+    /// which may exceed the capacity if there was nowhere else to go.
+    BikeShareReturned {
+        trip: TripID,
+        dock: BikeShareDockID,
+        bike: CarID,
+        inventory: usize,
+    },
+    /// Somebody reached a dock to pick up a bike, but it was empty
+    BikeShareDockEmpty {
+        trip: TripID,
+        dock: BikeShareDockID,
+    },
+    /// Somebody reached a dock to return a bike, but it was full
+    BikeShareDockFull {
+        trip: TripID,
+        dock: BikeShareDockID,
+    },
+    /// The operator moved some bikes from one dock to another. The inventories are afterwards.
+    BikeShareRebalanced {
+        from: BikeShareDockID,
+        to: BikeShareDockID,
+        bikes: usize,
+        from_inventory: usize,
+        to_inventory: usize,
+    },
 
     TripFinished {
         trip: TripID,
@@ -57,11 +176,34 @@ pub enum Event {
     /// Just use for parking replanning. Not happy about copying the full path in here, but the way
     /// to plumb info into Analytics is Event.
     PathAmended(Path),
+    /// Estimated tailpipe emissions from a vehicle finishing part of a lane or turn
+    VehicleEmissions(CarID, Traversable, Emissions),
+    /// A vehicle crossed an entire lane, including any time spent waiting at the end of it
+    VehicleCrossedLane(CarID, LaneID, Duration),
+    /// An electric vehicle ran out of charge. It keeps driving anyway.
+    BatteryDepleted(CarID),
+    /// An electric vehicle finished charging, after being plugged in for some amount of time
+    ChargingSessionEnded {
+        car: CarID,
+        station: ChargingStationID,
+        duration: Duration,
+        kwh: f64,
+    },
+    /// An electric vehicle with a low battery couldn't find a free charger anywhere near this
+    /// point
+    NoChargerAvailable(CarID, Pt2D),
+    /// A driver couldn't find a free spot where they expected and had to drive this much farther
+    /// to reach one.
+    CruisingForParking(CarID, Distance),
+    /// A driver switched to a different route partway through their trip. If they went around
+    /// congestion, this is how much time they expected to save. None means the old route was cut
+    /// off by live map edits.
+    Rerouted(CarID, Option<Duration>),
 
     Alert(AlertLocation, String),
 }
 
-#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize, JsonSchema)]
 pub enum AlertLocation {
     Nil,
     Intersection(IntersectionID),
@@ -69,7 +211,7 @@ pub enum AlertLocation {
     Building(BuildingID),
 }
 
-#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize, JsonSchema)]
 pub enum TripPhaseType {
     Driving,
     Walking,
@@ -78,6 +220,9 @@ pub enum TripPhaseType {
     WaitingForBus(BusRouteID, BusStopID),
     /// What stop did they board at?
     RidingBus(BusRouteID, BusStopID, CarID),
+    WaitingForRide,
+    RidingInCar(CarID),
+    Unloading(BuildingID),
     Cancelled,
     Finished,
     DelayedStart,
@@ -94,6 +239,11 @@ impl TripPhaseType {
                 format!("Waiting for bus {}", map.get_br(r).full_name)
             }
             TripPhaseType::RidingBus(r, _, _) => format!("Riding bus {}", map.get_br(r).full_name),
+            TripPhaseType::WaitingForRide => "Waiting to be picked up".to_string(),
+            TripPhaseType::RidingInCar(c) => format!("Riding in {}", c),
+            TripPhaseType::Unloading(b) => {
+                format!("Unloading a delivery at {}", map.get_b(b).address)
+            }
             TripPhaseType::Cancelled => "Trip was cancelled due to some bug".to_string(),
             TripPhaseType::Finished => "Trip finished".to_string(),
             TripPhaseType::DelayedStart => "Delayed by a previous trip taking too long".to_string(),
@@ -6,8 +6,8 @@
 //! glue together individual goals executed by the agents.
 //!
 //! Helpful terminology:
-//! - sov = single occupancy vehicle, a car with just a driver and no passengers. (Car passengers
-//!   are not currently modelled)
+//! - sov = single occupancy vehicle, a car with just a driver and no passengers. Car passengers
+//!   only exist for carpool trips (see `IndividTrip::ride_with`) and `TripMode::RideHail`.
 
 // Disable some noisy clippy warnings
 #![allow(clippy::type_complexity, clippy::too_many_arguments)]
@@ -19,13 +19,14 @@ extern crate log;
 
 use std::fmt;
 
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 use abstutil::{deserialize_usize, serialize_usize};
-use geom::{Distance, Speed, Time};
+use geom::{Distance, Duration, Speed, Time};
 use map_model::{
-    BuildingID, BusRouteID, BusStopID, IntersectionID, LaneID, Map, ParkingLotID, Path,
-    PathConstraints, Position,
+    BikeShareDockID, BuildingID, BusRouteID, BusStopID, IntersectionID, LaneID, Map, ParkingLotID,
+    Path, PathConstraints, Position,
 };
 
 pub use crate::render::{
@@ -34,19 +35,26 @@ pub use crate::render::{
 };
 
 pub use self::analytics::{Analytics, Problem, SlidingWindow, TripPhase};
-pub(crate) use self::events::Event;
-pub use self::events::{AlertLocation, TripPhaseType};
+pub(crate) use self::bike_share::BikeShareSystem;
+pub use self::charging::Battery;
+pub(crate) use self::charging::{energy_to_cross, estimate_energy, ChargingNetwork};
+pub use self::emissions::{
+    DrivingSegment, Emissions, EmissionsModel, EmissionsModelKind, FlatRateModel, SpeedCurveModel,
+};
+pub use self::events::{AlertLocation, Event, TripPhaseType};
 pub use self::make::{
-    fork_rng, BorderSpawnOverTime, ExternalPerson, ExternalTrip, ExternalTripEndpoint, IndividTrip,
-    MapBorders, PersonSpec, Scenario, ScenarioGenerator, ScenarioModifier, SimFlags, SpawnOverTime,
-    TripEndpoint, TripPurpose,
+    fork_rng, BorderSpawnOverTime, CarpoolDriver, DriverBehaviorRanges, ExternalPerson,
+    ExternalTrip, ExternalTripEndpoint, IndividTrip, MapBorders, PersonSpec, Scenario,
+    ScenarioGenerator, ScenarioModifier, SimFlags, SpawnOverTime, TripEndpoint, TripPurpose,
 };
 pub(crate) use self::make::{StartTripArgs, TripSpec};
 pub(crate) use self::mechanics::{
-    DrivingSimState, IntersectionSimState, ParkingSim, ParkingSimState, WalkingSimState,
+    choose_spot, spot_policy, DrivingSimState, IntersectionSimState, ParkingSim, ParkingSimState,
+    WalkingSimState,
 };
 pub(crate) use self::pandemic::PandemicModel;
 pub(crate) use self::recorder::TrafficRecorder;
+pub(crate) use self::ridehail::{FleetVehicleState, RideHailFleet};
 pub(crate) use self::router::{ActionAtEnd, Router};
 pub(crate) use self::scheduler::{Command, Scheduler};
 pub use self::sim::{AgentProperties, AlertHandler, DelayCause, Sim, SimCallback, SimOptions};
@@ -56,15 +64,21 @@ pub use self::trips::{CommutersVehiclesCounts, Person, PersonState, TripInfo, Tr
 pub(crate) use self::trips::{TripLeg, TripManager};
 
 mod analytics;
+mod bike_share;
+mod charging;
+mod emissions;
 mod events;
 mod make;
 mod mechanics;
 mod pandemic;
 mod recorder;
 mod render;
+mod ridehail;
 mod router;
 mod scheduler;
 mod sim;
+#[cfg(test)]
+mod testing;
 mod transit;
 mod trips;
 
@@ -75,6 +89,14 @@ pub(crate) const MAX_CAR_LENGTH: Distance = Distance::const_meters(6.5);
 // Note this is more than MAX_CAR_LENGTH
 pub(crate) const BUS_LENGTH: Distance = Distance::const_meters(12.5);
 pub(crate) const LIGHT_RAIL_LENGTH: Distance = Distance::const_meters(60.0);
+// Seated and standing passengers. A light rail train is a few coupled cars.
+pub(crate) const BUS_CAPACITY: usize = 80;
+pub(crate) const LIGHT_RAIL_CAPACITY: usize = 400;
+// Delivery vans and small box trucks. Bigger trucks wouldn't fit in street parking.
+pub(crate) const MIN_TRUCK_LENGTH: Distance = Distance::const_meters(6.5);
+pub(crate) const MAX_TRUCK_LENGTH: Distance = Distance::const_meters(8.0);
+// A typical electric car's battery, in kWh
+pub(crate) const BATTERY_KWH: f64 = 60.0;
 
 /// At all speeds (including at rest), cars must be at least this far apart, measured from front of
 /// one car to the back of the other.
@@ -85,7 +107,9 @@ pub(crate) const FOLLOWING_DISTANCE: Distance = Distance::const_meters(1.0);
 pub(crate) const SPAWN_DIST: Distance = Distance::const_meters(0.05);
 
 // TODO Implement Eq, Hash, Ord manually to guarantee this.
-#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, PartialOrd, Ord, Serialize, Deserialize)]
+#[derive(
+    Clone, Copy, Debug, Eq, Hash, PartialEq, PartialOrd, Ord, Serialize, Deserialize, JsonSchema,
+)]
 pub struct CarID {
     /// The numeric ID must be globally unique, without considering VehicleType.
     #[serde(
@@ -104,11 +128,14 @@ impl fmt::Display for CarID {
             VehicleType::Bus => write!(f, "Bus #{}", self.id),
             VehicleType::Train => write!(f, "Train #{}", self.id),
             VehicleType::Bike => write!(f, "Bike #{}", self.id),
+            VehicleType::Truck => write!(f, "Truck #{}", self.id),
         }
     }
 }
 
-#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, PartialOrd, Ord, Serialize, Deserialize)]
+#[derive(
+    Clone, Copy, Debug, Eq, Hash, PartialEq, PartialOrd, Ord, Serialize, Deserialize, JsonSchema,
+)]
 pub struct PedestrianID(
     #[serde(
         serialize_with = "serialize_usize",
@@ -123,12 +150,16 @@ impl fmt::Display for PedestrianID {
     }
 }
 
-#[derive(Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug, Hash)]
+#[derive(
+    Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug, Hash, JsonSchema,
+)]
 pub enum AgentID {
     Car(CarID),
     Pedestrian(PedestrianID),
     // TODO Rename...
     BusPassenger(PersonID, CarID),
+    /// Riding in somebody else's car, either as a carpool passenger or a ride-hailing customer
+    CarPassenger(PersonID, CarID),
 }
 
 impl AgentID {
@@ -146,9 +177,11 @@ impl AgentID {
                 VehicleType::Bike => AgentType::Bike,
                 VehicleType::Bus => AgentType::Bus,
                 VehicleType::Train => AgentType::Train,
+                VehicleType::Truck => AgentType::Truck,
             },
             AgentID::Pedestrian(_) => AgentType::Pedestrian,
             AgentID::BusPassenger(_, _) => AgentType::TransitRider,
+            AgentID::CarPassenger(_, _) => AgentType::CarPassenger,
         }
     }
 
@@ -156,7 +189,7 @@ impl AgentID {
         match self {
             AgentID::Car(c) => Some(c.vehicle_type),
             AgentID::Pedestrian(_) => None,
-            AgentID::BusPassenger(_, _) => None,
+            AgentID::BusPassenger(_, _) | AgentID::CarPassenger(_, _) => None,
         }
     }
 
@@ -176,11 +209,14 @@ impl fmt::Display for AgentID {
             AgentID::Car(id) => write!(f, "AgentID({})", id),
             AgentID::Pedestrian(id) => write!(f, "AgentID({})", id),
             AgentID::BusPassenger(person, bus) => write!(f, "AgentID({} on {})", person, bus),
+            AgentID::CarPassenger(person, car) => write!(f, "AgentID({} in {})", person, car),
         }
     }
 }
 
-#[derive(Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug, Hash)]
+#[derive(
+    Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug, Hash, JsonSchema,
+)]
 pub enum AgentType {
     Car,
     Bike,
@@ -188,6 +224,8 @@ pub enum AgentType {
     Train,
     Pedestrian,
     TransitRider,
+    CarPassenger,
+    Truck,
 }
 
 impl AgentType {
@@ -199,6 +237,8 @@ impl AgentType {
             AgentType::Train,
             AgentType::Pedestrian,
             AgentType::TransitRider,
+            AgentType::CarPassenger,
+            AgentType::Truck,
         ]
     }
 
@@ -210,6 +250,8 @@ impl AgentType {
             AgentType::Train => "Train",
             AgentType::Pedestrian => "Pedestrian",
             AgentType::TransitRider => "Transit rider",
+            AgentType::CarPassenger => "Car passenger",
+            AgentType::Truck => "Truck",
         }
     }
 
@@ -221,6 +263,8 @@ impl AgentType {
             AgentType::Train => "trains",
             AgentType::Pedestrian => "pedestrians",
             AgentType::TransitRider => "transit riders",
+            AgentType::CarPassenger => "car passengers",
+            AgentType::Truck => "trucks",
         }
     }
 
@@ -231,11 +275,15 @@ impl AgentType {
             AgentType::Bus | AgentType::Train => unreachable!(),
             AgentType::Pedestrian => "walking",
             AgentType::TransitRider => "riding transit",
+            AgentType::CarPassenger => "riding in a car",
+            AgentType::Truck => "delivering",
         }
     }
 }
 
-#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, PartialOrd, Ord, Serialize, Deserialize)]
+#[derive(
+    Clone, Copy, Debug, Eq, Hash, PartialEq, PartialOrd, Ord, Serialize, Deserialize, JsonSchema,
+)]
 pub struct TripID(
     #[serde(
         serialize_with = "serialize_usize",
@@ -252,7 +300,9 @@ impl fmt::Display for TripID {
     }
 }
 
-#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, PartialOrd, Ord, Serialize, Deserialize)]
+#[derive(
+    Clone, Copy, Debug, Eq, Hash, PartialEq, PartialOrd, Ord, Serialize, Deserialize, JsonSchema,
+)]
 pub struct PersonID(
     #[serde(
         serialize_with = "serialize_usize",
@@ -281,12 +331,16 @@ pub struct OrigPersonID(
     pub usize,
 );
 
-#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug, Hash, PartialOrd, Ord)]
+#[derive(
+    Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug, Hash, PartialOrd, Ord, JsonSchema,
+)]
 pub enum VehicleType {
     Car,
     Bus,
     Train,
     Bike,
+    /// A delivery van or other heavy goods vehicle
+    Truck,
 }
 
 impl fmt::Display for VehicleType {
@@ -296,6 +350,7 @@ impl fmt::Display for VehicleType {
             VehicleType::Bus => write!(f, "bus"),
             VehicleType::Train => write!(f, "train"),
             VehicleType::Bike => write!(f, "bike"),
+            VehicleType::Truck => write!(f, "truck"),
         }
     }
 }
@@ -307,6 +362,7 @@ impl VehicleType {
             VehicleType::Bus => PathConstraints::Bus,
             VehicleType::Train => PathConstraints::Train,
             VehicleType::Bike => PathConstraints::Bike,
+            VehicleType::Truck => PathConstraints::Truck,
         }
     }
 
@@ -316,6 +372,16 @@ impl VehicleType {
             VehicleType::Bus => true,
             VehicleType::Train => true,
             VehicleType::Bike => false,
+            VehicleType::Truck => false,
+        }
+    }
+
+    /// How many passengers fit in a transit vehicle. None for other vehicles.
+    pub fn transit_capacity(self) -> Option<usize> {
+        match self {
+            VehicleType::Bus => Some(BUS_CAPACITY),
+            VehicleType::Train => Some(LIGHT_RAIL_CAPACITY),
+            VehicleType::Car | VehicleType::Bike | VehicleType::Truck => None,
         }
     }
 }
@@ -327,6 +393,17 @@ pub struct Vehicle {
     pub vehicle_type: VehicleType,
     pub length: Distance,
     pub max_speed: Option<Speed>,
+    /// Only for electric vehicles
+    pub battery: Option<Battery>,
+    pub behavior: DriverBehavior,
+}
+
+impl Vehicle {
+    /// How far behind the vehicle in front this one stops, measured from the back of the leader
+    /// to the front of this vehicle.
+    pub(crate) fn following_dist(&self) -> Distance {
+        FOLLOWING_DISTANCE + self.behavior.extra_headway
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
@@ -334,6 +411,37 @@ pub struct VehicleSpec {
     pub vehicle_type: VehicleType,
     pub length: Distance,
     pub max_speed: Option<Speed>,
+    /// Only for electric vehicles; the initial state of charge
+    #[serde(default)]
+    pub battery: Option<Battery>,
+    #[serde(default)]
+    pub behavior: DriverBehavior,
+}
+
+/// Not everybody drives the same way. The default is a perfectly law-abiding, average driver.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct DriverBehavior {
+    /// Multiplies the speed limit. 1.1 means driving 10% over the limit. Vehicles with their own
+    /// max speed never exceed it.
+    pub speeding_factor: f64,
+    /// Space kept behind the vehicle in front when stopped, beyond the minimum
+    /// `FOLLOWING_DISTANCE`
+    pub extra_headway: Distance,
+    /// After being stuck behind somebody, how long it takes to get going again once they move
+    pub reaction_time: Duration,
+    /// Between 0 and 1. Eager drivers switch lanes to get around fewer queued vehicles.
+    pub lane_change_eagerness: f64,
+}
+
+impl Default for DriverBehavior {
+    fn default() -> DriverBehavior {
+        DriverBehavior {
+            speeding_factor: 1.0,
+            extra_headway: Distance::ZERO,
+            reaction_time: Duration::ZERO,
+            lane_change_eagerness: 0.5,
+        }
+    }
 }
 
 impl VehicleSpec {
@@ -345,11 +453,15 @@ impl VehicleSpec {
             vehicle_type: self.vehicle_type,
             length: self.length,
             max_speed: self.max_speed,
+            battery: self.battery,
+            behavior: self.behavior,
         }
     }
 }
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[derive(
+    Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, JsonSchema,
+)]
 pub enum ParkingSpot {
     /// Lane and idx
     Onstreet(LaneID, usize),
@@ -369,13 +481,26 @@ pub struct ParkedCar {
 pub(crate) enum DrivingGoal {
     ParkNear(BuildingID),
     Border(IntersectionID, LaneID),
+    /// Briefly stop here to pick up or drop off a carpool passenger
+    CarpoolStop {
+        pos: Position,
+        passenger: TripID,
+        pick_up: bool,
+    },
+    /// Stop in the driving lane in front of a building to unload a delivery, blocking the lane
+    LoadingZone {
+        pos: Position,
+        building: BuildingID,
+    },
+    /// Return a shared bike to a dock
+    BikeShareDock(BikeShareDockID),
 }
 
 impl DrivingGoal {
     pub fn goal_pos(&self, constraints: PathConstraints, map: &Map) -> Option<Position> {
         match self {
             DrivingGoal::ParkNear(b) => match constraints {
-                PathConstraints::Car => {
+                PathConstraints::Car | PathConstraints::Truck => {
                     let driving_lane = map.find_driving_lane_near_building(*b);
                     let sidewalk_pos = map.get_b(*b).sidewalk_pos;
                     if driving_lane.road == sidewalk_pos.lane().road {
@@ -390,21 +515,41 @@ impl DrivingGoal {
                 }
             },
             DrivingGoal::Border(_, l) => Some(Position::end(*l, map)),
+            DrivingGoal::CarpoolStop { pos, .. } | DrivingGoal::LoadingZone { pos, .. } => {
+                Some(*pos)
+            }
+            DrivingGoal::BikeShareDock(dock) => {
+                Some(map.get_bike_share_dock(*dock).biking_connection(map)?.0)
+            }
         }
     }
 
-    pub fn make_router(&self, owner: CarID, path: Path, map: &Map) -> Router {
+    /// `planned_stay` is how long a car will stay parked at the end, if known.
+    pub fn make_router(
+        &self,
+        owner: CarID,
+        path: Path,
+        planned_stay: Option<Duration>,
+        map: &Map,
+    ) -> Router {
         match self {
             DrivingGoal::ParkNear(b) => {
                 if owner.vehicle_type == VehicleType::Bike {
                     Router::bike_then_stop(owner, path, SidewalkSpot::bike_rack(*b, map).unwrap())
                 } else {
-                    Router::park_near(owner, path, *b)
+                    Router::park_near(owner, path, *b, planned_stay)
                 }
             }
             DrivingGoal::Border(i, last_lane) => {
                 Router::end_at_border(owner, path, map.get_l(*last_lane).length(), *i)
             }
+            DrivingGoal::CarpoolStop { .. } => Router::passenger_stop(owner, path),
+            DrivingGoal::LoadingZone { .. } => Router::loading_zone(owner, path),
+            DrivingGoal::BikeShareDock(dock) => Router::bike_then_stop(
+                owner,
+                path,
+                SidewalkSpot::bike_share_dock(*dock, map).unwrap(),
+            ),
         }
     }
 }
@@ -467,6 +612,16 @@ impl SidewalkSpot {
         })
     }
 
+    /// Start or stop riding a shared bike at a dock. The connection is the same as for a bike rack
+    /// in front of a building.
+    pub fn bike_share_dock(dock: BikeShareDockID, map: &Map) -> Option<SidewalkSpot> {
+        let (bike_pos, sidewalk_pos) = map.get_bike_share_dock(dock).biking_connection(map)?;
+        Some(SidewalkSpot {
+            connection: SidewalkPOI::BikeRack(bike_pos),
+            sidewalk_pos,
+        })
+    }
+
     pub fn bus_stop(stop: BusStopID, map: &Map) -> SidewalkSpot {
         SidewalkSpot {
             sidewalk_pos: map.get_bs(stop).sidewalk_pos,
@@ -609,9 +764,10 @@ pub(crate) struct CreateCar {
     pub vehicle: Vehicle,
     pub router: Router,
     pub maybe_parked_car: Option<ParkedCar>,
-    /// None for buses
+    /// None for buses and ride-hailing vehicles
     pub trip_and_person: Option<(TripID, PersonID)>,
-    pub maybe_route: Option<BusRouteID>,
+    /// For buses, the route and the scheduled departure time
+    pub maybe_route: Option<(BusRouteID, Time)>,
 }
 
 impl CreateCar {
@@ -645,4 +801,15 @@ impl CreateCar {
             maybe_route: None,
         }
     }
+
+    /// Ride-hailing vehicles aren't driven by anybody doing a trip.
+    pub fn for_ride_hail(parked_car: ParkedCar, router: Router) -> CreateCar {
+        CreateCar {
+            vehicle: parked_car.vehicle.clone(),
+            router,
+            maybe_parked_car: Some(parked_car),
+            trip_and_person: None,
+            maybe_route: None,
+        }
+    }
 }
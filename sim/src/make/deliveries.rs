@@ -0,0 +1,95 @@
+//! Generates delivery tours: a truck leaves a depot at a commercial building, stops to unload in
+//! front of a handful of other commercial buildings, then returns to the depot.
+
+use rand::seq::SliceRandom;
+use rand::Rng;
+use rand_xorshift::XorShiftRng;
+
+use geom::{Duration, Time};
+use map_model::{BuildingID, BuildingType, Map};
+
+use crate::{IndividTrip, PersonSpec, ScenarioGenerator, TripEndpoint, TripMode, TripPurpose};
+
+const MIN_STOPS_PER_TOUR: usize = 3;
+const MAX_STOPS_PER_TOUR: usize = 8;
+
+impl ScenarioGenerator {
+    /// Create one person per tour. Depots and stops are both chosen among buildings with some
+    /// commercial use, weighted by how many people work there. Returns nothing if the map doesn't
+    /// have enough of these buildings.
+    pub fn delivery_tours(map: &Map, num_tours: usize, rng: &mut XorShiftRng) -> Vec<PersonSpec> {
+        let mut commercial: Vec<BuildingID> = Vec::new();
+        let mut num_bldgs = 0;
+        for b in map.all_buildings() {
+            let workers = match b.bldg_type {
+                BuildingType::Commercial(worker_cap) => worker_cap,
+                BuildingType::ResidentialCommercial(_, worker_cap) => worker_cap,
+                BuildingType::Residential { .. } | BuildingType::Empty => continue,
+            };
+            // Every commercial building gets at least a small chance of a delivery
+            for _ in 0..workers.max(1) {
+                commercial.push(b.id);
+            }
+            num_bldgs += 1;
+        }
+        if num_bldgs <= MAX_STOPS_PER_TOUR {
+            warn!(
+                "Only {} commercial buildings; not generating delivery tours",
+                num_bldgs
+            );
+            return Vec::new();
+        }
+
+        let mut people = Vec::new();
+        for _ in 0..num_tours {
+            let depot = *commercial.choose(rng).unwrap();
+            let num_stops = rng.gen_range(MIN_STOPS_PER_TOUR..=MAX_STOPS_PER_TOUR);
+            let mut remaining: Vec<BuildingID> = Vec::new();
+            while remaining.len() < num_stops {
+                let b = *commercial.choose(rng).unwrap();
+                if b != depot && !remaining.contains(&b) {
+                    remaining.push(b);
+                }
+            }
+
+            // Visit the stops greedily, always heading to the closest one next. Straight-line
+            // distance is good enough for this.
+            let mut stops = Vec::new();
+            let mut current = map.get_b(depot).label_center;
+            while !remaining.is_empty() {
+                let (idx, _) = remaining
+                    .iter()
+                    .enumerate()
+                    .min_by_key(|(_, b)| map.get_b(**b).label_center.dist_to(current))
+                    .unwrap();
+                let b = remaining.remove(idx);
+                current = map.get_b(b).label_center;
+                stops.push(b);
+            }
+
+            let depart = rand_time(
+                rng,
+                Time::START_OF_DAY + Duration::hours(6),
+                Time::START_OF_DAY + Duration::hours(14),
+            );
+            let mut trip = IndividTrip::new(
+                depart,
+                TripPurpose::Work,
+                TripEndpoint::Bldg(depot),
+                TripEndpoint::Bldg(depot),
+                TripMode::Drive,
+            );
+            trip.delivery_stops = stops;
+            people.push(PersonSpec {
+                orig_id: None,
+                trips: vec![trip],
+            });
+        }
+        people
+    }
+}
+
+fn rand_time(rng: &mut XorShiftRng, low: Time, high: Time) -> Time {
+    assert!(high > low);
+    Time::START_OF_DAY + Duration::seconds(rng.gen_range(low.inner_seconds()..high.inner_seconds()))
+}
@@ -2,6 +2,7 @@
 //! simulation input data; import it here.
 
 use anyhow::Result;
+use schemars::JsonSchema;
 use serde::Deserialize;
 
 use geom::{Distance, FindClosest, LonLat, Time};
@@ -9,12 +10,12 @@ use map_model::{IntersectionID, Map, PathConstraints};
 
 use crate::{IndividTrip, PersonSpec, TripEndpoint, TripMode, TripPurpose};
 
-#[derive(Deserialize)]
+#[derive(Deserialize, JsonSchema)]
 pub struct ExternalPerson {
     pub trips: Vec<ExternalTrip>,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, JsonSchema)]
 pub struct ExternalTrip {
     pub departure: Time,
     pub origin: ExternalTripEndpoint,
@@ -23,7 +24,7 @@ pub struct ExternalTrip {
     pub purpose: TripPurpose,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, JsonSchema)]
 pub enum ExternalTripEndpoint {
     TripEndpoint(TripEndpoint),
     Position(LonLat),
@@ -187,8 +188,13 @@ impl MapBorders {
         &Vec<(IntersectionID, LonLat)>,
     ) {
         match mode {
-            TripMode::Walk | TripMode::Transit => (&self.incoming_walking, &self.outgoing_walking),
-            TripMode::Drive => (&self.incoming_driving, &self.outgoing_driving),
+            // Bike-share riders walk to and from the docks
+            TripMode::Walk | TripMode::Transit | TripMode::BikeShare => {
+                (&self.incoming_walking, &self.outgoing_walking)
+            }
+            TripMode::Drive | TripMode::RideHail | TripMode::ParkAndRide => {
+                (&self.incoming_driving, &self.outgoing_driving)
+            }
             TripMode::Bike => (&self.incoming_biking, &self.outgoing_biking),
         }
     }
@@ -8,15 +8,19 @@ pub use self::external::{ExternalPerson, ExternalTrip, ExternalTripEndpoint, Map
 pub use self::generator::{BorderSpawnOverTime, ScenarioGenerator, SpawnOverTime};
 pub use self::load::SimFlags;
 pub use self::modifier::ScenarioModifier;
-pub use self::scenario::{IndividTrip, PersonSpec, Scenario, TripPurpose};
+pub use self::scenario::{
+    CarpoolDriver, DriverBehaviorRanges, IndividTrip, PersonSpec, Scenario, TripPurpose,
+};
 pub use self::spawner::TripEndpoint;
 pub(crate) use self::spawner::{StartTripArgs, TripSpec};
 
 mod activity_model;
+mod deliveries;
 mod external;
 mod generator;
 mod load;
 mod modifier;
+mod park_and_ride;
 mod scenario;
 mod spawner;
 
@@ -1,15 +1,19 @@
 use std::collections::BTreeSet;
 
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
+use rand::SeedableRng;
+use rand_xorshift::XorShiftRng;
+
 use abstutil::Timer;
 use geom::{Duration, Time};
 use map_model::Map;
 
-use crate::{Scenario, TripMode};
+use crate::{DriverBehaviorRanges, Scenario, ScenarioGenerator, TripMode};
 
 /// Transforms an existing Scenario before instantiating it.
-#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Serialize, Deserialize)]
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Serialize, Deserialize, JsonSchema)]
 pub enum ScenarioModifier {
     RepeatDays(usize),
     ChangeMode {
@@ -21,6 +25,15 @@ pub enum ScenarioModifier {
     },
     /// Scenario name
     AddExtraTrips(String),
+    /// Replace the number of ride-hailing vehicles
+    RideHailFleet(usize),
+    /// Add this many delivery trucks, each making one tour from a commercial building
+    AddDeliveryTours(usize),
+    /// Replace the percent of people's cars that are electric
+    ElectricCars(usize),
+    /// Give every driver their own behavior, with the fastest going this percent over the speed
+    /// limit
+    VaryDriverBehavior(usize),
 }
 
 impl ScenarioModifier {
@@ -57,6 +70,11 @@ impl ScenarioModifier {
                             continue;
                         }
                         if let Some(to_mode) = *to_mode {
+                            // Delivery tours start and end at the same place, so they only make
+                            // sense with a truck
+                            if trip.is_delivery_tour() {
+                                continue;
+                            }
                             trip.mode = to_mode;
                             trip.modified = true;
                         } else {
@@ -84,6 +102,30 @@ impl ScenarioModifier {
                 }
                 s
             }
+            ScenarioModifier::RideHailFleet(n) => {
+                s.ride_hail_fleet = *n;
+                s
+            }
+            ScenarioModifier::ElectricCars(pct) => {
+                s.pct_electric_cars = *pct;
+                s
+            }
+            ScenarioModifier::VaryDriverBehavior(pct) => {
+                s.driver_behavior = Some(DriverBehaviorRanges::typical(*pct));
+                s
+            }
+            ScenarioModifier::AddDeliveryTours(n) => {
+                // Seeded by the number of tours, so the same modifier always produces the same
+                // tours
+                let mut rng = XorShiftRng::seed_from_u64(*n as u64);
+                for mut p in ScenarioGenerator::delivery_tours(map, *n, &mut rng) {
+                    for trip in &mut p.trips {
+                        trip.modified = true;
+                    }
+                    s.people.push(p);
+                }
+                s
+            }
         }
     }
 
@@ -105,6 +147,12 @@ impl ScenarioModifier {
                 to_mode.map(|m| m.verb())
             ),
             ScenarioModifier::AddExtraTrips(name) => format!("Add extra trips from {}", name),
+            ScenarioModifier::RideHailFleet(n) => format!("use {} ride-hailing vehicles", n),
+            ScenarioModifier::AddDeliveryTours(n) => format!("add {} delivery truck tours", n),
+            ScenarioModifier::ElectricCars(pct) => format!("make {}% of cars electric", pct),
+            ScenarioModifier::VaryDriverBehavior(pct) => {
+                format!("vary driver behavior, up to {}% over the speed limit", pct)
+            }
         }
     }
 }
@@ -0,0 +1,237 @@
+//! Plans the first half of park-and-ride trips: where to leave the car, so that riding transit the
+//! rest of the way beats just driving.
+
+use geom::{Distance, Duration};
+use map_model::{BuildingID, BusRouteID, BusStop, BusStopID, Map, PathConstraints, PathRequest};
+
+use crate::{DrivingGoal, SidewalkSpot};
+
+/// Only consider this many stops, the ones involving the smallest detour in a straight line.
+const MAX_CANDIDATE_STOPS: usize = 5;
+/// Stops this close to the start are within walking distance, so there's no point driving to them.
+const MIN_DRIVING_DIST: Distance = Distance::const_meters(800.0);
+
+#[derive(Debug)]
+pub(crate) struct ParkAndRidePlan {
+    /// Park near this building, then walk to stop1
+    pub park_near: BuildingID,
+    pub route: BusRouteID,
+    pub stop1: BusStopID,
+    pub maybe_stop2: Option<BusStopID>,
+}
+
+/// Decide where to park, by comparing the estimated time to drive to a few stops and ride transit
+/// from there against the time to drive the whole way. Returns None if driving directly is faster,
+/// or if no stop works. These estimates ignore traffic, waiting for transit, and searching for
+/// parking.
+pub(crate) fn plan_park_and_ride(
+    start_bldg: BuildingID,
+    goal: &SidewalkSpot,
+    direct_goal: &DrivingGoal,
+    map: &Map,
+) -> Option<ParkAndRidePlan> {
+    let start = DrivingGoal::ParkNear(start_bldg).goal_pos(PathConstraints::Car, map)?;
+    let drive_to = |goal: &DrivingGoal| -> Option<Duration> {
+        let end = goal.goal_pos(PathConstraints::Car, map)?;
+        let req = PathRequest::leave_from_driveway(start, end, PathConstraints::Car, map);
+        Some(map.pathfind(req).ok()?.estimate_duration(map, None))
+    };
+    // If it's impossible to drive directly, any plan that works is better.
+    let direct = drive_to(direct_goal).unwrap_or(Duration::hours(24));
+
+    let start_pt = map.get_b(start_bldg).sidewalk_pos.pt(map);
+    let goal_pt = goal.sidewalk_pos.pt(map);
+    let mut candidates: Vec<(Distance, &BusStop)> = map
+        .all_bus_stops()
+        .values()
+        .filter_map(|stop| {
+            let pt = stop.sidewalk_pos.pt(map);
+            if pt.dist_to(start_pt) < MIN_DRIVING_DIST
+                || map.get_routes_serving_stop(stop.id).is_empty()
+            {
+                return None;
+            }
+            Some((pt.dist_to(start_pt) + pt.dist_to(goal_pt), stop))
+        })
+        .collect();
+    candidates.sort_by_key(|(detour, _)| *detour);
+    candidates.truncate(MAX_CANDIDATE_STOPS);
+
+    let mut best: Option<(Duration, ParkAndRidePlan)> = None;
+    for (_, stop) in candidates {
+        let park_near = match transfer_bldg(stop, map) {
+            Some(b) => b,
+            None => continue,
+        };
+        let transfer_pos = map.get_b(park_near).sidewalk_pos;
+        // The person must actually ride transit from the transfer point
+        let (stop1, maybe_stop2, route) =
+            match map.should_use_transit(transfer_pos, goal.sidewalk_pos) {
+                Some(x) => x,
+                None => continue,
+            };
+        let cost = match (
+            drive_to(&DrivingGoal::ParkNear(park_near)),
+            map.estimate_transit_duration(transfer_pos, goal.sidewalk_pos),
+        ) {
+            (Some(drive), Some(ride)) => drive + ride,
+            _ => continue,
+        };
+        if cost < direct && best.as_ref().map(|(c, _)| cost < *c).unwrap_or(true) {
+            best = Some((
+                cost,
+                ParkAndRidePlan {
+                    park_near,
+                    route,
+                    stop1,
+                    maybe_stop2,
+                },
+            ));
+        }
+    }
+    best.map(|(_, plan)| plan)
+}
+
+/// People parking for transit look for a spot near the building closest to the stop.
+fn transfer_bldg(stop: &BusStop, map: &Map) -> Option<BuildingID> {
+    let pt = stop.sidewalk_pos.pt(map);
+    map.road_to_buildings(stop.sidewalk_pos.lane().road)
+        .iter()
+        .min_by_key(|b| map.get_b(**b).sidewalk_pos.pt(map).dist_to(pt))
+        .cloned()
+}
+
+#[cfg(test)]
+mod tests {
+    use abstutil::Timer;
+    use geom::Time;
+    use map_model::toy::{add_building, add_bus_route, build, building, node, road, toy_raw_map};
+    use map_model::{osm, IntersectionType, LaneType};
+
+    use crate::testing;
+    use crate::{Event, IndividTrip, ParkingSpot, TripEndpoint, TripMode, TripPurpose};
+
+    #[test]
+    fn drive_to_transit_and_back() {
+        // A long street running east, with a block at each end so drivers can turn around. Past
+        // b, only buses can go straight; cars have to crawl around a slow detour to the north, so
+        // it's faster to park and ride.
+        let mut raw = toy_raw_map(
+            &[
+                ("w", 0.0, 100.0, IntersectionType::Border),
+                ("a", 100.0, 100.0, IntersectionType::StopSign),
+                ("b", 1600.0, 100.0, IntersectionType::StopSign),
+                ("c", 4600.0, 100.0, IntersectionType::StopSign),
+                ("e", 5000.0, 100.0, IntersectionType::StopSign),
+                ("sa", 100.0, 300.0, IntersectionType::StopSign),
+                ("sb", 1600.0, 300.0, IntersectionType::StopSign),
+                ("ssa", 100.0, 400.0, IntersectionType::Border),
+                ("ssb", 1600.0, 400.0, IntersectionType::Border),
+                ("nb", 1600.0, -500.0, IntersectionType::StopSign),
+                ("nc", 4600.0, -500.0, IntersectionType::StopSign),
+                ("nnb", 1600.0, -600.0, IntersectionType::Border),
+                ("nnc", 4600.0, -600.0, IntersectionType::Border),
+                ("sc", 4600.0, 300.0, IntersectionType::StopSign),
+                ("se", 5000.0, 300.0, IntersectionType::StopSign),
+                ("ee", 5100.0, 100.0, IntersectionType::Border),
+                ("ssc", 4600.0, 400.0, IntersectionType::Border),
+                ("sse", 5000.0, 400.0, IntersectionType::Border),
+            ],
+            &[
+                ("w", "a"),
+                ("a", "b"),
+                ("b", "c"),
+                ("c", "e"),
+                ("a", "sa"),
+                ("sa", "sb"),
+                ("b", "sb"),
+                ("sa", "ssa"),
+                ("sb", "ssb"),
+                ("b", "nb"),
+                ("nb", "nc"),
+                ("nc", "c"),
+                ("nb", "nnb"),
+                ("nc", "nnc"),
+                ("c", "sc"),
+                ("sc", "se"),
+                ("e", "se"),
+                ("e", "ee"),
+                ("sc", "ssc"),
+                ("se", "sse"),
+            ],
+        );
+        for (id, raw_road) in &mut raw.roads {
+            let endpts = (id.i1, id.i2);
+            if endpts == (node("a"), node("b")) {
+                raw_road.osm_tags.insert(osm::PARKING_BOTH, "parallel");
+            }
+            if [("b", "nb"), ("nb", "nc"), ("nc", "c")]
+                .iter()
+                .any(|(i1, i2)| endpts == (node(i1), node(i2)))
+            {
+                raw_road.osm_tags.insert(osm::MAXSPEED, "5 mph");
+            }
+        }
+        add_building(&mut raw, "home", 120.0, 200.0, 1);
+        // Next to the first stop
+        add_building(&mut raw, "library", 1400.0, 120.0, 0);
+        add_building(&mut raw, "office", 4800.0, 120.0, 0);
+        let mut map = build(raw);
+
+        let transit_mall = road(&map, "b", "c").id;
+        let mut edits = map.get_edits().clone();
+        edits.commands.push(map.edit_road_cmd(transit_mall, |new| {
+            for spec in &mut new.lanes_ltr {
+                if spec.lt == LaneType::Driving {
+                    spec.lt = LaneType::Bus;
+                }
+            }
+        }));
+        map.must_apply_edits(edits, &mut Timer::throwaway());
+        map.recalculate_pathfinding_after_edits(&mut Timer::throwaway());
+        add_bus_route(
+            &mut map,
+            "A",
+            &[("a", "b", 1300.0), ("c", "e", 150.0)],
+            geom::Duration::minutes(10),
+        );
+
+        let home = TripEndpoint::Bldg(building(&map, "home"));
+        let office = TripEndpoint::Bldg(building(&map, "office"));
+        let trip = |hour, from, to| {
+            IndividTrip::new(
+                Time::START_OF_DAY + geom::Duration::hours(hour),
+                TripPurpose::Work,
+                from,
+                to,
+                TripMode::ParkAndRide,
+            )
+        };
+        let scenario = testing::scenario(
+            &map,
+            vec![vec![trip(7, home, office), trip(17, office, home)]],
+        );
+        let (sim, events) = testing::run(&map, &scenario, geom::Duration::hours(20));
+        assert_eq!(sim.num_trips(), (2, 0));
+
+        // In the morning, they ride the bus after parking. No buses run back, so in the evening,
+        // they walk to their car and drive home.
+        let boarded = events
+            .iter()
+            .filter(|ev| matches!(ev, Event::PassengerBoardsTransit(..)))
+            .count();
+        assert_eq!(boarded, 1);
+        // The car ends the day back at home
+        let home = building(&map, "home");
+        assert!(matches!(
+            events
+                .iter()
+                .rev()
+                .find(|ev| matches!(ev, Event::CarReachedParkingSpot(..))),
+            Some(Event::CarReachedParkingSpot(_, ParkingSpot::Offstreet(b, _))) if *b == home
+        ));
+        assert!(!events
+            .iter()
+            .any(|ev| matches!(ev, Event::TripCancelled(..))));
+    }
+}
@@ -5,17 +5,19 @@ use anyhow::Result;
 use rand::seq::SliceRandom;
 use rand::{Rng, SeedableRng};
 use rand_xorshift::XorShiftRng;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 use abstio::MapName;
 use abstutil::{prettyprint_usize, Counter, Timer};
-use geom::{Distance, Speed, Time};
+use geom::{Distance, Duration, Speed, Time};
 use map_model::{BuildingID, Map, OffstreetParking, RoadID};
 
 use crate::make::fork_rng;
 use crate::{
-    OrigPersonID, ParkingSpot, Sim, StartTripArgs, TripEndpoint, TripInfo, TripMode, Vehicle,
-    VehicleSpec, VehicleType, BIKE_LENGTH, MAX_CAR_LENGTH, MIN_CAR_LENGTH,
+    Battery, DriverBehavior, OrigPersonID, ParkingSpot, Sim, StartTripArgs, TripEndpoint, TripID,
+    TripInfo, TripMode, Vehicle, VehicleSpec, VehicleType, BATTERY_KWH, BIKE_LENGTH,
+    MAX_CAR_LENGTH, MAX_TRUCK_LENGTH, MIN_CAR_LENGTH, MIN_TRUCK_LENGTH,
 };
 
 /// A Scenario describes all the input to a simulation. Usually a scenario covers one day.
@@ -27,6 +29,67 @@ pub struct Scenario {
     pub people: Vec<PersonSpec>,
     /// None means seed all buses. Otherwise the route name must be present here.
     pub only_seed_buses: Option<BTreeSet<String>>,
+    /// How many ride-hailing vehicles serve `TripMode::RideHail` trips. They start parked
+    /// throughout the map.
+    #[serde(default)]
+    pub ride_hail_fleet: usize,
+    /// What percent of people's cars are electric
+    #[serde(default)]
+    pub pct_electric_cars: usize,
+    /// If set, every car and truck driver gets their own behavior, sampled from these ranges.
+    /// Otherwise everybody drives exactly the same way.
+    #[serde(default)]
+    pub driver_behavior: Option<DriverBehaviorRanges>,
+}
+
+/// Each driver's behavior is sampled uniformly from these ranges. See `DriverBehavior` for the
+/// meaning of each.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct DriverBehaviorRanges {
+    pub speeding_factor: (f64, f64),
+    pub extra_headway: (Distance, Distance),
+    pub reaction_time: (Duration, Duration),
+    pub lane_change_eagerness: (f64, f64),
+}
+
+impl DriverBehaviorRanges {
+    /// A spread of ordinary drivers, where the fastest go some percent over the speed limit.
+    pub fn typical(max_pct_speeding: usize) -> DriverBehaviorRanges {
+        DriverBehaviorRanges {
+            speeding_factor: (0.95, 1.0 + (max_pct_speeding as f64) / 100.0),
+            extra_headway: (Distance::ZERO, Distance::meters(3.0)),
+            reaction_time: (Duration::seconds(0.5), Duration::seconds(1.5)),
+            lane_change_eagerness: (0.2, 0.8),
+        }
+    }
+
+    fn sample(&self, rng: &mut XorShiftRng) -> DriverBehavior {
+        DriverBehavior {
+            speeding_factor: rand_between(rng, self.speeding_factor),
+            extra_headway: Distance::meters(rand_between(
+                rng,
+                (
+                    self.extra_headway.0.inner_meters(),
+                    self.extra_headway.1.inner_meters(),
+                ),
+            )),
+            reaction_time: Duration::seconds(rand_between(
+                rng,
+                (
+                    self.reaction_time.0.inner_seconds(),
+                    self.reaction_time.1.inner_seconds(),
+                ),
+            )),
+            lane_change_eagerness: rand_between(rng, self.lane_change_eagerness),
+        }
+    }
+}
+
+fn rand_between(rng: &mut XorShiftRng, (low, high): (f64, f64)) -> f64 {
+    if low >= high {
+        return low;
+    }
+    rng.gen_range(low..high)
 }
 
 #[derive(Clone, Serialize, Deserialize, Debug)]
@@ -49,6 +112,25 @@ pub struct IndividTrip {
     pub cancelled: bool,
     /// Did a ScenarioModifier affect this?
     pub modified: bool,
+    /// If this is a driving trip between two buildings, the person can ride along as a passenger
+    /// in somebody else's car instead. The driver picks them up at the origin and drops them off
+    /// at the destination.
+    #[serde(default)]
+    pub ride_with: Option<CarpoolDriver>,
+    /// If this is a driving trip and this isn't empty, then it's a delivery tour. The person drives
+    /// a truck instead of a car, stopping in front of each building in order to unload, before
+    /// heading to the destination.
+    #[serde(default)]
+    pub delivery_stops: Vec<BuildingID>,
+}
+
+/// Refers to another person's trip in the same Scenario.
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct CarpoolDriver {
+    /// An index into `Scenario::people`
+    pub person: usize,
+    /// An index into that person's trips
+    pub trip: usize,
 }
 
 impl IndividTrip {
@@ -67,12 +149,24 @@ impl IndividTrip {
             purpose,
             cancelled: false,
             modified: false,
+            ride_with: None,
+            delivery_stops: Vec::new(),
         }
     }
+
+    /// Is this person riding along in somebody else's car?
+    pub fn is_carpool_passenger(&self) -> bool {
+        self.ride_with.is_some() && self.mode == TripMode::Drive
+    }
+
+    /// Is this person driving a truck to make deliveries?
+    pub fn is_delivery_tour(&self) -> bool {
+        !self.delivery_stops.is_empty() && self.mode == TripMode::Drive && self.ride_with.is_none()
+    }
 }
 
 /// Lifted from Seattle's Soundcast model, but seems general enough to use anyhere.
-#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, JsonSchema)]
 pub enum TripPurpose {
     Home,
     Work,
@@ -143,9 +237,31 @@ impl Scenario {
             }
         }
 
+        // TripIDs are assigned in order, so figure out each person's first trip upfront. Carpool
+        // passengers need to refer to a driver's trip, which may belong to a later person.
+        let mut first_trip_per_person = Vec::new();
+        {
+            let (finished, unfinished) = sim.num_trips();
+            let mut next_id = finished + unfinished;
+            for p in &self.people {
+                first_trip_per_person.push(next_id);
+                next_id += p.trips.len();
+            }
+        }
+
         timer.start_iter("trips for People", self.people.len());
         let mut parked_cars: Vec<(Vehicle, BuildingID)> = Vec::new();
         let mut schedule_trips = Vec::new();
+        // Fork, so electric cars and driver behavior don't affect anything else.
+        let mut ev_rng = if self.pct_electric_cars > 0 {
+            Some(fork_rng(rng))
+        } else {
+            None
+        };
+        let mut behavior_rng = self
+            .driver_behavior
+            .as_ref()
+            .map(|ranges| (ranges, fork_rng(rng)));
         for p in &self.people {
             timer.next();
 
@@ -153,13 +269,70 @@ impl Scenario {
                 panic!("{}", err);
             }
 
-            let (vehicle_specs, cars_initially_parked_at, vehicle_foreach_trip) =
+            let (mut vehicle_specs, cars_initially_parked_at, vehicle_foreach_trip) =
                 p.get_vehicles(rng);
+            if let Some(ref mut ev_rng) = ev_rng {
+                for spec in &mut vehicle_specs {
+                    if spec.vehicle_type == VehicleType::Car
+                        && ev_rng.gen_range(0..100) < self.pct_electric_cars
+                    {
+                        spec.battery = Some(Scenario::rand_battery(ev_rng));
+                    }
+                }
+            }
+            if let Some((ranges, ref mut behavior_rng)) = behavior_rng {
+                for spec in &mut vehicle_specs {
+                    if matches!(spec.vehicle_type, VehicleType::Car | VehicleType::Truck) {
+                        spec.behavior = ranges.sample(behavior_rng);
+                    }
+                }
+            }
             let person = sim.new_person(p.orig_id, Scenario::rand_ped_speed(rng), vehicle_specs);
             for (idx, b) in cars_initially_parked_at {
                 parked_cars.push((person.vehicles[idx].clone(), b));
             }
             for (trip, maybe_idx) in p.trips.iter().zip(vehicle_foreach_trip) {
+                let mut cancellation_reason = if trip.cancelled {
+                    Some("cancelled by ScenarioModifier".to_string())
+                } else {
+                    None
+                };
+                let mut ride_with = None;
+                if trip.is_carpool_passenger() {
+                    let driver = trip.ride_with.unwrap();
+                    match self
+                        .people
+                        .get(driver.person)
+                        .and_then(|p| p.trips.get(driver.trip))
+                    {
+                        None => {
+                            warn!(
+                                "Person ({:?}) wants to ride with {:?}, which doesn't exist",
+                                p.orig_id, driver
+                            );
+                            cancellation_reason =
+                                Some("the driver of the carpool doesn't exist".to_string());
+                        }
+                        Some(driver_trip) => {
+                            if driver_trip.is_carpool_passenger() {
+                                cancellation_reason = Some(
+                                    "the driver of the carpool is a passenger too".to_string(),
+                                );
+                            } else if driver_trip.mode != TripMode::Drive {
+                                cancellation_reason = Some(format!(
+                                    "the driver of the carpool is {} instead",
+                                    driver_trip.mode.ongoing_verb()
+                                ));
+                            } else if driver_trip.cancelled && cancellation_reason.is_none() {
+                                cancellation_reason =
+                                    Some("the driver's trip was cancelled".to_string());
+                            }
+                            ride_with =
+                                Some(TripID(first_trip_per_person[driver.person] + driver.trip));
+                        }
+                    }
+                }
+
                 schedule_trips.push((
                     person.id,
                     TripInfo {
@@ -169,10 +342,12 @@ impl Scenario {
                         end: trip.destination,
                         purpose: trip.purpose,
                         modified: trip.modified,
-                        cancellation_reason: if trip.cancelled {
-                            Some("cancelled by ScenarioModifier".to_string())
+                        cancellation_reason,
+                        ride_with,
+                        delivery_stops: if trip.is_delivery_tour() {
+                            trip.delivery_stops.clone()
                         } else {
-                            None
+                            Vec::new()
                         },
                     },
                     StartTripArgs {
@@ -183,6 +358,39 @@ impl Scenario {
             }
         }
 
+        if self.ride_hail_fleet > 0 {
+            // Buildings don't change with map edits, but fork anyway, so the fleet doesn't affect
+            // anything else.
+            let mut fleet_rng = fork_rng(rng);
+            for _ in 0..self.ride_hail_fleet {
+                if let Some(b) = map.all_buildings().choose(&mut fleet_rng) {
+                    let vehicle = sim.new_ride_hail_vehicle(Scenario::rand_car(&mut fleet_rng));
+                    parked_cars.push((vehicle, b.id));
+                }
+            }
+        }
+
+        if self
+            .people
+            .iter()
+            .any(|p| p.trips.iter().any(|t| t.mode == TripMode::BikeShare))
+        {
+            // Docks start half full. Fork, so the bikes don't affect anything else.
+            let mut dock_rng = fork_rng(rng);
+            for dock in map.all_bike_share_docks() {
+                let bikes = (0..dock.capacity / 2)
+                    .map(|_| {
+                        if dock.scooters {
+                            Scenario::rand_scooter(&mut dock_rng)
+                        } else {
+                            Scenario::rand_bike(&mut dock_rng)
+                        }
+                    })
+                    .collect();
+                sim.seed_bike_share_dock(dock.id, dock.capacity, bikes);
+            }
+        }
+
         // parked_cars is stable over map edits, so don't fork.
         parked_cars.shuffle(rng);
         seed_parked_cars(parked_cars, sim, map, rng, timer);
@@ -204,6 +412,9 @@ impl Scenario {
             map_name: map.get_name().clone(),
             people: Vec::new(),
             only_seed_buses: Some(BTreeSet::new()),
+            ride_hail_fleet: 0,
+            pct_electric_cars: 0,
+            driver_behavior: None,
         }
     }
 
@@ -213,6 +424,29 @@ impl Scenario {
             vehicle_type: VehicleType::Car,
             length,
             max_speed: None,
+            battery: None,
+            behavior: DriverBehavior::default(),
+        }
+    }
+
+    /// Some cars start the day fully charged, others not so much
+    fn rand_battery(rng: &mut XorShiftRng) -> Battery {
+        Battery::new(BATTERY_KWH, rng.gen_range(0.2..1.0) * BATTERY_KWH)
+    }
+
+    fn rand_truck(rng: &mut XorShiftRng) -> VehicleSpec {
+        let length = Scenario::rand_dist(rng, MIN_TRUCK_LENGTH, MAX_TRUCK_LENGTH);
+        let max_speed = Some(Scenario::rand_speed(
+            rng,
+            Speed::miles_per_hour(45.0),
+            map_model::MAX_TRUCK_SPEED,
+        ));
+        VehicleSpec {
+            vehicle_type: VehicleType::Truck,
+            length,
+            max_speed,
+            battery: None,
+            behavior: DriverBehavior::default(),
         }
     }
 
@@ -226,6 +460,8 @@ impl Scenario {
             vehicle_type: VehicleType::Bike,
             length: BIKE_LENGTH,
             max_speed,
+            battery: None,
+            behavior: DriverBehavior::default(),
         }
     }
 
@@ -241,6 +477,22 @@ impl Scenario {
         )
     }
 
+    /// Kick-scooters are modeled as slower, shorter bikes.
+    fn rand_scooter(rng: &mut XorShiftRng) -> VehicleSpec {
+        let max_speed = Some(Scenario::rand_speed(
+            rng,
+            Speed::miles_per_hour(5.0),
+            Speed::miles_per_hour(9.0),
+        ));
+        VehicleSpec {
+            vehicle_type: VehicleType::Bike,
+            length: BIKE_LENGTH * 0.75,
+            max_speed,
+            battery: None,
+            behavior: DriverBehavior::default(),
+        }
+    }
+
     pub fn rand_ped_speed(rng: &mut XorShiftRng) -> Speed {
         Scenario::rand_speed(
             rng,
@@ -262,6 +514,15 @@ impl Scenario {
         per_bldg
     }
 
+    /// Check that everybody's trips make sense, before `instantiate` trips over something like a
+    /// carpool passenger who doesn't start or end at a building.
+    pub fn validate(&self) -> Result<()> {
+        for p in &self.people {
+            p.check_schedule()?;
+        }
+        Ok(())
+    }
+
     pub fn remove_weird_schedules(mut self) -> Scenario {
         let orig = self.people.len();
         self.people.retain(|person| match person.check_schedule() {
@@ -439,7 +700,22 @@ impl PersonSpec {
         }
 
         for trip in &self.trips {
-            if trip.origin == trip.destination {
+            if trip.is_carpool_passenger()
+                && !(matches!(trip.origin, TripEndpoint::Bldg(_))
+                    && matches!(trip.destination, TripEndpoint::Bldg(_)))
+            {
+                bail!(
+                    "Person ({:?}) is a carpool passenger, but not between two buildings: {:?} to \
+                     {:?}",
+                    self.orig_id,
+                    trip.origin,
+                    trip.destination
+                );
+            }
+        }
+
+        for trip in &self.trips {
+            if trip.origin == trip.destination && !trip.is_delivery_tour() {
                 bail!(
                     "Person ({:?}) has a trip from/to the same place: {:?}",
                     self.orig_id,
@@ -466,11 +742,20 @@ impl PersonSpec {
         let mut bike_idx = None;
         // For each indexed car, is it parked somewhere, or off-map?
         let mut car_locations: Vec<(usize, Option<BuildingID>)> = Vec::new();
+        // Same for trucks; delivery tours don't use a car
+        let mut truck_locations: Vec<(usize, Option<BuildingID>)> = Vec::new();
 
         // TODO If the trip is cancelled, this should be affected...
         for trip in &self.trips {
+            if trip.is_carpool_passenger() {
+                // Somebody else is driving
+                vehicle_foreach_trip.push(None);
+                continue;
+            }
             let use_for_trip = match trip.mode {
-                TripMode::Walk | TripMode::Transit => None,
+                TripMode::Walk | TripMode::Transit | TripMode::RideHail | TripMode::BikeShare => {
+                    None
+                }
                 TripMode::Bike => {
                     if bike_idx.is_none() {
                         bike_idx = Some(vehicle_specs.len());
@@ -478,14 +763,22 @@ impl PersonSpec {
                     }
                     bike_idx
                 }
-                TripMode::Drive => {
+                // The car winds up near a transit stop, but the next trip heading back to it should
+                // still use the same car
+                TripMode::Drive | TripMode::ParkAndRide => {
                     let need_parked_at = match trip.origin {
                         TripEndpoint::Bldg(b) => Some(b),
                         _ => None,
                     };
 
+                    let locations = if trip.is_delivery_tour() {
+                        &mut truck_locations
+                    } else {
+                        &mut car_locations
+                    };
+
                     // Any available cars in the right spot?
-                    let idx = if let Some(idx) = car_locations
+                    let idx = if let Some(idx) = locations
                         .iter()
                         .find(|(_, parked_at)| *parked_at == need_parked_at)
                         .map(|(idx, _)| *idx)
@@ -494,7 +787,11 @@ impl PersonSpec {
                     } else {
                         // Need a new car, starting in the right spot
                         let idx = vehicle_specs.len();
-                        vehicle_specs.push(Scenario::rand_car(rng));
+                        vehicle_specs.push(if trip.is_delivery_tour() {
+                            Scenario::rand_truck(rng)
+                        } else {
+                            Scenario::rand_car(rng)
+                        });
                         if let Some(b) = need_parked_at {
                             cars_initially_parked_at.push((idx, b));
                         }
@@ -502,13 +799,13 @@ impl PersonSpec {
                     };
 
                     // Where does this car wind up?
-                    car_locations.retain(|(i, _)| idx != *i);
+                    locations.retain(|(i, _)| idx != *i);
                     match trip.destination {
                         TripEndpoint::Bldg(b) => {
-                            car_locations.push((idx, Some(b)));
+                            locations.push((idx, Some(b)));
                         }
                         TripEndpoint::Border(_) | TripEndpoint::SuddenlyAppear(_) => {
-                            car_locations.push((idx, None));
+                            locations.push((idx, None));
                         }
                     }
 
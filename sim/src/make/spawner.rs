@@ -2,14 +2,18 @@
 //! https://github.com/a-b-street/abstreet/issues/258
 
 use anyhow::Result;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-use geom::Pt2D;
+use geom::{Pt2D, Time};
 use map_model::{
-    BuildingID, BusRouteID, BusStopID, IntersectionID, Map, PathConstraints, PathRequest, Position,
+    BikeShareDockID, BuildingID, BusRouteID, BusStopID, IntersectionID, Map, PathConstraints,
+    PathRequest, Position, TransitItinerary,
 };
 
-use crate::{CarID, DrivingGoal, SidewalkSpot, TripLeg, TripMode, VehicleType, SPAWN_DIST};
+use crate::bike_share::closest_dock;
+use crate::make::park_and_ride::plan_park_and_ride;
+use crate::{CarID, DrivingGoal, SidewalkSpot, TripLeg, TripMode, SPAWN_DIST};
 
 /// We need to remember a few things from scenario instantiation that're used for starting the
 /// trip.
@@ -53,9 +57,36 @@ pub(crate) enum TripSpec {
     UsingTransit {
         start: SidewalkSpot,
         goal: SidewalkSpot,
+        /// Which vehicles to ride and when, planned when the trip starts
+        itinerary: TransitItinerary,
+    },
+    UsingBikeShare {
+        start: SidewalkSpot,
+        goal: SidewalkSpot,
+        pick_up: BikeShareDockID,
+        drop_off: BikeShareDockID,
+    },
+    /// Drive to somewhere near a transit stop, park, and ride transit the rest of the way.
+    DriveThenTransit {
+        /// This must be a currently parked vehicle owned by the person.
+        car: CarID,
+        start_bldg: BuildingID,
+        park_near: BuildingID,
         route: BusRouteID,
         stop1: BusStopID,
         maybe_stop2: Option<BusStopID>,
+        goal: SidewalkSpot,
+    },
+    /// Ride transit back to a car left by an earlier park-and-ride trip, then drive the rest of
+    /// the way.
+    TransitThenDrive {
+        start: SidewalkSpot,
+        route: BusRouteID,
+        stop1: BusStopID,
+        stop2: BusStopID,
+        /// This must be a vehicle owned by the person, parked near stop2.
+        car: CarID,
+        goal: DrivingGoal,
     },
 }
 
@@ -84,11 +115,7 @@ impl TripSpec {
                     }
                 }
 
-                let constraints = if use_vehicle.vehicle_type == VehicleType::Bike {
-                    PathConstraints::Bike
-                } else {
-                    PathConstraints::Car
-                };
+                let constraints = use_vehicle.vehicle_type.to_constraints();
 
                 legs.push(TripLeg::Drive(*use_vehicle, goal.clone()));
                 if let DrivingGoal::ParkNear(b) = goal {
@@ -116,7 +143,10 @@ impl TripSpec {
                     DrivingGoal::ParkNear(b) => {
                         legs.push(TripLeg::Walk(SidewalkSpot::building(*b, map)));
                     }
-                    DrivingGoal::Border(_, _) => {}
+                    DrivingGoal::Border(_, _)
+                    | DrivingGoal::CarpoolStop { .. }
+                    | DrivingGoal::LoadingZone { .. }
+                    | DrivingGoal::BikeShareDock(_) => {}
                 }
             }
             TripSpec::JustWalking { start, goal, .. } => {
@@ -141,6 +171,9 @@ impl TripSpec {
                             goal,
                         })
                     }
+                    DrivingGoal::CarpoolStop { .. }
+                    | DrivingGoal::LoadingZone { .. }
+                    | DrivingGoal::BikeShareDock(_) => unreachable!(),
                 };
 
                 if let Some(start_spot) = SidewalkSpot::bike_rack(*start, map) {
@@ -169,7 +202,10 @@ impl TripSpec {
                         DrivingGoal::ParkNear(b) => {
                             legs.push(TripLeg::Walk(SidewalkSpot::building(*b, map)));
                         }
-                        DrivingGoal::Border(_, _) => {}
+                        DrivingGoal::Border(_, _)
+                        | DrivingGoal::CarpoolStop { .. }
+                        | DrivingGoal::LoadingZone { .. }
+                        | DrivingGoal::BikeShareDock(_) => {}
                     }
                 } else if let Some(plan) = backup_plan {
                     info!("Can't start biking from {}. Walking instead", start);
@@ -186,21 +222,80 @@ impl TripSpec {
                 }
             }
             TripSpec::UsingTransit {
-                route,
-                stop1,
-                maybe_stop2,
+                itinerary, goal, ..
+            } => {
+                for leg in &itinerary.legs {
+                    legs.push(TripLeg::Walk(SidewalkSpot::bus_stop(leg.stop1, map)));
+                    legs.push(TripLeg::RideBus(leg.route, leg.maybe_stop2));
+                }
+                if itinerary.legs.last().unwrap().maybe_stop2.is_some() {
+                    legs.push(TripLeg::Walk(goal.clone()));
+                }
+            }
+            TripSpec::UsingBikeShare {
                 goal,
+                pick_up,
+                drop_off,
                 ..
             } => {
-                let walk_to = SidewalkSpot::bus_stop(*stop1, map);
-                if let Some(stop2) = maybe_stop2 {
+                if let Some(walk_to) = SidewalkSpot::bike_share_dock(*pick_up, map) {
                     legs = vec![
                         TripLeg::Walk(walk_to),
-                        TripLeg::RideBus(*route, Some(*stop2)),
+                        TripLeg::BikeShare {
+                            pick_up: *pick_up,
+                            drop_off: *drop_off,
+                        },
                         TripLeg::Walk(goal.clone()),
                     ];
                 } else {
-                    legs = vec![TripLeg::Walk(walk_to), TripLeg::RideBus(*route, None)];
+                    return TripSpec::SpawningFailure {
+                        use_vehicle: None,
+                        error: format!("{} isn't next to anywhere to bike", pick_up),
+                    }
+                    .into_plan(map);
+                }
+            }
+            TripSpec::DriveThenTransit {
+                car,
+                park_near,
+                route,
+                stop1,
+                maybe_stop2,
+                goal,
+                ..
+            } => {
+                legs = vec![
+                    TripLeg::Walk(SidewalkSpot::deferred_parking_spot()),
+                    TripLeg::Drive(*car, DrivingGoal::ParkNear(*park_near)),
+                    TripLeg::Walk(SidewalkSpot::bus_stop(*stop1, map)),
+                    TripLeg::RideBus(*route, *maybe_stop2),
+                ];
+                if maybe_stop2.is_some() {
+                    legs.push(TripLeg::Walk(goal.clone()));
+                }
+            }
+            TripSpec::TransitThenDrive {
+                route,
+                stop1,
+                stop2,
+                car,
+                goal,
+                ..
+            } => {
+                legs = vec![
+                    TripLeg::Walk(SidewalkSpot::bus_stop(*stop1, map)),
+                    TripLeg::RideBus(*route, Some(*stop2)),
+                    TripLeg::Walk(SidewalkSpot::deferred_parking_spot()),
+                    TripLeg::Drive(*car, goal.clone()),
+                ];
+                match goal {
+                    DrivingGoal::ParkNear(b) => {
+                        legs.push(TripLeg::Walk(SidewalkSpot::building(*b, map)));
+                    }
+                    DrivingGoal::Border(_, _)
+                    | DrivingGoal::CarpoolStop { .. }
+                    | DrivingGoal::LoadingZone { .. }
+                    | DrivingGoal::BikeShareDock(_) => {}
                 }
             }
         };
@@ -208,23 +303,26 @@ impl TripSpec {
         (self, legs)
     }
 
-    /// Turn an origin/destination pair and mode into a specific plan for instantiating a trip.
-    /// Decisions like how to use public transit happen here.
+    /// Turn an origin/destination pair and mode into a specific plan for instantiating a trip
+    /// starting now. Decisions like how to use public transit happen here. If an earlier
+    /// park-and-ride trip left the vehicle near a transit stop, `left_car_at` is its position on
+    /// the sidewalk.
     pub fn maybe_new(
+        now: Time,
         from: TripEndpoint,
         to: TripEndpoint,
         mode: TripMode,
         use_vehicle: Option<CarID>,
+        left_car_at: Option<Position>,
         retry_if_no_room: bool,
         map: &Map,
     ) -> Result<TripSpec> {
         Ok(match mode {
             TripMode::Drive | TripMode::Bike => {
-                let constraints = if mode == TripMode::Drive {
-                    PathConstraints::Car
-                } else {
-                    PathConstraints::Bike
-                };
+                // Trucks can't go everywhere cars can
+                let constraints = use_vehicle
+                    .map(|v| v.vehicle_type.to_constraints())
+                    .unwrap_or_else(|| mode.to_constraints());
                 let goal = to.driving_goal(constraints, map)?;
                 match from {
                     TripEndpoint::Bldg(start_bldg) => {
@@ -267,6 +365,7 @@ impl TripSpec {
                     },
                 }
             }
+            TripMode::RideHail => bail!("ride-hailing trips are handled by the dispatcher"),
             TripMode::Walk => TripSpec::JustWalking {
                 start: from.start_sidewalk_spot(map)?,
                 goal: to.end_sidewalk_spot(map)?,
@@ -274,15 +373,13 @@ impl TripSpec {
             TripMode::Transit => {
                 let start = from.start_sidewalk_spot(map)?;
                 let goal = to.end_sidewalk_spot(map)?;
-                if let Some((stop1, maybe_stop2, route)) =
-                    map.should_use_transit(start.sidewalk_pos, goal.sidewalk_pos)
+                if let Some(itinerary) =
+                    map.plan_transit_trip(start.sidewalk_pos, goal.sidewalk_pos, now)
                 {
                     TripSpec::UsingTransit {
                         start,
                         goal,
-                        route,
-                        stop1,
-                        maybe_stop2,
+                        itinerary,
                     }
                 } else {
                     //warn!("{:?} not actually using transit, because pathfinding didn't find any
@@ -290,12 +387,98 @@ impl TripSpec {
                     TripSpec::JustWalking { start, goal }
                 }
             }
+            TripMode::BikeShare => {
+                let start = from.start_sidewalk_spot(map)?;
+                let goal = to.end_sidewalk_spot(map)?;
+                match (
+                    closest_dock(start.sidewalk_pos.pt(map), map, |_| true),
+                    closest_dock(goal.sidewalk_pos.pt(map), map, |_| true),
+                ) {
+                    (Some(pick_up), Some(drop_off)) if pick_up != drop_off => {
+                        TripSpec::UsingBikeShare {
+                            start,
+                            goal,
+                            pick_up,
+                            drop_off,
+                        }
+                    }
+                    // No docks nearby, or they're the same one. Just walk.
+                    _ => TripSpec::JustWalking { start, goal },
+                }
+            }
+            TripMode::ParkAndRide => {
+                let car = use_vehicle.unwrap();
+                if let Some(car_pos) = left_car_at {
+                    // Head back to the car
+                    let goal = to.driving_goal(PathConstraints::Car, map)?;
+                    let start = from.start_sidewalk_spot(map)?;
+                    if let Some((stop1, Some(stop2), route)) =
+                        map.should_use_transit(start.sidewalk_pos, car_pos)
+                    {
+                        return Ok(TripSpec::TransitThenDrive {
+                            start,
+                            route,
+                            stop1,
+                            stop2,
+                            car,
+                            goal,
+                        });
+                    }
+                    match from {
+                        // Transit doesn't help; just walk to the car
+                        TripEndpoint::Bldg(start_bldg) => TripSpec::UsingParkedCar {
+                            car,
+                            start_bldg,
+                            goal,
+                        },
+                        TripEndpoint::Border(_) | TripEndpoint::SuddenlyAppear(_) => {
+                            bail!("can't get from {:?} back to {}", from, car)
+                        }
+                    }
+                } else {
+                    let plan = match from {
+                        TripEndpoint::Bldg(start_bldg) => {
+                            let goal = to.end_sidewalk_spot(map)?;
+                            let direct_goal = to.driving_goal(PathConstraints::Car, map)?;
+                            plan_park_and_ride(start_bldg, &goal, &direct_goal, map).map(|plan| {
+                                TripSpec::DriveThenTransit {
+                                    car,
+                                    start_bldg,
+                                    park_near: plan.park_near,
+                                    route: plan.route,
+                                    stop1: plan.stop1,
+                                    maybe_stop2: plan.maybe_stop2,
+                                    goal,
+                                }
+                            })
+                        }
+                        // Vehicles appearing from off-map can't easily switch modes
+                        TripEndpoint::Border(_) | TripEndpoint::SuddenlyAppear(_) => None,
+                    };
+                    match plan {
+                        Some(spec) => spec,
+                        // Driving the whole way is best
+                        None => TripSpec::maybe_new(
+                            now,
+                            from,
+                            to,
+                            TripMode::Drive,
+                            use_vehicle,
+                            None,
+                            retry_if_no_room,
+                            map,
+                        )?,
+                    }
+                }
+            }
         })
     }
 }
 
 /// Specifies where a trip begins or ends.
-#[derive(Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Debug, Clone, Copy)]
+#[derive(
+    Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Debug, Clone, Copy, JsonSchema,
+)]
 pub enum TripEndpoint {
     Bldg(BuildingID),
     Border(IntersectionID),
@@ -317,16 +500,20 @@ impl TripEndpoint {
         let start = from.pos(mode, true, map)?;
         let end = to.pos(mode, false, map)?;
         Some(match mode {
-            TripMode::Walk | TripMode::Transit => PathRequest::walking(start, end),
+            TripMode::Walk | TripMode::Transit | TripMode::BikeShare => {
+                PathRequest::walking(start, end)
+            }
             TripMode::Bike => PathRequest::vehicle(start, end, PathConstraints::Bike),
             // Only cars leaving from a building might turn out from the driveway in a special way
-            TripMode::Drive => {
+            TripMode::Drive | TripMode::ParkAndRide => {
                 if matches!(from, TripEndpoint::Bldg(_)) {
                     PathRequest::leave_from_driveway(start, end, PathConstraints::Car, map)
                 } else {
                     PathRequest::vehicle(start, end, PathConstraints::Car)
                 }
             }
+            // Ride-hailing vehicles stop at the curb
+            TripMode::RideHail => PathRequest::vehicle(start, end, PathConstraints::Car),
         })
     }
 
@@ -370,14 +557,14 @@ impl TripEndpoint {
 
     fn pos(self, mode: TripMode, from: bool, map: &Map) -> Option<Position> {
         match mode {
-            TripMode::Walk | TripMode::Transit => (if from {
+            TripMode::Walk | TripMode::Transit | TripMode::BikeShare => (if from {
                 self.start_sidewalk_spot(map)
             } else {
                 self.end_sidewalk_spot(map)
             })
             .ok()
             .map(|spot| spot.sidewalk_pos),
-            TripMode::Drive | TripMode::Bike => {
+            TripMode::Drive | TripMode::Bike | TripMode::RideHail | TripMode::ParkAndRide => {
                 if from {
                     match self {
                         // Fall through and use DrivingGoal also to start.
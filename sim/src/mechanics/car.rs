@@ -2,7 +2,7 @@ use std::collections::{BTreeSet, VecDeque};
 
 use serde::{Deserialize, Serialize};
 
-use geom::{Distance, Duration, PolyLine, Time, EPSILON_DIST};
+use geom::{Distance, Duration, PolyLine, Speed, Time, EPSILON_DIST};
 use map_model::{Direction, LaneID, Map, Traversable};
 
 use crate::{
@@ -29,6 +29,14 @@ pub(crate) struct Car {
     /// Since lane over-taking isn't implemented yet, a vehicle tends to be stuck behind a slow
     /// leader for a while. Avoid duplicate events.
     pub wants_to_overtake: BTreeSet<CarID>,
+
+    /// When and where the vehicle started its current step, and its average speed over the
+    /// previous step. Used to estimate emissions.
+    pub step_started: (Time, Distance),
+    pub prev_speed: Speed,
+    /// When the vehicle entered its current lane, if it started from the beginning of it. Used to
+    /// measure how long crossing the lane took.
+    pub lane_entered_at: Option<Time>,
 }
 
 impl Car {
@@ -45,13 +53,38 @@ impl Car {
         self.crossing_state_with_end_dist(dist_int, start_time, map)
     }
 
+    /// Like crossing_state, but for a vehicle that was stuck behind somebody. It takes the driver
+    /// a moment to react once the way is clear, so they arrive a bit later.
+    pub fn crossing_state_after_queueing(
+        &self,
+        start_dist: Distance,
+        start_time: Time,
+        map: &Map,
+    ) -> CarState {
+        match self.crossing_state(start_dist, start_time, map) {
+            CarState::Crossing {
+                time_int,
+                dist_int,
+                steep_uphill,
+            } => CarState::Crossing {
+                time_int: TimeInterval::new(
+                    time_int.start,
+                    time_int.end + self.vehicle.behavior.reaction_time,
+                ),
+                dist_int,
+                steep_uphill,
+            },
+            _ => unreachable!(),
+        }
+    }
+
     pub fn crossing_state_with_end_dist(
         &self,
         dist_int: DistanceInterval,
         start_time: Time,
         map: &Map,
     ) -> CarState {
-        let (speed, percent_incline) = self
+        let (mut speed, percent_incline) = self
             .router
             .get_path()
             .current_step()
@@ -60,6 +93,14 @@ impl Car {
                 self.vehicle.vehicle_type.to_constraints(),
                 map,
             );
+        // Some drivers ignore the speed limit, but they can't go faster than their vehicle
+        // allows.
+        if self.vehicle.behavior.speeding_factor != 1.0 {
+            speed = speed * self.vehicle.behavior.speeding_factor;
+            if let Some(max) = self.vehicle.max_speed {
+                speed = speed.min(max);
+            }
+        }
         let dt = (dist_int.end - dist_int.start) / speed;
         CarState::Crossing {
             time_int: TimeInterval::new(start_time, start_time + dt),
@@ -3,20 +3,24 @@ use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
 use serde::{Deserialize, Serialize};
 
 use abstutil::{deserialize_hashmap, serialize_hashmap, FixedMap, IndexableKey};
-use geom::{Distance, Duration, PolyLine, Time};
-use map_model::{DrivingSide, IntersectionID, LaneID, Map, Path, PathStep, Position, Traversable};
+use geom::{Distance, Duration, PolyLine, Speed, Time};
+use map_model::{
+    DrivingSide, IntersectionID, LaneID, Map, Path, PathStep, Position, Traversable, TurnID,
+};
 
 use crate::mechanics::car::{Car, CarState};
 use crate::mechanics::queue::{Queue, QueueEntry, Queued};
 use crate::sim::Ctx;
 use crate::{
-    ActionAtEnd, AgentID, AgentProperties, CarID, CarStatus, Command, CreateCar, DelayCause,
-    DistanceInterval, DrawCarInput, Event, IntersectionSimState, ParkedCar, ParkingSim,
-    ParkingSpot, PersonID, Problem, SimOptions, TimeInterval, TransitSimState, TripID, TripManager,
-    UnzoomedAgent, Vehicle, VehicleType, WalkingSimState, FOLLOWING_DISTANCE, MAX_CAR_LENGTH,
+    energy_to_cross, ActionAtEnd, AgentID, AgentProperties, CarID, CarStatus, Command, CreateCar,
+    DelayCause, DistanceInterval, DrawCarInput, DrivingSegment, Emissions, EmissionsModel,
+    EmissionsModelKind, Event, IntersectionSimState, ParkedCar, ParkingSim, ParkingSpot, PersonID,
+    Problem, SimOptions, TimeInterval, TransitSimState, TripID, TripManager, UnzoomedAgent,
+    Vehicle, VehicleType, WalkingSimState, FOLLOWING_DISTANCE, MAX_CAR_LENGTH,
 };
 
-const TIME_TO_WAIT_AT_BUS_STOP: Duration = Duration::const_seconds(10.0);
+const TIME_TO_PICK_UP_PASSENGERS: Duration = Duration::const_seconds(30.0);
+const TIME_TO_UNLOAD_DELIVERY: Duration = Duration::const_seconds(180.0);
 const TIME_TO_CHANGE_LANES: Duration = Duration::const_seconds(1.0);
 
 // TODO Do something else.
@@ -50,6 +54,9 @@ pub(crate) struct DrivingSimState {
     time_to_park_onstreet: Duration,
     time_to_unpark_offstreet: Duration,
     time_to_park_offstreet: Duration,
+
+    emissions_model: EmissionsModelKind,
+    reroute_threshold: Option<Duration>,
 }
 
 // Mutations
@@ -67,6 +74,9 @@ impl DrivingSimState {
             time_to_park_onstreet: Duration::seconds(15.0),
             time_to_unpark_offstreet: Duration::seconds(5.0),
             time_to_park_offstreet: Duration::seconds(5.0),
+
+            emissions_model: opts.emissions_model.clone(),
+            reroute_threshold: opts.reroute_threshold,
         };
         if opts.infinite_parking {
             sim.time_to_unpark_offstreet = Duration::seconds(0.1);
@@ -174,6 +184,7 @@ impl DrivingSimState {
         if let Some(idx) = self.queues[&Traversable::Lane(first_lane)].get_idx_to_insert_car(
             start_dist,
             params.vehicle.length,
+            params.vehicle.following_dist(),
             now,
             &self.cars,
             &self.queues,
@@ -191,6 +202,9 @@ impl DrivingSimState {
                 total_blocked_time: Duration::ZERO,
                 trip_and_person: params.trip_and_person,
                 wants_to_overtake: BTreeSet::new(),
+                step_started: (now, start_dist),
+                prev_speed: Speed::ZERO,
+                lane_entered_at: None,
             };
             if let Some(p) = params.maybe_parked_car {
                 let delay = match p.spot {
@@ -330,7 +344,7 @@ impl DrivingSimState {
             // checker, temporarily move one of them out of the map.
             let mut car = self.cars.remove(&id).unwrap();
             // Responsibility of update_car to manage scheduling stuff!
-            need_distances = self.update_car_without_distances(&mut car, now, ctx, transit);
+            need_distances = self.update_car_without_distances(&mut car, now, ctx, trips, transit);
             self.cars.insert(id, car);
         }
         // Note we might set need_distances to true, so both of these conditionals might run.
@@ -368,6 +382,7 @@ impl DrivingSimState {
         car: &mut Car,
         now: Time,
         ctx: &mut Ctx,
+        trips: &TripManager,
         transit: &mut TransitSimState,
     ) -> bool {
         match car.state {
@@ -389,6 +404,7 @@ impl DrivingSimState {
                             &self.queues,
                             ctx.map,
                             self.handle_uber_turns,
+                            car.vehicle.behavior.lane_change_eagerness,
                         );
                     }
                     ctx.scheduler.push(now, Command::UpdateCar(car.vehicle.id));
@@ -503,6 +519,29 @@ impl DrivingSimState {
                 // We do NOT need to update the follower. If they were Queued, they'll remain that
                 // way, until laggy_head is None.
 
+                // Electric vehicles pay for the lane they just finished
+                if let (Some(battery), Traversable::Lane(l)) = (car.vehicle.battery.as_mut(), from)
+                {
+                    let was_empty = battery.is_empty();
+                    battery.drain(energy_to_cross(ctx.map.get_l(l), ctx.map));
+                    if battery.is_empty() && !was_empty {
+                        self.events.push(Event::BatteryDepleted(car.vehicle.id));
+                    }
+                }
+                let end_dist = from.get_polyline(ctx.map).length();
+                self.record_emissions(car, from, end_dist, now, ctx.map);
+                if let (Some(entered_at), Traversable::Lane(l)) = (car.lane_entered_at, from) {
+                    self.events.push(Event::VehicleCrossedLane(
+                        car.vehicle.id,
+                        l,
+                        now - entered_at,
+                    ));
+                }
+                car.lane_entered_at = match goto {
+                    Traversable::Lane(_) => Some(now),
+                    Traversable::Turn(_) => None,
+                };
+
                 let last_step = car.router.advance(
                     &car.vehicle,
                     ctx.parking,
@@ -511,9 +550,34 @@ impl DrivingSimState {
                     &mut self.events,
                 );
                 car.total_blocked_time += now - blocked_since;
+                if let (Some(threshold), Traversable::Lane(_)) = (self.reroute_threshold, goto) {
+                    if let Some(saved) =
+                        car.router
+                            .maybe_reroute(threshold, now, ctx.intersections, ctx.map)
+                    {
+                        self.events
+                            .push(Event::PathAmended(car.router.get_path().clone()));
+                        self.events
+                            .push(Event::Rerouted(car.vehicle.id, Some(saved)));
+                    }
+                }
                 car.state = car.crossing_state(Distance::ZERO, now, ctx.map);
                 ctx.scheduler
                     .push(car.state.get_end_time(), Command::UpdateCar(car.vehicle.id));
+                // Buses and trains ask the next traffic signal for priority as soon as they're on
+                // the approach
+                if car.vehicle.vehicle_type.is_transit() && matches!(goto, Traversable::Lane(_)) {
+                    if let Some(Traversable::Turn(t)) = car.router.maybe_next() {
+                        ctx.intersections.transit_check_in(
+                            now,
+                            car.vehicle.id,
+                            t,
+                            car.state.get_end_time(),
+                            ctx.map,
+                            ctx.scheduler,
+                        );
+                    }
+                }
                 self.events.push(Event::AgentEntersTraversable(
                     AgentID::Car(car.vehicle.id),
                     car.trip_and_person.map(|(t, _)| t),
@@ -521,7 +585,7 @@ impl DrivingSimState {
                     if car.vehicle.vehicle_type.is_transit() {
                         Some(transit.get_passengers(car.vehicle.id).len())
                     } else {
-                        None
+                        Some(trips.num_car_passengers(car.vehicle.id)).filter(|n| *n > 0)
                     },
                 ));
 
@@ -627,6 +691,8 @@ impl DrivingSimState {
                 ) {
                     Some(ActionAtEnd::VanishAtBorder(i)) => {
                         car.total_blocked_time += now - blocked_since;
+                        let on = car.router.head();
+                        self.record_emissions(car, on, our_dist, now, ctx.map);
                         // Don't do this for buses
                         if car.trip_and_person.is_some() {
                             trips.car_or_bike_reached_border(
@@ -642,19 +708,30 @@ impl DrivingSimState {
                     }
                     Some(ActionAtEnd::GiveUpOnParking) => {
                         car.total_blocked_time += now - blocked_since;
-                        trips.cancel_trip(
-                            now,
-                            car.trip_and_person.unwrap().0,
-                            "no available parking anywhere".to_string(),
-                            // If we couldn't find parking normally, doesn't make sense to warp the
-                            // car to the destination. There's no parking!
-                            None,
-                            ctx,
-                        );
+                        let on = car.router.head();
+                        self.record_emissions(car, on, our_dist, now, ctx.map);
+                        if let Some((trip, _)) = car.trip_and_person {
+                            trips.cancel_trip(
+                                now,
+                                trip,
+                                "no available parking anywhere".to_string(),
+                                // If we couldn't find parking normally, doesn't make sense to warp
+                                // the car to the destination. There's no parking!
+                                None,
+                                ctx,
+                            );
+                        } else {
+                            trips.ride_hail_vehicle_out_of_service(
+                                car.vehicle.id,
+                                car.router.get_path().total_length(),
+                            );
+                        }
                         false
                     }
                     Some(ActionAtEnd::StartParking(spot)) => {
                         car.total_blocked_time += now - blocked_since;
+                        let on = car.router.head();
+                        self.record_emissions(car, on, our_dist, now, ctx.map);
                         let delay = match spot {
                             ParkingSpot::Onstreet(_, _) => self.time_to_park_onstreet,
                             ParkingSpot::Offstreet(_, _) | ParkingSpot::Lot(_, _) => {
@@ -690,12 +767,46 @@ impl DrivingSimState {
                         );
                         false
                     }
+                    Some(ActionAtEnd::PassengerStop) => {
+                        car.total_blocked_time += now - blocked_since;
+                        let delay = if trips.car_reached_passenger_stop(
+                            now,
+                            car.vehicle.id,
+                            car.router.get_path().total_length(),
+                            ctx,
+                        ) {
+                            TIME_TO_PICK_UP_PASSENGERS
+                        } else {
+                            Duration::ZERO
+                        };
+                        car.state =
+                            CarState::IdlingAtStop(our_dist, TimeInterval::new(now, now + delay));
+                        ctx.scheduler
+                            .push(car.state.get_end_time(), Command::UpdateCar(car.vehicle.id));
+                        true
+                    }
+                    Some(ActionAtEnd::LoadingZone) => {
+                        car.total_blocked_time += now - blocked_since;
+                        trips.truck_reached_loading_zone(
+                            car.vehicle.id,
+                            car.router.get_path().total_length(),
+                        );
+                        car.state = CarState::IdlingAtStop(
+                            our_dist,
+                            TimeInterval::new(now, now + TIME_TO_UNLOAD_DELIVERY),
+                        );
+                        ctx.scheduler
+                            .push(car.state.get_end_time(), Command::UpdateCar(car.vehicle.id));
+                        true
+                    }
                     Some(ActionAtEnd::BusAtStop) => {
                         car.total_blocked_time += now - blocked_since;
-                        if transit.bus_arrived_at_stop(now, car.vehicle.id, trips, walking, ctx) {
+                        if let Some(dwell) =
+                            transit.bus_arrived_at_stop(now, car.vehicle.id, trips, walking, ctx)
+                        {
                             car.state = CarState::IdlingAtStop(
                                 our_dist,
-                                TimeInterval::new(now, now + TIME_TO_WAIT_AT_BUS_STOP),
+                                TimeInterval::new(now, now + dwell),
                             );
                             ctx.scheduler
                                 .push(car.state.get_end_time(), Command::UpdateCar(car.vehicle.id));
@@ -750,8 +861,61 @@ impl DrivingSimState {
                 );
                 false
             }
-            CarState::IdlingAtStop(dist, _) => {
-                car.router = transit.bus_departed_from_stop(car.vehicle.id, ctx.map);
+            CarState::IdlingAtStop(dist, time_int) => {
+                if car.vehicle.vehicle_type.is_transit() {
+                    if let Some(delay) = transit.late_boarding_delay(car.vehicle.id) {
+                        car.state = CarState::IdlingAtStop(
+                            dist,
+                            TimeInterval::new(time_int.start, now + delay),
+                        );
+                        ctx.scheduler
+                            .push(car.state.get_end_time(), Command::UpdateCar(car.vehicle.id));
+                        return true;
+                    }
+                }
+
+                if car.vehicle.vehicle_type == VehicleType::Truck {
+                    let vehicles_behind = dists[idx + 1..]
+                        .iter()
+                        .filter(|entry| match entry.member {
+                            Queued::Vehicle(c) => {
+                                matches!(self.cars[&c].state, CarState::Queued { .. })
+                            }
+                            _ => false,
+                        })
+                        .count();
+                    self.events.push(Event::TruckLeftLoadingZone {
+                        truck: car.vehicle.id,
+                        lane: car.router.head().as_lane(),
+                        blocked_for: now - time_int.start,
+                        vehicles_behind,
+                    });
+                }
+
+                if car.vehicle.vehicle_type.is_transit() {
+                    car.router = transit.bus_departed_from_stop(car.vehicle.id, ctx.map);
+                } else if let Some(router) = trips.car_leaving_stop(
+                    now,
+                    car.vehicle.id,
+                    Position::new(car.router.head().as_lane(), dist),
+                    ctx,
+                ) {
+                    car.router = router;
+                    if car.router.last_step() {
+                        // Like when unparking, trigger the side effect of choosing an end_dist
+                        car.router.maybe_handle_end(
+                            dist,
+                            &car.vehicle,
+                            ctx.parking,
+                            ctx.map,
+                            car.trip_and_person,
+                            &mut self.events,
+                        );
+                    }
+                } else {
+                    // The trip was cancelled and the car warped away
+                    return false;
+                }
                 self.events
                     .push(Event::PathAmended(car.router.get_path().clone()));
                 car.state = car.crossing_state(dist, now, ctx.map);
@@ -765,6 +929,42 @@ impl DrivingSimState {
         }
     }
 
+    /// A vehicle finished crossing part of a lane or turn, ending at `end_dist`. Estimate what it
+    /// emitted along the way.
+    fn record_emissions(
+        &mut self,
+        car: &mut Car,
+        on: Traversable,
+        end_dist: Distance,
+        now: Time,
+        map: &Map,
+    ) {
+        let (started_at, start_dist) = car.step_started;
+        let dist = (end_dist - start_dist).max(Distance::ZERO);
+        let climb = match on {
+            Traversable::Lane(l) => {
+                let lane = map.get_l(l);
+                let rise = map.get_i(lane.dst_i).elevation - map.get_i(lane.src_i).elevation;
+                rise.max(Distance::ZERO) * (dist / lane.length())
+            }
+            Traversable::Turn(_) => Distance::ZERO,
+        };
+        let segment = DrivingSegment {
+            dist,
+            duration: now - started_at,
+            prev_speed: car.prev_speed,
+            climb,
+        };
+        car.step_started = (now, Distance::ZERO);
+        car.prev_speed = segment.avg_speed();
+
+        let emissions = self.emissions_model.emissions(&car.vehicle, segment);
+        if emissions != Emissions::ZERO {
+            self.events
+                .push(Event::VehicleEmissions(car.vehicle.id, on, emissions));
+        }
+    }
+
     /// Abruptly remove a vehicle from the simulation. They may be in any arbitrary state, like in
     /// the middle of a turn or parking.
     pub fn delete_car(&mut self, c: CarID, now: Time, ctx: &mut Ctx) -> Vehicle {
@@ -786,7 +986,7 @@ impl DrivingSimState {
             let queue = self.queues.get_mut(&car.router.head()).unwrap();
             // delete_car_internal will call free_reserved_space, so this is necessary to balance
             // that.
-            queue.reserved_length += car.vehicle.length + FOLLOWING_DISTANCE;
+            queue.reserved_length += car.vehicle.length + car.vehicle.following_dist();
             ctx.intersections.agent_deleted_mid_turn(AgentID::Car(c), t);
 
             // Free any reserved space on the next step.
@@ -877,7 +1077,8 @@ impl DrivingSimState {
 
                     // Prevent them from jumping forwards.
                     follower.total_blocked_time += now - blocked_since;
-                    follower.state = follower.crossing_state(follower_dist, now, ctx.map);
+                    follower.state =
+                        follower.crossing_state_after_queueing(follower_dist, now, ctx.map);
                     ctx.scheduler.update(
                         follower.state.get_end_time(),
                         Command::UpdateCar(follower_id),
@@ -1051,6 +1252,7 @@ impl DrivingSimState {
                                             &self.queues,
                                             ctx.map,
                                             self.handle_uber_turns,
+                                            follower.vehicle.behavior.lane_change_eagerness,
                                         );
                                     }
                                     ctx.scheduler
@@ -1177,6 +1379,7 @@ impl DrivingSimState {
             .get_idx_to_insert_car(
                 front_target_queue,
                 car.vehicle.length,
+                car.vehicle.following_dist(),
                 now,
                 &self.cars,
                 &self.queues,
@@ -1224,6 +1427,53 @@ impl DrivingSimState {
         std::mem::take(&mut self.events)
     }
 
+    /// Live map edits cut off the rest of this car's path. If re-routing is enabled, try to find
+    /// another way, returning false if that's impossible.
+    pub fn reroute_around_live_edits(
+        &mut self,
+        id: CarID,
+        now: Time,
+        ctx: &mut Ctx,
+        edited_lanes: &BTreeSet<LaneID>,
+    ) -> bool {
+        if self.reroute_threshold.is_none() {
+            return false;
+        }
+        let car = self.cars.get_mut(&id).unwrap();
+        match car.state {
+            CarState::Crossing { .. }
+            | CarState::Queued { .. }
+            | CarState::WaitingToAdvance { .. } => {}
+            _ => {
+                return false;
+            }
+        }
+        if let Traversable::Lane(l) = car.router.head() {
+            if edited_lanes.contains(&l) {
+                return false;
+            }
+        }
+        // The car might've already asked to make the next turn
+        let old_turn = car.router.maybe_next();
+        if !car
+            .router
+            .reroute_around_edits(now, ctx.intersections, ctx.map)
+        {
+            return false;
+        }
+        if let Some(Traversable::Turn(t)) = old_turn {
+            ctx.intersections.cancel_request(AgentID::Car(id), t);
+        }
+        if let CarState::WaitingToAdvance { .. } = car.state {
+            // Nothing would wake this car up otherwise
+            ctx.scheduler.update(now, Command::UpdateCar(id));
+        }
+        self.events
+            .push(Event::PathAmended(car.router.get_path().clone()));
+        self.events.push(Event::Rerouted(id, None));
+        true
+    }
+
     pub fn handle_live_edits(&mut self, map: &Map) {
         // Calculate all queues that should exist now.
         let mut new_queues = HashSet::new();
@@ -1516,8 +1766,11 @@ impl DrivingSimState {
         for car in self.cars.values() {
             if let Some(spot) = car.router.get_parking_spot_goal() {
                 if !spots.contains(spot) {
-                    // Buses don't park
-                    affected.push((AgentID::Car(car.vehicle.id), car.trip_and_person.unwrap().0));
+                    // Buses don't park. TODO Ride-hailing vehicles going to park aren't handled
+                    // yet.
+                    if let Some((trip, _)) = car.trip_and_person {
+                        affected.push((AgentID::Car(car.vehicle.id), trip));
+                    }
                 }
             }
         }
@@ -1561,6 +1814,45 @@ impl DrivingSimState {
         }
     }
 
+    /// For every vehicle on a lane leading into this intersection, find the turn they'll make
+    /// next, and count them.
+    pub fn count_queued_turns(&self, i: IntersectionID, map: &Map) -> BTreeMap<TurnID, usize> {
+        let mut counts = BTreeMap::new();
+        for l in &map.get_i(i).incoming_lanes {
+            if let Some(queue) = self.queues.get(&Traversable::Lane(*l)) {
+                for car in queue.get_active_cars() {
+                    if let Some(Traversable::Turn(t)) = self.cars[&car].router.maybe_next() {
+                        if t.parent == i {
+                            *counts.entry(t).or_insert(0) += 1;
+                        }
+                    }
+                }
+            }
+        }
+        counts
+    }
+
+    /// For max-pressure signal control: for every vehicle turn through an intersection, how many
+    /// vehicles are queued to make it, minus how many vehicles are already on the destination
+    /// lane.
+    pub fn get_turn_pressure(&self, i: IntersectionID, map: &Map) -> BTreeMap<TurnID, isize> {
+        let queued = self.count_queued_turns(i, map);
+        let mut pressure = BTreeMap::new();
+        for turn in &map.get_i(i).turns {
+            if turn.between_sidewalks() {
+                continue;
+            }
+            let upstream = queued.get(&turn.id).cloned().unwrap_or(0);
+            let downstream = self
+                .queues
+                .get(&Traversable::Lane(turn.id.dst))
+                .map(|q| q.get_active_cars().len())
+                .unwrap_or(0);
+            pressure.insert(turn.id, upstream as isize - downstream as isize);
+        }
+        pressure
+    }
+
     pub fn debug_queue_lengths(&self, l: LaneID) -> Option<(Distance, Distance)> {
         let queue = self.queues.get(&Traversable::Lane(l))?;
         Some((queue.reserved_length, queue.geom_len))
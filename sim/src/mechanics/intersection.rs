@@ -5,18 +5,21 @@ use serde::{Deserialize, Serialize};
 use abstutil::{deserialize_btreemap, prettyprint_usize, serialize_btreemap, FixedMap};
 use geom::{Duration, Time};
 use map_model::{
-    ControlStopSign, ControlTrafficSignal, Intersection, IntersectionID, LaneID, Map, StageType,
-    Traversable, TurnID, TurnPriority, TurnType, UberTurn,
+    ControlStopSign, ControlTrafficSignal, Intersection, IntersectionID, LaneID, Map, MovementID,
+    Stage, StageType, Traversable, TurnID, TurnPriority, TurnType, UberTurn,
 };
 
 use crate::mechanics::car::{Car, CarState};
 use crate::mechanics::Queue;
 use crate::{
-    AgentID, AlertLocation, CarID, Command, DelayCause, Event, Scheduler, SimOptions, Speed,
+    AgentID, AlertLocation, CarID, Command, DelayCause, DrivingSimState, Event, Scheduler,
+    SimOptions, Speed,
 };
 
 const WAIT_AT_STOP_SIGN: Duration = Duration::const_seconds(0.5);
 const WAIT_BEFORE_YIELD_AT_TRAFFIC_SIGNAL: Duration = Duration::const_seconds(0.2);
+/// After this long, the wait measured for the last vehicle to make some movement is stale.
+const RECENT_DELAY_WINDOW: Duration = Duration::const_seconds(300.0);
 
 /// Manages conflicts at intersections. When an agent has reached the end of a lane, they call
 /// maybe_start_turn to make a Request. Based on the intersection type (stop sign, traffic signal,
@@ -38,6 +41,13 @@ pub(crate) struct IntersectionSimState {
     // structure.
     blocked_by: BTreeSet<(CarID, CarID)>,
     events: Vec<Event>,
+    // For each movement, when did the last vehicle start it, and how long had it waited? This is
+    // live delay data for drivers deciding to re-route.
+    #[serde(
+        serialize_with = "serialize_btreemap",
+        deserialize_with = "deserialize_btreemap"
+    )]
+    recent_delays: BTreeMap<MovementID, (Time, Duration)>,
 
     // Count how many calls to maybe_start_turn there are aside from the initial call. Break down
     // failures by those not allowed by the current intersection state vs those blocked by a
@@ -72,12 +82,32 @@ struct State {
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 struct SignalState {
-    // The current stage of the signal, zero based
+    // The signal may switch between plans at different times of day. This is an index into
+    // ControlTrafficSignal plans.
+    current_plan: usize,
+    // The current stage of the signal, zero based, in the current plan
     current_stage: usize,
     // The time when the signal is checked for advancing
     stage_ends_at: Time,
     // The number of times a variable signal has been extended during the current stage.
     extensions_count: usize,
+    // Actuated and max-pressure stages can only last so long
+    stage_started_at: Time,
+    // For actuated stages, the last time a vehicle was detected waiting for each movement
+    #[serde(
+        serialize_with = "serialize_btreemap",
+        deserialize_with = "deserialize_btreemap"
+    )]
+    last_actuation: BTreeMap<MovementID, Time>,
+    // Crosswalks where a pedestrian has pushed the button, but hasn't gotten to walk yet
+    ped_calls: BTreeSet<MovementID>,
+    // Crosswalks in the current stage that have a walk signal. Actuated stages only give one if a
+    // pedestrian called before the stage started.
+    walk_granted: BTreeSet<MovementID>,
+    // Transit vehicles can only extend or truncate each stage once
+    transit_priority_used: bool,
+    // A transit vehicle cut the current stage short. When it ends, skip straight to this stage.
+    truncated_for_transit: Option<usize>,
 }
 
 #[derive(PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, Clone, Debug)]
@@ -98,6 +128,7 @@ impl IntersectionSimState {
             disable_turn_conflicts: opts.disable_turn_conflicts,
             blocked_by: BTreeSet::new(),
             events: Vec::new(),
+            recent_delays: BTreeMap::new(),
 
             total_repeat_requests: 0,
             not_allowed_requests: 0,
@@ -219,12 +250,12 @@ impl IntersectionSimState {
                 protected.push(req);
             }
         } else if let Some(signal) = map.maybe_get_traffic_signal(i) {
-            let current_stage = self.state[&i].signal.as_ref().unwrap().current_stage;
-            let stage = &signal.stages[current_stage];
+            let signal_state = self.state[&i].signal.as_ref().unwrap();
+            let stage = &signal.get_stages(signal_state.current_plan)[signal_state.current_stage];
             let reserved = &self.state[&i].reserved;
             let i = map.get_i(i);
             for (req, _, _) in all {
-                match stage.get_priority_of_turn(req.turn, i) {
+                match signal_state.get_priority_of_turn(stage, req.turn, i) {
                     TurnPriority::Protected => {
                         protected.push(req);
                     }
@@ -279,6 +310,7 @@ impl IntersectionSimState {
         id: IntersectionID,
         map: &Map,
         scheduler: &mut Scheduler,
+        driving: &DrivingSimState,
     ) {
         let i = map.get_i(id);
 
@@ -287,26 +319,55 @@ impl IntersectionSimState {
             signal_state: &mut SignalState,
             signal: &ControlTrafficSignal,
             i: &Intersection,
+            waiting: &BTreeMap<Request, (Time, bool)>,
+            now: Time,
             allow_crosswalk_skip: bool,
         ) -> Duration {
-            signal_state.current_stage = (signal_state.current_stage + 1) % signal.stages.len();
-            let stage = &signal.stages[signal_state.current_stage];
+            let stages = signal.get_stages(signal_state.current_plan);
+            let mut next_stage = (signal_state.current_stage + 1) % stages.len();
+            let stage = &stages[next_stage];
             // only skip for variable all-walk crosswalk
             if let StageType::Variable(_, _, _) = stage.stage_type {
                 if allow_crosswalk_skip && stage.max_crosswalk_time(i).is_some() {
                     // we can skip this stage, as its all walk and we're allowed to skip (no
                     // pedestrian waiting).
-                    signal_state.current_stage =
-                        (signal_state.current_stage + 1) % signal.stages.len();
+                    next_stage = (next_stage + 1) % stages.len();
+                }
+            }
+            // Skip actuated stages that nobody has called, but don't go all the way around
+            for _ in 1..stages.len() {
+                let stage = &stages[next_stage];
+                if !matches!(stage.stage_type, StageType::Actuated(_, _, _))
+                    || signal_state.has_call(stage, waiting, i)
+                {
+                    break;
                 }
+                next_stage = (next_stage + 1) % stages.len();
             }
-            signal.stages[signal_state.current_stage]
-                .stage_type
-                .simple_duration()
+            signal_state.enter_stage(next_stage, now, signal, i)
         }
         let state = self.state.get_mut(&id).unwrap();
         let signal_state = state.signal.as_mut().unwrap();
         let signal = map.get_traffic_signal(id);
+        assert_eq!(now, signal_state.stage_ends_at);
+
+        // If a different plan has taken effect, switch to it at the end of the current stage,
+        // starting wherever the new plan's offset says to.
+        if signal.plan_idx_at(now) != signal_state.current_plan {
+            // Pedestrians who already pushed the button and vehicles sitting on detectors are
+            // still there, so the new plan has to answer them.
+            let ped_calls = std::mem::take(&mut signal_state.ped_calls);
+            let last_actuation = std::mem::take(&mut signal_state.last_actuation);
+            *signal_state = SignalState::new(id, now, map, scheduler);
+            signal_state.ped_calls = ped_calls
+                .difference(&signal_state.walk_granted)
+                .cloned()
+                .collect();
+            signal_state.last_actuation = last_actuation;
+            self.wakeup_waiting(now, id, scheduler, map);
+            return;
+        }
+
         let ped_waiting = state.waiting.keys().any(|req| {
             if let AgentID::Pedestrian(_) = req.agent {
                 return true;
@@ -315,11 +376,15 @@ impl IntersectionSimState {
         });
         let duration: Duration;
         // Switch to a new stage?
-        assert_eq!(now, signal_state.stage_ends_at);
-        let old_stage = &signal.stages[signal_state.current_stage];
+        let old_stage = &signal.get_stages(signal_state.current_plan)[signal_state.current_stage];
         match old_stage.stage_type {
+            // Don't extend a stage that a bus or train cut short
+            _ if signal_state.truncated_for_transit.is_some() => {
+                let next_stage = signal_state.truncated_for_transit.unwrap();
+                duration = signal_state.enter_stage(next_stage, now, signal, i);
+            }
             StageType::Fixed(_) => {
-                duration = advance(signal_state, signal, i, !ped_waiting);
+                duration = advance(signal_state, signal, i, &state.waiting, now, !ped_waiting);
             }
             StageType::Variable(min, delay, additional) => {
                 // test if anyone is waiting in current stage, and if so, extend the signal cycle.
@@ -337,8 +402,7 @@ impl IntersectionSimState {
                             min, delay, additional, signal_state.extensions_count
                         ),
                     ));
-                    duration = advance(signal_state, signal, i, !ped_waiting);
-                    signal_state.extensions_count = 0;
+                    duration = advance(signal_state, signal, i, &state.waiting, now, !ped_waiting);
                 } else if state.waiting.keys().all(|req| {
                     if let AgentID::Pedestrian(_) = req.agent {
                         return true;
@@ -347,8 +411,7 @@ impl IntersectionSimState {
                     // currently only the protected demand control extended.
                     old_stage.get_priority_of_turn(req.turn, i) != TurnPriority::Protected
                 }) {
-                    signal_state.extensions_count = 0;
-                    duration = advance(signal_state, signal, i, !ped_waiting);
+                    duration = advance(signal_state, signal, i, &state.waiting, now, !ped_waiting);
                 } else {
                     signal_state.extensions_count += 1;
                     duration = delay;
@@ -361,6 +424,49 @@ impl IntersectionSimState {
                     ));
                 }
             }
+            StageType::Actuated(_, passage, max) => {
+                let elapsed = now - signal_state.stage_started_at;
+                // A vehicle still waiting for a protected movement is continuously detected
+                let presence = state.waiting.keys().any(|req| {
+                    !matches!(req.agent, AgentID::Pedestrian(_))
+                        && old_stage.get_priority_of_turn(req.turn, i) == TurnPriority::Protected
+                });
+                let last_actuation = old_stage
+                    .protected_movements
+                    .iter()
+                    .filter_map(|m| signal_state.last_actuation.get(m))
+                    .max()
+                    .cloned();
+                if elapsed >= max {
+                    // Max out
+                    duration = advance(signal_state, signal, i, &state.waiting, now, false);
+                } else if presence {
+                    duration = passage.min(max - elapsed);
+                } else if let Some(t) = last_actuation.filter(|t| now - *t < passage) {
+                    duration = (t + passage - now).min(max - elapsed);
+                } else {
+                    // Gap out
+                    duration = advance(signal_state, signal, i, &state.waiting, now, false);
+                }
+            }
+            StageType::MaxPressure(min, max) => {
+                let elapsed = now - signal_state.stage_started_at;
+                let turn_pressure = driving.get_turn_pressure(id, map);
+                let next_stage = signal_state.max_pressure_stage(
+                    signal,
+                    i,
+                    &state.waiting,
+                    &turn_pressure,
+                    elapsed < max,
+                );
+                if next_stage == signal_state.current_stage && elapsed < max {
+                    duration = min.min(max - elapsed);
+                } else {
+                    // If the current stage maxed out but there's nothing else to pick, restart
+                    // it. Validation rejects plans like that, but never get stuck.
+                    duration = signal_state.enter_stage(next_stage, now, signal, i);
+                }
+            }
         }
 
         signal_state.stage_ends_at = now + duration;
@@ -368,6 +474,147 @@ impl IntersectionSimState {
         self.wakeup_waiting(now, id, scheduler, map);
     }
 
+    /// Immediately switch a traffic signal to some stage, overriding its normal timing. The stage
+    /// lasts for its usual duration, then the signal resumes its cycle from there.
+    pub fn force_stage(
+        &mut self,
+        now: Time,
+        id: IntersectionID,
+        stage: usize,
+        map: &Map,
+        scheduler: &mut Scheduler,
+    ) {
+        let signal = map.get_traffic_signal(id);
+        let signal_state = self.state.get_mut(&id).unwrap().signal.as_mut().unwrap();
+        assert!(stage < signal.get_stages(signal_state.current_plan).len());
+        let duration = signal_state.enter_stage(stage, now, signal, map.get_i(id));
+        signal_state.stage_ends_at = now + duration;
+        scheduler.update(signal_state.stage_ends_at, Command::UpdateIntersection(id));
+        self.wakeup_waiting(now, id, scheduler, map);
+    }
+
+    /// A bus or train expects to reach a traffic signal at `eta` and make some turn. If the signal
+    /// has transit priority, hold the current green until the vehicle arrives, or cut the current
+    /// red short and skip to a stage that lets the vehicle through.
+    pub fn transit_check_in(
+        &mut self,
+        now: Time,
+        car: CarID,
+        turn: TurnID,
+        eta: Time,
+        map: &Map,
+        scheduler: &mut Scheduler,
+    ) {
+        if self.use_freeform_policy_everywhere {
+            return;
+        }
+        let signal = if let Some(ts) = map.maybe_get_traffic_signal(turn.parent) {
+            ts
+        } else {
+            return;
+        };
+        let tsp = if let Some(ref tsp) = signal.transit_priority {
+            tsp
+        } else {
+            return;
+        };
+        let i = map.get_i(turn.parent);
+        let state = self.state.get_mut(&turn.parent).unwrap();
+        let signal_state = state.signal.as_mut().unwrap();
+        // Don't interfere right before switching to a new plan
+        if signal_state.transit_priority_used
+            || signal.plan_idx_at(signal_state.stage_ends_at) != signal_state.current_plan
+        {
+            return;
+        }
+        let stages = signal.get_stages(signal_state.current_plan);
+        let stage = &stages[signal_state.current_stage];
+
+        // How many agents are already waiting for a green or a red?
+        let mut waiting_for_green = 0;
+        let mut waiting_for_red = 0;
+        for req in state.waiting.keys() {
+            if signal_state.get_priority_of_turn(stage, req.turn, i) == TurnPriority::Banned {
+                waiting_for_red += 1;
+            } else {
+                waiting_for_green += 1;
+            }
+        }
+
+        let extended;
+        let change;
+        let new_end;
+        let transit_delay_saved;
+        let cross_street_delay_added;
+        if signal_state.get_priority_of_turn(stage, turn, i) != TurnPriority::Banned {
+            // Only extend if it's enough to get the vehicle through
+            if eta <= signal_state.stage_ends_at
+                || eta - signal_state.stage_ends_at > tsp.max_extension
+            {
+                return;
+            }
+            extended = true;
+            change = eta - signal_state.stage_ends_at;
+            new_end = eta;
+            // Without the extension, the vehicle would wait for the next stage that allows its
+            // turn
+            let mut next_green = signal_state.stage_ends_at;
+            for offset in 1..stages.len() {
+                let next = &stages[(signal_state.current_stage + offset) % stages.len()];
+                if next.get_priority_of_turn(turn, i) != TurnPriority::Banned {
+                    break;
+                }
+                next_green += next.stage_type.simple_duration();
+            }
+            transit_delay_saved = next_green - eta;
+            cross_street_delay_added = change * (waiting_for_red as f64);
+        } else {
+            // Skip straight to the next stage that lets the vehicle through. Without priority, it
+            // would wait through every stage in between.
+            let mut next_green = signal_state.stage_ends_at;
+            let mut green_stage = None;
+            for offset in 1..stages.len() {
+                let idx = (signal_state.current_stage + offset) % stages.len();
+                if stages[idx].get_priority_of_turn(turn, i) != TurnPriority::Banned {
+                    green_stage = Some(idx);
+                    break;
+                }
+                next_green += stages[idx].stage_type.simple_duration();
+            }
+            if green_stage.is_none() {
+                return;
+            }
+            // Never cut off pedestrians in the middle of crossing
+            let earliest_end =
+                now.max(signal_state.stage_started_at + stage.get_min_crossing_time(i));
+            new_end = earliest_end.max(signal_state.stage_ends_at - tsp.max_truncation);
+            // Only bother if the vehicle would otherwise have to wait
+            if new_end >= signal_state.stage_ends_at || eta >= next_green {
+                return;
+            }
+            extended = false;
+            change = signal_state.stage_ends_at - new_end;
+            transit_delay_saved = next_green - eta.max(new_end);
+            cross_street_delay_added = change * (waiting_for_green as f64);
+            signal_state.truncated_for_transit = green_stage;
+        }
+
+        signal_state.transit_priority_used = true;
+        signal_state.stage_ends_at = new_end;
+        scheduler.update(
+            signal_state.stage_ends_at,
+            Command::UpdateIntersection(turn.parent),
+        );
+        self.events.push(Event::TransitSignalPriority {
+            intersection: turn.parent,
+            vehicle: car,
+            extended,
+            change,
+            transit_delay_saved,
+            cross_street_delay_added,
+        });
+    }
+
     /// For cars: The head car calls this when they're at the end of the lane WaitingToAdvance. If
     /// this returns true, then the head car MUST actually start this turn.
     /// For peds: Likewise -- only called when the ped is at the start of the turn. They must
@@ -414,6 +661,11 @@ impl IntersectionSimState {
 
         let shared_sidewalk_corner =
             map.get_t(req.turn).turn_type == TurnType::SharedSidewalkCorner;
+        if !shared_sidewalk_corner {
+            if let Some(signal_state) = self.state.get_mut(&turn.parent).unwrap().signal.as_mut() {
+                signal_state.detect(agent, turn, now, map);
+            }
+        }
 
         let readonly_pair = maybe_cars_and_queues.as_ref().map(|(_, c, q)| (*c, &**q));
         let started_uber_turn = |state: &Self, car: &Car| {
@@ -559,8 +811,12 @@ impl IntersectionSimState {
         // TODO For now, we're only interested in signals, and there's too much raw data to store
         // for stop signs too.
         let state = self.state.get_mut(&turn.parent).unwrap();
-        state.waiting.remove(&req).unwrap();
+        let (started_waiting, _) = state.waiting.remove(&req).unwrap();
         state.accepted.insert(req);
+        if let AgentID::Car(_) = agent {
+            self.recent_delays
+                .insert(turn.to_movement(map), (now, now - started_waiting));
+        }
         if self.break_turn_conflict_cycles {
             if let AgentID::Car(car) = agent {
                 self.blocked_by.retain(|(c, _)| *c != car);
@@ -585,7 +841,13 @@ impl IntersectionSimState {
                 state.signal.as_mut(),
             ) {
                 (Some(ts), Some(signal_state)) => {
-                    if signal_state.current_stage >= ts.stages.len() {
+                    if signal_state.current_plan >= ts.num_plans() {
+                        // A plan was deleted. It'll switch to the right plan when the current
+                        // stage ends.
+                        signal_state.current_plan = 0;
+                    }
+                    if signal_state.current_stage >= ts.get_stages(signal_state.current_plan).len()
+                    {
                         // Just jump back to the first one. Shrug.
                         signal_state.current_stage = 0;
                         println!(
@@ -701,11 +963,28 @@ impl IntersectionSimState {
         candidates
     }
 
-    pub fn current_stage_and_remaining_time(
+    /// How long would a vehicle arriving now expect to wait to make this movement? This is the
+    /// longest wait of any vehicle currently stuck there, or if the last vehicle to make the
+    /// movement did so recently, how long that one waited.
+    pub fn live_delay(&self, mvmnt: MovementID, now: Time, map: &Map) -> Duration {
+        let mut delay = match self.recent_delays.get(&mvmnt) {
+            Some((when, delay)) if now - *when <= RECENT_DELAY_WINDOW => *delay,
+            _ => Duration::ZERO,
+        };
+        for (req, (since, _)) in &self.state[&mvmnt.parent].waiting {
+            if matches!(req.agent, AgentID::Car(_)) && req.turn.to_movement(map) == mvmnt {
+                delay = delay.max(now - *since);
+            }
+        }
+        delay
+    }
+
+    /// Returns the current plan, the stage within that plan, and the time remaining in the stage.
+    pub fn current_plan_stage_and_remaining_time(
         &self,
         now: Time,
         i: IntersectionID,
-    ) -> (usize, Duration) {
+    ) -> (usize, usize, Duration) {
         let state = &self.state[&i].signal.as_ref().unwrap();
         if now > state.stage_ends_at {
             panic!(
@@ -713,7 +992,11 @@ impl IntersectionSimState {
                 now, i, state.stage_ends_at
             );
         }
-        (state.current_stage, state.stage_ends_at - now)
+        (
+            state.current_plan,
+            state.current_stage,
+            state.stage_ends_at - now,
+        )
     }
 
     pub fn describe_stats(&self) -> Vec<String> {
@@ -857,13 +1140,13 @@ impl IntersectionSimState {
 
         let state = &self.state[&req.turn.parent];
         let signal_state = state.signal.as_ref().unwrap();
-        let stage = &signal.stages[signal_state.current_stage];
+        let stage = &signal.get_stages(signal_state.current_plan)[signal_state.current_stage];
         let full_stage_duration = stage.stage_type.simple_duration();
         let remaining_stage_time = signal_state.stage_ends_at - now;
         let (our_time, _) = state.waiting[req];
 
         // Can't go at all this stage.
-        let our_priority = stage.get_priority_of_turn(req.turn, map.get_i(state.id));
+        let our_priority = signal_state.get_priority_of_turn(stage, req.turn, map.get_i(state.id));
         if our_priority == TurnPriority::Banned {
             return false;
         }
@@ -1034,33 +1317,175 @@ impl IntersectionSimState {
 
 impl SignalState {
     fn new(id: IntersectionID, now: Time, map: &Map, scheduler: &mut Scheduler) -> SignalState {
+        let signal = map.get_traffic_signal(id);
+        let plan = signal.plan_idx_at(now);
+        let stages = signal.get_stages(plan);
         let mut state = SignalState {
+            current_plan: plan,
             current_stage: 0,
             stage_ends_at: now,
             extensions_count: 0,
+            stage_started_at: now,
+            last_actuation: BTreeMap::new(),
+            ped_calls: BTreeSet::new(),
+            walk_granted: BTreeSet::new(),
+            transit_priority_used: false,
+            truncated_for_transit: None,
         };
 
-        let signal = map.get_traffic_signal(id);
         // What stage are we starting with?
-        let mut offset = (now - Time::START_OF_DAY) + signal.offset;
+        let mut offset = (now - Time::START_OF_DAY) + signal.get_offset(plan);
         loop {
-            let dt = signal.stages[state.current_stage]
-                .stage_type
-                .simple_duration();
+            let dt = stages[state.current_stage].stage_type.simple_duration();
             if offset >= dt {
                 offset -= dt;
                 state.current_stage += 1;
-                if state.current_stage == signal.stages.len() {
+                if state.current_stage == stages.len() {
                     state.current_stage = 0;
                 }
             } else {
                 state.stage_ends_at = now + dt - offset;
+                state.stage_started_at = now - offset;
                 break;
             }
         }
+        // Don't make pedestrians push the button for the stage the signal starts in
+        state.walk_granted = stages[state.current_stage]
+            .protected_movements
+            .iter()
+            .filter(|m| m.crosswalk)
+            .cloned()
+            .collect();
         scheduler.push(state.stage_ends_at, Command::UpdateIntersection(id));
         state
     }
+
+    /// Switch to a new stage, returning how long it initially lasts.
+    fn enter_stage(
+        &mut self,
+        idx: usize,
+        now: Time,
+        signal: &ControlTrafficSignal,
+        i: &Intersection,
+    ) -> Duration {
+        let stage = &signal.get_stages(self.current_plan)[idx];
+        self.current_stage = idx;
+        self.stage_started_at = now;
+        self.extensions_count = 0;
+        self.transit_priority_used = false;
+        self.truncated_for_transit = None;
+
+        // Answer pedestrian calls
+        self.walk_granted.clear();
+        for m in &stage.protected_movements {
+            if m.crosswalk {
+                let called = self.ped_calls.remove(m);
+                if called || !matches!(stage.stage_type, StageType::Actuated(_, _, _)) {
+                    self.walk_granted.insert(*m);
+                }
+            }
+        }
+
+        let mut duration = stage.stage_type.simple_duration();
+        if let StageType::Actuated(_, _, _) = stage.stage_type {
+            if !self.walk_granted.is_empty() {
+                // Give pedestrians enough time to cross
+                duration = duration.max(stage.get_min_crossing_time(i));
+            }
+        }
+        duration
+    }
+
+    /// An agent has started to wait for a turn; act like a detector or pedestrian push-button.
+    fn detect(&mut self, agent: AgentID, turn: TurnID, now: Time, map: &Map) {
+        let movement = map.get_i(turn.parent).turn_to_movement(turn).0;
+        if let AgentID::Pedestrian(_) = agent {
+            if movement.crosswalk {
+                self.ped_calls.insert(movement);
+            }
+        } else {
+            self.last_actuation.insert(movement, now);
+        }
+    }
+
+    // Like Stage::get_priority_of_turn, but crosswalks in actuated stages need a pedestrian call
+    fn get_priority_of_turn(&self, stage: &Stage, turn: TurnID, i: &Intersection) -> TurnPriority {
+        let priority = stage.get_priority_of_turn(turn, i);
+        if let StageType::Actuated(_, _, _) = stage.stage_type {
+            let movement = i.turn_to_movement(turn).0;
+            if movement.crosswalk && !self.walk_granted.contains(&movement) {
+                return TurnPriority::Banned;
+            }
+        }
+        priority
+    }
+
+    // Is anybody waiting for a movement that this stage allows?
+    fn has_call(
+        &self,
+        stage: &Stage,
+        waiting: &BTreeMap<Request, (Time, bool)>,
+        i: &Intersection,
+    ) -> bool {
+        stage
+            .protected_movements
+            .iter()
+            .any(|m| self.ped_calls.contains(m))
+            || waiting.keys().any(|req| {
+                !matches!(req.agent, AgentID::Pedestrian(_))
+                    && stage.get_priority_of_turn(req.turn, i) != TurnPriority::Banned
+            })
+    }
+
+    /// Pick the stage in the current plan with the most pressure. The current stage is only a
+    /// candidate if `allow_current` is true; ties go to the current stage, then the earliest one.
+    fn max_pressure_stage(
+        &self,
+        signal: &ControlTrafficSignal,
+        i: &Intersection,
+        waiting: &BTreeMap<Request, (Time, bool)>,
+        turn_pressure: &BTreeMap<TurnID, isize>,
+        allow_current: bool,
+    ) -> usize {
+        let mut peds_waiting: BTreeMap<MovementID, isize> = BTreeMap::new();
+        for req in waiting.keys() {
+            if let AgentID::Pedestrian(_) = req.agent {
+                *peds_waiting
+                    .entry(i.turn_to_movement(req.turn).0)
+                    .or_insert(0) += 1;
+            }
+        }
+
+        let stages = signal.get_stages(self.current_plan);
+        let mut best: Option<(isize, usize)> = None;
+        for (idx, stage) in stages.iter().enumerate() {
+            if idx == self.current_stage && !allow_current {
+                continue;
+            }
+            let mut pressure = 0;
+            for m in &stage.protected_movements {
+                if m.crosswalk {
+                    pressure += peds_waiting.get(m).cloned().unwrap_or(0);
+                } else {
+                    for t in &i.movements[m].members {
+                        pressure += turn_pressure.get(t).cloned().unwrap_or(0);
+                    }
+                }
+            }
+            let better = match best {
+                None => true,
+                Some((best_pressure, _)) => {
+                    pressure > best_pressure
+                        || (pressure == best_pressure && idx == self.current_stage)
+                }
+            };
+            if better {
+                best = Some((pressure, idx));
+            }
+        }
+        // Only possible if there's just one stage and it's maxed out
+        best.map(|(_, idx)| idx).unwrap_or(self.current_stage)
+    }
 }
 
 fn allow_block_the_box(i: &Intersection) -> bool {
@@ -1102,3 +1527,220 @@ fn allow_block_the_box(i: &Intersection) -> bool {
     }
     false
 }
+
+#[cfg(test)]
+mod tests {
+    use map_model::toy::toy_map;
+    use map_model::{IntersectionType, SignalPlan, TransitSignalPriority};
+
+    use super::*;
+    use crate::PedestrianID;
+
+    #[test]
+    fn transit_priority_skips_to_green() {
+        let mut map = four_way_signal();
+        let id = map
+            .all_intersections()
+            .iter()
+            .find(|i| i.is_traffic_signal())
+            .unwrap()
+            .id;
+        // Actuated stages would normally skip the stage the bus needs, since nobody's waiting
+        // for it yet
+        let mut signal = map.get_traffic_signal(id).clone();
+        for stage in &mut signal.stages {
+            stage.stage_type = StageType::Actuated(
+                Duration::seconds(30.0),
+                Duration::seconds(3.0),
+                Duration::seconds(60.0),
+            );
+        }
+        signal.transit_priority = Some(TransitSignalPriority::default_limits());
+        signal.validate(map.get_i(id)).unwrap();
+        map.incremental_edit_traffic_signal(signal.clone());
+
+        let opts = SimOptions::new("test_transit_priority");
+        let mut scheduler = Scheduler::new();
+        let mut intersections = IntersectionSimState::new(&map, &mut scheduler, &opts);
+        let driving = DrivingSimState::new(&map, &opts);
+        let current_stage = |intersections: &IntersectionSimState| {
+            intersections.state[&id]
+                .signal
+                .as_ref()
+                .unwrap()
+                .current_stage
+        };
+        let start_stage = current_stage(&intersections);
+        // A bus wants to make a turn that's red right now
+        let turn = map
+            .get_i(id)
+            .turns
+            .iter()
+            .find(|t| {
+                t.turn_type != TurnType::Crosswalk
+                    && signal.stages[start_stage].get_priority_of_turn(t.id, map.get_i(id))
+                        == TurnPriority::Banned
+            })
+            .unwrap()
+            .id;
+        let bus_stage = signal
+            .stages
+            .iter()
+            .position(|stage| {
+                stage.get_priority_of_turn(turn, map.get_i(id)) != TurnPriority::Banned
+            })
+            .unwrap();
+        let bus = CarID {
+            id: 0,
+            vehicle_type: crate::VehicleType::Bus,
+        };
+
+        let now = Time::START_OF_DAY + Duration::seconds(15.0);
+        intersections.transit_check_in(
+            now,
+            bus,
+            turn,
+            now + Duration::seconds(10.0),
+            &map,
+            &mut scheduler,
+        );
+        let ends_at = intersections.state[&id]
+            .signal
+            .as_ref()
+            .unwrap()
+            .stage_ends_at;
+        assert!(ends_at < Time::START_OF_DAY + Duration::seconds(30.0));
+        assert!(matches!(
+            intersections.events.last(),
+            Some(Event::TransitSignalPriority {
+                extended: false,
+                ..
+            })
+        ));
+
+        // Like the main loop, pop the command before handling it
+        assert_eq!(scheduler.peek_next_time(), Some(ends_at));
+        assert!(matches!(
+            scheduler.get_next(),
+            Some(Command::UpdateIntersection(_))
+        ));
+        intersections.update_intersection(ends_at, id, &map, &mut scheduler, &driving);
+        assert_eq!(current_stage(&intersections), bus_stage);
+    }
+
+    #[test]
+    fn ped_call_survives_plan_change() {
+        let mut map = four_way_signal();
+        let id = map
+            .all_intersections()
+            .iter()
+            .find(|i| i.is_traffic_signal())
+            .unwrap()
+            .id;
+        // Crosswalks in actuated stages only get a walk signal after somebody pushes the button.
+        // A second plan with the same stages takes over after two minutes.
+        let mut signal = map.get_traffic_signal(id).clone();
+        for stage in &mut signal.stages {
+            stage.stage_type = StageType::Actuated(
+                Duration::seconds(30.0),
+                Duration::seconds(3.0),
+                Duration::seconds(60.0),
+            );
+        }
+        signal.later_plans = vec![SignalPlan {
+            start_time: Duration::minutes(2),
+            stages: signal.stages.clone(),
+            offset: Duration::ZERO,
+        }];
+        signal.validate(map.get_i(id)).unwrap();
+        map.incremental_edit_traffic_signal(signal.clone());
+
+        let opts = SimOptions::new("test_ped_call_survives_plan_change");
+        let mut scheduler = Scheduler::new();
+        let mut intersections = IntersectionSimState::new(&map, &mut scheduler, &opts);
+        let driving = DrivingSimState::new(&map, &opts);
+
+        // Pick a crosswalk that doesn't get a walk signal for free in the stage the new plan
+        // starts with
+        let switch = Time::START_OF_DAY + Duration::minutes(2);
+        let start_stage =
+            &signal.stages[SignalState::new(id, switch, &map, &mut Scheduler::new()).current_stage];
+        let crosswalk = signal
+            .stages
+            .iter()
+            .flat_map(|stage| stage.protected_movements.iter())
+            .find(|m| m.crosswalk && !start_stage.protected_movements.contains(m))
+            .cloned()
+            .unwrap();
+        let turn = map
+            .get_i(id)
+            .turns
+            .iter()
+            .find(|t| {
+                t.turn_type == TurnType::Crosswalk
+                    && map.get_i(id).turn_to_movement(t.id).0 == crosswalk
+            })
+            .unwrap()
+            .id;
+
+        // Run the signal until the last stage before the new plan, then push the button
+        let step = |intersections: &mut IntersectionSimState, scheduler: &mut Scheduler| {
+            let now = scheduler.peek_next_time().unwrap();
+            assert!(matches!(
+                scheduler.get_next(),
+                Some(Command::UpdateIntersection(_))
+            ));
+            intersections.update_intersection(now, id, &map, scheduler, &driving);
+            now
+        };
+        while scheduler.peek_next_time().unwrap() < switch {
+            step(&mut intersections, &mut scheduler);
+        }
+        let signal_state =
+            |intersections: &IntersectionSimState| intersections.state[&id].signal.clone().unwrap();
+        assert_eq!(signal_state(&intersections).current_plan, 0);
+        intersections
+            .state
+            .get_mut(&id)
+            .unwrap()
+            .signal
+            .as_mut()
+            .unwrap()
+            .detect(
+                AgentID::Pedestrian(PedestrianID(0)),
+                turn,
+                switch - Duration::seconds(1.0),
+                &map,
+            );
+
+        // The new plan takes over and eventually lets them walk
+        loop {
+            let now = step(&mut intersections, &mut scheduler);
+            let state = signal_state(&intersections);
+            if state.walk_granted.contains(&crosswalk) {
+                assert_eq!(state.current_plan, 1);
+                break;
+            }
+            assert!(now < switch + Duration::minutes(5), "the call was dropped");
+        }
+    }
+
+    /// Two two-way roads crossing at a traffic signal
+    fn four_way_signal() -> Map {
+        toy_map(
+            &[
+                ("center", 200.0, 200.0, IntersectionType::TrafficSignal),
+                ("n", 200.0, 0.0, IntersectionType::Border),
+                ("e", 400.0, 200.0, IntersectionType::Border),
+                ("s", 200.0, 400.0, IntersectionType::Border),
+                ("w", 0.0, 200.0, IntersectionType::Border),
+            ],
+            &[
+                ("center", "n"),
+                ("center", "e"),
+                ("center", "s"),
+                ("center", "w"),
+            ],
+        )
+    }
+}
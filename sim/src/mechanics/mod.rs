@@ -1,6 +1,6 @@
 pub(crate) use self::driving::DrivingSimState;
 pub(crate) use self::intersection::IntersectionSimState;
-pub(crate) use self::parking::{ParkingSim, ParkingSimState};
+pub(crate) use self::parking::{choose_spot, spot_policy, ParkingSim, ParkingSimState};
 pub(crate) use self::queue::Queue;
 pub(crate) use self::walking::WalkingSimState;
 
@@ -9,10 +9,10 @@ use abstutil::{
     deserialize_btreemap, deserialize_multimap, serialize_btreemap, serialize_multimap, MultiMap,
     Timer,
 };
-use geom::{Distance, PolyLine, Pt2D};
+use geom::{Distance, Duration, PolyLine, Pt2D};
 use map_model::{
-    BuildingID, Lane, LaneID, LaneType, Map, OffstreetParking, ParkingLotID, PathConstraints,
-    PathStep, Position, Traversable, TurnID,
+    BuildingID, Lane, LaneID, LaneType, Map, OffstreetParking, ParkingLotID, ParkingPolicy,
+    PathConstraints, PathStep, Position, Traversable, TurnID,
 };
 
 use crate::{CarID, CarStatus, DrawCarInput, Event, ParkedCar, ParkingSpot, PersonID, Vehicle};
@@ -64,12 +64,15 @@ pub trait ParkingSim {
     /// them there, producing some nice, realistic churn if there's too much contention. But
     /// the implementation has some internal jitter between different vehicles, to discourage
     /// everybody near one spot from all competing for it.
+    /// Spots that don't allow staying for `planned_stay` are skipped, and cheaper spots are
+    /// preferred; see `choose_spot`.
     /// Note the first PathStep is the turn after start, NOT PathStep::Lane(start).
     fn path_to_free_parking_spot(
         &self,
         start: LaneID,
         vehicle: &Vehicle,
         target: BuildingID,
+        planned_stay: Option<Duration>,
         map: &Map,
     ) -> Option<(Vec<PathStep>, ParkingSpot, Position)>;
     fn collect_events(&mut self) -> Vec<Event>;
@@ -77,6 +80,44 @@ pub trait ParkingSim {
     fn bldg_to_parked_cars(&self, b: BuildingID) -> Vec<CarID>;
 }
 
+/// Drivers weigh walking farther against paying more. This is how much farther somebody would
+/// walk to save a dollar.
+const WALK_DIST_PER_DOLLAR: Distance = Distance::const_meters(200.0);
+
+/// Onstreet parking and lots may have a price and time limit. Parking at a building is always
+/// free.
+pub(crate) fn spot_policy(spot: ParkingSpot, map: &Map) -> ParkingPolicy {
+    match spot {
+        ParkingSpot::Onstreet(l, _) => map.get_parent(l).parking_policy.clone(),
+        ParkingSpot::Offstreet(_, _) => ParkingPolicy::default(),
+        ParkingSpot::Lot(pl, _) => map.get_pl(pl).parking_policy.clone(),
+    }
+}
+
+/// Picks the best of some candidate spots, skipping any that don't allow staying for
+/// `planned_stay`. The cost of each spot is `dist` plus the price of staying there, expressed as
+/// walking distance. If the length of the stay isn't known, assume an hour.
+pub(crate) fn choose_spot<F: Fn(&Position) -> Distance>(
+    candidates: Vec<(ParkingSpot, Position)>,
+    planned_stay: Option<Duration>,
+    map: &Map,
+    dist: F,
+) -> Option<(ParkingSpot, Position)> {
+    let stay = planned_stay.unwrap_or_else(|| Duration::hours(1));
+    candidates
+        .into_iter()
+        .filter_map(|(spot, pos)| {
+            let policy = spot_policy(spot, map);
+            if planned_stay.is_some() && !policy.allows_stay(stay) {
+                return None;
+            }
+            let dollars = (policy.cost(stay) as f64) / 100.0;
+            Some((dist(&pos) + dollars * WALK_DIST_PER_DOLLAR, spot, pos))
+        })
+        .min_by_key(|(cost, _, _)| *cost)
+        .map(|(_, spot, pos)| (spot, pos))
+}
+
 #[enum_dispatch]
 #[derive(Serialize, Deserialize, Clone)]
 pub enum ParkingSimState {
@@ -317,8 +358,11 @@ impl ParkingSim for NormalParkingSimState {
         if self.occupants.remove(&p.spot).is_none() {
             panic!("remove_parked_car {:?} missing from occupants", p);
         }
-        self.events
-            .push(Event::CarLeftParkingSpot(p.vehicle.id, p.spot));
+        self.events.push(Event::CarLeftParkingSpot(
+            p.vehicle.id,
+            p.spot,
+            p.parked_since,
+        ));
     }
 
     fn add_parked_car(&mut self, p: ParkedCar) {
@@ -563,6 +607,7 @@ impl ParkingSim for NormalParkingSimState {
         start: LaneID,
         vehicle: &Vehicle,
         target: BuildingID,
+        planned_stay: Option<Duration>,
         map: &Map,
     ) -> Option<(Vec<PathStep>, ParkingSpot, Position)> {
         let mut backrefs: HashMap<LaneID, TurnID> = HashMap::new();
@@ -584,13 +629,14 @@ impl ParkingSim for NormalParkingSimState {
             // If the current lane has a spot open, we wouldn't be asking. This can happen if a spot
             // opens up on the 'start' lane, but behind the car.
             if current != start {
-                // Pick the closest to the start of the lane, since that's closest to where we came
-                // from
-                if let Some((spot, pos)) = self
-                    .get_all_free_spots(Position::start(current), vehicle, target, map)
-                    .into_iter()
-                    .min_by_key(|(_, pos)| pos.dist_along())
-                {
+                // Prefer spots close to the start of the lane, since that's closest to where we
+                // came from
+                if let Some((spot, pos)) = choose_spot(
+                    self.get_all_free_spots(Position::start(current), vehicle, target, map),
+                    planned_stay,
+                    map,
+                    |pos| pos.dist_along(),
+                ) {
                     let mut steps = vec![PathStep::Lane(current)];
                     let mut current = current;
                     loop {
@@ -633,14 +679,10 @@ impl ParkingSim for NormalParkingSimState {
     }
 
     fn all_parked_car_positions(&self, map: &Map) -> Vec<(Position, PersonID)> {
+        // Ride-hailing vehicles don't have an owner
         self.parked_cars
             .values()
-            .map(|p| {
-                (
-                    self.spot_to_sidewalk_pos(p.spot, map),
-                    p.vehicle.owner.unwrap(),
-                )
-            })
+            .filter_map(|p| Some((self.spot_to_sidewalk_pos(p.spot, map), p.vehicle.owner?)))
             .collect()
     }
 
@@ -871,8 +913,11 @@ impl ParkingSim for InfiniteParkingSimState {
         self.occupants
             .remove(&p.spot)
             .expect("remove_parked_car missing from occupants");
-        self.events
-            .push(Event::CarLeftParkingSpot(p.vehicle.id, p.spot));
+        self.events.push(Event::CarLeftParkingSpot(
+            p.vehicle.id,
+            p.spot,
+            p.parked_since,
+        ));
 
         if let ParkingSpot::Offstreet(b, _) = p.spot {
             *self.num_occupants_per_offstreet.get_mut(&b).unwrap() -= 1;
@@ -989,6 +1034,7 @@ impl ParkingSim for InfiniteParkingSimState {
         start: LaneID,
         vehicle: &Vehicle,
         target: BuildingID,
+        planned_stay: Option<Duration>,
         map: &Map,
     ) -> Option<(Vec<PathStep>, ParkingSpot, Position)> {
         // TODO This impl is copied from NormalParkingSimState. Instead, we already know the
@@ -1005,13 +1051,14 @@ impl ParkingSim for InfiniteParkingSimState {
             // If the current lane has a spot open, we wouldn't be asking. This can happen if a spot
             // opens up on the 'start' lane, but behind the car.
             if current != start {
-                // Pick the closest to the start of the lane, since that's closest to where we came
-                // from
-                if let Some((spot, pos)) = self
-                    .get_all_free_spots(Position::start(current), vehicle, target, map)
-                    .into_iter()
-                    .min_by_key(|(_, pos)| pos.dist_along())
-                {
+                // Prefer spots close to the start of the lane, since that's closest to where we
+                // came from
+                if let Some((spot, pos)) = choose_spot(
+                    self.get_all_free_spots(Position::start(current), vehicle, target, map),
+                    planned_stay,
+                    map,
+                    |pos| pos.dist_along(),
+                ) {
                     let mut steps = vec![PathStep::Lane(current)];
                     let mut current = current;
                     loop {
@@ -1046,14 +1093,10 @@ impl ParkingSim for InfiniteParkingSimState {
     }
 
     fn all_parked_car_positions(&self, map: &Map) -> Vec<(Position, PersonID)> {
+        // Ride-hailing vehicles don't have an owner
         self.parked_cars
             .values()
-            .map(|p| {
-                (
-                    self.spot_to_sidewalk_pos(p.spot, map),
-                    p.vehicle.owner.unwrap(),
-                )
-            })
+            .filter_map(|p| Some((self.spot_to_sidewalk_pos(p.spot, map), p.vehicle.owner?)))
             .collect()
     }
 
@@ -1070,3 +1113,100 @@ impl ParkingSim for InfiniteParkingSimState {
         cars
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use abstutil::Timer;
+    use geom::{Duration, Pt2D};
+    use map_model::toy::{build, node, road, toy_raw_map};
+    use map_model::{
+        osm, Direction, IntersectionType, LaneType, Map, ParkingPolicy, Position, RoadID,
+    };
+
+    use super::choose_spot;
+    use crate::ParkingSpot;
+
+    fn set_policy(map: &mut Map, r: RoadID, policy: ParkingPolicy) {
+        let mut edits = map.get_edits().clone();
+        edits.commands.push(map.edit_road_cmd(r, |new| {
+            new.parking_policy = policy.clone();
+        }));
+        map.must_apply_edits(edits, &mut Timer::throwaway());
+    }
+
+    #[test]
+    fn price_is_weighed_against_walking() {
+        // Two stretches of street with parking on both sides, meeting at a side street
+        let mut raw = toy_raw_map(
+            &[
+                ("w", 0.0, 0.0, IntersectionType::Border),
+                ("m", 200.0, 0.0, IntersectionType::StopSign),
+                ("e", 400.0, 0.0, IntersectionType::Border),
+                ("s", 200.0, 100.0, IntersectionType::Border),
+            ],
+            &[("w", "m"), ("m", "e"), ("m", "s")],
+        );
+        for (id, raw_road) in &mut raw.roads {
+            if id.i2 != node("s") {
+                raw_road.osm_tags.insert(osm::PARKING_BOTH, "parallel");
+            }
+        }
+        let mut map = build(raw);
+        let near = road(&map, "w", "m").id;
+        let far = road(&map, "m", "e").id;
+
+        // One eastbound spot three-quarters of the way along each stretch. The destination is by
+        // the near one, and the far one is about 200m away.
+        let candidates = |map: &Map| {
+            [near, far]
+                .into_iter()
+                .map(|r| {
+                    let lane = map
+                        .get_r(r)
+                        .lanes
+                        .iter()
+                        .find(|l| l.lane_type == LaneType::Parking && l.dir == Direction::Fwd)
+                        .unwrap();
+                    let pos = Position::new(lane.id, 0.75 * lane.length());
+                    (ParkingSpot::Onstreet(lane.id, 0), pos)
+                })
+                .collect::<Vec<_>>()
+        };
+        let goal = Pt2D::new(150.0, 0.0);
+        let choose = |map: &Map, stay| {
+            let (spot, _) =
+                choose_spot(candidates(map), stay, map, |pos| pos.pt(map).dist_to(goal)).unwrap();
+            match spot {
+                ParkingSpot::Onstreet(l, _) => l.road,
+                _ => unreachable!(),
+            }
+        };
+
+        // Everything is free at first
+        assert_eq!(choose(&map, Some(Duration::hours(2))), near);
+
+        // $3 an hour is worth walking 200m to avoid for a long stay, but not a short one
+        set_policy(
+            &mut map,
+            near,
+            ParkingPolicy {
+                cents_per_hour: 300,
+                max_duration: None,
+            },
+        );
+        assert_eq!(choose(&map, Some(Duration::hours(2))), far);
+        assert_eq!(choose(&map, Some(Duration::minutes(10))), near);
+
+        // A free spot with a time limit is only skipped if the driver knows they'll overstay
+        set_policy(
+            &mut map,
+            near,
+            ParkingPolicy {
+                cents_per_hour: 0,
+                max_duration: Some(Duration::hours(1)),
+            },
+        );
+        assert_eq!(choose(&map, Some(Duration::hours(2))), far);
+        assert_eq!(choose(&map, None), near);
+    }
+}
@@ -129,8 +129,10 @@ impl Queue {
         // starting the loop, handling the laggy head case.
         let mut previous: Option<QueueEntry> = None;
         for queued in self.members.iter().cloned() {
+            // Some drivers keep more space than others
+            let gap = following_dist(&queued, cars);
             let bound = match previous {
-                Some(entry) => entry.back - FOLLOWING_DISTANCE,
+                Some(entry) => entry.back - gap,
                 None => match self.laggy_head {
                     Some(id) => {
                         // The simple but broken version:
@@ -180,12 +182,10 @@ impl Queue {
                             }
                             // They might actually be out of the way, but laggy_head hasn't been
                             // updated yet.
-                            if dist_away_from_this_queue
-                                < leader.vehicle.length + FOLLOWING_DISTANCE
-                            {
+                            if dist_away_from_this_queue < leader.vehicle.length + gap {
                                 self.geom_len
                                     - (cars[&id].vehicle.length - dist_away_from_this_queue)
-                                    - FOLLOWING_DISTANCE
+                                    - gap
                             } else {
                                 self.geom_len
                             }
@@ -298,6 +298,7 @@ impl Queue {
         &self,
         start_dist: Distance,
         vehicle_len: Distance,
+        gap: Distance,
         now: Time,
         cars: &FixedMap<CarID, Car>,
         queues: &HashMap<Traversable, Queue>,
@@ -325,18 +326,21 @@ impl Queue {
                 // TODO We can be more precise! We already call get_car_positions, and that
                 // calculates exactly where the laggy head is. We just need to plumb that bound
                 // back here.
-                if self.geom_len - cars[&c].vehicle.length - FOLLOWING_DISTANCE < start_dist {
+                if self.geom_len - cars[&c].vehicle.length - gap < start_dist {
                     return None;
                 }
             }
         }
 
         // Are we too close to the leader?
-        if idx != 0 && dists[idx - 1].back - FOLLOWING_DISTANCE < start_dist {
+        if idx != 0 && dists[idx - 1].back - gap < start_dist {
             return None;
         }
         // Or the follower?
-        if idx != dists.len() && start_dist - vehicle_len - FOLLOWING_DISTANCE < dists[idx].front {
+        if idx != dists.len()
+            && start_dist - vehicle_len - following_dist(&dists[idx].member, cars)
+                < dists[idx].front
+        {
             return None;
         }
 
@@ -347,7 +351,7 @@ impl Queue {
     /// -- the same index and immediately after passing that query.
     pub fn insert_car_at_idx(&mut self, idx: usize, car: &Car) {
         self.members.insert(idx, Queued::Vehicle(car.vehicle.id));
-        self.reserved_length += car.vehicle.length + FOLLOWING_DISTANCE;
+        self.reserved_length += car.vehicle.length + car.vehicle.following_dist();
     }
 
     /// Record that a car has entered a queue at the end. It's assumed that try_to_reserve_entry
@@ -385,7 +389,7 @@ impl Queue {
         // Reserve the normal amount of space; the next car trying to enter will get rejected.
         // Also allow this don't-block-the-box prevention to be disabled.
         if self.room_for_car(car) || force_entry {
-            self.reserved_length += car.vehicle.length + FOLLOWING_DISTANCE;
+            self.reserved_length += car.vehicle.length + car.vehicle.following_dist();
             return true;
         }
         false
@@ -400,12 +404,13 @@ impl Queue {
     /// Can a car start a turn for this queue?
     pub fn room_for_car(&self, car: &Car) -> bool {
         self.reserved_length == Distance::ZERO
-            || self.reserved_length + car.vehicle.length + FOLLOWING_DISTANCE < self.geom_len
+            || self.reserved_length + car.vehicle.length + car.vehicle.following_dist()
+                < self.geom_len
     }
 
     /// Once a car has fully exited a queue, free up the space it was reserving.
     pub fn free_reserved_space(&mut self, car: &Car) {
-        self.reserved_length -= car.vehicle.length + FOLLOWING_DISTANCE;
+        self.reserved_length -= car.vehicle.length + car.vehicle.following_dist();
         assert!(
             self.reserved_length >= Distance::ZERO,
             "invalid reserved length: {:?}, car: {:?}",
@@ -497,7 +502,9 @@ impl Queue {
             idx,
             Queued::DynamicBlockage {
                 cause: car.vehicle.id,
-                vehicle_len: car.vehicle.length,
+                // Include any extra space the driver keeps, so clearing the blockage frees
+                // exactly what the car reserved
+                vehicle_len: car.vehicle.length + car.vehicle.behavior.extra_headway,
             },
         );
         // We don't need to touch reserved_length -- it's still vehicle_len + FOLLOWING_DISTANCE
@@ -526,7 +533,14 @@ impl Queue {
         cars: &FixedMap<CarID, Car>,
         queues: &HashMap<Traversable, Queue>,
     ) -> Option<usize> {
-        self.get_idx_to_insert_car(pos.dist_along(), vehicle_len, now, cars, queues)
+        self.get_idx_to_insert_car(
+            pos.dist_along(),
+            vehicle_len,
+            FOLLOWING_DISTANCE,
+            now,
+            cars,
+            queues,
+        )
     }
 
     /// Get all cars in the queue, not including the laggy head or blockages.
@@ -556,6 +570,14 @@ impl Queue {
     }
 }
 
+/// How much space this member of a queue keeps behind whatever's in front of it
+fn following_dist(queued: &Queued, cars: &FixedMap<CarID, Car>) -> Distance {
+    match queued {
+        Queued::Vehicle(id) => cars[id].vehicle.following_dist(),
+        Queued::StaticBlockage { .. } | Queued::DynamicBlockage { .. } => FOLLOWING_DISTANCE,
+    }
+}
+
 fn validate_positions(
     dists: &[QueueEntry],
     cars: &FixedMap<CarID, Car>,
@@ -78,6 +78,9 @@ impl TrafficRecorder {
             map_name: map.get_name().clone(),
             people,
             only_seed_buses: None,
+            ride_hail_fleet: 0,
+            pct_electric_cars: 0,
+            driver_behavior: None,
         }
         .save();
     }
@@ -0,0 +1,200 @@
+//! A fleet of ride-hailing vehicles (or taxis) that don't belong to any person. A rider waits
+//! inside their building until the dispatcher sends the nearest idle vehicle to pick them up. After
+//! dropping somebody off, a vehicle heads straight to the next rider still waiting, or otherwise
+//! looks for a place to park and wait for the next request.
+//!
+//! This just tracks what each vehicle is doing; TripManager drives the state changes, since they
+//! always involve some rider's trip.
+
+use std::collections::{BTreeMap, VecDeque};
+
+use serde::{Deserialize, Serialize};
+
+use abstutil::{deserialize_btreemap, serialize_btreemap};
+use geom::Time;
+use map_model::BuildingID;
+
+use crate::{CarID, TripID};
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct RideHailFleet {
+    #[serde(
+        serialize_with = "serialize_btreemap",
+        deserialize_with = "deserialize_btreemap"
+    )]
+    vehicles: BTreeMap<CarID, FleetVehicleState>,
+    /// Riders who haven't been assigned a vehicle yet, in the order they asked for one
+    waiting: VecDeque<(TripID, Time)>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub(crate) enum FleetVehicleState {
+    /// Parked somewhere, waiting to be dispatched. If live map edits evict the vehicle from its
+    /// spot, it just never gets dispatched again.
+    Idle,
+    /// Driving empty to pick up a rider, who asked for a ride at some time
+    PickingUp(TripID, Time),
+    /// Driving a rider to their destination
+    DroppingOff(TripID),
+    /// Driving empty, about to look for parking near a building
+    Returning(BuildingID),
+}
+
+impl RideHailFleet {
+    pub fn new() -> RideHailFleet {
+        RideHailFleet {
+            vehicles: BTreeMap::new(),
+            waiting: VecDeque::new(),
+        }
+    }
+
+    pub fn add_vehicle(&mut self, car: CarID) {
+        self.vehicles.insert(car, FleetVehicleState::Idle);
+    }
+
+    /// A vehicle that couldn't find parking anywhere leaves the fleet for good.
+    pub fn remove_vehicle(&mut self, car: CarID) {
+        self.vehicles.remove(&car).unwrap();
+    }
+
+    pub fn has_vehicle(&self, car: CarID) -> bool {
+        self.vehicles.contains_key(&car)
+    }
+
+    pub fn len(&self) -> usize {
+        self.vehicles.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.vehicles.is_empty()
+    }
+
+    pub fn get_state(&self, car: CarID) -> FleetVehicleState {
+        self.vehicles[&car]
+    }
+
+    pub fn set_state(&mut self, car: CarID, state: FleetVehicleState) {
+        *self.vehicles.get_mut(&car).unwrap() = state;
+    }
+
+    pub fn idle_vehicles(&self) -> impl Iterator<Item = CarID> + '_ {
+        self.vehicles
+            .iter()
+            .filter(|(_, state)| **state == FleetVehicleState::Idle)
+            .map(|(car, _)| *car)
+    }
+
+    pub fn request_ride(&mut self, rider: TripID, now: Time) {
+        self.waiting.push_back((rider, now));
+    }
+
+    /// The rider who's been waiting the longest for a vehicle
+    pub fn next_waiting(&mut self) -> Option<(TripID, Time)> {
+        self.waiting.pop_front()
+    }
+
+    /// Put a rider back at the front of the line, because there's no vehicle for them yet.
+    pub fn still_waiting(&mut self, rider: TripID, requested: Time) {
+        self.waiting.push_front((rider, requested));
+    }
+
+    pub fn cancel_request(&mut self, rider: TripID) {
+        self.waiting.retain(|(t, _)| *t != rider);
+    }
+
+    /// How many vehicles are busy (anything besides parked and waiting), and how many are there in
+    /// total?
+    pub fn utilization(&self) -> (usize, usize) {
+        let busy = self
+            .vehicles
+            .values()
+            .filter(|state| **state != FleetVehicleState::Idle)
+            .count();
+        (busy, self.vehicles.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use geom::Duration;
+    use map_model::toy::{add_building, build, building};
+
+    use super::*;
+    use crate::testing;
+    use crate::{Event, IndividTrip, TripEndpoint, TripMode, TripPurpose};
+
+    #[test]
+    fn one_vehicle_serves_riders_in_order() {
+        let mut raw = testing::two_blocks();
+        for (name, x, y) in [
+            ("a", 100.0, 20.0),
+            ("b", 380.0, 200.0),
+            ("c", 300.0, 380.0),
+            ("d", 20.0, 200.0),
+        ] {
+            add_building(&mut raw, name, x, y, 2);
+        }
+        let map = build(raw);
+        let trip = |depart, from, to| {
+            IndividTrip::new(
+                Time::START_OF_DAY + depart,
+                TripPurpose::Shopping,
+                TripEndpoint::Bldg(building(&map, from)),
+                TripEndpoint::Bldg(building(&map, to)),
+                TripMode::RideHail,
+            )
+        };
+        let mut scenario = testing::scenario(
+            &map,
+            vec![
+                vec![trip(Duration::hours(7), "a", "c")],
+                vec![trip(Duration::hours(7) + Duration::seconds(10.0), "b", "d")],
+            ],
+        );
+        scenario.ride_hail_fleet = 1;
+        let (sim, events) = testing::run(&map, &scenario, Duration::hours(8));
+        assert_eq!(sim.num_trips(), (2, 0));
+
+        // The only vehicle picks up the first rider, drops them off, then heads straight for the
+        // second
+        let stops: Vec<(bool, TripID, CarID)> = events
+            .iter()
+            .filter_map(|ev| match ev {
+                Event::RideHailPickUp { vehicle, rider, .. } => Some((true, *rider, *vehicle)),
+                Event::RideHailDropOff { vehicle, rider, .. } => Some((false, *rider, *vehicle)),
+                _ => None,
+            })
+            .collect();
+        let vehicle = stops[0].2;
+        assert_eq!(
+            stops,
+            vec![
+                (true, TripID(0), vehicle),
+                (false, TripID(0), vehicle),
+                (true, TripID(1), vehicle),
+                (false, TripID(1), vehicle),
+            ]
+        );
+        let waited: Vec<Duration> = events
+            .iter()
+            .filter_map(|ev| match ev {
+                Event::RideHailPickUp { waited, .. } => Some(*waited),
+                _ => None,
+            })
+            .collect();
+        assert!(waited[1] > waited[0]);
+        let with_rider = events
+            .iter()
+            .filter(|ev| {
+                matches!(
+                    ev,
+                    Event::RideHailDistance {
+                        with_rider: true,
+                        ..
+                    }
+                )
+            })
+            .count();
+        assert_eq!(with_rider, 2);
+    }
+}
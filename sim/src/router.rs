@@ -1,20 +1,22 @@
 //! For vehicles only, not pedestrians. Follows a Path from map_model, but can opportunistically
-//! lane-change to avoid a slow lane, can can handle re-planning to look for available parking.
+//! lane-change to avoid a slow lane, can can handle re-planning to look for available parking or
+//! to avoid congestion.
 
 use std::collections::HashMap;
 
 use serde::{Deserialize, Serialize};
 
-use geom::Distance;
+use geom::{Distance, Duration, Time};
+use map_model::connectivity::{vehicle_cost, zone_cost};
 use map_model::{
-    BuildingID, IntersectionID, LaneID, Map, Path, PathConstraints, PathRequest, PathStep,
-    Position, Traversable, Turn, TurnID,
+    BuildingID, IntersectionID, LaneID, Map, MovementID, Path, PathConstraints, PathRequest,
+    PathStep, Position, Traversable, Turn, TurnID,
 };
 
 use crate::mechanics::Queue;
 use crate::{
-    AlertLocation, CarID, Event, ParkingSim, ParkingSimState, ParkingSpot, PersonID, SidewalkSpot,
-    TripID, TripPhaseType, Vehicle, VehicleType,
+    choose_spot, AlertLocation, CarID, Event, IntersectionSimState, ParkingSim, ParkingSimState,
+    ParkingSpot, PersonID, SidewalkSpot, TripID, TripPhaseType, Vehicle, VehicleType,
 };
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
@@ -32,6 +34,8 @@ pub(crate) enum ActionAtEnd {
     GotoLaneEnd,
     StopBiking(SidewalkSpot),
     BusAtStop,
+    PassengerStop,
+    LoadingZone,
     GiveUpOnParking,
 }
 
@@ -44,6 +48,9 @@ enum Goal {
         /// No parking available at all!
         stuck_end_dist: Option<Distance>,
         started_looking: bool,
+        /// How long the driver expects to leave the car parked, if known. Spots with a shorter
+        /// time limit are skipped.
+        planned_stay: Option<Duration>,
     },
     EndAtBorder {
         end_dist: Distance,
@@ -55,6 +62,12 @@ enum Goal {
     FollowBusRoute {
         end_dist: Distance,
     },
+    PassengerStop {
+        end_dist: Distance,
+    },
+    LoadingZone {
+        end_dist: Distance,
+    },
 }
 
 impl Router {
@@ -85,7 +98,12 @@ impl Router {
         }
     }
 
-    pub fn park_near(owner: CarID, path: Path, bldg: BuildingID) -> Router {
+    pub fn park_near(
+        owner: CarID,
+        path: Path,
+        bldg: BuildingID,
+        planned_stay: Option<Duration>,
+    ) -> Router {
         Router {
             path,
             goal: Goal::ParkNearBuilding {
@@ -93,6 +111,7 @@ impl Router {
                 spot: None,
                 stuck_end_dist: None,
                 started_looking: false,
+                planned_stay,
             },
             owner,
         }
@@ -116,6 +135,26 @@ impl Router {
         }
     }
 
+    pub fn passenger_stop(owner: CarID, path: Path) -> Router {
+        Router {
+            goal: Goal::PassengerStop {
+                end_dist: path.get_req().end.dist_along(),
+            },
+            path,
+            owner,
+        }
+    }
+
+    pub fn loading_zone(owner: CarID, path: Path) -> Router {
+        Router {
+            goal: Goal::LoadingZone {
+                end_dist: path.get_req().end.dist_along(),
+            },
+            path,
+            owner,
+        }
+    }
+
     pub fn head(&self) -> Traversable {
         self.path.current_step().as_traversable()
     }
@@ -144,6 +183,8 @@ impl Router {
             } => stuck_end_dist.unwrap_or_else(|| spot.unwrap().1),
             Goal::BikeThenStop { ref goal } => goal.sidewalk_pos.dist_along(),
             Goal::FollowBusRoute { end_dist } => end_dist,
+            Goal::PassengerStop { end_dist } => end_dist,
+            Goal::LoadingZone { end_dist } => end_dist,
         }
     }
 
@@ -214,6 +255,7 @@ impl Router {
                 ref mut stuck_end_dist,
                 target,
                 ref mut started_looking,
+                planned_stay,
             } => {
                 if let Some(d) = stuck_end_dist {
                     if *d == front {
@@ -236,26 +278,21 @@ impl Router {
                         target,
                         map,
                     );
-                    let best =
-                        if let Some((driving_pos, _)) = map.get_b(target).driving_connection(map) {
-                            if driving_pos.lane() == current_lane {
-                                let target_dist = driving_pos.dist_along();
-                                // Closest to the building
-                                candidates
-                                    .into_iter()
-                                    .min_by_key(|(_, pos)| (pos.dist_along() - target_dist).abs())
-                            } else {
-                                // Closest to the road endpoint, I guess
-                                candidates
-                                    .into_iter()
-                                    .min_by_key(|(_, pos)| pos.dist_along())
-                            }
+                    let target_dist = map
+                        .get_b(target)
+                        .driving_connection(map)
+                        .map(|(pos, _)| pos)
+                        .filter(|pos| pos.lane() == current_lane)
+                        .map(|pos| pos.dist_along());
+                    let best = choose_spot(candidates, planned_stay, map, |pos| {
+                        if let Some(target_dist) = target_dist {
+                            // Closest to the building
+                            (pos.dist_along() - target_dist).abs()
                         } else {
                             // Closest to the road endpoint, I guess
-                            candidates
-                                .into_iter()
-                                .min_by_key(|(_, pos)| pos.dist_along())
-                        };
+                            pos.dist_along()
+                        }
+                    });
                     if let Some((new_spot, new_pos)) = best {
                         if let Some((t, p)) = trip_and_person {
                             events.push(Event::TripPhaseStarting(
@@ -264,7 +301,7 @@ impl Router {
                                 Some(PathRequest::vehicle(
                                     Position::new(current_lane, front),
                                     new_pos,
-                                    PathConstraints::Car,
+                                    vehicle.vehicle_type.to_constraints(),
                                 )),
                                 TripPhaseType::Parking,
                             ));
@@ -273,15 +310,24 @@ impl Router {
                         assert!(new_pos.dist_along() >= front);
                         *spot = Some((new_spot, new_pos.dist_along()));
                     } else {
-                        if let Some((new_path_steps, new_spot, new_pos)) =
-                            parking.path_to_free_parking_spot(current_lane, vehicle, target, map)
+                        if let Some((new_path_steps, new_spot, new_pos)) = parking
+                            .path_to_free_parking_spot(
+                                current_lane,
+                                vehicle,
+                                target,
+                                planned_stay,
+                                map,
+                            )
                         {
                             assert!(!new_path_steps.is_empty());
+                            let mut cruising = Distance::ZERO;
                             for step in new_path_steps {
+                                cruising += step.as_traversable().get_polyline(map).length();
                                 self.path.add(step, map);
                             }
                             *spot = Some((new_spot, new_pos.dist_along()));
                             events.push(Event::PathAmended(self.path.clone()));
+                            events.push(Event::CruisingForParking(vehicle.id, cruising));
                             // TODO This path might not be the same as the one found here...
                             if let Some((t, p)) = trip_and_person {
                                 events.push(Event::TripPhaseStarting(
@@ -290,7 +336,7 @@ impl Router {
                                     Some(PathRequest::vehicle(
                                         Position::new(current_lane, front),
                                         new_pos,
-                                        PathConstraints::Car,
+                                        vehicle.vehicle_type.to_constraints(),
                                     )),
                                     TripPhaseType::Parking,
                                 ));
@@ -332,14 +378,30 @@ impl Router {
                     None
                 }
             }
+            Goal::PassengerStop { end_dist } => {
+                if end_dist == front {
+                    Some(ActionAtEnd::PassengerStop)
+                } else {
+                    None
+                }
+            }
+            Goal::LoadingZone { end_dist } => {
+                if end_dist == front {
+                    Some(ActionAtEnd::LoadingZone)
+                } else {
+                    None
+                }
+            }
         }
     }
 
+    /// `eagerness` is between 0 and 1; see `DriverBehavior`.
     pub fn opportunistically_lanechange(
         &mut self,
         queues: &HashMap<Traversable, Queue>,
         map: &Map,
         handle_uber_turns: bool,
+        eagerness: f64,
     ) {
         // if we're already in the uber-turn, we're committed, but if we're about to enter one, lock
         // in the best path through it now.
@@ -386,11 +448,14 @@ impl Router {
                 // make our choice based on each penalty in order, breaking ties by moving onto the
                 // next thing. With one exception: To produce more realistic behavior, we combine
                 // `vehicles + lc` as one score to avoid switching lanes just to get around one car.
+                // Eager drivers weigh the lane-changing penalty less; an average driver uses it
+                // as-is.
                 if self.owner.vehicle_type == VehicleType::Bike {
                     bike = 0;
                 } else {
                     slow_lane = 0;
                 }
+                let lc = ((lc as f64) * 2.0 * (1.0 - eagerness)).round() as usize;
 
                 (lt, bike, slow_lane, vehicles + lc)
             };
@@ -466,6 +531,117 @@ impl Router {
         }
     }
 
+    /// Called at every intersection. If the rest of the path is slower than some alternative by at
+    /// least `threshold`, given how long vehicles are currently waiting at intersections, switch
+    /// to the alternative. Returns the time expected to be saved.
+    pub fn maybe_reroute(
+        &mut self,
+        threshold: Duration,
+        now: Time,
+        intersections: &IntersectionSimState,
+        map: &Map,
+    ) -> Option<Duration> {
+        if !self.can_reroute() {
+            return None;
+        }
+        let delay = |mvmnt| intersections.live_delay(mvmnt, now, map);
+
+        // Finding an alternative is expensive. The current path was the fastest one ignoring
+        // delays, so unless it's delayed by at least the threshold, no alternative can be that
+        // much faster.
+        let movements: Vec<MovementID> = self
+            .path
+            .get_steps()
+            .iter()
+            .filter_map(|step| match step {
+                PathStep::Turn(t) => Some(t.to_movement(map)),
+                _ => None,
+            })
+            .collect();
+        let total_delay = movements
+            .iter()
+            .fold(Duration::ZERO, |sum, mvmnt| sum + delay(*mvmnt));
+        if total_delay < threshold {
+            return None;
+        }
+
+        let constraints = self.owner.vehicle_type.to_constraints();
+        let mut current_cost = total_delay;
+        for mvmnt in movements {
+            current_cost += vehicle_cost(mvmnt.from, mvmnt, constraints, map.routing_params(), map)
+                + zone_cost(mvmnt, constraints, map);
+        }
+        let (path, cost) = self.find_new_path(delay, map)?;
+        let saved = current_cost - cost;
+        if saved < threshold {
+            return None;
+        }
+        self.path.replace_remaining(path, map);
+        Some(saved)
+    }
+
+    /// The rest of the path was cut off by live map edits. Try to find another way, returning
+    /// false if that's impossible.
+    pub fn reroute_around_edits(
+        &mut self,
+        now: Time,
+        intersections: &IntersectionSimState,
+        map: &Map,
+    ) -> bool {
+        if !self.can_reroute() {
+            return false;
+        }
+        match self.find_new_path(|mvmnt| intersections.live_delay(mvmnt, now, map), map) {
+            Some((path, _)) => {
+                self.path.replace_remaining(path, map);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn can_reroute(&self) -> bool {
+        if self.path.is_last_step() || self.path.currently_inside_ut().is_some() {
+            return false;
+        }
+        if !matches!(self.path.current_step(), PathStep::Lane(_)) {
+            return false;
+        }
+        match self.goal {
+            // Buses and trains have to serve their stops
+            Goal::FollowBusRoute { .. } => false,
+            // Once somebody's circling around for parking, that's the only plan
+            Goal::ParkNearBuilding {
+                started_looking, ..
+            } => !started_looking,
+            _ => true,
+        }
+    }
+
+    /// Find a path from the end of the current lane to the same destination. Also returns the
+    /// expected cost of the new path.
+    fn find_new_path<F: Fn(MovementID) -> Duration>(
+        &self,
+        extra_cost: F,
+        map: &Map,
+    ) -> Option<(Path, Duration)> {
+        let current_lane = self.path.current_step().as_lane();
+        let req = PathRequest::vehicle(
+            Position::end(current_lane, map),
+            self.path.get_req().end,
+            self.owner.vehicle_type.to_constraints(),
+        );
+        let path = map.pathfind_v2_with_extra_costs(req, extra_cost).ok()?;
+        let cost = path.get_cost();
+        let path = path.into_v1(map).ok()?;
+        // The new path might start with a turn only possible from a different lane. Changing lanes
+        // partway through isn't worth the complexity here, so just give up.
+        if path.current_step() != self.path.current_step() {
+            return None;
+        }
+        Some((path, cost))
+    }
+
     pub fn can_lanechange(&self, from: LaneID, to: LaneID, map: &Map) -> bool {
         let steps = self.path.get_steps();
         if steps.len() < 3 {
@@ -513,3 +689,96 @@ impl Router {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use abstutil::Timer;
+    use map_model::toy::{intersection, road, toy_map};
+    use map_model::{IntersectionType, LaneType};
+
+    use crate::testing;
+    use crate::{IndividTrip, SimOptions, TripEndpoint, TripMode, TripPurpose};
+
+    use super::*;
+
+    #[test]
+    fn reroute_around_live_closure() {
+        // A direct road from west to east, and a longer detour around the north
+        //
+        //  n1        n2
+        //  |          |
+        //  nw ------ ne
+        //  |          |
+        //  w -------- e
+        //  |          |
+        //  sw        se
+        let mut map = toy_map(
+            &[
+                ("sw", 0.0, 500.0, IntersectionType::Border),
+                ("w", 0.0, 300.0, IntersectionType::StopSign),
+                ("nw", 0.0, 100.0, IntersectionType::StopSign),
+                ("n1", 0.0, 0.0, IntersectionType::Border),
+                ("n2", 400.0, 0.0, IntersectionType::Border),
+                ("ne", 400.0, 100.0, IntersectionType::StopSign),
+                ("e", 400.0, 300.0, IntersectionType::StopSign),
+                ("se", 400.0, 500.0, IntersectionType::Border),
+            ],
+            &[
+                ("sw", "w"),
+                ("w", "e"),
+                ("w", "nw"),
+                ("nw", "n1"),
+                ("nw", "ne"),
+                ("ne", "n2"),
+                ("ne", "e"),
+                ("e", "se"),
+            ],
+        );
+        let border = |name| TripEndpoint::Border(intersection(&map, name));
+        let depart = Time::START_OF_DAY + Duration::hours(7);
+        let trip = IndividTrip::new(
+            depart,
+            TripPurpose::Work,
+            border("sw"),
+            border("se"),
+            TripMode::Drive,
+        );
+        let scenario = testing::scenario(&map, vec![vec![trip]]);
+        let mut opts = SimOptions::new("test");
+        opts.reroute_threshold = Some(Duration::minutes(1));
+        let mut sim = testing::start(&map, &scenario, opts);
+
+        // Close the direct road while the driver is still heading for it
+        sim.timed_step(
+            &map,
+            depart + Duration::seconds(5.0) - sim.time(),
+            &mut None,
+            &mut Timer::throwaway(),
+        );
+        let direct = road(&map, "w", "e").id;
+        let mut edits = map.get_edits().clone();
+        edits.commands.push(map.edit_road_cmd(direct, |new| {
+            for spec in &mut new.lanes_ltr {
+                if spec.lt == LaneType::Driving {
+                    spec.lt = LaneType::Construction;
+                }
+            }
+        }));
+        map.must_apply_edits(edits, &mut Timer::throwaway());
+        map.recalculate_pathfinding_after_edits(&mut Timer::throwaway());
+        let (cancelled, _) = sim.handle_live_edits(&map, &mut Timer::throwaway());
+        assert!(cancelled.is_empty());
+
+        let events = testing::run_until_done(&mut sim, &map, Duration::hours(8));
+        assert_eq!(sim.num_trips(), (1, 0));
+        let reroutes = &sim.get_analytics().reroutes;
+        assert_eq!(reroutes.len(), 1);
+        assert_eq!(reroutes[0].2, None);
+        // The driver went around the north
+        let detour = road(&map, "nw", "ne").id;
+        assert!(events.iter().any(|ev| matches!(
+            ev,
+            Event::AgentEntersTraversable(_, _, Traversable::Lane(l), _) if l.road == detour
+        )));
+    }
+}
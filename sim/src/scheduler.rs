@@ -12,6 +12,7 @@ use crate::{
     pandemic, AgentID, CarID, CreateCar, CreatePedestrian, PedestrianID, StartTripArgs, TripID,
 };
 
+#[allow(clippy::large_enum_variant)]
 #[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
 pub(crate) enum Command {
     /// If true, retry when there's no room to spawn somewhere
@@ -27,6 +28,8 @@ pub(crate) enum Command {
     Pandemic(pandemic::Cmd),
     /// The Time is redundant, just used to dedupe commands
     StartBus(BusRouteID, Time),
+    /// Periodically move shared bikes between docks
+    RebalanceBikeShare,
 }
 
 impl Command {
@@ -34,7 +37,7 @@ impl Command {
         match id {
             AgentID::Car(c) => Command::UpdateCar(c),
             AgentID::Pedestrian(p) => Command::UpdatePed(p),
-            AgentID::BusPassenger(_, _) => unreachable!(),
+            AgentID::BusPassenger(_, _) | AgentID::CarPassenger(_, _) => unreachable!(),
         }
     }
 
@@ -50,6 +53,7 @@ impl Command {
             Command::Callback(_) => CommandType::Callback,
             Command::Pandemic(ref p) => CommandType::Pandemic(p.clone()),
             Command::StartBus(r, t) => CommandType::StartBus(*r, *t),
+            Command::RebalanceBikeShare => CommandType::RebalanceBikeShare,
         }
     }
 
@@ -65,6 +69,7 @@ impl Command {
             Command::Callback(_) => SimpleCommandType::Callback,
             Command::Pandemic(_) => SimpleCommandType::Pandemic,
             Command::StartBus(_, _) => SimpleCommandType::StartBus,
+            Command::RebalanceBikeShare => SimpleCommandType::RebalanceBikeShare,
         }
     }
 }
@@ -81,6 +86,7 @@ enum CommandType {
     Callback,
     Pandemic(pandemic::Cmd),
     StartBus(BusRouteID, Time),
+    RebalanceBikeShare,
 }
 
 /// A more compressed form of CommandType, just used for keeping stats on event processing.
@@ -94,6 +100,7 @@ enum SimpleCommandType {
     Callback,
     Pandemic,
     StartBus,
+    RebalanceBikeShare,
 }
 
 #[derive(Serialize, Deserialize, PartialEq, Eq, Clone)]
@@ -14,23 +14,26 @@ use abstio::{CityName, MapName};
 use abstutil::{prettyprint_usize, serialized_size_bytes, CmdArgs, Timer};
 use geom::{Distance, Duration, Speed, Time};
 use map_model::{
-    BuildingID, BusRoute, IntersectionID, LaneID, Map, ParkingLotID, Path, PathConstraints,
-    PathRequest, Position, Traversable,
+    BikeShareDockID, BuildingID, BusRoute, EditRoad, IntersectionID, LaneID, Map, ParkingLotID,
+    Path, PathConstraints, PathRequest, Position, TimeDependentPathfinder, Traversable,
 };
 
 pub use self::queries::{AgentProperties, DelayCause};
 use crate::{
-    AgentID, AlertLocation, Analytics, CarID, Command, CreateCar, DrivingSimState, Event,
-    IntersectionSimState, OrigPersonID, PandemicModel, ParkedCar, ParkingSim, ParkingSimState,
-    ParkingSpot, Person, PersonID, Router, Scheduler, SidewalkPOI, SidewalkSpot, StartTripArgs,
-    TrafficRecorder, TransitSimState, TripID, TripInfo, TripManager, TripPhaseType, Vehicle,
-    VehicleSpec, VehicleType, WalkingSimState, BUS_LENGTH, LIGHT_RAIL_LENGTH, MIN_CAR_LENGTH,
+    AgentID, AlertLocation, Analytics, CarID, Command, CreateCar, DriverBehavior, DrivingSimState,
+    EmissionsModelKind, Event, FlatRateModel, IntersectionSimState, OrigPersonID, PandemicModel,
+    ParkedCar, ParkingSim, ParkingSimState, ParkingSpot, Person, PersonID, Router, Scheduler,
+    SidewalkPOI, SidewalkSpot, SpeedCurveModel, StartTripArgs, TrafficRecorder, TransitSimState,
+    TripID, TripInfo, TripManager, TripPhaseType, Vehicle, VehicleSpec, VehicleType,
+    WalkingSimState, BUS_LENGTH, LIGHT_RAIL_LENGTH, MIN_CAR_LENGTH,
 };
 
 mod queries;
 
 // TODO Do something else.
 const BLIND_RETRY_TO_SPAWN: Duration = Duration::const_seconds(5.0);
+// How often the bike-share operator moves bikes between docks
+const BIKE_SHARE_REBALANCE_FREQUENCY: Duration = Duration::const_seconds(3600.0);
 
 /// The Sim ties together all the pieces of the simulation. Its main property is the current time.
 #[derive(Serialize, Deserialize, Clone)]
@@ -62,6 +65,11 @@ pub struct Sim {
 
     #[serde(skip_serializing, skip_deserializing)]
     alerts: AlertHandler,
+
+    // Only external API clients ask for this, and there's no reason to preserve it for
+    // savestates.
+    #[serde(skip_serializing, skip_deserializing)]
+    buffered_events: Option<Vec<(Time, Event)>>,
 }
 
 pub(crate) struct Ctx<'a> {
@@ -124,6 +132,21 @@ pub struct SimOptions {
     /// quickly.
     #[structopt(long)]
     pub skip_analytics: bool,
+    /// How to estimate vehicle emissions: `speed-curve` accounts for speed, acceleration, and
+    /// hills, while `flat-rate` just uses a fixed rate per distance.
+    #[structopt(long, parse(try_from_str = parse_emissions_model), default_value = "speed-curve")]
+    pub emissions_model: EmissionsModelKind,
+    /// Let drivers re-route mid-trip. At every intersection, they switch to a different route if
+    /// it's expected to be faster by at least this much, given how long vehicles are currently
+    /// waiting at intersections. Drivers whose route gets cut off by live map edits also re-route,
+    /// instead of cancelling their trip. Parsed like "1:30" for 90 seconds.
+    #[structopt(long, parse(try_from_str = Duration::parse))]
+    pub reroute_threshold: Option<Duration>,
+    /// Plan the routes of cars and trucks around congestion observed in an earlier simulation.
+    /// This is the path to that run's Analytics, such as prebaked results, and roads are costed
+    /// by how long crossing them took at each hour of the day.
+    #[structopt(long)]
+    pub congestion_from: Option<String>,
 }
 
 impl SimOptions {
@@ -140,6 +163,9 @@ impl SimOptions {
             infinite_parking: false,
             disable_turn_conflicts: false,
             skip_analytics: false,
+            emissions_model: EmissionsModelKind::SpeedCurve(SpeedCurveModel),
+            reroute_threshold: None,
+            congestion_from: None,
         }
     }
 
@@ -171,6 +197,11 @@ impl SimOptions {
             infinite_parking: args.enabled("--infinite_parking"),
             disable_turn_conflicts: args.enabled("--disable_turn_conflicts"),
             skip_analytics: args.enabled("--skip_analytics"),
+            emissions_model: args
+                .optional_parse("--emissions_model", parse_emissions_model)
+                .unwrap_or(EmissionsModelKind::SpeedCurve(SpeedCurveModel)),
+            reroute_threshold: args.optional_parse("--reroute_threshold", Duration::parse),
+            congestion_from: args.optional("--congestion_from"),
         }
     }
 }
@@ -211,6 +242,14 @@ fn parse_alert_handler(x: &str) -> Result<AlertHandler> {
     }
 }
 
+fn parse_emissions_model(x: &str) -> Result<EmissionsModelKind> {
+    match x {
+        "speed-curve" => Ok(EmissionsModelKind::SpeedCurve(SpeedCurveModel)),
+        "flat-rate" => Ok(EmissionsModelKind::FlatRate(FlatRateModel)),
+        _ => bail!("Bad --emissions_model={}. Must be speed-curve|flat-rate", x),
+    }
+}
+
 // Setup
 impl Sim {
     pub fn new(map: &Map, mut opts: SimOptions) -> Sim {
@@ -233,13 +272,18 @@ impl Sim {
             opts.allow_block_the_box = true;
         }
 
+        let congestion = opts.congestion_from.as_ref().map(|path| {
+            let previous: Analytics = abstio::read_binary(path.clone(), &mut timer);
+            TimeDependentPathfinder::new(previous.hourly_travel_times())
+        });
+
         Sim {
             driving: DrivingSimState::new(map, &opts),
             parking: ParkingSimState::new(map, opts.infinite_parking, &mut timer),
             walking: WalkingSimState::new(),
             intersections: IntersectionSimState::new(map, &mut scheduler, &opts),
             transit: TransitSimState::new(map),
-            trips: TripManager::new(),
+            trips: TripManager::new(congestion),
             pandemic: opts.enable_pandemic_model.map(PandemicModel::new),
             scheduler,
             time: Time::START_OF_DAY,
@@ -253,6 +297,7 @@ impl Sim {
 
             analytics: Analytics::new(!opts.skip_analytics),
             recorder: None,
+            buffered_events: None,
         }
     }
 
@@ -314,6 +359,8 @@ impl Sim {
             vehicle_type: VehicleType::Car,
             length: MIN_CAR_LENGTH,
             max_speed: None,
+            battery: None,
+            behavior: DriverBehavior::default(),
         };
         let driving_lane = map.find_driving_lane_near_building(b);
 
@@ -329,7 +376,7 @@ impl Sim {
         } else {
             let (_, spot, _) =
                 self.parking
-                    .path_to_free_parking_spot(driving_lane, &vehicle, b, map)?;
+                    .path_to_free_parking_spot(driving_lane, &vehicle, b, None, map)?;
             spot
         };
 
@@ -346,6 +393,25 @@ impl Sim {
     ) -> &Person {
         self.trips.new_person(orig_id, ped_speed, vehicle_specs)
     }
+    pub(crate) fn new_ride_hail_vehicle(&mut self, spec: VehicleSpec) -> Vehicle {
+        self.trips.new_ride_hail_vehicle(spec)
+    }
+    /// Stock a bike-share dock at the start of the simulation. The operator starts rebalancing
+    /// once the first dock exists.
+    pub(crate) fn seed_bike_share_dock(
+        &mut self,
+        dock: BikeShareDockID,
+        capacity: usize,
+        bikes: Vec<VehicleSpec>,
+    ) {
+        if !self.trips.has_bike_share_docks() {
+            self.scheduler.push(
+                self.time + BIKE_SHARE_REBALANCE_FREQUENCY,
+                Command::RebalanceBikeShare,
+            );
+        }
+        self.trips.new_bike_share_dock(dock, capacity, bikes);
+    }
     pub(crate) fn seed_parked_car(&mut self, vehicle: Vehicle, spot: ParkingSpot) {
         self.parking.reserve_spot(spot, vehicle.id);
         self.parking.add_parked_car(ParkedCar {
@@ -361,7 +427,7 @@ impl Sim {
         }
     }
 
-    fn start_bus(&mut self, route: &BusRoute, map: &Map) {
+    fn start_bus(&mut self, route: &BusRoute, departure: Time, map: &Map) {
         // Spawn one bus for the first leg.
         let path = self.transit.create_empty_route(route, map);
 
@@ -376,6 +442,8 @@ impl Sim {
             vehicle_type,
             length,
             max_speed: None,
+            battery: None,
+            behavior: DriverBehavior::default(),
         }
         .make(
             CarID {
@@ -393,7 +461,7 @@ impl Sim {
                     vehicle,
                     maybe_parked_car: None,
                     trip_and_person: None,
-                    maybe_route: Some(route.id),
+                    maybe_route: Some((route.id, departure)),
                 },
                 true,
             ),
@@ -497,13 +565,21 @@ impl Sim {
                     }
                 }
                 if !ok {
-                    self.trips.cancel_trip(
-                        self.time,
-                        create_car.trip_and_person.unwrap().0,
-                        "path is no longer valid after map edits".to_string(),
-                        Some(create_car.vehicle),
-                        &mut ctx,
-                    );
+                    if let Some((trip, _)) = create_car.trip_and_person {
+                        self.trips.cancel_trip(
+                            self.time,
+                            trip,
+                            "path is no longer valid after map edits".to_string(),
+                            Some(create_car.vehicle),
+                            &mut ctx,
+                        );
+                    } else {
+                        self.trips.ride_hail_vehicle_cant_spawn(
+                            self.time,
+                            create_car.vehicle.id,
+                            &mut ctx,
+                        );
+                    }
                 } else {
                     // create_car contains a Path, which is expensive to clone. We need different
                     // parts of create_car after attempting start_car_on_lane.
@@ -556,25 +632,25 @@ impl Sim {
                                 trip,
                                 person,
                                 Some(req),
-                                if id.vehicle_type == VehicleType::Car {
-                                    TripPhaseType::Driving
-                                } else {
+                                if id.vehicle_type == VehicleType::Bike {
                                     TripPhaseType::Biking
+                                } else {
+                                    TripPhaseType::Driving
                                 },
                             ));
                         }
                         if let Some(parked_car) = maybe_parked_car {
-                            if let ParkingSpot::Offstreet(b, _) = parked_car.spot {
-                                // Buses don't start in parking garages, so trip must exist
-                                events.push(Event::PersonLeavesBuilding(
-                                    trip_and_person.unwrap().1,
-                                    b,
-                                ));
+                            // Buses don't start in parking garages, but ride-hailing vehicles
+                            // might, and nobody's inside
+                            if let (ParkingSpot::Offstreet(b, _), Some((_, person))) =
+                                (parked_car.spot, trip_and_person)
+                            {
+                                events.push(Event::PersonLeavesBuilding(person, b));
                             }
                             self.parking.remove_parked_car(parked_car);
                         }
-                        if let Some(route) = maybe_route {
-                            self.transit.bus_created(id, route);
+                        if let Some((route, departure)) = maybe_route {
+                            self.transit.bus_created(id, route, departure);
                         }
                         self.analytics
                             .record_demand(self.driving.get_path(id).unwrap(), map);
@@ -642,8 +718,13 @@ impl Sim {
                 );
             }
             Command::UpdateIntersection(i) => {
-                self.intersections
-                    .update_intersection(self.time, i, map, &mut self.scheduler);
+                self.intersections.update_intersection(
+                    self.time,
+                    i,
+                    map,
+                    &mut self.scheduler,
+                    &self.driving,
+                );
             }
             Command::Callback(frequency) => {
                 self.scheduler
@@ -658,8 +739,18 @@ impl Sim {
                     .unwrap()
                     .handle_cmd(self.time, cmd, &mut self.scheduler);
             }
-            Command::StartBus(r, _) => {
-                self.start_bus(map.get_br(r), map);
+            Command::StartBus(r, departure) => {
+                // The route may have been deleted by live edits since this was scheduled
+                if let Some(route) = map.maybe_get_br(r).filter(|r| !r.is_deleted()) {
+                    self.start_bus(route, departure, map);
+                }
+            }
+            Command::RebalanceBikeShare => {
+                self.trips.rebalance_bike_share();
+                self.scheduler.push(
+                    self.time + BIKE_SHARE_REBALANCE_FREQUENCY,
+                    Command::RebalanceBikeShare,
+                );
             }
         }
 
@@ -683,6 +774,9 @@ impl Sim {
             if let Some(ref mut r) = self.recorder {
                 r.handle_event(self.time, &ev, map, &self.driving);
             }
+            if let Some(ref mut list) = self.buffered_events {
+                list.push((self.time, ev.clone()));
+            }
 
             self.analytics.event(ev, self.time, map);
         }
@@ -854,6 +948,15 @@ impl Sim {
     pub fn load_savestate(path: String, timer: &mut Timer) -> Result<Sim> {
         abstio::maybe_read_binary(path, timer)
     }
+
+    /// A savestate is only valid for the map and edits it was created with.
+    pub fn get_map_name(&self) -> &MapName {
+        &self.map_name
+    }
+
+    pub fn get_edits_name(&self) -> &str {
+        &self.edits_name
+    }
 }
 
 // Live edits
@@ -863,14 +966,16 @@ impl Sim {
             .handle_live_edited_traffic_signals(self.time, map, &mut self.scheduler)
     }
 
-    /// Respond to arbitrary map edits without resetting the simulation. Returns the number of
-    /// (trips cancelled, parked cars displaced).
-    pub fn handle_live_edits(&mut self, map: &Map, timer: &mut Timer) -> (usize, usize) {
+    /// Respond to arbitrary map edits without resetting the simulation. Returns the (trips
+    /// cancelled, number of parked cars displaced).
+    pub fn handle_live_edits(&mut self, map: &Map, timer: &mut Timer) -> (BTreeSet<TripID>, usize) {
         self.edits_name = map.get_edits().edits_name.clone();
 
-        let (affected, num_parked_cars) = self.find_trips_affected_by_live_edits(map, timer);
-        let num_trips_cancelled = affected.len();
-        let affected_agents: BTreeSet<AgentID> = affected.iter().map(|(a, _)| *a).collect();
+        let (affected, ride_hail_vehicles, num_parked_cars) =
+            self.find_trips_affected_by_live_edits(map, timer);
+        let cancelled_trips: BTreeSet<TripID> = affected.iter().map(|(_, t)| *t).collect();
+        let mut affected_agents: BTreeSet<AgentID> = affected.iter().map(|(a, _)| *a).collect();
+        affected_agents.extend(ride_hail_vehicles.iter().map(|car| AgentID::Car(*car)));
 
         // V1: Just cancel every trip crossing an affected area.
         // (V2 is probably rerouting everyone, only cancelling when that fails)
@@ -908,26 +1013,42 @@ impl Sim {
                     self.trips
                         .trip_abruptly_cancelled(trip, AgentID::Pedestrian(ped));
                 }
-                AgentID::BusPassenger(_, _) => unreachable!(),
+                AgentID::BusPassenger(_, _) | AgentID::CarPassenger(_, _) => unreachable!(),
             }
         }
+        // Ride-hailing vehicles with nowhere to park don't have a trip to cancel
+        for car in ride_hail_vehicles {
+            let distance_crossed = self.driving.agent_properties(car, self.time).dist_crossed;
+            self.driving.delete_car(car, self.time, &mut ctx);
+            self.trips
+                .ride_hail_vehicle_out_of_service(car, distance_crossed);
+        }
 
         self.driving.handle_live_edits(map);
         self.intersections.handle_live_edits(map);
+        self.trips.handle_live_edits();
 
-        (num_trips_cancelled, num_parked_cars)
+        // Start running routes created by the edits. Any departures already passed are skipped.
+        for r in self.transit.new_routes(map) {
+            for t in &map.get_br(r).spawn_times {
+                if *t >= self.time {
+                    self.scheduler.update(*t, Command::StartBus(r, *t));
+                }
+            }
+        }
+
+        (cancelled_trips, num_parked_cars)
     }
 
-    /// Returns (trips affected, number of parked cars displaced)
+    /// Returns (trips affected, ride-hailing vehicles to take out of service, number of parked
+    /// cars displaced)
     fn find_trips_affected_by_live_edits(
         &mut self,
         map: &Map,
         timer: &mut Timer,
-    ) -> (BTreeSet<(AgentID, TripID)>, usize) {
+    ) -> (BTreeSet<(AgentID, TripID)>, Vec<CarID>, usize) {
         let mut affected: BTreeSet<(AgentID, TripID)> = BTreeSet::new();
 
-        // TODO Handle changes to access restrictions
-
         {
             // Find every active trip whose path crosses a modified lane or intersection
             let (edited_lanes, _) = map.get_edits().changed_lanes(map);
@@ -956,20 +1077,69 @@ impl Sim {
                 }
             }
 
+            // Drivers who haven't reached the edited area yet might be able to go around it
+            let mut ctx = Ctx {
+                parking: &mut self.parking,
+                intersections: &mut self.intersections,
+                scheduler: &mut self.scheduler,
+                map,
+                handling_live_edits: None,
+            };
+            let now = self.time;
+            let driving = &mut self.driving;
+            affected.retain(|(a, _)| match a {
+                AgentID::Car(car) => {
+                    !driving.reroute_around_live_edits(*car, now, &mut ctx, &edited_lanes)
+                }
+                _ => true,
+            });
+
             affected.extend(
                 self.driving
                     .find_vehicles_affected_by_live_edits(&closed_intersections, &edited_lanes),
             );
         }
 
+        // Find every active trip that now cuts through a zone that doesn't allow it. Only bother
+        // checking when some road's access restrictions have been edited.
+        if map.get_edits().changed_roads.iter().any(|r| {
+            let r = map.get_r(*r);
+            r.access_restrictions
+                != EditRoad::get_orig_from_osm(r, map.get_config()).access_restrictions
+        }) {
+            for (a, trip) in self.trips.active_agents_and_trips() {
+                if let Some(path) = self.get_path(*a) {
+                    if path.find_illegal_zone_entrance(map).is_some() {
+                        affected.insert((*a, *trip));
+                    }
+                }
+            }
+        }
+
+        // Pedestrians waiting for a transit route that no longer goes where they need
+        for ped in self.transit.handle_live_edits(map) {
+            let a = AgentID::Pedestrian(ped);
+            if let Some(trip) = self.agent_to_trip(a) {
+                affected.insert((a, trip));
+            }
+        }
+
+        let mut ride_hail_vehicles = Vec::new();
         let num_evicted = {
             let (evicted_cars, cars_parking_in_the_void) =
                 self.parking.handle_live_edits(map, timer);
             let num_evicted = evicted_cars.len();
+            for p in &evicted_cars {
+                self.trips.parked_car_evicted(self.time, p.vehicle.id);
+            }
             affected.extend(self.walking.find_trips_to_parking(evicted_cars));
             for car in cars_parking_in_the_void {
                 let a = AgentID::Car(car);
-                affected.insert((a, self.agent_to_trip(a).unwrap()));
+                if let Some(trip) = self.agent_to_trip(a) {
+                    affected.insert((a, trip));
+                } else if self.trips.is_ride_hail_vehicle(car) {
+                    ride_hail_vehicles.push(car);
+                }
             }
 
             if !self.parking.is_infinite() {
@@ -982,7 +1152,7 @@ impl Sim {
             num_evicted
         };
 
-        (affected, num_evicted)
+        (affected, ride_hail_vehicles, num_evicted)
     }
 }
 
@@ -1052,6 +1222,54 @@ impl Sim {
     }
 }
 
+// Observing events
+impl Sim {
+    /// Start remembering every event the simulation produces, so that callers outside this crate
+    /// can observe what's happening. This is expensive, so only do it when needed.
+    pub fn start_buffering_events(&mut self) {
+        if self.buffered_events.is_none() {
+            self.buffered_events = Some(Vec::new());
+        }
+    }
+
+    pub fn stop_buffering_events(&mut self) {
+        self.buffered_events = None;
+    }
+
+    /// Returns all events that've happened since the last call, along with the time they occurred.
+    pub fn drain_buffered_events(&mut self) -> Vec<(Time, Event)> {
+        self.buffered_events
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default()
+    }
+}
+
+// Controlling traffic signals
+impl Sim {
+    /// Immediately switch a traffic signal to some stage of its current plan, instead of waiting
+    /// for the current stage to end. This lets an external controller decide the order and timing
+    /// of stages; the signal resumes its normal cycle after the forced stage ends.
+    pub fn force_traffic_signal_stage(
+        &mut self,
+        map: &Map,
+        i: IntersectionID,
+        stage: usize,
+    ) -> Result<()> {
+        let signal = map
+            .maybe_get_traffic_signal(i)
+            .ok_or_else(|| anyhow!("{} isn't a traffic signal", i))?;
+        let (plan, _, _) = self.current_plan_stage_and_remaining_time(i);
+        let num_stages = signal.get_stages(plan).len();
+        if stage >= num_stages {
+            bail!("{} only has {} stages in its current plan", i, num_stages);
+        }
+        self.intersections
+            .force_stage(self.time, i, stage, map, &mut self.scheduler);
+        Ok(())
+    }
+}
+
 // Managing highlighted people
 impl Sim {
     pub fn set_highlighted_people(&mut self, people: BTreeSet<PersonID>) {
@@ -1,14 +1,15 @@
 //! All sorts of read-only queries about a simulation
 
 use anyhow::Result;
+use schemars::JsonSchema;
 use serde::Serialize;
 use std::collections::{BTreeMap, BTreeSet};
 
 use abstutil::Counter;
 use geom::{Distance, Duration, PolyLine, Pt2D, Time};
 use map_model::{
-    BuildingID, BusRouteID, BusStopID, IntersectionID, Lane, LaneID, Map, Path, Position,
-    Traversable, TurnID,
+    BikeShareDockID, BuildingID, BusRouteID, BusStopID, IntersectionID, Lane, LaneID, Map,
+    MovementID, Path, Position, Traversable, TurnID, TurnType,
 };
 
 use crate::analytics::SlidingWindow;
@@ -68,7 +69,9 @@ impl Sim {
         match id {
             AgentID::Car(id) => self.driving.debug_car_json(id),
             AgentID::Pedestrian(id) => self.walking.debug_ped_json(id),
-            AgentID::BusPassenger(_, id) => self.driving.debug_car_json(id),
+            AgentID::BusPassenger(_, id) | AgentID::CarPassenger(_, id) => {
+                self.driving.debug_car_json(id)
+            }
         }
     }
 
@@ -86,7 +89,7 @@ impl Sim {
             AgentID::Pedestrian(id) => self.walking.agent_properties(map, id, self.time),
             AgentID::Car(id) => self.driving.agent_properties(id, self.time),
             // TODO Harder to measure some of this stuff
-            AgentID::BusPassenger(_, _) => AgentProperties {
+            AgentID::BusPassenger(_, _) | AgentID::CarPassenger(_, _) => AgentProperties {
                 total_time: Duration::ZERO,
                 waiting_here: Duration::ZERO,
                 total_waiting: Duration::ZERO,
@@ -204,6 +207,7 @@ impl Sim {
             VehicleType::Bike,
             VehicleType::Bus,
             VehicleType::Train,
+            VehicleType::Truck,
         ] {
             let id = CarID {
                 id: idx,
@@ -214,13 +218,15 @@ impl Sim {
             }
         }
 
-        let id = CarID {
-            id: idx,
-            vehicle_type: VehicleType::Car,
-        };
-        // Only cars can be parked.
-        if self.parking.lookup_parked_car(id).is_some() {
-            return Some(id);
+        // Only cars and trucks can be parked.
+        for vehicle_type in [VehicleType::Car, VehicleType::Truck] {
+            let id = CarID {
+                id: idx,
+                vehicle_type,
+            };
+            if self.parking.lookup_parked_car(id).is_some() {
+                return Some(id);
+            }
         }
 
         None
@@ -230,7 +236,7 @@ impl Sim {
         match id {
             AgentID::Car(car) => self.driving.get_path(car),
             AgentID::Pedestrian(ped) => self.walking.get_path(ped),
-            AgentID::BusPassenger(_, _) => None,
+            AgentID::BusPassenger(_, _) | AgentID::CarPassenger(_, _) => None,
         }
     }
     pub fn get_all_driving_paths(&self) -> Vec<&Path> {
@@ -241,7 +247,7 @@ impl Sim {
         match id {
             AgentID::Car(car) => self.driving.trace_route(self.time, car, map),
             AgentID::Pedestrian(ped) => self.walking.trace_route(self.time, ped, map),
-            AgentID::BusPassenger(_, _) => None,
+            AgentID::BusPassenger(_, _) | AgentID::CarPassenger(_, _) => None,
         }
     }
 
@@ -272,7 +278,9 @@ impl Sim {
                 .canonical_pt(id, map)
                 .or_else(|| Some(self.get_draw_car(id, map)?.body.last_pt())),
             AgentID::Pedestrian(id) => Some(self.get_draw_ped(id, map)?.pos),
-            AgentID::BusPassenger(_, bus) => Some(self.get_draw_car(bus, map)?.body.last_pt()),
+            AgentID::BusPassenger(_, car) | AgentID::CarPassenger(_, car) => {
+                Some(self.get_draw_car(car, map)?.body.last_pt())
+            }
         }
     }
 
@@ -338,9 +346,48 @@ impl Sim {
             .max(Time::START_OF_DAY + Duration::hours(24))
     }
 
+    /// Returns the stage of a traffic signal's current plan, and the time remaining in it.
     pub fn current_stage_and_remaining_time(&self, i: IntersectionID) -> (usize, Duration) {
+        let (_, stage, remaining) = self.current_plan_stage_and_remaining_time(i);
+        (stage, remaining)
+    }
+
+    /// Returns the plan a traffic signal is currently using, the stage within that plan, and the
+    /// time remaining in the stage.
+    pub fn current_plan_stage_and_remaining_time(
+        &self,
+        i: IntersectionID,
+    ) -> (usize, usize, Duration) {
         self.intersections
-            .current_stage_and_remaining_time(self.time, i)
+            .current_plan_stage_and_remaining_time(self.time, i)
+    }
+
+    /// For each movement through an intersection, count the vehicles queued on incoming lanes
+    /// who'll make that movement next, plus pedestrians waiting to cross.
+    pub fn get_queue_lengths_per_movement(
+        &self,
+        map: &Map,
+        i: IntersectionID,
+    ) -> BTreeMap<MovementID, usize> {
+        let intersection = map.get_i(i);
+        let mut counts: BTreeMap<MovementID, usize> =
+            intersection.movements.keys().map(|m| (*m, 0)).collect();
+        let queued_turns = self.driving.count_queued_turns(i, map);
+        let waiting_peds = self
+            .intersections
+            .get_waiting_agents(i)
+            .into_iter()
+            .filter(|(a, _, _)| matches!(a, AgentID::Pedestrian(_)))
+            .map(|(_, t, _)| (t, 1));
+        for (t, cnt) in queued_turns.into_iter().chain(waiting_peds) {
+            if map.get_t(t).turn_type == TurnType::SharedSidewalkCorner {
+                continue;
+            }
+            *counts
+                .entry(intersection.turn_to_movement(t).0)
+                .or_insert(0) += cnt;
+        }
+        counts
     }
 
     // TODO This is an awkward copy of raw_throughput
@@ -386,6 +433,11 @@ impl Sim {
         self.transit.get_people_waiting_at_stop(at)
     }
 
+    /// Every bike-share dock, with the number of bikes currently docked and the capacity
+    pub fn bike_share_inventory(&self) -> Vec<(BikeShareDockID, usize, usize)> {
+        self.trips.bike_share_inventory()
+    }
+
     pub fn generate_scenario(&self, map: &Map, name: String) -> Scenario {
         self.trips.generate_scenario(map, name)
     }
@@ -425,9 +477,11 @@ impl Sim {
                     .get_person(self.trips.trip_to_person(id).unwrap())
                     .unwrap();
                 let max_speed = match info.mode {
-                    TripMode::Walk | TripMode::Transit => Some(person.ped_speed),
+                    TripMode::Walk | TripMode::Transit | TripMode::BikeShare => {
+                        Some(person.ped_speed)
+                    }
                     // TODO We should really search the vehicles and grab it from there
-                    TripMode::Drive => None,
+                    TripMode::Drive | TripMode::RideHail | TripMode::ParkAndRide => None,
                     // Assume just one bike
                     TripMode::Bike => {
                         person
@@ -531,7 +585,7 @@ pub struct AgentProperties {
 
 /// Why is an agent delayed? If there are multiple reasons, arbitrarily pick one -- ie, somebody
 /// could be blocked by two conflicting turns.
-#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Serialize)]
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Serialize, JsonSchema)]
 pub enum DelayCause {
     /// Queued behind someone, or someone's doing a conflicting turn, or someone's eating up space
     /// in a target queue
@@ -0,0 +1,95 @@
+//! Run small scenarios on the handcrafted maps from `map_model::toy`, for tests.
+
+use abstutil::Timer;
+use geom::{Duration, Time};
+use map_model::raw::RawMap;
+use map_model::toy::toy_raw_map;
+use map_model::{IntersectionType, Map};
+use rand::SeedableRng;
+use rand_xorshift::XorShiftRng;
+
+use crate::{Event, IndividTrip, PersonSpec, Scenario, Sim, SimOptions};
+
+/// Two blocks side by side, making up a 400m square, with a short street leading off the map from
+/// each corner. Every lane can reach every other one, so buildings added anywhere inside the
+/// square can reach each other by car. Finish it with `map_model::toy::build`.
+pub fn two_blocks() -> RawMap {
+    toy_raw_map(
+        &[
+            ("nw", 0.0, 0.0, IntersectionType::StopSign),
+            ("n", 200.0, 0.0, IntersectionType::StopSign),
+            ("ne", 400.0, 0.0, IntersectionType::StopSign),
+            ("se", 400.0, 400.0, IntersectionType::StopSign),
+            ("s", 200.0, 400.0, IntersectionType::StopSign),
+            ("sw", 0.0, 400.0, IntersectionType::StopSign),
+            ("nw border", -100.0, 0.0, IntersectionType::Border),
+            ("ne border", 500.0, 0.0, IntersectionType::Border),
+            ("se border", 500.0, 400.0, IntersectionType::Border),
+            ("sw border", -100.0, 400.0, IntersectionType::Border),
+        ],
+        &[
+            ("nw", "n"),
+            ("n", "ne"),
+            ("ne", "se"),
+            ("se", "s"),
+            ("s", "sw"),
+            ("sw", "nw"),
+            ("n", "s"),
+            ("nw border", "nw"),
+            ("ne", "ne border"),
+            ("se", "se border"),
+            ("sw border", "sw"),
+        ],
+    )
+}
+
+/// A scenario with one person per list of trips, and buses running on every route
+pub fn scenario(map: &Map, people: Vec<Vec<IndividTrip>>) -> Scenario {
+    let mut scenario = Scenario::empty(map, "test");
+    scenario.only_seed_buses = None;
+    scenario.people = people
+        .into_iter()
+        .map(|trips| PersonSpec {
+            orig_id: None,
+            trips,
+        })
+        .collect();
+    scenario
+}
+
+/// Start a scenario without running it
+pub fn start(map: &Map, scenario: &Scenario, opts: SimOptions) -> Sim {
+    let mut sim = Sim::new(map, opts);
+    sim.start_buffering_events();
+    scenario.validate().unwrap();
+    scenario.instantiate(
+        &mut sim,
+        map,
+        &mut XorShiftRng::seed_from_u64(42),
+        &mut Timer::throwaway(),
+    );
+    sim
+}
+
+/// Keep running until every trip is done or it's `limit` into the day, and return everything that
+/// happened.
+pub fn run_until_done(sim: &mut Sim, map: &Map, limit: Duration) -> Vec<Event> {
+    let mut events = Vec::new();
+    while !sim.is_done() && sim.time() < Time::START_OF_DAY + limit {
+        sim.timed_step(
+            map,
+            Duration::seconds(30.0),
+            &mut None,
+            &mut Timer::throwaway(),
+        );
+        events.extend(sim.drain_buffered_events().into_iter().map(|(_, ev)| ev));
+    }
+    events
+}
+
+/// Start and run a scenario with the default options.
+pub fn run(map: &Map, scenario: &Scenario, limit: Duration) -> (Sim, Vec<Event>) {
+    let mut sim = start(map, scenario, SimOptions::new("test"));
+    let events = run_until_done(&mut sim, map, limit);
+    (sim, events)
+}
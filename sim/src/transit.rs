@@ -3,7 +3,7 @@ use std::collections::{BTreeMap, BTreeSet};
 use serde::{Deserialize, Serialize};
 
 use abstutil::{deserialize_btreemap, serialize_btreemap};
-use geom::Time;
+use geom::{Duration, Time};
 use map_model::{BusRoute, BusRouteID, BusStopID, Map, Path, PathRequest, Position};
 
 use crate::sim::Ctx;
@@ -15,6 +15,13 @@ use crate::{
 // These index stops along a route, not stops along a single sidewalk.
 type StopIdx = usize;
 
+/// Opening and closing the doors, even if nobody gets on or off
+const MIN_DWELL_TIME: Duration = Duration::const_seconds(10.0);
+const TIME_PER_BOARDING: Duration = Duration::const_seconds(3.0);
+const TIME_PER_ALIGHTING: Duration = Duration::const_seconds(2.0);
+/// Trains have many doors, so passengers get on and off in parallel.
+const TRAIN_DOORS: f64 = 8.0;
+
 #[derive(Serialize, Deserialize, Clone)]
 struct Stop {
     id: BusStopID,
@@ -34,9 +41,16 @@ struct Route {
 struct Bus {
     car: CarID,
     route: BusRouteID,
+    /// When this vehicle was scheduled to begin its route
+    departure: Time,
     /// Where does each passenger want to deboard?
     passengers: Vec<(PersonID, Option<BusStopID>)>,
     state: BusState,
+    /// While stopped, how many people showed up and boarded after the doors opened. The vehicle
+    /// waits longer for them.
+    late_boardings: usize,
+    /// While stopped, how many people couldn't board because the vehicle was full
+    left_behind: usize,
 }
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -67,6 +81,15 @@ pub(crate) struct TransitSimState {
         deserialize_with = "deserialize_btreemap"
     )]
     peds_waiting: BTreeMap<BusStopID, Vec<(PedestrianID, BusRouteID, Option<BusStopID>, Time)>>,
+    /// When a route is edited live, vehicles already serving it finish the old version of the
+    /// route, without picking up anybody new.
+    #[serde(
+        serialize_with = "serialize_btreemap",
+        deserialize_with = "deserialize_btreemap"
+    )]
+    retired: BTreeMap<CarID, Route>,
+    /// How many routes the map had the last time new ones were checked for
+    num_routes: usize,
 
     events: Vec<Event>,
 }
@@ -83,6 +106,8 @@ impl TransitSimState {
             buses: BTreeMap::new(),
             routes: BTreeMap::new(),
             peds_waiting,
+            retired: BTreeMap::new(),
+            num_routes: map.all_bus_routes().len(),
             events: Vec::new(),
         }
     }
@@ -153,7 +178,7 @@ impl TransitSimState {
         self.routes[&bus_route.id].start.clone()
     }
 
-    pub fn bus_created(&mut self, bus: CarID, r: BusRouteID) {
+    pub fn bus_created(&mut self, bus: CarID, r: BusRouteID, departure: Time) {
         let route = self.routes.get_mut(&r).unwrap();
         route.active_vehicles.insert(bus);
         self.buses.insert(
@@ -161,14 +186,17 @@ impl TransitSimState {
             Bus {
                 car: bus,
                 route: r,
+                departure,
                 passengers: Vec::new(),
                 state: BusState::DrivingToStop(0),
+                late_boardings: 0,
+                left_behind: 0,
             },
         );
     }
 
-    /// If true, the bus is idling. If false, the bus actually arrived at a border and should now
-    /// vanish.
+    /// If the bus is idling at a stop, returns how long it waits there to let passengers on and
+    /// off. If None, the bus actually arrived at a border and should now vanish.
     pub fn bus_arrived_at_stop(
         &mut self,
         now: Time,
@@ -176,19 +204,42 @@ impl TransitSimState {
         trips: &mut TripManager,
         walking: &mut WalkingSimState,
         ctx: &mut Ctx,
-    ) -> bool {
+    ) -> Option<Duration> {
         let mut bus = self.buses.get_mut(&id).unwrap();
+        let retired = self.retired.contains_key(&id);
         match bus.state {
             BusState::DrivingToStop(stop_idx) => {
                 bus.state = BusState::AtStop(stop_idx);
-                let stop1 = self.routes[&bus.route].stops[stop_idx].id;
+                let stop1 = self
+                    .retired
+                    .get(&id)
+                    .unwrap_or_else(|| &self.routes[&bus.route])
+                    .stops[stop_idx]
+                    .id;
                 self.events
                     .push(Event::BusArrivedAtStop(id, bus.route, stop1));
+                let scheduled = if retired {
+                    None
+                } else {
+                    ctx.map
+                        .get_br(bus.route)
+                        .scheduled_arrival(bus.departure, stop_idx)
+                };
+                if let Some(scheduled) = scheduled {
+                    self.events.push(Event::TransitScheduleDeviation {
+                        bus: id,
+                        route: bus.route,
+                        stop: stop1,
+                        late_by: now - scheduled,
+                    });
+                }
 
                 // Deboard existing passengers.
                 let mut still_riding = Vec::new();
+                let mut alightings = 0;
                 for (person, maybe_stop2) in bus.passengers.drain(..) {
                     if Some(stop1) == maybe_stop2 {
+                        alightings += 1;
                         trips.person_left_bus(now, person, bus.car, ctx);
                         self.events.push(Event::PassengerAlightsTransit(
                             person, bus.car, bus.route, stop1,
@@ -199,12 +250,21 @@ impl TransitSimState {
                 }
                 bus.passengers = still_riding;
 
-                // Board new passengers.
+                // Board new passengers, until the vehicle fills up.
+                let capacity = id.vehicle_type.transit_capacity().unwrap();
                 let mut still_waiting = Vec::new();
+                let mut boardings = 0;
+                // Retired buses may reach stops since removed by live edits
                 for (ped, route, maybe_stop2, started_waiting) in
-                    self.peds_waiting.remove(&stop1).unwrap()
+                    self.peds_waiting.remove(&stop1).unwrap_or_default()
                 {
-                    if bus.route == route {
+                    if bus.route != route || retired {
+                        still_waiting.push((ped, route, maybe_stop2, started_waiting));
+                    } else if bus.passengers.len() >= capacity {
+                        bus.left_behind += 1;
+                        still_waiting.push((ped, route, maybe_stop2, started_waiting));
+                    } else {
+                        boardings += 1;
                         let (trip, person) = trips.ped_boarded_bus(
                             now,
                             ped,
@@ -239,19 +299,22 @@ impl TransitSimState {
                             TripPhaseType::RidingBus(route, stop1, bus.car),
                         ));
                         bus.passengers.push((person, maybe_stop2));
-                    } else {
-                        still_waiting.push((ped, route, maybe_stop2, started_waiting));
                     }
                 }
-                self.peds_waiting.insert(stop1, still_waiting);
-                true
+                if ctx.map.maybe_get_bs(stop1).is_some() {
+                    self.peds_waiting.insert(stop1, still_waiting);
+                }
+
+                Some(MIN_DWELL_TIME.max(boarding_time(id, boardings, alightings)))
             }
             BusState::DrivingOffMap => {
-                self.routes
-                    .get_mut(&bus.route)
-                    .unwrap()
-                    .active_vehicles
-                    .remove(&id);
+                if self.retired.remove(&id).is_none() {
+                    self.routes
+                        .get_mut(&bus.route)
+                        .unwrap()
+                        .active_vehicles
+                        .remove(&id);
+                }
                 bus.state = BusState::Done;
                 for (person, maybe_stop2) in bus.passengers.drain(..) {
                     if let Some(stop2) = maybe_stop2 {
@@ -263,21 +326,52 @@ impl TransitSimState {
                     }
                     trips.transit_rider_reached_border(now, person, id, ctx);
                 }
-                false
+                None
             }
             BusState::AtStop(_) | BusState::Done => unreachable!(),
         }
     }
 
+    /// A vehicle finished idling at a stop. If people boarded while it was there, returns how much
+    /// longer it has to wait for them before departing.
+    pub fn late_boarding_delay(&mut self, id: CarID) -> Option<Duration> {
+        let bus = self.buses.get_mut(&id).unwrap();
+        if bus.late_boardings == 0 {
+            return None;
+        }
+        let delay = boarding_time(id, bus.late_boardings, 0);
+        bus.late_boardings = 0;
+        Some(delay)
+    }
+
     pub fn bus_departed_from_stop(&mut self, id: CarID, map: &Map) -> Router {
         let mut bus = self.buses.get_mut(&id).unwrap();
-        let route = self.routes.get_mut(&bus.route).unwrap();
-        match bus.state {
+        let route = match self.retired.get_mut(&id) {
+            Some(route) => route,
+            None => self.routes.get_mut(&bus.route).unwrap(),
+        };
+        let router = match bus.state {
             BusState::DrivingToStop(_) | BusState::DrivingOffMap | BusState::Done => unreachable!(),
             BusState::AtStop(stop_idx) => {
                 let stop = &route.stops[stop_idx];
                 self.events
                     .push(Event::BusDepartedFromStop(id, bus.route, stop.id));
+                if bus.left_behind > 0 {
+                    self.events.push(Event::TransitBoardingDenied {
+                        bus: id,
+                        route: bus.route,
+                        stop: stop.id,
+                        left_behind: bus.left_behind,
+                    });
+                    bus.left_behind = 0;
+                }
+                self.events.push(Event::TransitVehicleLoad {
+                    bus: id,
+                    route: bus.route,
+                    stop: stop.id,
+                    passengers: bus.passengers.len(),
+                    capacity: id.vehicle_type.transit_capacity().unwrap(),
+                });
                 if let Some(path) = stop.next_stop.clone() {
                     bus.state = BusState::DrivingToStop(stop_idx + 1);
                     Router::follow_bus_route(id, path)
@@ -297,7 +391,11 @@ impl TransitSimState {
                     Router::vanish_bus(id, stop.driving_pos, map)
                 }
             }
+        };
+        if matches!(bus.state, BusState::Done) {
+            self.retired.remove(&id);
         }
+        router
     }
 
     /// Returns the bus if the pedestrian boarded immediately.
@@ -317,11 +415,15 @@ impl TransitSimState {
             for bus in &route.active_vehicles {
                 if let BusState::AtStop(idx) = self.buses[bus].state {
                     if route.stops[idx].id == stop1 {
-                        self.buses
-                            .get_mut(bus)
-                            .unwrap()
-                            .passengers
-                            .push((person, maybe_stop2));
+                        let vehicle = self.buses.get_mut(bus).unwrap();
+                        if vehicle.passengers.len() >= bus.vehicle_type.transit_capacity().unwrap()
+                        {
+                            // Reported when the vehicle departs
+                            vehicle.left_behind += 1;
+                            continue;
+                        }
+                        vehicle.passengers.push((person, maybe_stop2));
+                        vehicle.late_boardings += 1;
                         self.events.push(Event::TripPhaseStarting(
                             trip,
                             person,
@@ -347,13 +449,85 @@ impl TransitSimState {
             );
         }
 
+        // The stop may have been removed by live edits while the pedestrian was walking to it.
+        // Like an uninstantiated route, they'll just wait.
         self.peds_waiting
-            .get_mut(&stop1)
-            .unwrap()
+            .entry(stop1)
+            .or_default()
             .push((ped, route_id, maybe_stop2, now));
         None
     }
 
+    /// Routes and stops may have been created, changed, or deleted. Returns pedestrians waiting
+    /// for a route that no longer takes them where they're going; their trips must be cancelled.
+    pub fn handle_live_edits(&mut self, map: &Map) -> Vec<PedestrianID> {
+        let mut stranded = Vec::new();
+        for bs in map.all_bus_stops().keys() {
+            self.peds_waiting.entry(*bs).or_default();
+        }
+        self.peds_waiting.retain(|bs, waiting| {
+            if map.maybe_get_bs(*bs).is_some() {
+                return true;
+            }
+            stranded.extend(waiting.iter().map(|(ped, _, _, _)| *ped));
+            false
+        });
+
+        // Throw away any instantiated route that doesn't match the map anymore. It'll be created
+        // again when the next vehicle starts.
+        let stale: Vec<BusRouteID> = self
+            .routes
+            .iter()
+            .filter(|(id, route)| match map.maybe_get_br(**id) {
+                Some(r) => {
+                    r.is_deleted()
+                        || r.stops != route.stops.iter().map(|s| s.id).collect::<Vec<_>>()
+                        || r.start != route.start.get_req().start.lane()
+                        || r.end_border
+                            != route.end_at_border.as_ref().map(|p| p.get_req().end.lane())
+                }
+                None => true,
+            })
+            .map(|(id, _)| *id)
+            .collect();
+        for id in stale {
+            let route = self.routes.remove(&id).unwrap();
+            for bus in &route.active_vehicles {
+                let mut retired = route.clone();
+                retired.active_vehicles = std::iter::once(*bus).collect();
+                self.retired.insert(*bus, retired);
+            }
+        }
+
+        for (stop1, waiting) in self.peds_waiting.iter_mut() {
+            waiting.retain(|(ped, route, maybe_stop2, _)| {
+                let ok = map
+                    .maybe_get_br(*route)
+                    .and_then(|r| {
+                        let idx1 = r.stops.iter().position(|bs| bs == stop1)?;
+                        Some(match maybe_stop2 {
+                            Some(stop2) => r.stops[idx1 + 1..].contains(stop2),
+                            None => r.end_border.is_some(),
+                        })
+                    })
+                    .unwrap_or(false);
+                if !ok {
+                    stranded.push(*ped);
+                }
+                ok
+            });
+        }
+        stranded
+    }
+
+    /// Returns routes created since the last call, which need vehicles scheduled.
+    pub fn new_routes(&mut self, map: &Map) -> Vec<BusRouteID> {
+        let num_routes = map.all_bus_routes().len();
+        let routes = (self.num_routes..num_routes).map(BusRouteID).collect();
+        self.num_routes = num_routes;
+        routes
+    }
+
     pub fn collect_events(&mut self) -> Vec<Event> {
         self.events.drain(..).collect()
     }
@@ -396,7 +570,7 @@ impl TransitSimState {
     pub fn active_vehicles(&self) -> (usize, usize) {
         let mut buses = 0;
         let mut trains = 0;
-        for r in self.routes.values() {
+        for r in self.routes.values().chain(self.retired.values()) {
             let len = r.active_vehicles.len();
             if len > 0 {
                 if r.active_vehicles.iter().next().unwrap().vehicle_type == VehicleType::Bus {
@@ -448,3 +622,80 @@ impl TransitSimState {
         results
     }
 }
+
+/// How long it takes people to get on and off a vehicle
+fn boarding_time(vehicle: CarID, boardings: usize, alightings: usize) -> Duration {
+    let time = TIME_PER_BOARDING * (boardings as f64) + TIME_PER_ALIGHTING * (alightings as f64);
+    if vehicle.vehicle_type == VehicleType::Train {
+        time / TRAIN_DOORS
+    } else {
+        time
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use map_model::toy::{add_building, add_bus_route, build, building, toy_raw_map};
+    use map_model::IntersectionType;
+
+    use super::*;
+    use crate::testing;
+    use crate::{IndividTrip, TripEndpoint, TripMode, TripPurpose, BUS_CAPACITY};
+
+    #[test]
+    fn full_bus_leaves_riders_behind() {
+        let mut raw = toy_raw_map(
+            &[
+                ("w", 0.0, 100.0, IntersectionType::Border),
+                ("a", 100.0, 100.0, IntersectionType::StopSign),
+                ("b", 2100.0, 100.0, IntersectionType::StopSign),
+                ("e", 2200.0, 100.0, IntersectionType::Border),
+                ("na", 100.0, 0.0, IntersectionType::Border),
+                ("nb", 2100.0, 0.0, IntersectionType::Border),
+            ],
+            &[("w", "a"), ("a", "b"), ("b", "e"), ("a", "na"), ("b", "nb")],
+        );
+        add_building(&mut raw, "home", 200.0, 120.0, 0);
+        add_building(&mut raw, "shop", 2000.0, 120.0, 0);
+        let mut map = build(raw);
+        let (route, stops) = add_bus_route(
+            &mut map,
+            "A",
+            &[("a", "b", 100.0), ("a", "b", 1900.0)],
+            Duration::minutes(15),
+        );
+
+        // More people than fit on one bus all show up for the same one
+        let riders = BUS_CAPACITY + 5;
+        let trip = IndividTrip::new(
+            Time::START_OF_DAY + Duration::hours(7) + Duration::minutes(50),
+            TripPurpose::Shopping,
+            TripEndpoint::Bldg(building(&map, "home")),
+            TripEndpoint::Bldg(building(&map, "shop")),
+            TripMode::Transit,
+        );
+        let scenario = testing::scenario(&map, vec![vec![trip]; riders]);
+        let (sim, events) = testing::run(&map, &scenario, Duration::hours(10));
+
+        let denied: Vec<(BusRouteID, BusStopID, usize)> = events
+            .iter()
+            .filter_map(|ev| match ev {
+                Event::TransitBoardingDenied {
+                    route,
+                    stop,
+                    left_behind,
+                    ..
+                } => Some((*route, *stop, *left_behind)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(denied, vec![(route, stops[0], 5)]);
+        // Everybody gets there eventually, on the next bus
+        assert_eq!(sim.num_trips(), (riders, 0));
+        let boarded = events
+            .iter()
+            .filter(|ev| matches!(ev, Event::PassengerBoardsTransit(..)))
+            .count();
+        assert_eq!(boarded, riders);
+    }
+}
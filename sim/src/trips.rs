@@ -1,20 +1,25 @@
-use std::collections::{BTreeMap, VecDeque};
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
 
+use anyhow::Result;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 use abstutil::{deserialize_btreemap, serialize_btreemap, Counter};
 use geom::{Distance, Duration, Speed, Time};
 use map_model::{
-    BuildingID, BusRouteID, BusStopID, IntersectionID, Map, PathConstraints, PathRequest, Position,
+    BikeShareDockID, BuildingID, BusRouteID, BusStopID, ChargingStationID, IntersectionID, Map,
+    Path, PathConstraints, PathRequest, Position, TimeDependentPathfinder,
 };
 
+use crate::bike_share::closest_dock;
 use crate::sim::Ctx;
 use crate::{
-    AgentID, AgentType, AlertLocation, CarID, Command, CreateCar, CreatePedestrian, DrivingGoal,
-    Event, IndividTrip, OrigPersonID, ParkedCar, ParkingSim, ParkingSpot, PedestrianID, PersonID,
-    PersonSpec, Scenario, SidewalkPOI, SidewalkSpot, StartTripArgs, TransitSimState, TripEndpoint,
-    TripID, TripPhaseType, TripPurpose, TripSpec, Vehicle, VehicleSpec, VehicleType,
-    WalkingSimState,
+    estimate_energy, AgentID, AgentType, AlertLocation, BikeShareSystem, CarID, CarpoolDriver,
+    ChargingNetwork, Command, CreateCar, CreatePedestrian, DrivingGoal, Event, FleetVehicleState,
+    IndividTrip, OrigPersonID, ParkedCar, ParkingSim, ParkingSpot, PedestrianID, PersonID,
+    PersonSpec, RideHailFleet, Router, Scenario, SidewalkPOI, SidewalkSpot, StartTripArgs,
+    TransitSimState, TripEndpoint, TripID, TripPhaseType, TripPurpose, TripSpec, Vehicle,
+    VehicleSpec, VehicleType, WalkingSimState,
 };
 
 /// Manages people, each of which executes some trips through the day. Each trip is further broken
@@ -34,6 +39,26 @@ pub(crate) struct TripManager {
     )]
     active_trip_mode: BTreeMap<AgentID, TripID>,
     unfinished_trips: usize,
+    /// For each driver giving somebody a ride, all of the passengers' trips
+    #[serde(
+        serialize_with = "serialize_btreemap",
+        deserialize_with = "deserialize_btreemap"
+    )]
+    carpool_passengers: BTreeMap<TripID, Vec<TripID>>,
+    /// Who's currently riding in each car, not counting the driver
+    #[serde(
+        serialize_with = "serialize_btreemap",
+        deserialize_with = "deserialize_btreemap"
+    )]
+    car_riders: BTreeMap<CarID, Vec<PersonID>>,
+    ride_hail: RideHailFleet,
+    bike_share: BikeShareSystem,
+    /// Cars left near a transit stop by the first half of a park-and-ride trip
+    parked_for_transit: BTreeSet<CarID>,
+    charging: ChargingNetwork,
+    /// If present, cars and trucks plan their route around congestion observed in an earlier
+    /// simulation when they start driving.
+    congestion: Option<TimeDependentPathfinder>,
 
     car_id_counter: usize,
 
@@ -42,12 +67,19 @@ pub(crate) struct TripManager {
 
 // Initialization
 impl TripManager {
-    pub fn new() -> TripManager {
+    pub fn new(congestion: Option<TimeDependentPathfinder>) -> TripManager {
         TripManager {
             trips: Vec::new(),
             people: Vec::new(),
             active_trip_mode: BTreeMap::new(),
             unfinished_trips: 0,
+            carpool_passengers: BTreeMap::new(),
+            car_riders: BTreeMap::new(),
+            ride_hail: RideHailFleet::new(),
+            bike_share: BikeShareSystem::new(),
+            parked_for_transit: BTreeSet::new(),
+            charging: ChargingNetwork::new(),
+            congestion,
             car_id_counter: 0,
             events: Vec::new(),
         }
@@ -82,6 +114,7 @@ impl TripManager {
             vehicles,
             delayed_trips: Vec::new(),
             on_bus: None,
+            in_car: None,
         });
         self.get_person(id).unwrap()
     }
@@ -126,6 +159,9 @@ impl TripManager {
             }
         }
         person.trips.push(id);
+        if let Some(driver) = trip.info.ride_with {
+            self.carpool_passengers.entry(driver).or_default().push(id);
+        }
         self.trips.push(trip);
         id
     }
@@ -156,12 +192,29 @@ impl TripManager {
         }
         self.trips[trip.0].started = true;
 
+        if self.trips[trip.0].info.ride_with.is_some() {
+            self.start_carpool_passenger(now, trip, ctx);
+            return;
+        }
+        if self.trips[trip.0].info.mode == TripMode::RideHail {
+            self.start_ride_hail_rider(now, trip, ctx);
+            return;
+        }
+
         let info = &self.trips[trip.0].info;
+        // Is the car still waiting near a transit stop from the first half of a park-and-ride?
+        let left_car_at = args
+            .use_vehicle
+            .filter(|car| self.parked_for_transit.contains(car))
+            .and_then(|car| ctx.parking.lookup_parked_car(car))
+            .map(|p| SidewalkSpot::parking_spot(p.spot, ctx.map, ctx.parking).sidewalk_pos);
         let spec = match TripSpec::maybe_new(
+            now,
             info.start,
             info.end,
             info.mode,
             args.use_vehicle,
+            left_car_at,
             args.retry_if_no_room,
             ctx.map,
         ) {
@@ -175,13 +228,16 @@ impl TripManager {
         let (spec, legs) = spec.into_plan(ctx.map);
         assert!(self.trips[trip.0].legs.is_empty());
         self.trips[trip.0].legs.extend(legs);
+        self.add_carpool_stops(now, trip, ctx);
+        self.add_delivery_stops(trip, ctx);
+        let person = &mut self.people[self.trips[trip.0].person.0];
 
         match spec {
             TripSpec::VehicleAppearing {
                 start_pos,
-                goal,
                 retry_if_no_room,
                 use_vehicle,
+                ..
             } => {
                 assert_eq!(person.state, PersonState::OffMap);
                 self.events.push(Event::PersonEntersMap(
@@ -193,11 +249,12 @@ impl TripManager {
 
                 let vehicle = person.get_vehicle(use_vehicle);
                 assert!(ctx.parking.lookup_parked_car(vehicle.id).is_none());
-                let constraints = if use_vehicle.vehicle_type == VehicleType::Bike {
-                    PathConstraints::Bike
-                } else {
-                    PathConstraints::Car
+                // Head for the first carpool or delivery stop, if there is one
+                let goal = match self.trips[trip.0].legs[0] {
+                    TripLeg::Drive(_, ref goal) => goal.clone(),
+                    _ => unreachable!(),
                 };
+                let constraints = use_vehicle.vehicle_type.to_constraints();
                 let req = PathRequest::vehicle(
                     start_pos,
                     goal.goal_pos(constraints, ctx.map).unwrap(),
@@ -205,9 +262,14 @@ impl TripManager {
                 );
                 let person = person.id;
 
-                match ctx.map.pathfind(req) {
+                match self.pathfind_vehicle(now, req, ctx.map) {
                     Ok(path) => {
-                        let router = goal.make_router(vehicle.id, path, ctx.map);
+                        let router = goal.make_router(
+                            vehicle.id,
+                            path,
+                            Some(self.planned_stay(now, trip)),
+                            ctx.map,
+                        );
                         ctx.scheduler.push(
                             now,
                             Command::SpawnCar(
@@ -229,6 +291,9 @@ impl TripManager {
             }
             TripSpec::UsingParkedCar {
                 car, start_bldg, ..
+            }
+            | TripSpec::DriveThenTransit {
+                car, start_bldg, ..
             } => {
                 assert_eq!(person.state, PersonState::Inside(start_bldg));
                 person.state = PersonState::Trip(trip);
@@ -379,7 +444,7 @@ impl TripManager {
                     );
                 }
             }
-            TripSpec::UsingTransit { start, stop1, .. } => {
+            TripSpec::UsingTransit { start, .. } | TripSpec::TransitThenDrive { start, .. } => {
                 assert_eq!(
                     person.state,
                     match start.connection {
@@ -407,7 +472,72 @@ impl TripManager {
                 );
                 person.state = PersonState::Trip(trip);
 
-                let walk_to = SidewalkSpot::bus_stop(stop1, ctx.map);
+                // Head to the first stop
+                let walk_to = match self.trips[trip.0].legs[0] {
+                    TripLeg::Walk(ref spot) => spot.clone(),
+                    _ => unreachable!(),
+                };
+                let req = PathRequest::walking(start.sidewalk_pos, walk_to.sidewalk_pos);
+                match ctx.map.pathfind(req) {
+                    Ok(path) => {
+                        ctx.scheduler.push(
+                            now,
+                            Command::SpawnPed(CreatePedestrian {
+                                id: person.ped,
+                                speed: person.ped_speed,
+                                start,
+                                goal: walk_to,
+                                path,
+                                trip,
+                                person: person.id,
+                            }),
+                        );
+                    }
+                    Err(err) => {
+                        self.cancel_trip(now, trip, err.to_string(), None, ctx);
+                    }
+                }
+            }
+            TripSpec::UsingBikeShare { start, pick_up, .. } => {
+                assert_eq!(
+                    person.state,
+                    match start.connection {
+                        SidewalkPOI::Building(b) => PersonState::Inside(b),
+                        SidewalkPOI::Border(i) => {
+                            self.events.push(Event::PersonEntersMap(
+                                person.id,
+                                AgentID::Pedestrian(person.ped),
+                                i,
+                            ));
+                            PersonState::OffMap
+                        }
+                        SidewalkPOI::SuddenlyAppear => {
+                            self.events.push(Event::PersonEntersMap(
+                                person.id,
+                                AgentID::Pedestrian(person.ped),
+                                ctx.map.get_l(start.sidewalk_pos.lane()).src_i,
+                            ));
+                            PersonState::OffMap
+                        }
+                        _ => unreachable!(),
+                    }
+                );
+                person.state = PersonState::Trip(trip);
+
+                // Live map edits may have removed the dock's connection to a bike lane
+                let walk_to = match SidewalkSpot::bike_share_dock(pick_up, ctx.map) {
+                    Some(spot) => spot,
+                    None => {
+                        self.cancel_trip(
+                            now,
+                            trip,
+                            format!("{} isn't next to anywhere to bike", pick_up),
+                            None,
+                            ctx,
+                        );
+                        return;
+                    }
+                };
                 let req = PathRequest::walking(start.sidewalk_pos, walk_to.sidewalk_pos);
                 match ctx.map.pathfind(req) {
                     Ok(path) => {
@@ -435,6 +565,719 @@ impl TripManager {
     pub fn collect_events(&mut self) -> Vec<Event> {
         std::mem::take(&mut self.events)
     }
+
+    /// A carpool passenger doesn't do anything until the driver shows up.
+    fn start_carpool_passenger(&mut self, now: Time, id: TripID, ctx: &mut Ctx) {
+        let trip = &mut self.trips[id.0];
+        let driver = trip.info.ride_with.unwrap();
+        let start = match trip.info.start {
+            TripEndpoint::Bldg(b) => b,
+            _ => unreachable!(),
+        };
+        trip.legs.push_back(TripLeg::RideCar(driver));
+        let person = trip.person;
+
+        if !self.carpool_pick_up_pending(driver, id) {
+            self.cancel_trip(
+                now,
+                id,
+                format!("missed their ride with {}", driver),
+                None,
+                ctx,
+            );
+            return;
+        }
+
+        assert_eq!(self.people[person.0].state, PersonState::Inside(start));
+        self.people[person.0].state = PersonState::Trip(id);
+        self.events.push(Event::TripPhaseStarting(
+            id,
+            person,
+            None,
+            TripPhaseType::WaitingForRide,
+        ));
+    }
+
+    /// Will the driver still come by to pick up this passenger?
+    fn carpool_pick_up_pending(&self, driver: TripID, passenger: TripID) -> bool {
+        let trip = &self.trips[driver.0];
+        if trip.finished_at.is_some() || trip.info.cancellation_reason.is_some() {
+            return false;
+        }
+        if !trip.started {
+            return true;
+        }
+        trip.legs
+            .iter()
+            .any(|leg| leg.carpool_stop() == Some((passenger, true)))
+    }
+
+    /// When a driver starts a trip, insert a stop to pick up each passenger, then a stop to drop
+    /// each of them off, before heading to the final destination.
+    fn add_carpool_stops(&mut self, now: Time, driver: TripID, ctx: &mut Ctx) {
+        let passengers = if let Some(list) = self.carpool_passengers.get(&driver) {
+            list.clone()
+        } else {
+            return;
+        };
+
+        let trip = &self.trips[driver.0];
+        // Only cars can carry passengers, and if the trip already failed to start, there's no
+        // vehicle at all.
+        let insert_at = trip.legs.iter().rposition(
+            |leg| matches!(leg, TripLeg::Drive(c, _) if c.vehicle_type == VehicleType::Car),
+        );
+        if let Some(idx) = insert_at {
+            let mut stops = Vec::new();
+            for pick_up in [true, false] {
+                for passenger in &passengers {
+                    let info = &self.trips[passenger.0].info;
+                    if info.cancellation_reason.is_some() {
+                        continue;
+                    }
+                    let b = match (pick_up, info.start, info.end) {
+                        (true, TripEndpoint::Bldg(b), _) | (false, _, TripEndpoint::Bldg(b)) => b,
+                        _ => unreachable!(),
+                    };
+                    let car = match trip.legs[idx] {
+                        TripLeg::Drive(c, _) => c,
+                        _ => unreachable!(),
+                    };
+                    stops.push(TripLeg::Drive(
+                        car,
+                        DrivingGoal::CarpoolStop {
+                            pos: DrivingGoal::ParkNear(b)
+                                .goal_pos(PathConstraints::Car, ctx.map)
+                                .unwrap(),
+                            passenger: *passenger,
+                            pick_up,
+                        },
+                    ));
+                }
+            }
+            let legs = &mut self.trips[driver.0].legs;
+            for (offset, leg) in stops.into_iter().enumerate() {
+                legs.insert(idx + offset, leg);
+            }
+        }
+
+        // Anybody already waiting for this driver may be out of luck
+        self.cancel_stranded_carpool_passengers(now, driver, ctx);
+    }
+
+    /// Cancel any passenger that's waiting for a driver who's no longer coming, or still riding
+    /// with a driver whose trip was cancelled.
+    fn cancel_stranded_carpool_passengers(&mut self, now: Time, driver: TripID, ctx: &mut Ctx) {
+        let passengers = if let Some(list) = self.carpool_passengers.get(&driver) {
+            list.clone()
+        } else {
+            return;
+        };
+        let driver_cancelled = self.trips[driver.0].info.cancellation_reason.is_some();
+        for id in passengers {
+            let trip = &self.trips[id.0];
+            if !trip.started
+                || trip.finished_at.is_some()
+                || trip.info.cancellation_reason.is_some()
+            {
+                continue;
+            }
+            let person = trip.person;
+            if let Some(car) = self.people[person.0].in_car {
+                if !driver_cancelled {
+                    continue;
+                }
+                self.passenger_leaves_car(person, car);
+            } else {
+                if self.carpool_pick_up_pending(driver, id) {
+                    continue;
+                }
+                // They've been waiting inside
+                if let TripEndpoint::Bldg(b) = trip.info.start {
+                    self.events.push(Event::PersonLeavesBuilding(person, b));
+                }
+            }
+            self.cancel_trip(
+                now,
+                id,
+                format!("their ride with {} didn't work out", driver),
+                None,
+                ctx,
+            );
+        }
+    }
+}
+
+// Ride-hailing
+impl TripManager {
+    pub fn new_ride_hail_vehicle(&mut self, spec: VehicleSpec) -> Vehicle {
+        let vehicle = spec.make(
+            CarID {
+                id: self.new_car_id(),
+                vehicle_type: VehicleType::Car,
+            },
+            None,
+        );
+        self.ride_hail.add_vehicle(vehicle.id);
+        vehicle
+    }
+
+    pub fn is_ride_hail_vehicle(&self, car: CarID) -> bool {
+        self.ride_hail.has_vehicle(car)
+    }
+
+    /// A ride-hailing rider waits inside until a vehicle shows up.
+    fn start_ride_hail_rider(&mut self, now: Time, id: TripID, ctx: &mut Ctx) {
+        let trip = &mut self.trips[id.0];
+        trip.legs.push_back(TripLeg::RideHail);
+        let person = trip.person;
+
+        let error = match (trip.info.start, trip.info.end) {
+            (TripEndpoint::Bldg(b1), TripEndpoint::Bldg(b2)) => {
+                if self.ride_hail.is_empty() {
+                    Some("there aren't any ride-hailing vehicles".to_string())
+                } else if curb_pos(b1, ctx.map).is_none() || curb_pos(b2, ctx.map).is_none() {
+                    Some(format!(
+                        "a ride-hailing vehicle can't stop in front of {} or {}",
+                        b1, b2
+                    ))
+                } else {
+                    None
+                }
+            }
+            _ => Some("ride-hailing only works between two buildings".to_string()),
+        };
+        if let Some(error) = error {
+            self.cancel_trip(now, id, error, None, ctx);
+            return;
+        }
+
+        self.people[person.0].state = PersonState::Trip(id);
+        self.events.push(Event::TripPhaseStarting(
+            id,
+            person,
+            None,
+            TripPhaseType::WaitingForRide,
+        ));
+        self.ride_hail.request_ride(id, now);
+        self.dispatch_ride_hail(now, ctx);
+    }
+
+    /// Where does this rider get picked up and dropped off?
+    fn ride_hail_endpoints(&self, rider: TripID) -> (BuildingID, BuildingID) {
+        match (self.trips[rider.0].info.start, self.trips[rider.0].info.end) {
+            (TripEndpoint::Bldg(b1), TripEndpoint::Bldg(b2)) => (b1, b2),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Is this rider still inside, waiting for a vehicle?
+    fn waiting_for_ride_hail(&self, rider: TripID) -> bool {
+        let trip = &self.trips[rider.0];
+        let person = &self.people[trip.person.0];
+        trip.info.cancellation_reason.is_none()
+            && trip.finished_at.is_none()
+            && person.state == PersonState::Trip(rider)
+            && person.in_car.is_none()
+    }
+
+    /// Send the nearest idle vehicle to each rider still waiting, in the order they asked, until
+    /// there are no more riders or idle vehicles.
+    fn dispatch_ride_hail(&mut self, now: Time, ctx: &mut Ctx) {
+        while let Some((rider, requested)) = self.ride_hail.next_waiting() {
+            if !self.waiting_for_ride_hail(rider) {
+                continue;
+            }
+            let pick_up = curb_pos(self.ride_hail_endpoints(rider).0, ctx.map).unwrap();
+            let pt = pick_up.pt(ctx.map);
+            let nearest = self
+                .ride_hail
+                .idle_vehicles()
+                .filter_map(|car| ctx.parking.lookup_parked_car(car))
+                .min_by_key(|p| {
+                    ctx.parking
+                        .spot_to_driving_pos(p.spot, &p.vehicle, ctx.map)
+                        .pt(ctx.map)
+                        .dist_to(pt)
+                })
+                .cloned();
+            let parked_car = if let Some(p) = nearest {
+                p
+            } else {
+                self.ride_hail.still_waiting(rider, requested);
+                return;
+            };
+
+            let car = parked_car.vehicle.id;
+            let start =
+                ctx.parking
+                    .spot_to_driving_pos(parked_car.spot, &parked_car.vehicle, ctx.map);
+            let req = match parked_car.spot {
+                ParkingSpot::Onstreet(_, _) => {
+                    PathRequest::vehicle(start, pick_up, PathConstraints::Car)
+                }
+                ParkingSpot::Offstreet(_, _) | ParkingSpot::Lot(_, _) => {
+                    PathRequest::leave_from_driveway(start, pick_up, PathConstraints::Car, ctx.map)
+                }
+            };
+            match ctx.map.pathfind(req) {
+                Ok(path) => {
+                    self.set_ride_hail_state(car, FleetVehicleState::PickingUp(rider, requested));
+                    ctx.scheduler.push(
+                        now,
+                        Command::SpawnCar(
+                            CreateCar::for_ride_hail(parked_car, Router::passenger_stop(car, path)),
+                            true,
+                        ),
+                    );
+                }
+                Err(err) => {
+                    self.cancel_waiting_ride_hail_rider(now, rider, err.to_string(), ctx);
+                }
+            }
+        }
+    }
+
+    fn cancel_waiting_ride_hail_rider(
+        &mut self,
+        now: Time,
+        rider: TripID,
+        reason: String,
+        ctx: &mut Ctx,
+    ) {
+        // They've been waiting inside
+        let person = self.trips[rider.0].person;
+        let b = self.ride_hail_endpoints(rider).0;
+        self.events.push(Event::PersonLeavesBuilding(person, b));
+        self.cancel_trip(now, rider, reason, None, ctx);
+    }
+
+    fn set_ride_hail_state(&mut self, car: CarID, state: FleetVehicleState) {
+        let before = self.ride_hail.utilization();
+        self.ride_hail.set_state(car, state);
+        let (busy, total) = self.ride_hail.utilization();
+        if (busy, total) != before {
+            self.events
+                .push(Event::RideHailFleetUtilization { busy, total });
+        }
+    }
+
+    fn ride_hail_vehicle_reached_stop(
+        &mut self,
+        now: Time,
+        car: CarID,
+        distance_crossed: Distance,
+        ctx: &mut Ctx,
+    ) -> bool {
+        match self.ride_hail.get_state(car) {
+            FleetVehicleState::PickingUp(rider, requested) => {
+                self.events.push(Event::RideHailDistance {
+                    vehicle: car,
+                    distance: distance_crossed,
+                    with_rider: false,
+                });
+                let b = self.ride_hail_endpoints(rider).0;
+                if !self.waiting_for_ride_hail(rider) {
+                    self.set_ride_hail_state(car, FleetVehicleState::Returning(b));
+                    return false;
+                }
+
+                let person = self.trips[rider.0].person;
+                self.events.push(Event::PersonLeavesBuilding(person, b));
+                self.events.push(Event::PassengerEntersCar(person, car, b));
+                self.events.push(Event::RideHailPickUp {
+                    vehicle: car,
+                    rider,
+                    lane: curb_pos(b, ctx.map).unwrap().lane(),
+                    waited: now - requested,
+                });
+                self.events.push(Event::TripPhaseStarting(
+                    rider,
+                    person,
+                    None,
+                    TripPhaseType::RidingInCar(car),
+                ));
+                self.passenger_enters_car(person, car, rider);
+                self.set_ride_hail_state(car, FleetVehicleState::DroppingOff(rider));
+                true
+            }
+            FleetVehicleState::DroppingOff(rider) => {
+                self.events.push(Event::RideHailDistance {
+                    vehicle: car,
+                    distance: distance_crossed,
+                    with_rider: true,
+                });
+                let b = self.ride_hail_endpoints(rider).1;
+                let trip = &mut self.trips[rider.0];
+                trip.total_distance += distance_crossed;
+                assert_eq!(trip.legs.pop_front(), Some(TripLeg::RideHail));
+                let person = trip.person;
+
+                self.passenger_leaves_car(person, car);
+                self.events.push(Event::PassengerLeavesCar(person, car, b));
+                self.events.push(Event::RideHailDropOff {
+                    vehicle: car,
+                    rider,
+                    lane: curb_pos(b, ctx.map).unwrap().lane(),
+                });
+                self.people[person.0].state = PersonState::Inside(b);
+                self.events.push(Event::PersonEntersBuilding(person, b));
+                self.set_ride_hail_state(car, FleetVehicleState::Returning(b));
+                self.trip_finished(now, rider, ctx);
+                true
+            }
+            FleetVehicleState::Idle | FleetVehicleState::Returning(_) => unreachable!(),
+        }
+    }
+
+    /// After picking up a rider, head to their destination. Otherwise, go pick up whoever's been
+    /// waiting the longest, or look for somewhere to park nearby.
+    fn ride_hail_vehicle_leaving_stop(
+        &mut self,
+        now: Time,
+        car: CarID,
+        pos: Position,
+        ctx: &mut Ctx,
+    ) -> Router {
+        if let FleetVehicleState::DroppingOff(rider) = self.ride_hail.get_state(car) {
+            let (start, end) = self.ride_hail_endpoints(rider);
+            let req =
+                PathRequest::vehicle(pos, curb_pos(end, ctx.map).unwrap(), PathConstraints::Car);
+            match ctx.map.pathfind(req) {
+                Ok(path) => {
+                    return Router::passenger_stop(car, path);
+                }
+                Err(err) => {
+                    // The rider gets warped to their destination, and the vehicle is free again
+                    let person = self.trips[rider.0].person;
+                    self.passenger_leaves_car(person, car);
+                    self.cancel_trip(now, rider, err.to_string(), None, ctx);
+                    self.set_ride_hail_state(car, FleetVehicleState::Returning(start));
+                }
+            }
+        }
+
+        while let Some((rider, requested)) = self.ride_hail.next_waiting() {
+            if !self.waiting_for_ride_hail(rider) {
+                continue;
+            }
+            let req = PathRequest::vehicle(
+                pos,
+                curb_pos(self.ride_hail_endpoints(rider).0, ctx.map).unwrap(),
+                PathConstraints::Car,
+            );
+            match ctx.map.pathfind(req) {
+                Ok(path) => {
+                    self.set_ride_hail_state(car, FleetVehicleState::PickingUp(rider, requested));
+                    return Router::passenger_stop(car, path);
+                }
+                Err(err) => {
+                    self.cancel_waiting_ride_hail_rider(now, rider, err.to_string(), ctx);
+                }
+            }
+        }
+
+        let near = match self.ride_hail.get_state(car) {
+            FleetVehicleState::Returning(b) => b,
+            _ => unreachable!(),
+        };
+        let path = Path::one_step(
+            PathRequest::vehicle(
+                pos,
+                Position::end(pos.lane(), ctx.map),
+                PathConstraints::Car,
+            ),
+            ctx.map,
+        );
+        Router::park_near(car, path, near, None)
+    }
+
+    fn ride_hail_vehicle_parked(
+        &mut self,
+        now: Time,
+        car: CarID,
+        distance_crossed: Distance,
+        ctx: &mut Ctx,
+    ) {
+        self.events.push(Event::RideHailDistance {
+            vehicle: car,
+            distance: distance_crossed,
+            with_rider: false,
+        });
+        self.set_ride_hail_state(car, FleetVehicleState::Idle);
+        self.dispatch_ride_hail(now, ctx);
+    }
+
+    /// A ride-hailing vehicle couldn't find anywhere to park, so it leaves the fleet.
+    pub fn ride_hail_vehicle_out_of_service(&mut self, car: CarID, distance_crossed: Distance) {
+        self.events.push(Event::RideHailDistance {
+            vehicle: car,
+            distance: distance_crossed,
+            with_rider: false,
+        });
+        self.ride_hail.remove_vehicle(car);
+        let (busy, total) = self.ride_hail.utilization();
+        self.events
+            .push(Event::RideHailFleetUtilization { busy, total });
+    }
+
+    /// A ride-hailing vehicle was dispatched, but live map edits broke its path before it could
+    /// leave its parking spot. It's still parked, so just dispatch again.
+    pub fn ride_hail_vehicle_cant_spawn(&mut self, now: Time, car: CarID, ctx: &mut Ctx) {
+        if let FleetVehicleState::PickingUp(rider, requested) = self.ride_hail.get_state(car) {
+            self.ride_hail.still_waiting(rider, requested);
+        }
+        self.set_ride_hail_state(car, FleetVehicleState::Idle);
+        self.dispatch_ride_hail(now, ctx);
+    }
+}
+
+// Bike-sharing
+impl TripManager {
+    /// Stock a dock with shared bikes at the start of the simulation.
+    pub fn new_bike_share_dock(
+        &mut self,
+        dock: BikeShareDockID,
+        capacity: usize,
+        bikes: Vec<VehicleSpec>,
+    ) {
+        let bikes = bikes
+            .into_iter()
+            .map(|spec| {
+                spec.make(
+                    CarID {
+                        id: self.new_car_id(),
+                        vehicle_type: VehicleType::Bike,
+                    },
+                    None,
+                )
+            })
+            .collect();
+        self.bike_share.add_dock(dock, capacity, bikes);
+    }
+
+    pub fn has_bike_share_docks(&self) -> bool {
+        !self.bike_share.is_empty()
+    }
+
+    /// All docks, with the number of bikes currently docked and the capacity
+    pub fn bike_share_inventory(&self) -> Vec<(BikeShareDockID, usize, usize)> {
+        self.bike_share.inventory().collect()
+    }
+
+    /// Somebody reached a dock to pick up a bike. If it's empty, they try the next closest dock
+    /// with bikes, or otherwise walk the rest of the way.
+    fn ped_ready_to_bike_share(
+        &mut self,
+        now: Time,
+        id: TripID,
+        spot: SidewalkSpot,
+        ctx: &mut Ctx,
+    ) {
+        let (pick_up, drop_off) = match self.trips[id.0].legs[0] {
+            TripLeg::BikeShare { pick_up, drop_off } => (pick_up, drop_off),
+            _ => unreachable!(),
+        };
+
+        if let Some(bike) = self.bike_share.unlock(pick_up, drop_off) {
+            self.events.push(Event::BikeShareUnlocked {
+                trip: id,
+                dock: pick_up,
+                bike,
+                inventory: self.bike_share.num_bikes(pick_up),
+            });
+            self.trips[id.0].legs[0] = TripLeg::Drive(bike, DrivingGoal::BikeShareDock(drop_off));
+            self.ride_shared_bike(now, id, bike, &spot, drop_off, ctx);
+            return;
+        }
+
+        self.events.push(Event::BikeShareDockEmpty {
+            trip: id,
+            dock: pick_up,
+        });
+        let bike_share = &self.bike_share;
+        if let Some(other) = closest_dock(spot.sidewalk_pos.pt(ctx.map), ctx.map, |d| {
+            d != pick_up && d != drop_off && bike_share.num_bikes(d) > 0
+        }) {
+            let trip = &mut self.trips[id.0];
+            trip.legs[0] = TripLeg::BikeShare {
+                pick_up: other,
+                drop_off,
+            };
+            trip.legs.push_front(TripLeg::Walk(
+                SidewalkSpot::bike_share_dock(other, ctx.map).unwrap(),
+            ));
+        } else {
+            self.trips[id.0].legs.pop_front();
+        }
+        self.spawn_ped(now, id, spot, ctx);
+    }
+
+    /// Start riding a shared bike from a dock (or from a full dock) towards another dock.
+    fn ride_shared_bike(
+        &mut self,
+        now: Time,
+        id: TripID,
+        bike: CarID,
+        spot: &SidewalkSpot,
+        drop_off: BikeShareDockID,
+        ctx: &mut Ctx,
+    ) {
+        let start = match spot.connection {
+            SidewalkPOI::BikeRack(pos) => pos,
+            _ => unreachable!(),
+        };
+        let goal = DrivingGoal::BikeShareDock(drop_off);
+        let maybe_router = goal
+            .goal_pos(PathConstraints::Bike, ctx.map)
+            .ok_or_else(|| anyhow!("{} isn't next to anywhere to bike", drop_off))
+            .and_then(|end| {
+                ctx.map
+                    .pathfind(PathRequest::vehicle(start, end, PathConstraints::Bike))
+            })
+            .map(|path| goal.make_router(bike, path, None, ctx.map));
+        match maybe_router {
+            Ok(router) => {
+                ctx.scheduler.push(
+                    now,
+                    Command::SpawnCar(
+                        CreateCar::for_appearing(
+                            self.bike_share.get_vehicle(bike),
+                            router,
+                            id,
+                            self.trips[id.0].person,
+                        ),
+                        true,
+                    ),
+                );
+            }
+            Err(err) => {
+                self.cancel_trip(now, id, err.to_string(), None, ctx);
+            }
+        }
+    }
+
+    /// A shared bike reached the dock it was headed for. If the dock is full, head for the next
+    /// closest dock with room. If there isn't one, squeeze the bike in anyway.
+    fn shared_bike_reached_dock(
+        &mut self,
+        now: Time,
+        id: TripID,
+        bike: CarID,
+        dock: BikeShareDockID,
+        spot: SidewalkSpot,
+        ctx: &mut Ctx,
+    ) {
+        if !self.bike_share.has_room(dock) {
+            self.events
+                .push(Event::BikeShareDockFull { trip: id, dock });
+            let bike_share = &self.bike_share;
+            if let Some(other) = closest_dock(spot.sidewalk_pos.pt(ctx.map), ctx.map, |d| {
+                d != dock && bike_share.has_room(d)
+            }) {
+                self.bike_share.change_destination(bike, other);
+                self.trips[id.0]
+                    .legs
+                    .push_front(TripLeg::Drive(bike, DrivingGoal::BikeShareDock(other)));
+                self.ride_shared_bike(now, id, bike, &spot, other, ctx);
+                return;
+            }
+        }
+
+        let inventory = self.bike_share.return_bike(bike, dock);
+        self.events.push(Event::BikeShareReturned {
+            trip: id,
+            dock,
+            bike,
+            inventory,
+        });
+        self.spawn_ped(now, id, spot, ctx);
+    }
+
+    /// The operator moves bikes from full docks to empty ones.
+    pub fn rebalance_bike_share(&mut self) {
+        for (from, to, bikes) in self.bike_share.rebalance() {
+            self.events.push(Event::BikeShareRebalanced {
+                from,
+                to,
+                bikes,
+                from_inventory: self.bike_share.num_bikes(from),
+                to_inventory: self.bike_share.num_bikes(to),
+            });
+        }
+    }
+}
+
+// Deliveries
+impl TripManager {
+    /// When somebody starts a delivery tour, insert a stop in front of each building before
+    /// heading to the final destination.
+    fn add_delivery_stops(&mut self, id: TripID, ctx: &Ctx) {
+        let trip = &mut self.trips[id.0];
+        if trip.info.delivery_stops.is_empty() {
+            return;
+        }
+        // If the trip already failed to start, there's no truck at all.
+        let idx = if let Some(idx) = trip.legs.iter().rposition(
+            |leg| matches!(leg, TripLeg::Drive(c, _) if c.vehicle_type == VehicleType::Truck),
+        ) {
+            idx
+        } else {
+            return;
+        };
+        let truck = match trip.legs[idx] {
+            TripLeg::Drive(c, _) => c,
+            _ => unreachable!(),
+        };
+
+        let mut stops = Vec::new();
+        for b in &trip.info.delivery_stops {
+            if let Some(pos) = DrivingGoal::ParkNear(*b).goal_pos(PathConstraints::Truck, ctx.map) {
+                stops.push(TripLeg::Drive(
+                    truck,
+                    DrivingGoal::LoadingZone { pos, building: *b },
+                ));
+            }
+        }
+        for (offset, leg) in stops.into_iter().enumerate() {
+            trip.legs.insert(idx + offset, leg);
+        }
+    }
+
+    /// A truck stopped in the driving lane in front of a building, and will block the lane while
+    /// unloading.
+    pub fn truck_reached_loading_zone(&mut self, truck: CarID, distance_crossed: Distance) {
+        let id = self.active_trip_mode[&AgentID::Car(truck)];
+        let trip = &mut self.trips[id.0];
+        trip.total_distance += distance_crossed;
+
+        // Unload everything going to this same spot at once
+        let mut here = None;
+        while let Some(TripLeg::Drive(c, DrivingGoal::LoadingZone { pos, building })) =
+            trip.legs.front().cloned()
+        {
+            if here.map(|x| x != pos).unwrap_or(false) {
+                break;
+            }
+            assert_eq!(c, truck);
+            trip.legs.pop_front();
+            if here.is_none() {
+                self.events.push(Event::TripPhaseStarting(
+                    id,
+                    trip.person,
+                    None,
+                    TripPhaseType::Unloading(building),
+                ));
+            }
+            here = Some(pos);
+            self.events.push(Event::TruckUnloading {
+                truck,
+                trip: id,
+                building,
+                lane: pos.lane(),
+            });
+        }
+    }
 }
 
 // Transitions between different legs of a trip
@@ -449,6 +1292,44 @@ impl TripManager {
         self.active_trip_mode.insert(agent, t);
     }
 
+    /// How long somebody plans to leave their car parked at the end of a trip: until their next
+    /// trip departs, or the end of the day. This is decided when they start driving, so it
+    /// overestimates by however long the drive takes.
+    fn planned_stay(&self, now: Time, trip: TripID) -> Duration {
+        let person = &self.people[self.trips[trip.0].person.0];
+        let end = person
+            .trips
+            .iter()
+            .position(|t| *t == trip)
+            .and_then(|idx| person.trips.get(idx + 1))
+            .map(|next| self.trips[next.0].info.departure)
+            .unwrap_or_else(|| Time::START_OF_DAY + Duration::hours(24));
+        if end > now {
+            end - now
+        } else {
+            Duration::ZERO
+        }
+    }
+
+    /// Paths planned before the map was edited might use roads that've since been closed.
+    pub fn handle_live_edits(&mut self) {
+        if let Some(ref mut congestion) = self.congestion {
+            congestion.invalidate();
+        }
+    }
+
+    /// Plan the route for a vehicle starting to drive now. Cars and trucks avoid roads that were
+    /// congested around this time of day in an earlier simulation, if that's known.
+    fn pathfind_vehicle(&mut self, now: Time, req: PathRequest, map: &Map) -> Result<Path> {
+        let constraints = req.constraints;
+        if let Some(ref mut congestion) = self.congestion {
+            if constraints == PathConstraints::Car || constraints == PathConstraints::Truck {
+                return congestion.pathfind(req, now, map)?.into_v1(map);
+            }
+        }
+        map.pathfind(req)
+    }
+
     pub fn car_reached_parking_spot(
         &mut self,
         now: Time,
@@ -458,6 +1339,16 @@ impl TripManager {
         distance_crossed: Distance,
         ctx: &mut Ctx,
     ) {
+        if self.ride_hail.has_vehicle(car) {
+            self.ride_hail_vehicle_parked(now, car, distance_crossed, ctx);
+            return;
+        }
+        // Plug in, if the driver came here to charge
+        let pt = ctx.parking.spot_to_sidewalk_pos(spot, ctx.map).pt(ctx.map);
+        if let Some(None) = self.charging.car_parked(now, car, pt, ctx.map) {
+            self.events.push(Event::NoChargerAvailable(car, pt));
+        }
+
         let trip = &mut self.trips[self.active_trip_mode.remove(&AgentID::Car(car)).unwrap().0];
         trip.total_blocked_time += blocked_time;
         trip.total_distance += distance_crossed;
@@ -486,6 +1377,10 @@ impl TripManager {
             },
             _ => unreachable!(),
         };
+        // Leaving the car to ride transit; a later trip will come back for it
+        if let Some(TripLeg::RideBus(_, _)) = trip.legs.get(1) {
+            self.parked_for_transit.insert(car);
+        }
 
         let id = trip.id;
         self.spawn_ped(
@@ -496,6 +1391,165 @@ impl TripManager {
         );
     }
 
+    /// A car reached a carpool or ride-hailing stop. Returns true if anybody got in or out of the
+    /// car.
+    pub fn car_reached_passenger_stop(
+        &mut self,
+        now: Time,
+        car: CarID,
+        distance_crossed: Distance,
+        ctx: &mut Ctx,
+    ) -> bool {
+        if self.ride_hail.has_vehicle(car) {
+            return self.ride_hail_vehicle_reached_stop(now, car, distance_crossed, ctx);
+        }
+        let id = self.active_trip_mode[&AgentID::Car(car)];
+        self.trips[id.0].total_distance += distance_crossed;
+
+        // Handle every stop at this same spot at once
+        let mut anybody = false;
+        let mut here = None;
+        loop {
+            let trip = &mut self.trips[id.0];
+            let (pos, passenger, pick_up) = match trip.legs.front() {
+                Some(TripLeg::Drive(
+                    c,
+                    DrivingGoal::CarpoolStop {
+                        pos,
+                        passenger,
+                        pick_up,
+                    },
+                )) if here.map(|x| x == *pos).unwrap_or(true) => {
+                    assert_eq!(car, *c);
+                    (*pos, *passenger, *pick_up)
+                }
+                _ => break,
+            };
+            here = Some(pos);
+            trip.legs.pop_front();
+
+            let ride = &self.trips[passenger.0];
+            let person = ride.person;
+            let riding = self.people[person.0].in_car == Some(car);
+            if pick_up {
+                let waiting = ride.started
+                    && ride.info.cancellation_reason.is_none()
+                    && self.people[person.0].state == PersonState::Trip(passenger)
+                    && !riding;
+                if !waiting {
+                    // They weren't ready. Don't bother detouring to drop them off later; if their
+                    // trip starts, they'll notice they missed the ride.
+                    self.trips[id.0]
+                        .legs
+                        .retain(|leg| leg.carpool_stop().map(|(p, _)| p) != Some(passenger));
+                    continue;
+                }
+                let b = match ride.info.start {
+                    TripEndpoint::Bldg(b) => b,
+                    _ => unreachable!(),
+                };
+                self.events.push(Event::PersonLeavesBuilding(person, b));
+                self.events.push(Event::PassengerEntersCar(person, car, b));
+                self.events.push(Event::TripPhaseStarting(
+                    passenger,
+                    person,
+                    None,
+                    TripPhaseType::RidingInCar(car),
+                ));
+                self.passenger_enters_car(person, car, passenger);
+                anybody = true;
+            } else if riding {
+                let b = match ride.info.end {
+                    TripEndpoint::Bldg(b) => b,
+                    _ => unreachable!(),
+                };
+                self.passenger_leaves_car(person, car);
+                let ride = &mut self.trips[passenger.0];
+                match ride.legs.pop_front() {
+                    Some(TripLeg::RideCar(t)) => assert_eq!(t, id),
+                    _ => unreachable!(),
+                }
+                self.events.push(Event::PassengerLeavesCar(person, car, b));
+                self.people[person.0].state = PersonState::Inside(b);
+                self.events.push(Event::PersonEntersBuilding(person, b));
+                self.trip_finished(now, passenger, ctx);
+                anybody = true;
+            }
+        }
+        anybody
+    }
+
+    /// After stopping to pick up or drop off passengers or to unload a delivery, figure out where
+    /// the vehicle goes next. If there's no way to get there, the trip is cancelled and None is
+    /// returned.
+    pub fn car_leaving_stop(
+        &mut self,
+        now: Time,
+        car: CarID,
+        pos: Position,
+        ctx: &mut Ctx,
+    ) -> Option<Router> {
+        if self.ride_hail.has_vehicle(car) {
+            return Some(self.ride_hail_vehicle_leaving_stop(now, car, pos, ctx));
+        }
+        let id = self.active_trip_mode[&AgentID::Car(car)];
+        let trip = &self.trips[id.0];
+        let drive_to = match trip.legs[0] {
+            TripLeg::Drive(c, ref to) => {
+                assert_eq!(c, car);
+                to.clone()
+            }
+            _ => unreachable!(),
+        };
+        let constraints = car.vehicle_type.to_constraints();
+        let end = drive_to.goal_pos(constraints, ctx.map).unwrap();
+        self.events.push(Event::TripPhaseStarting(
+            id,
+            trip.person,
+            Some(PathRequest::vehicle(pos, end, constraints)),
+            TripPhaseType::Driving,
+        ));
+        match ctx
+            .map
+            .pathfind(PathRequest::vehicle(pos, end, constraints))
+        {
+            Ok(path) => {
+                Some(drive_to.make_router(car, path, Some(self.planned_stay(now, id)), ctx.map))
+            }
+            Err(err) => {
+                let vehicle = self.people[trip.person.0].get_vehicle(car);
+                self.cancel_trip(now, id, err.to_string(), Some(vehicle), ctx);
+                self.trip_abruptly_cancelled(id, AgentID::Car(car));
+                None
+            }
+        }
+    }
+
+    /// How many carpool passengers are in this car?
+    pub fn num_car_passengers(&self, car: CarID) -> usize {
+        self.car_riders.get(&car).map(|x| x.len()).unwrap_or(0)
+    }
+
+    fn passenger_enters_car(&mut self, person: PersonID, car: CarID, trip: TripID) {
+        self.active_trip_mode
+            .insert(AgentID::CarPassenger(person, car), trip);
+        self.people[person.0].in_car = Some(car);
+        self.car_riders.entry(car).or_default().push(person);
+    }
+
+    fn passenger_leaves_car(&mut self, person: PersonID, car: CarID) {
+        self.active_trip_mode
+            .remove(&AgentID::CarPassenger(person, car))
+            .unwrap();
+        self.people[person.0].in_car = None;
+        if let Some(riders) = self.car_riders.get_mut(&car) {
+            riders.retain(|p| *p != person);
+            if riders.is_empty() {
+                self.car_riders.remove(&car);
+            }
+        }
+    }
+
     pub fn ped_reached_parking_spot(
         &mut self,
         now: Time,
@@ -515,38 +1569,76 @@ impl TripManager {
         trip.total_distance += distance_crossed;
 
         trip.assert_walking_leg(SidewalkSpot::deferred_parking_spot());
-        let parked_car = ctx.parking.get_car_at_spot(spot).unwrap().clone();
-        let drive_to = match trip.legs[0] {
+        let mut parked_car = ctx.parking.get_car_at_spot(spot).unwrap().clone();
+        let mut drive_to = match trip.legs[0] {
             TripLeg::Drive(c, ref to) => {
                 assert_eq!(c, parked_car.vehicle.id);
                 to.clone()
             }
             _ => unreachable!(),
         };
+        let car = parked_car.vehicle.id;
+        self.parked_for_transit.remove(&car);
+        if let Some((station, duration)) = self.charging.unplug(now, car) {
+            if let Some(ref mut battery) = parked_car.vehicle.battery {
+                let kwh = battery.charge_for(duration);
+                self.events.push(Event::ChargingSessionEnded {
+                    car,
+                    station,
+                    duration,
+                    kwh,
+                });
+            }
+        }
+        if let ParkingSpot::Offstreet(b, _) = spot {
+            self.events
+                .push(Event::PersonEntersBuilding(trip.person, b));
+        }
 
         let base_start =
             ctx.parking
                 .spot_to_driving_pos(parked_car.spot, &parked_car.vehicle, ctx.map);
-        let end = drive_to.goal_pos(PathConstraints::Car, ctx.map).unwrap();
-        let req = match spot {
-            ParkingSpot::Onstreet(_, _) => {
-                PathRequest::vehicle(base_start, end, PathConstraints::Car)
-            }
-            ParkingSpot::Offstreet(b, _) => {
-                self.events
-                    .push(Event::PersonEntersBuilding(trip.person, b));
-                PathRequest::leave_from_driveway(base_start, end, PathConstraints::Car, ctx.map)
-            }
-            ParkingSpot::Lot(_, _) => {
-                PathRequest::leave_from_driveway(base_start, end, PathConstraints::Car, ctx.map)
-            }
+        let constraints = parked_car.vehicle.vehicle_type.to_constraints();
+        let request = |goal: &DrivingGoal| -> Result<PathRequest> {
+            let end = goal
+                .goal_pos(constraints, ctx.map)
+                .ok_or_else(|| anyhow!("{:?} isn't reachable by {:?}", goal, constraints))?;
+            Ok(match spot {
+                ParkingSpot::Onstreet(_, _) => PathRequest::vehicle(base_start, end, constraints),
+                ParkingSpot::Offstreet(_, _) | ParkingSpot::Lot(_, _) => {
+                    PathRequest::leave_from_driveway(base_start, end, constraints, ctx.map)
+                }
+            })
         };
 
         let person = trip.person;
         let trip = trip.id;
-        match ctx.map.pathfind(req) {
+        let mut maybe_path =
+            request(&drive_to).and_then(|req| self.pathfind_vehicle(now, req, ctx.map));
+        if let Ok(ref path) = maybe_path {
+            if let Some((station, b)) =
+                self.should_charge_at_destination(&parked_car.vehicle, &drive_to, path, ctx.map)
+            {
+                let goal = DrivingGoal::ParkNear(b);
+                // If the charger is unreachable, just go to the original destination
+                if let Ok(path) =
+                    request(&goal).and_then(|req| self.pathfind_vehicle(now, req, ctx.map))
+                {
+                    self.charging.head_to(car, station);
+                    self.trips[trip.0].legs[0] = TripLeg::Drive(car, goal.clone());
+                    drive_to = goal;
+                    maybe_path = Ok(path);
+                }
+            }
+        }
+        match maybe_path {
             Ok(path) => {
-                let router = drive_to.make_router(parked_car.vehicle.id, path, ctx.map);
+                let router = drive_to.make_router(
+                    parked_car.vehicle.id,
+                    path,
+                    Some(self.planned_stay(now, trip)),
+                    ctx.map,
+                );
                 ctx.scheduler.push(
                     now,
                     Command::SpawnCar(
@@ -563,6 +1655,38 @@ impl TripManager {
         }
     }
 
+    /// An electric car about to drive somewhere that'd arrive with a low battery looks for a
+    /// charging station near the destination. Returns the station and a building to park near.
+    fn should_charge_at_destination(
+        &mut self,
+        vehicle: &Vehicle,
+        goal: &DrivingGoal,
+        path: &Path,
+        map: &Map,
+    ) -> Option<(ChargingStationID, BuildingID)> {
+        let battery = vehicle.battery.as_ref()?;
+        let b = match goal {
+            DrivingGoal::ParkNear(b) => *b,
+            _ => {
+                return None;
+            }
+        };
+        if !battery.low_after(estimate_energy(path, map)) {
+            return None;
+        }
+        let pt = map.get_b(b).sidewalk_pos.pt(map);
+        let result = self.charging.find_charger(pt, map);
+        if result.is_none() {
+            self.events.push(Event::NoChargerAvailable(vehicle.id, pt));
+        }
+        result
+    }
+
+    /// A parked car was removed by map edits, so free up any charger it was using.
+    pub fn parked_car_evicted(&mut self, now: Time, car: CarID) {
+        self.charging.unplug(now, car);
+    }
+
     pub fn ped_ready_to_bike(
         &mut self,
         now: Time,
@@ -581,6 +1705,11 @@ impl TripManager {
         trip.total_distance += distance_crossed;
 
         trip.assert_walking_leg(spot.clone());
+        if let TripLeg::BikeShare { .. } = trip.legs[0] {
+            let id = trip.id;
+            self.ped_ready_to_bike_share(now, id, spot, ctx);
+            return;
+        }
         let (bike, drive_to) = match trip.legs[0] {
             TripLeg::Drive(bike, ref to) => (bike, to.clone()),
             _ => unreachable!(),
@@ -614,7 +1743,7 @@ impl TripManager {
         } else {
             ctx.map
                 .pathfind(req)
-                .map(|path| drive_to.make_router(bike, path, ctx.map))
+                .map(|path| drive_to.make_router(bike, path, None, ctx.map))
         };
         match maybe_router {
             Ok(router) => {
@@ -659,6 +1788,12 @@ impl TripManager {
             Some(TripLeg::Drive(c, DrivingGoal::ParkNear(_))) => {
                 assert_eq!(c, bike);
             }
+            Some(TripLeg::Drive(c, DrivingGoal::BikeShareDock(dock))) => {
+                assert_eq!(c, bike);
+                let id = trip.id;
+                self.shared_bike_reached_dock(now, id, bike, dock, bike_rack, ctx);
+                return;
+            }
             _ => unreachable!(),
         };
 
@@ -905,6 +2040,7 @@ impl TripManager {
         });
 
         let person = trip.person;
+        self.cancel_stranded_carpool_passengers(now, id, ctx);
         self.start_delayed_trip(now, person, ctx);
     }
 
@@ -928,10 +2064,30 @@ impl TripManager {
 
     fn spawn_ped(&mut self, now: Time, id: TripID, start: SidewalkSpot, ctx: &mut Ctx) {
         let trip = &self.trips[id.0];
-        let walk_to = match trip.legs[0] {
+        let mut walk_to = match trip.legs[0] {
             TripLeg::Walk(ref to) => to.clone(),
             _ => unreachable!(),
         };
+        // Heading back to a car parked earlier in the trip, like after riding transit back from a
+        // park-and-ride
+        if walk_to.connection == SidewalkPOI::DeferredParkingSpot {
+            let car = match trip.legs[1] {
+                TripLeg::Drive(c, _) => c,
+                _ => unreachable!(),
+            };
+            if let Some(parked_car) = ctx.parking.lookup_parked_car(car) {
+                walk_to = SidewalkSpot::parking_spot(parked_car.spot, ctx.map, ctx.parking);
+            } else {
+                self.cancel_trip(
+                    now,
+                    id,
+                    format!("should have {} parked somewhere, but it's unavailable", car),
+                    None,
+                    ctx,
+                );
+                return;
+            }
+        }
 
         let req = PathRequest::walking(start.sidewalk_pos, walk_to.sidewalk_pos);
         match ctx.map.pathfind(req) {
@@ -978,6 +2134,7 @@ impl TripManager {
         abandoned_vehicle: Option<Vehicle>,
         ctx: &mut Ctx,
     ) {
+        let abandoned_vehicle_id = abandoned_vehicle.as_ref().map(|v| v.id);
         let trip = &mut self.trips[id.0];
         self.unfinished_trips -= 1;
         trip.info.cancellation_reason = Some(reason);
@@ -1005,11 +2162,12 @@ impl TripManager {
 
         // Don't forget the car!
         if let Some(vehicle) = abandoned_vehicle {
-            if vehicle.vehicle_type == VehicleType::Car {
+            if matches!(vehicle.vehicle_type, VehicleType::Car | VehicleType::Truck) {
                 // First remove the parked car, if needed. Maybe the trip was cancelled while the
                 // car was parked in the starting building.
                 if let Some(parked_car) = ctx.parking.lookup_parked_car(vehicle.id).cloned() {
                     ctx.parking.remove_parked_car(parked_car);
+                    self.charging.unplug(now, vehicle.id);
                 }
 
                 if let TripEndpoint::Bldg(b) = trip.info.end {
@@ -1023,7 +2181,7 @@ impl TripManager {
                         .map(|(spot, _)| *spot)
                         .or_else(|| {
                             ctx.parking
-                                .path_to_free_parking_spot(driving_lane, &vehicle, b, ctx.map)
+                                .path_to_free_parking_spot(driving_lane, &vehicle, b, None, ctx.map)
                                 .map(|(_, spot, _)| spot)
                         })
                     {
@@ -1061,6 +2219,24 @@ impl TripManager {
             }
         }
 
+        // Shared bikes go back to a dock
+        let maybe_bike = abandoned_vehicle_id.or_else(|| match self.trips[id.0].legs.front() {
+            Some(TripLeg::Drive(c, _)) => Some(*c),
+            _ => None,
+        });
+        if let Some(bike) = maybe_bike {
+            if let Some((dock, inventory)) = self.bike_share.abandon(bike) {
+                self.events.push(Event::BikeShareReturned {
+                    trip: id,
+                    dock,
+                    bike,
+                    inventory,
+                });
+            }
+        }
+
+        self.ride_hail.cancel_request(id);
+        self.cancel_stranded_carpool_passengers(now, id, ctx);
         self.start_delayed_trip(now, person, ctx);
     }
 
@@ -1102,6 +2278,18 @@ impl TripManager {
             TripLeg::Walk(_) => AgentID::Pedestrian(person.ped),
             TripLeg::Drive(c, _) => AgentID::Car(*c),
             TripLeg::RideBus(_, _) => AgentID::BusPassenger(person.id, person.on_bus.unwrap()),
+            TripLeg::RideCar(_) | TripLeg::RideHail => {
+                if let Some(car) = person.in_car {
+                    AgentID::CarPassenger(person.id, car)
+                } else {
+                    // Still waiting to be picked up
+                    return TripResult::ModeChange;
+                }
+            }
+            // Standing at the dock, about to ride off or walk somewhere else
+            TripLeg::BikeShare { .. } => {
+                return TripResult::ModeChange;
+            }
         };
         if self.active_trip_mode.get(&a) == Some(&id) {
             TripResult::Ok(a)
@@ -1154,8 +2342,14 @@ impl TripManager {
             walking_to_from_bike: 0,
 
             cyclists: 0,
+            bike_share_riders: 0,
 
             sov_drivers: 0,
+            carpool_drivers: 0,
+            carpool_passengers: 0,
+            ride_hail_vehicles: self.ride_hail.utilization().0,
+            ride_hail_riders: 0,
+            trucks: 0,
 
             buses,
             trains,
@@ -1167,10 +2361,20 @@ impl TripManager {
             match a {
                 AgentID::Car(c) => match c.vehicle_type {
                     VehicleType::Car => {
-                        cnt.sov_drivers += 1;
+                        if self.car_riders.contains_key(c) {
+                            cnt.carpool_drivers += 1;
+                        } else {
+                            cnt.sov_drivers += 1;
+                        }
                     }
                     VehicleType::Bike => {
                         cnt.cyclists += 1;
+                        if self.bike_share.is_in_use(*c) {
+                            cnt.bike_share_riders += 1;
+                        }
+                    }
+                    VehicleType::Truck => {
+                        cnt.trucks += 1;
                     }
                     VehicleType::Bus | VehicleType::Train => unreachable!(),
                 },
@@ -1181,8 +2385,15 @@ impl TripManager {
                     VehicleType::Train => {
                         cnt.train_riders += 1;
                     }
-                    VehicleType::Car | VehicleType::Bike => unreachable!(),
+                    VehicleType::Car | VehicleType::Bike | VehicleType::Truck => unreachable!(),
                 },
+                AgentID::CarPassenger(_, c) => {
+                    if self.ride_hail.has_vehicle(*c) {
+                        cnt.ride_hail_riders += 1;
+                    } else {
+                        cnt.carpool_passengers += 1;
+                    }
+                }
                 // These're counted separately
                 AgentID::Pedestrian(_) => {}
             }
@@ -1261,9 +2472,11 @@ impl TripManager {
                 if i == at {
                     // We can make some assumptions here.
                     let agent_type = match t.info.mode {
-                        TripMode::Walk => AgentType::Pedestrian,
+                        // Bike-share riders start by walking to a dock
+                        TripMode::Walk | TripMode::BikeShare => AgentType::Pedestrian,
                         TripMode::Bike => AgentType::Bike,
-                        TripMode::Drive => AgentType::Car,
+                        TripMode::Drive | TripMode::ParkAndRide => AgentType::Car,
+                        TripMode::RideHail => AgentType::CarPassenger,
                         // TODO Not true for long. People will be able to spawn at borders already
                         // on a bus.
                         TripMode::Transit => AgentType::Pedestrian,
@@ -1280,6 +2493,7 @@ impl TripManager {
     /// original Scenario used.
     pub fn generate_scenario(&self, map: &Map, name: String) -> Scenario {
         let mut scenario = Scenario::empty(map, &name);
+        scenario.ride_hail_fleet = self.ride_hail.len();
         for p in &self.people {
             scenario.people.push(PersonSpec {
                 orig_id: p.orig_id,
@@ -1288,13 +2502,26 @@ impl TripManager {
                     .iter()
                     .map(|t| {
                         let trip = &self.trips[t.0];
-                        IndividTrip::new(
+                        let mut individ = IndividTrip::new(
                             trip.info.departure,
                             trip.info.purpose,
                             trip.info.start,
                             trip.info.end,
                             trip.info.mode,
-                        )
+                        );
+                        individ.ride_with = trip.info.ride_with.map(|driver| {
+                            let person = self.trips[driver.0].person;
+                            CarpoolDriver {
+                                person: person.0,
+                                trip: self.people[person.0]
+                                    .trips
+                                    .iter()
+                                    .position(|t| *t == driver)
+                                    .unwrap(),
+                            }
+                        });
+                        individ.delivery_stops = trip.info.delivery_stops.clone();
+                        individ
                     })
                     .collect(),
             });
@@ -1303,6 +2530,11 @@ impl TripManager {
     }
 }
 
+/// Where a ride-hailing vehicle stops in front of a building
+fn curb_pos(b: BuildingID, map: &Map) -> Option<Position> {
+    DrivingGoal::ParkNear(b).goal_pos(PathConstraints::Car, map)
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 struct Trip {
     id: TripID,
@@ -1327,6 +2559,10 @@ pub struct TripInfo {
     /// Did a ScenarioModifier apply to this?
     pub modified: bool,
     pub cancellation_reason: Option<String>,
+    /// If this person is a carpool passenger, the driver's trip
+    pub ride_with: Option<TripID>,
+    /// If this is a delivery tour, the buildings to stop at along the way
+    pub delivery_stops: Vec<BuildingID>,
 }
 
 impl Trip {
@@ -1349,14 +2585,49 @@ pub(crate) enum TripLeg {
     Drive(CarID, DrivingGoal),
     /// Maybe get off at a stop, maybe ride off-map
     RideBus(BusRouteID, Option<BusStopID>),
+    /// Ride along with the driver doing this trip
+    RideCar(TripID),
+    /// Ride in whichever ride-hailing vehicle gets dispatched
+    RideHail,
+    /// Unlock a shared bike at one dock and ride it to another. Once the bike is unlocked, this
+    /// becomes a `Drive` leg.
+    BikeShare {
+        pick_up: BikeShareDockID,
+        drop_off: BikeShareDockID,
+    },
 }
 
-#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone, Copy, PartialOrd, Ord)]
+impl TripLeg {
+    /// If this leg is driving to a carpool stop, which passenger is it for, and is it to pick them
+    /// up?
+    fn carpool_stop(&self) -> Option<(TripID, bool)> {
+        match self {
+            TripLeg::Drive(
+                _,
+                DrivingGoal::CarpoolStop {
+                    passenger, pick_up, ..
+                },
+            ) => Some((*passenger, *pick_up)),
+            _ => None,
+        }
+    }
+}
+
+#[derive(
+    Serialize, Deserialize, PartialEq, Eq, Debug, Clone, Copy, PartialOrd, Ord, JsonSchema,
+)]
 pub enum TripMode {
     Walk,
     Bike,
     Transit,
     Drive,
+    /// Get picked up by a ride-hailing vehicle or taxi. Only works between two buildings.
+    RideHail,
+    /// Walk to a dock, ride a shared bike or scooter to another dock, and walk the rest of the way
+    BikeShare,
+    /// Drive to a transit stop, park nearby, and ride transit the rest of the way. A later
+    /// park-and-ride trip rides transit back to the car and drives the rest of the way.
+    ParkAndRide,
 }
 
 impl TripMode {
@@ -1366,6 +2637,9 @@ impl TripMode {
             TripMode::Bike,
             TripMode::Transit,
             TripMode::Drive,
+            TripMode::RideHail,
+            TripMode::BikeShare,
+            TripMode::ParkAndRide,
         ]
     }
 
@@ -1375,6 +2649,9 @@ impl TripMode {
             TripMode::Bike => "bike",
             TripMode::Transit => "use transit",
             TripMode::Drive => "drive",
+            TripMode::RideHail => "hail a ride",
+            TripMode::BikeShare => "use bike-share",
+            TripMode::ParkAndRide => "park and ride",
         }
     }
 
@@ -1385,6 +2662,9 @@ impl TripMode {
             TripMode::Bike => "biking",
             TripMode::Transit => "using transit",
             TripMode::Drive => "driving",
+            TripMode::RideHail => "riding in a taxi",
+            TripMode::BikeShare => "using bike-share",
+            TripMode::ParkAndRide => "parking and riding",
         }
     }
 
@@ -1394,16 +2674,19 @@ impl TripMode {
             TripMode::Bike => "Bike",
             TripMode::Transit => "Bus",
             TripMode::Drive => "Car",
+            TripMode::RideHail => "Taxi",
+            TripMode::BikeShare => "Shared bike",
+            TripMode::ParkAndRide => "Park-and-ride",
         }
     }
 
     pub fn to_constraints(self) -> PathConstraints {
         match self {
             TripMode::Walk => PathConstraints::Pedestrian,
-            TripMode::Bike => PathConstraints::Bike,
+            TripMode::Bike | TripMode::BikeShare => PathConstraints::Bike,
             // TODO WRONG
             TripMode::Transit => PathConstraints::Bus,
-            TripMode::Drive => PathConstraints::Car,
+            TripMode::Drive | TripMode::RideHail | TripMode::ParkAndRide => PathConstraints::Car,
         }
     }
 
@@ -1413,7 +2696,7 @@ impl TripMode {
             PathConstraints::Bike => TripMode::Bike,
             // TODO The bijection breaks down... transit rider vs train vs bus...
             PathConstraints::Bus | PathConstraints::Train => TripMode::Transit,
-            PathConstraints::Car => TripMode::Drive,
+            PathConstraints::Car | PathConstraints::Truck => TripMode::Drive,
         }
     }
 }
@@ -1461,6 +2744,8 @@ pub struct Person {
 
     delayed_trips: Vec<(TripID, StartTripArgs)>,
     on_bus: Option<CarID>,
+    /// Riding in somebody else's car
+    in_car: Option<CarID>,
 }
 
 impl Person {
@@ -1484,11 +2769,156 @@ pub struct CommutersVehiclesCounts {
     pub walking_to_from_bike: usize,
 
     pub cyclists: usize,
+    /// Cyclists riding a shared bike. They're also counted in `cyclists`.
+    pub bike_share_riders: usize,
 
     pub sov_drivers: usize,
+    pub carpool_drivers: usize,
+    pub carpool_passengers: usize,
+    /// Ride-hailing vehicles that aren't parked, whether or not they have a rider
+    pub ride_hail_vehicles: usize,
+    pub ride_hail_riders: usize,
+    /// Delivery trucks, whether they're driving or stopped to unload
+    pub trucks: usize,
 
     pub buses: usize,
     pub trains: usize,
     pub bus_riders: usize,
     pub train_riders: usize,
 }
+
+#[cfg(test)]
+mod tests {
+    use map_model::toy::{add_building, build, building, toy_raw_map};
+    use map_model::IntersectionType;
+
+    use super::*;
+    use crate::testing;
+
+    /// One street with four houses along it, all on the same side, west to east
+    fn street_with_houses() -> Map {
+        let mut raw = toy_raw_map(
+            &[
+                ("w", 0.0, 0.0, IntersectionType::Border),
+                ("e", 400.0, 0.0, IntersectionType::Border),
+            ],
+            &[("w", "e")],
+        );
+        for (name, x) in [
+            ("home", 50.0),
+            ("friend", 150.0),
+            ("office", 250.0),
+            ("work", 350.0),
+        ] {
+            add_building(&mut raw, name, x, 20.0, 5);
+        }
+        build(raw)
+    }
+
+    #[test]
+    fn carpool() {
+        let map = street_with_houses();
+        let bldg = |name| TripEndpoint::Bldg(building(&map, name));
+        let depart = Time::START_OF_DAY + Duration::hours(7);
+        let driver = IndividTrip::new(
+            depart,
+            TripPurpose::Work,
+            bldg("home"),
+            bldg("work"),
+            TripMode::Drive,
+        );
+        let mut passenger = IndividTrip::new(
+            depart - Duration::minutes(1),
+            TripPurpose::Work,
+            bldg("friend"),
+            bldg("office"),
+            TripMode::Drive,
+        );
+        passenger.ride_with = Some(CarpoolDriver { person: 0, trip: 0 });
+        let scenario = testing::scenario(&map, vec![vec![driver], vec![passenger]]);
+
+        let (sim, events) = testing::run(&map, &scenario, Duration::hours(8));
+        assert_eq!(sim.num_trips(), (2, 0));
+        assert!(!events
+            .iter()
+            .any(|ev| matches!(ev, Event::TripCancelled(_, _))));
+        let friend = building(&map, "friend");
+        let office = building(&map, "office");
+        assert!(events
+            .iter()
+            .any(|ev| matches!(ev, Event::PassengerEntersCar(_, _, b) if *b == friend)));
+        assert!(events
+            .iter()
+            .any(|ev| matches!(ev, Event::PassengerLeavesCar(_, _, b) if *b == office)));
+    }
+
+    #[test]
+    fn delivery_tour() {
+        let mut raw = testing::two_blocks();
+        for (name, x, y) in [
+            ("warehouse", 100.0, 20.0),
+            ("a", 380.0, 200.0),
+            ("b", 300.0, 380.0),
+        ] {
+            add_building(&mut raw, name, x, y, 2);
+        }
+        let map = build(raw);
+        let warehouse = TripEndpoint::Bldg(building(&map, "warehouse"));
+        let mut tour = IndividTrip::new(
+            Time::START_OF_DAY + Duration::hours(7),
+            TripPurpose::Work,
+            warehouse,
+            warehouse,
+            TripMode::Drive,
+        );
+        tour.delivery_stops = vec![building(&map, "a"), building(&map, "b")];
+        let scenario = testing::scenario(&map, vec![vec![tour]]);
+
+        let (sim, events) = testing::run(&map, &scenario, Duration::hours(8));
+        assert_eq!(sim.num_trips(), (1, 0));
+        let unloaded: Vec<(CarID, BuildingID)> = events
+            .iter()
+            .filter_map(|ev| match ev {
+                Event::TruckUnloading {
+                    truck, building, ..
+                } => Some((*truck, *building)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            unloaded.iter().map(|(_, b)| *b).collect::<Vec<_>>(),
+            vec![building(&map, "a"), building(&map, "b")]
+        );
+        assert!(unloaded
+            .iter()
+            .all(|(truck, _)| truck.vehicle_type == VehicleType::Truck));
+        let left = events
+            .iter()
+            .filter(|ev| matches!(ev, Event::TruckLeftLoadingZone { .. }))
+            .count();
+        assert_eq!(left, 2);
+    }
+
+    #[test]
+    fn carpool_passenger_must_use_buildings() {
+        let map = street_with_houses();
+        let mut passenger = IndividTrip::new(
+            Time::START_OF_DAY + Duration::hours(7),
+            TripPurpose::Work,
+            TripEndpoint::Border(map.all_intersections()[0].id),
+            TripEndpoint::Bldg(building(&map, "office")),
+            TripMode::Drive,
+        );
+        passenger.ride_with = Some(CarpoolDriver { person: 1, trip: 0 });
+        let driver = IndividTrip::new(
+            Time::START_OF_DAY + Duration::hours(7),
+            TripPurpose::Work,
+            TripEndpoint::Bldg(building(&map, "home")),
+            TripEndpoint::Bldg(building(&map, "work")),
+            TripMode::Drive,
+        );
+        let scenario = testing::scenario(&map, vec![vec![passenger], vec![driver]]);
+        let err = scenario.validate().unwrap_err().to_string();
+        assert!(err.contains("not between two buildings"), "{}", err);
+    }
+}
@@ -1,10 +1,11 @@
 //! A representation of traffic signal configuration that references OpenStreetMap IDs and is
 //! hopefully robust to minor edits over time.
 
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use std::collections::{BTreeMap, BTreeSet};
 
-#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct TrafficSignal {
     /// The ID of the OSM node representing the intersection with the traffic signal. This node
     /// should be tagged `highway = traffic_signals` in OSM.
@@ -15,12 +16,27 @@ pub struct TrafficSignal {
     /// order of ascending `start_time_seconds`, the first plan must begin at `0` (midnight), and
     /// the last plan must not start after 24 hours.
     pub plans: Vec<Plan>,
+    /// If present, buses and light rail approaching the intersection can extend or cut short the
+    /// current stage.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transit_priority: Option<TransitPriority>,
+}
+
+/// Limits on how much transit vehicles can change a signal's timing.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct TransitPriority {
+    /// If a transit vehicle won't make it through before its green ends, hold the green for at
+    /// most this many seconds.
+    pub max_extension_seconds: usize,
+    /// If a transit vehicle approaches a red, end the current stage at most this many seconds
+    /// early. Stages still last long enough for pedestrians to finish crossing.
+    pub max_truncation_seconds: usize,
 }
 
 /// A plan describes how a traffic signal is configured during some period of time. Multiple plans
 /// allow a single intersection to behave differently in the middle of the night with low traffic,
 /// compared to the middle of rush hour.
-#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct Plan {
     /// This plan takes effect at this local time, measured in seconds after midnight. The plan
     /// lasts until the next plan in the listed sequence starts, or ends at midnight if it's the
@@ -34,7 +50,7 @@ pub struct Plan {
 }
 
 /// A traffic signal is in one stage at any time. The stage describes what movements are possible.
-#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct Stage {
     /// During this stage, these turns can be performed with the highest priority, protected by a
     /// green light. No two protected turns in the same stage should cross; that would be a
@@ -49,7 +65,7 @@ pub struct Stage {
 }
 
 /// How long a stage lasts before moving to the next one.
-#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub enum StageType {
     /// A fixed number of seconds.
     Fixed(usize),
@@ -60,13 +76,24 @@ pub enum StageType {
     /// is 20, and additional is 40, the maximum cycle duration is 60.
     /// If there are crosswalks, the minimum is the minimum for the maximum crosswalks
     Variable(usize, usize, usize),
+    /// Minimum green, Passage, Maximum green
+    /// A detector-actuated stage lasts at least the minimum, then extends as long as vehicles for
+    /// its protected turns arrive less than the passage time apart, up to the maximum. It's
+    /// skipped if nobody is waiting for it. Crosswalks only get a walk signal if a pedestrian
+    /// pushed the button before the stage started.
+    Actuated(usize, usize, usize),
+    /// Minimum, Maximum
+    /// An adaptive stage. Every time the minimum elapses, the signal switches to whichever stage
+    /// has the most vehicles and pedestrians waiting for it relative to the space downstream. A
+    /// stage can't be picked again once it's lasted the maximum.
+    MaxPressure(usize, usize),
 }
 
 /// A movement through an intersection.
 ///
 /// Movements over crosswalks are a little confusing to understand. See the crosswalk_turns.png
 /// diagram in this repository for some clarification.
-#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, JsonSchema)]
 pub struct Turn {
     /// The movement begins at the end of this road segment.
     pub from: DirectedRoad,
@@ -81,7 +108,7 @@ pub struct Turn {
 }
 
 /// A road segment connecting two intersections, and a direction along the segment.
-#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, JsonSchema)]
 pub struct DirectedRoad {
     /// The ID of the OSM way representing the road.
     pub osm_way_id: i64,